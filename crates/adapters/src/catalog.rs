@@ -1,6 +1,6 @@
-use crate::{DeCollectionHandle, DeZSetHandle, SerOutputBatchHandle};
-use dbsp::{algebra::ZRingValue, CollectionHandle, DBData, DBWeight};
-use serde::Deserialize;
+use crate::{seroutput::KeyedOutputHandle, DeCollectionHandle, DeZSetHandle, SerOutputBatchHandle};
+use dbsp::{algebra::ZRingValue, trace::Batch, CollectionHandle, DBData, DBWeight, OutputHandle};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 /// A catalog of input and output stream handles of a circuit.
@@ -31,6 +31,24 @@ impl Catalog {
         self.register_input_collection_handle(name, DeZSetHandle::new(handle));
     }
 
+    /// Add a named Z-set input stream handle that accepts weighted updates,
+    /// e.g., from the `weight_column` option of the CSV parser, to the
+    /// catalog.
+    ///
+    /// Handles registered with
+    /// [`register_input_zset_handle`](`Self::register_input_zset_handle`)
+    /// only accept weights `+1` and `-1`.
+    pub fn register_input_weighted_zset_handle<K, R>(
+        &mut self,
+        name: &str,
+        handle: CollectionHandle<K, R>,
+    ) where
+        K: DBData + for<'de> Deserialize<'de>,
+        R: DBWeight + ZRingValue + TryFrom<i64>,
+    {
+        self.register_input_collection_handle(name, DeZSetHandle::with_weights(handle));
+    }
+
     /// Add a named input stream handle to the catalog.
     pub fn register_input_collection_handle<H>(&mut self, name: &str, handle: H)
     where
@@ -49,6 +67,23 @@ impl Catalog {
             .insert(name.to_owned(), Box::new(handle));
     }
 
+    /// Add a named output stream handle whose keys can be deserialized to the
+    /// catalog.
+    ///
+    /// Unlike handles registered with
+    /// [`register_output_batch_handle`](`Self::register_output_batch_handle`),
+    /// snapshots of the stream, when materialized, can be restricted to a
+    /// range of keys (see [`Controller::snapshot`](`crate::Controller::snapshot`)).
+    pub fn register_keyed_output_batch_handle<B>(&mut self, name: &str, handle: OutputHandle<B>)
+    where
+        B: Batch<Time = ()> + Send + Sync,
+        B::Key: Serialize + DeserializeOwned,
+        B::Val: Serialize,
+        B::R: Into<i64>,
+    {
+        self.register_output_batch_handle(name, KeyedOutputHandle::new(handle));
+    }
+
     /// Look up an input stream handle by name.
     pub fn input_collection_handle(&self, name: &str) -> Option<&dyn DeCollectionHandle> {
         self.input_collection_handles.get(name).map(|b| &**b)
@@ -37,6 +37,14 @@ pub struct PipelineConfig {
     /// Output endpoint configuration.
     #[serde(default)]
     pub outputs: BTreeMap<Cow<'static, str>, OutputEndpointConfig>,
+
+    /// Output streams to materialize.
+    ///
+    /// The controller integrates all updates produced by each of these
+    /// streams, so that the current contents of the stream can be queried
+    /// using [`Controller::snapshot`](`crate::Controller::snapshot`).
+    #[serde(default)]
+    pub materialized_streams: Vec<Cow<'static, str>>,
 }
 
 /// Global pipeline configuration settings.
@@ -64,6 +72,27 @@ pub struct GlobalPipelineConfig {
     /// get buffered by the controller, defaults to 0.
     #[serde(default)]
     pub max_buffering_delay_usecs: u64,
+
+    /// Enable exactly-once processing.
+    ///
+    /// When `true`, the controller keeps track of the circuit step that
+    /// processes each input buffer and notifies input endpoints once the
+    /// outputs of the step have been delivered to all output endpoints (see
+    /// [`InputEndpoint::commit`](`crate::InputEndpoint::commit`)).  Input
+    /// ingestion is blocked while the circuit is executing a step.  End-to-end
+    /// exactly-once delivery additionally requires input and output transports
+    /// configured for exactly-once operation, e.g., Kafka endpoints with
+    /// manual offset commits and transactional producers.
+    ///
+    /// Only stateless circuits, whose outputs at each step depend only on the
+    /// inputs of the step, are supported: a restarted pipeline does not
+    /// replay committed inputs, so a stateful circuit would lose the state
+    /// computed from them.  The controller fails to start a stateful circuit
+    /// in this mode.
+    ///
+    /// Defaults to `false`.
+    #[serde(default)]
+    pub exactly_once: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize, ToSchema)]
@@ -89,6 +118,39 @@ pub struct InputEndpointConfig {
     /// The default is 1 million.
     #[serde(default = "default_max_buffered_records")]
     pub max_buffered_records: u64,
+
+    /// Maximal rate, in records per second, at which the endpoint ingests
+    /// data.
+    ///
+    /// The controller pauses the endpoint once it gets more than about a
+    /// second ahead of this rate and resumes it when the rate drops below
+    /// the limit.  Use this to prevent a high-volume input, e.g., a backfill
+    /// of historical data, from starving other endpoints of circuit
+    /// capacity.  Must be greater than 0.
+    ///
+    /// By default, the rate is not limited.
+    #[serde(default)]
+    pub max_records_per_second: Option<u64>,
+
+    /// Maximal rate, in bytes per second, at which the endpoint ingests data.
+    ///
+    /// Enforced the same way as `max_records_per_second`.  Must be greater
+    /// than 0.
+    ///
+    /// By default, the rate is not limited.
+    #[serde(default)]
+    pub max_bytes_per_second: Option<u64>,
+
+    /// Dead-letter queue for records that fail to parse.
+    ///
+    /// Invalid input records are skipped by the parser.  When this option is
+    /// specified, the controller additionally writes each rejected record,
+    /// along with the error message, to an output transport endpoint with
+    /// this configuration.  Rejected records are encoded as
+    /// newline-delimited JSON objects of the form
+    /// `{"endpoint": <endpoint_name>, "record": <raw_record>, "error": <error_message>}`.
+    #[serde(default)]
+    pub dead_letter_queue: Option<TransportConfig>,
 }
 
 #[derive(Clone, Serialize, Deserialize, ToSchema)]
@@ -108,6 +170,40 @@ pub struct OutputEndpointConfig {
     /// The default is 1 million.
     #[serde(default = "default_max_buffered_records")]
     pub max_buffered_records: u64,
+
+    /// Consolidate output batches before encoding them.
+    ///
+    /// The circuit produces a separate output batch per worker thread, and
+    /// these batches may contain updates that cancel out, e.g., an insertion
+    /// and a deletion of the same record.  When this option is `true`, the
+    /// endpoint merges all batches produced by a step into a single batch
+    /// with such updates removed.
+    ///
+    /// Defaults to `false`.
+    #[serde(default)]
+    pub consolidate: bool,
+
+    /// Maximal time, in microseconds, for which consolidated updates are
+    /// accumulated before being sent to the endpoint.
+    ///
+    /// When non-zero, the endpoint consolidates the outputs of all steps
+    /// performed within this time bound into a single batch.  Only used when
+    /// `consolidate` is `true`.  Defaults to 0, i.e., each step is sent
+    /// separately.
+    #[serde(default)]
+    pub max_consolidation_delay_usecs: u64,
+
+    /// Send the current contents of the stream to the endpoint when it is
+    /// connected.
+    ///
+    /// When `true`, an endpoint attached to a running pipeline first
+    /// receives the integral of all updates the stream has produced so far,
+    /// followed by subsequent updates.  The stream must be listed in
+    /// [`PipelineConfig::materialized_streams`].
+    ///
+    /// Defaults to `false`.
+    #[serde(default)]
+    pub initial_snapshot: bool,
 }
 
 /// Transport endpoint configuration.
@@ -14,6 +14,12 @@ pub enum ConfigError {
     /// Output endpoint with this name already exists.
     DuplicateOutputEndpoint { endpoint_name: String },
 
+    /// Input endpoint with this name does not exist.
+    UnknownInputEndpoint { endpoint_name: String },
+
+    /// Output endpoint with this name does not exist.
+    UnknownOutputEndpoint { endpoint_name: String },
+
     /// Endpoint configuration specifies unknown input format name.
     UnknownInputFormat { format_name: String },
 
@@ -37,6 +43,9 @@ impl Display for ConfigError {
             Self::DuplicateInputEndpoint { endpoint_name } => {
                 write!(f, "input endpoint '{endpoint_name}' already exists")
             }
+            Self::UnknownInputEndpoint { endpoint_name } => {
+                write!(f, "unknown input endpoint '{endpoint_name}'")
+            }
             Self::UnknownInputFormat { format_name } => {
                 write!(f, "unknown input format '{format_name}'")
             }
@@ -46,6 +55,9 @@ impl Display for ConfigError {
             Self::DuplicateOutputEndpoint { endpoint_name } => {
                 write!(f, "output endpoint '{endpoint_name}' already exists")
             }
+            Self::UnknownOutputEndpoint { endpoint_name } => {
+                write!(f, "unknown output endpoint '{endpoint_name}'")
+            }
             Self::UnknownOutputFormat { format_name } => {
                 write!(f, "unknown output format '{format_name}'")
             }
@@ -66,6 +78,12 @@ impl ConfigError {
         }
     }
 
+    pub fn unknown_input_endpoint(endpoint_name: &str) -> Self {
+        Self::UnknownInputEndpoint {
+            endpoint_name: endpoint_name.to_owned(),
+        }
+    }
+
     pub fn unknown_input_format(format_name: &str) -> Self {
         Self::UnknownInputFormat {
             format_name: format_name.to_owned(),
@@ -84,6 +102,12 @@ impl ConfigError {
         }
     }
 
+    pub fn unknown_output_endpoint(endpoint_name: &str) -> Self {
+        Self::UnknownOutputEndpoint {
+            endpoint_name: endpoint_name.to_owned(),
+        }
+    }
+
     pub fn unknown_output_format(format_name: &str) -> Self {
         Self::UnknownOutputFormat {
             format_name: format_name.to_owned(),
@@ -111,9 +135,9 @@ pub enum ControllerError {
 
     /// Parser error.
     ///
-    /// Error parsing the last input batch.  Parser errors are expected to be
-    /// recoverable, i.e., the parser should be able to successfully parse
-    /// new valid inputs after an error.
+    /// Error parsing an input record.  Parser errors are recoverable: the
+    /// invalid record is skipped, and the parser continues parsing
+    /// subsequent records.
     ParseError {
         endpoint_name: String,
         error: AnyError,
@@ -209,6 +233,12 @@ impl ControllerError {
         }
     }
 
+    pub fn unknown_input_endpoint(endpoint_name: &str) -> Self {
+        Self::Config {
+            config_error: ConfigError::unknown_input_endpoint(endpoint_name),
+        }
+    }
+
     pub fn unknown_input_format(format_name: &str) -> Self {
         Self::Config {
             config_error: ConfigError::unknown_input_format(format_name),
@@ -227,6 +257,12 @@ impl ControllerError {
         }
     }
 
+    pub fn unknown_output_endpoint(endpoint_name: &str) -> Self {
+        Self::Config {
+            config_error: ConfigError::unknown_output_endpoint(endpoint_name),
+        }
+    }
+
     pub fn unknown_output_format(format_name: &str) -> Self {
         Self::Config {
             config_error: ConfigError::unknown_output_format(format_name),
@@ -11,8 +11,9 @@
 //!
 //! The backpressure thread controls the flow of data through transport
 //! endpoints, pausing the endpoints either when the amount of data buffered by
-//! the endpoint exceeds a user-defined threshold or in response to an explicit
-//! user request.
+//! the endpoint exceeds a user-defined threshold, when the endpoint exceeds
+//! its configured input rate, or in response to an explicit user request,
+//! which can apply to the whole pipeline or to an individual endpoint.
 //!
 //! Both tasks require monitoring the state of the input buffers.  To this end,
 //! the controller injects `InputProbe`s between each input endpoint and format
@@ -32,30 +33,68 @@
 //! The probe passes the data through to the parser, while counting the number
 //! of transmitted bytes and records and updating respective performance
 //! counters in the controller.
+//!
+//! # Checkpoints
+//!
+//! [`Controller::checkpoint`] writes the state of the circuit (see
+//! [`DBSPHandle::checkpoint`]) along with the position of each input endpoint
+//! in its input stream, as last reported by the endpoint (see
+//! [`InputConsumer::input_at`]).  To make the two consistent, input probes
+//! hold a shared lock while pushing data to the circuit and recording the
+//! position, and the circuit thread holds the lock exclusively while
+//! checkpointing.  [`Controller::from_checkpoint`] resumes the pipeline by
+//! seeking each input endpoint to its recorded position (see
+//! [`InputEndpoint::seek`]).  Checkpoints also store the contents of
+//! materialized output streams, which cannot be recomputed from the state of
+//! the circuit.  Checkpoints require the `checkpoint` feature.
+//!
+//! # Exactly-once mode
+//!
+//! In [exactly-once mode](`GlobalPipelineConfig::exactly_once`), the circuit
+//! thread also holds the lock exclusively while executing a step, so that
+//! input probes can tell which step will process each input buffer.  Output
+//! batches are tagged with the step that produced them.  Once all output
+//! endpoints have delivered the outputs of a step, the controller invokes
+//! [`InputEndpoint::commit`] on all input endpoints.  Committed inputs are not
+//! replayed after a restart, and the circuit restarts without the state
+//! derived from them, so this mode only supports stateless circuits (see
+//! [`DBSPHandle::is_stateless`]).
 
 use crate::{
-    Catalog, Encoder, InputConsumer, InputEndpoint, InputFormat, InputTransport, OutputConsumer,
-    OutputEndpoint, OutputFormat, OutputTransport, Parser, PipelineState, SerBatch,
-    SerOutputBatchHandle,
+    Catalog, Encoder, InputConsumer, InputEndpoint, InputFormat, InputPosition, InputTransport,
+    OutputConsumer, OutputEndpoint, OutputFormat, OutputTransport, ParseError, Parser,
+    PipelineState, SerBatch, SerOutputBatchHandle, SerTrace, Step,
 };
 use anyhow::{Error as AnyError, Result as AnyResult};
+#[cfg(feature = "checkpoint")]
+use crossbeam::channel::{bounded, Sender};
 use crossbeam::{
     queue::SegQueue,
     sync::{Parker, ShardedLock, Unparker},
 };
+#[cfg(feature = "checkpoint")]
+use dbsp::circuit::checkpoint;
 use dbsp::DBSPHandle;
+use erased_serde::Serialize as ErasedSerialize;
 use log::{debug, error, info};
 use num_traits::FromPrimitive;
+use serde::Serialize;
 use std::{
     borrow::Cow,
     collections::{BTreeMap, BTreeSet, HashSet},
+    mem::take,
     sync::{
-        atomic::{AtomicBool, AtomicU32, Ordering},
-        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
     },
     thread::{spawn, JoinHandle},
     time::{Duration, Instant},
 };
+#[cfg(feature = "checkpoint")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 mod config;
 mod error;
@@ -70,6 +109,32 @@ pub use stats::{ControllerStatus, InputEndpointStatus, OutputEndpointStatus};
 
 pub(crate) type EndpointId = u64;
 
+/// Name of the file in a checkpoint that stores input endpoint positions.
+#[cfg(feature = "checkpoint")]
+const INPUT_POSITIONS_FILE: &str = "input_positions.json";
+
+/// Name of the file in a checkpoint that stores the contents of materialized
+/// output streams.
+#[cfg(feature = "checkpoint")]
+const MATERIALIZED_STREAMS_FILE: &str = "materialized_streams.bin";
+
+/// Input endpoint positions stored in a checkpoint, indexed by endpoint name.
+type InputPositions = BTreeMap<String, InputPosition>;
+
+/// Contents of materialized output streams stored in a checkpoint (see
+/// [`SerTrace::checkpoint`]), indexed by stream name.
+#[cfg(feature = "checkpoint")]
+type MaterializedStreams = BTreeMap<String, Vec<u8>>;
+
+/// Controller state stored in a checkpoint along with the state of the
+/// circuit.
+#[derive(Default)]
+struct ControllerCheckpoint {
+    input_positions: InputPositions,
+    #[cfg(feature = "checkpoint")]
+    materialized_streams: MaterializedStreams,
+}
+
 /// Controller that coordinates the creation, reconfiguration, teardown of
 /// input/output adapters, and implements runtime flow control.
 ///
@@ -117,11 +182,107 @@ impl Controller {
     ///
     /// * One or more of the endpoints fails to initialize.
     pub fn with_config(
+        circuit: DBSPHandle,
+        catalog: Catalog,
+        config: &PipelineConfig,
+        error_cb: Box<dyn Fn(ControllerError) + Send + Sync>,
+    ) -> AnyResult<Self> {
+        Self::with_config_and_checkpoint(
+            circuit,
+            catalog,
+            config,
+            ControllerCheckpoint::default(),
+            error_cb,
+        )
+    }
+
+    /// Resume a pipeline from a checkpoint created by [`Self::checkpoint`].
+    ///
+    /// Similar to [`Self::with_config`], but each input endpoint listed in
+    /// the checkpoint resumes from its position in the input stream when the
+    /// checkpoint was taken.  `circuit` must be restored
+    /// from the same checkpoint directory using
+    /// [`Runtime::init_circuit_from_checkpoint`](`dbsp::Runtime::init_circuit_from_checkpoint`).
+    ///
+    /// # Errors
+    ///
+    /// In addition to errors returned by [`Self::with_config`], this method
+    /// fails if the checkpoint cannot be read or if an input endpoint listed
+    /// in the checkpoint does not support [seeking](`InputEndpoint::seek`).
+    #[cfg(feature = "checkpoint")]
+    pub fn from_checkpoint(
+        circuit: DBSPHandle,
+        catalog: Catalog,
+        config: &PipelineConfig,
+        checkpoint_path: &Path,
+        error_cb: Box<dyn Fn(ControllerError) + Send + Sync>,
+    ) -> AnyResult<Self> {
+        let checkpoint = checkpoint::latest(checkpoint_path)?.ok_or_else(|| {
+            AnyError::msg(format!(
+                "no checkpoint found in '{}'",
+                checkpoint_path.display()
+            ))
+        })?;
+        let positions_path = checkpoint.join(INPUT_POSITIONS_FILE);
+        let positions = fs::read(&positions_path).map_err(|e| {
+            AnyError::msg(format!(
+                "failed to read input positions from '{}': {e}",
+                positions_path.display()
+            ))
+        })?;
+        let positions: InputPositions = serde_json::from_slice(&positions).map_err(|e| {
+            AnyError::msg(format!(
+                "invalid input positions in '{}': {e}",
+                positions_path.display()
+            ))
+        })?;
+        let materialized_path = checkpoint.join(MATERIALIZED_STREAMS_FILE);
+        let materialized = fs::read(&materialized_path).map_err(|e| {
+            AnyError::msg(format!(
+                "failed to read materialized streams from '{}': {e}",
+                materialized_path.display()
+            ))
+        })?;
+        let materialized: MaterializedStreams = checkpoint::decode(&materialized).map_err(|e| {
+            AnyError::msg(format!(
+                "invalid materialized streams in '{}': {e}",
+                materialized_path.display()
+            ))
+        })?;
+
+        Self::with_config_and_checkpoint(
+            circuit,
+            catalog,
+            config,
+            ControllerCheckpoint {
+                input_positions: positions,
+                materialized_streams: materialized,
+            },
+            error_cb,
+        )
+    }
+
+    fn with_config_and_checkpoint(
         mut circuit: DBSPHandle,
         catalog: Catalog,
         config: &PipelineConfig,
+        checkpoint: ControllerCheckpoint,
         error_cb: Box<dyn Fn(ControllerError) + Send + Sync>,
     ) -> AnyResult<Self> {
+        // Inputs are committed as soon as the outputs they produced have been
+        // delivered, so after a restart the circuit sees only uncommitted
+        // inputs.  This is only correct if the outputs of each step don't
+        // depend on inputs of earlier steps.
+        if config.global.exactly_once
+            && !circuit
+                .is_stateless()
+                .map_err(|e| AnyError::msg(format!("error inspecting the circuit: {e}")))?
+        {
+            return Err(AnyError::msg(
+                "exactly-once mode requires a stateless circuit",
+            ));
+        }
+
         let circuit_thread_parker = Parker::new();
         let circuit_thread_unparker = circuit_thread_parker.unparker().clone();
 
@@ -142,6 +303,15 @@ impl Controller {
                 .map_err(|e| AnyError::msg(format!("error enabling CPU profiler: {e}")))?;
         }
 
+        // Materialize streams first, so that output endpoints can request
+        // initial snapshots, and before the circuit thread starts updating
+        // them.
+        for stream in config.materialized_streams.iter() {
+            inner.materialize(stream)?;
+        }
+        #[cfg(feature = "checkpoint")]
+        inner.restore_materialized(&checkpoint.materialized_streams)?;
+
         let backpressure_thread_handle = {
             let inner = inner.clone();
             spawn(move || Self::backpressure_thread(inner, backpressure_thread_parker))
@@ -153,7 +323,8 @@ impl Controller {
         };
 
         for (input_name, input_config) in config.inputs.iter() {
-            inner.connect_input(input_name, input_config)?;
+            let position = checkpoint.input_positions.get(input_name.as_ref()).cloned();
+            inner.connect_input(input_name, input_config, position)?;
         }
 
         for (output_name, output_config) in config.outputs.iter() {
@@ -187,12 +358,85 @@ impl Controller {
         endpoint_name: &str,
         config: &InputEndpointConfig,
     ) -> AnyResult<()> {
-        self.inner.connect_input(endpoint_name, config)
+        self.inner.connect_input(endpoint_name, config, None)
+    }
+
+    /// Disconnect an input endpoint.
+    ///
+    /// Stops the endpoint and removes it from controller status.  Records
+    /// already received from the endpoint are still pushed to the circuit.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no input endpoint named `endpoint_name`.
+    pub fn disconnect_input(&self, endpoint_name: &str) -> AnyResult<()> {
+        self.inner.disconnect_input(endpoint_name)
+    }
+
+    /// Connect a new output endpoint with specified name and configuration.
+    ///
+    /// Creates an endpoint with data transport and format specified by
+    /// `config`.  The endpoint receives the outputs of all circuit steps
+    /// that start after this method returns.
+    ///
+    /// # Errors
+    ///
+    /// The method may fail for the following reasons:
+    ///
+    /// * The endpoint configuration is invalid, e.g., specifies an unknown
+    ///   output stream, transport or data format.
+    ///
+    /// * The endpoint fails to initialize.
+    pub fn connect_output(
+        &self,
+        endpoint_name: &str,
+        config: &OutputEndpointConfig,
+    ) -> AnyResult<()> {
+        self.inner.connect_output(endpoint_name, config)
+    }
+
+    /// Disconnect an output endpoint.
+    ///
+    /// Stops the endpoint thread and removes the endpoint from controller
+    /// status.  Output batches queued for the endpoint but not yet sent to
+    /// the transport are discarded.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no output endpoint named `endpoint_name`.
+    pub fn disconnect_output(&self, endpoint_name: &str) -> AnyResult<()> {
+        self.inner.disconnect_output(endpoint_name)
+    }
+
+    /// Pause input endpoint `endpoint_name`.
+    ///
+    /// The endpoint stays paused until [`Self::start_input_endpoint`] is
+    /// invoked for it, even if the pipeline is started or paused in the
+    /// meantime.  Like [`Self::pause`], this method is asynchronous.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no input endpoint named `endpoint_name`.
+    pub fn pause_input_endpoint(&self, endpoint_name: &str) -> AnyResult<()> {
+        self.inner.set_input_endpoint_paused(endpoint_name, true)
+    }
+
+    /// Resume input endpoint `endpoint_name` paused by
+    /// [`Self::pause_input_endpoint`].
+    ///
+    /// The endpoint starts streaming data once the pipeline is running.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no input endpoint named `endpoint_name`.
+    pub fn start_input_endpoint(&self, endpoint_name: &str) -> AnyResult<()> {
+        self.inner.set_input_endpoint_paused(endpoint_name, false)
     }
 
     /// Change the state of all input endpoints to running.
     ///
-    /// Start streaming data through all connected input endpoints.
+    /// Start streaming data through all connected input endpoints, except
+    /// those paused via [`Self::pause_input_endpoint`].
     pub fn start(&self) {
         self.inner.start();
     }
@@ -218,6 +462,61 @@ impl Controller {
         self.inner.dump_profile();
     }
 
+    /// Returns the current contents of a materialized output stream.
+    ///
+    /// The snapshot reflects the outputs of all circuit steps completed so
+    /// far.  `lower` and `upper`, when specified, are JSON-encoded keys that
+    /// restrict the snapshot to keys in the half-open range `[lower, upper)`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `stream` is not listed in
+    /// [`PipelineConfig::materialized_streams`], if bounds are specified for
+    /// a stream that wasn't registered with
+    /// [`Catalog::register_keyed_output_batch_handle`], or if the bounds
+    /// cannot be parsed as keys of the stream.
+    pub fn snapshot(
+        &self,
+        stream: &str,
+        lower: Option<&str>,
+        upper: Option<&str>,
+    ) -> AnyResult<Arc<dyn SerBatch>> {
+        self.inner
+            .materialized
+            .read()
+            .unwrap()
+            .get(stream)
+            .ok_or_else(|| AnyError::msg(format!("output stream '{stream}' is not materialized")))?
+            .snapshot(lower, upper)
+    }
+
+    /// Checkpoint the pipeline to checkpoint directory `path`.
+    ///
+    /// Processes all input records received so far and writes the state of
+    /// the circuit along with the current position of each input endpoint
+    /// and the contents of materialized streams to a new checkpoint in
+    /// `path`, replacing the previous checkpoint.  All of them are committed
+    /// atomically (see [`DBSPHandle::checkpoint`]).  Input
+    /// endpoints are blocked while the checkpoint is taken.  Use
+    /// [`Self::from_checkpoint`] to resume the pipeline from the checkpoint.
+    ///
+    /// Outputs produced before the checkpoint are queued to output endpoints,
+    /// but may not have been delivered by the time this method returns.
+    #[cfg(feature = "checkpoint")]
+    pub fn checkpoint(&self, path: &Path) -> AnyResult<()> {
+        let (sender, receiver) = bounded(1);
+        self.inner
+            .checkpoint_requests
+            .lock()
+            .unwrap()
+            .push((path.to_path_buf(), sender));
+        self.inner.unpark_circuit();
+
+        receiver
+            .recv()
+            .map_err(|_| AnyError::msg("pipeline terminated before completing the checkpoint"))?
+    }
+
     /// Terminate the controller, stop all input endpoints and destroy the
     /// circuit.
     pub fn stop(self) -> AnyResult<()> {
@@ -281,6 +580,15 @@ impl Controller {
                     }
                 }
             }
+
+            #[cfg(feature = "checkpoint")]
+            let checkpoint_requests = take(&mut *controller.checkpoint_requests.lock().unwrap());
+            #[cfg(feature = "checkpoint")]
+            for (path, reply) in checkpoint_requests {
+                // The requester may have given up waiting.
+                let _ = reply.send(Self::checkpoint_circuit(&mut circuit, &controller, &path));
+            }
+
             match controller.state() {
                 PipelineState::Running | PipelineState::Paused => {
                     // Backpressure in the output pipeline: wait for room in output buffers to
@@ -303,49 +611,13 @@ impl Controller {
                             .unwrap_or(false)
                     {
                         start = None;
-                        // Reset all counters of buffered records and bytes to 0.
-                        controller.status.consume_buffered_inputs();
-
-                        // All input records accumulated so far (and possibly some more) will
-                        // be fully processed after the `step()` call returns.
-                        let processed_records = controller.status.num_total_input_records();
-
-                        // Wake up the backpressure thread to unpause endpoints blocked due to
-                        // backpressure.
-                        controller.unpark_backpressure();
-                        debug!("circuit thread: calling 'circuit.step'");
-                        circuit
-                            .step()
-                            .unwrap_or_else(|e| controller.error(ControllerError::dbsp_error(e)));
-                        debug!("circuit thread: 'circuit.step' returned");
-
-                        controller
-                            .status
-                            .set_num_total_processed_records(processed_records);
-
-                        // Push output batches to output pipelines.
-                        let outputs = controller.outputs.read().unwrap();
-                        for (_stream, (output_handle, endpoints)) in outputs.iter_by_stream() {
-                            // TODO: add an endpoint config option to consolidate output batches.
-                            let batch = output_handle.take_from_all();
-                            let num_records = batch.iter().map(|b| b.len()).sum();
-
-                            for endpoint_id in endpoints.iter() {
-                                let endpoint = outputs.lookup_by_id(endpoint_id).unwrap();
-
-                                // Increment stats first, so we don't end up with negative counts.
-                                controller.status.enqueue_batch(*endpoint_id, num_records);
-
-                                // Associate the input frontier with the batch.  Once the batch has
-                                // been sent to the output endpoint, the endpoint will get labeled
-                                // with this frontier.
-                                endpoint.queue.push((batch.clone(), processed_records));
-
-                                // Wake up the output thread.  We're not trying to be smart here and
-                                // wake up the thread conditionally if it was previously idle, as I
-                                // don't expect this to make any real difference.
-                                endpoint.unparker.unpark();
-                            }
+                        let exactly_once = controller.status.global_config.exactly_once;
+                        let guard = exactly_once.then(|| controller.input_lock.write().unwrap());
+                        Self::step_circuit(&mut circuit, &controller);
+                        drop(guard);
+
+                        if exactly_once {
+                            controller.commit_inputs();
                         }
                     } else if buffered_records > 0 {
                         // We have some buffered data, but less than `min_batch_size_records` --
@@ -371,80 +643,195 @@ impl Controller {
         }
     }
 
+    /// Consume all buffered inputs, step the circuit and push output batches
+    /// produced by the circuit to output pipelines.
+    fn step_circuit(circuit: &mut DBSPHandle, controller: &ControllerInner) {
+        // Reset all counters of buffered records and bytes to 0.
+        controller.status.consume_buffered_inputs();
+
+        // All input records accumulated so far (and possibly some more) will
+        // be fully processed after the `step()` call returns.
+        let processed_records = controller.status.num_total_input_records();
+
+        // Wake up the backpressure thread to unpause endpoints blocked due to
+        // backpressure.
+        controller.unpark_backpressure();
+        let step = controller.step.load(Ordering::Acquire);
+        debug!("circuit thread: calling 'circuit.step'");
+        circuit
+            .step()
+            .unwrap_or_else(|e| controller.error(ControllerError::dbsp_error(e)));
+        debug!("circuit thread: 'circuit.step' returned");
+
+        // Push output batches to output pipelines and materialized streams.
+        let outputs = controller.outputs.read().unwrap();
+        let mut materialized = controller.materialized.write().unwrap();
+        for (stream, (output_handle, endpoints)) in outputs.iter_by_stream() {
+            // Endpoints configured to consolidate outputs do so in their own
+            // threads (see `OutputConsolidator`).
+            let batch = output_handle.take_from_all();
+            let num_records = batch.iter().map(|b| b.len()).sum();
+
+            if let Some(trace) = materialized.get_mut(stream) {
+                for b in batch.iter() {
+                    trace.insert(b.as_ref());
+                }
+            }
+
+            for endpoint_id in endpoints.iter() {
+                let endpoint = outputs.lookup_by_id(endpoint_id).unwrap();
+
+                // Increment stats first, so we don't end up with negative counts.
+                controller.status.enqueue_batch(*endpoint_id, num_records);
+
+                // Associate the input frontier with the batch.  Once the batch has
+                // been sent to the output endpoint, the endpoint will get labeled
+                // with this frontier.
+                endpoint
+                    .queue
+                    .push((batch.clone(), processed_records, Some(step)));
+
+                // Wake up the output thread.  We're not trying to be smart here and
+                // wake up the thread conditionally if it was previously idle, as I
+                // don't expect this to make any real difference.
+                endpoint.unparker.unpark();
+            }
+        }
+
+        // Advance the step counter while holding the `outputs` lock, so that
+        // `connect_output` can tell the first step whose outputs a new endpoint
+        // will receive.
+        controller.step.store(step + 1, Ordering::Release);
+
+        // Update the processed record count after updating materialized
+        // streams, so that their snapshots are up to date by the time the
+        // pipeline is reported complete.
+        controller
+            .status
+            .set_num_total_processed_records(processed_records);
+    }
+
+    /// Checkpoint the circuit, input endpoint positions, and materialized
+    /// streams to `path`.
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint_circuit(
+        circuit: &mut DBSPHandle,
+        controller: &ControllerInner,
+        path: &Path,
+    ) -> AnyResult<()> {
+        // Block input probes, so that the input positions don't change while
+        // the checkpoint is in progress.
+        let _guard = controller.input_lock.write().unwrap();
+
+        // Push all records received so far through the circuit, so that the
+        // state of the circuit reflects exactly the inputs up to the current
+        // positions.
+        Self::step_circuit(circuit, controller);
+
+        let mut positions = InputPositions::new();
+        for ep in controller.inputs.lock().unwrap().values() {
+            if let Some(position) = &*ep.position.lock().unwrap() {
+                let json = serde_json::to_value(&*position.position).map_err(|e| {
+                    AnyError::msg(format!(
+                        "failed to serialize the position of input endpoint '{}': {e}",
+                        ep.endpoint_name
+                    ))
+                })?;
+                positions.insert(
+                    ep.endpoint_name.clone(),
+                    InputPosition {
+                        position: json,
+                        unparsed_bytes: position.unparsed_bytes,
+                    },
+                );
+            }
+        }
+
+        // Serializing JSON values cannot fail.
+        let positions = serde_json::to_vec_pretty(&positions).unwrap();
+
+        let mut materialized = MaterializedStreams::new();
+        for (stream, trace) in controller.materialized.read().unwrap().iter() {
+            let state = trace.checkpoint().map_err(|e| {
+                AnyError::msg(format!(
+                    "failed to serialize materialized stream '{stream}': {e}"
+                ))
+            })?;
+            materialized.insert(stream.to_string(), state);
+        }
+        let materialized = checkpoint::encode(&materialized)
+            .map_err(|e| AnyError::msg(format!("failed to serialize materialized streams: {e}")))?;
+
+        // Store the positions and materialized streams in the same checkpoint
+        // as the state of the circuit, so that they are committed together.
+        circuit
+            .checkpoint_with(path, |checkpoint| {
+                checkpoint::write_file(checkpoint.join(INPUT_POSITIONS_FILE), &positions)?;
+                checkpoint::write_file(checkpoint.join(MATERIALIZED_STREAMS_FILE), &materialized)
+            })
+            .map_err(ControllerError::dbsp_error)?;
+
+        info!("pipeline checkpoint created in '{}'", path.display());
+        Ok(())
+    }
+
     /// Backpressure thread function.
     fn backpressure_thread(controller: Arc<ControllerInner>, parker: Parker) {
-        // `global_pause` flag is `true` when the entire controller is paused
-        // (the controller starts in a paused state and switches between
-        // paused and running states in responsed to `Controller::start()` and
-        // `Controller::pause()` methods).
-        let mut global_pause = true;
-
-        // Endpoints paused due to backpressure.
-        let mut paused_endpoints = HashSet::new();
+        // Endpoints that have been started and not paused since.  Endpoints
+        // are created in a paused state, so this set is initially empty and
+        // new endpoints are started once they are allowed to run.
+        let mut running_endpoints = HashSet::new();
 
         loop {
             let inputs = controller.inputs.lock().unwrap();
 
-            match controller.state() {
-                PipelineState::Paused => {
-                    // Pause circuit if not yet paused.
-                    if !global_pause {
-                        for (epid, ep) in inputs.iter() {
-                            // Pause the endpoint unless it's already paused due to backpressure.
-                            if !paused_endpoints.contains(epid) {
-                                ep.endpoint.pause().unwrap_or_else(|e| {
-                                    controller.input_transport_error(
-                                        *epid,
-                                        &ep.endpoint_name,
-                                        true,
-                                        e,
-                                    )
-                                });
-                            }
-                        }
-                    }
-                    global_pause = true;
+            // Forget disconnected endpoints.
+            running_endpoints.retain(|epid| inputs.contains_key(epid));
+
+            let state = controller.state();
+            if state == PipelineState::Terminated {
+                return;
+            }
+
+            // The earliest time when a throttled endpoint can be resumed.
+            let now = Instant::now();
+            let mut wakeup_time: Option<Instant> = None;
+
+            for (epid, ep) in inputs.iter() {
+                let throttled_until = controller.status.input_endpoint_throttled_until(epid, now);
+                if let Some(throttled_until) = throttled_until {
+                    wakeup_time = Some(wakeup_time.map_or(throttled_until, |wakeup_time| {
+                        wakeup_time.min(throttled_until)
+                    }));
                 }
-                PipelineState::Running => {
-                    // Resume endpoints that have buffer space, pause endpoints with full buffers.
-                    for (epid, ep) in inputs.iter() {
-                        if controller.status.input_endpoint_full(epid) {
-                            // The endpoint is full and is not yet in the paused state -- pause it
-                            // now.
-                            if !global_pause && !paused_endpoints.contains(epid) {
-                                ep.endpoint.pause().unwrap_or_else(|e| {
-                                    controller.input_transport_error(
-                                        *epid,
-                                        &ep.endpoint_name,
-                                        true,
-                                        e,
-                                    )
-                                });
-                            }
-                            paused_endpoints.insert(*epid);
-                        } else {
-                            // The endpoint is paused when it should be running -- unpause it.
-                            if global_pause || paused_endpoints.contains(epid) {
-                                ep.endpoint.start().unwrap_or_else(|e| {
-                                    controller.input_transport_error(
-                                        *epid,
-                                        &ep.endpoint_name,
-                                        true,
-                                        e,
-                                    )
-                                });
-                            }
-                            paused_endpoints.remove(epid);
-                        }
-                    }
-                    global_pause = false;
+
+                // Run the endpoint unless the pipeline or the endpoint is paused by
+                // the user, its buffer is full, or it has exceeded its rate limit.
+                let should_run = state == PipelineState::Running
+                    && !controller.status.input_endpoint_paused(epid)
+                    && !controller.status.input_endpoint_full(epid)
+                    && throttled_until.is_none();
+
+                if should_run && !running_endpoints.contains(epid) {
+                    ep.endpoint.start().unwrap_or_else(|e| {
+                        controller.input_transport_error(*epid, &ep.endpoint_name, true, e)
+                    });
+                    running_endpoints.insert(*epid);
+                } else if !should_run && running_endpoints.remove(epid) {
+                    ep.endpoint.pause().unwrap_or_else(|e| {
+                        controller.input_transport_error(*epid, &ep.endpoint_name, true, e)
+                    });
                 }
-                PipelineState::Terminated => return,
             }
 
             drop(inputs);
 
-            parker.park();
+            match wakeup_time {
+                Some(wakeup_time) => {
+                    parker.park_timeout(wakeup_time.saturating_duration_since(Instant::now()))
+                }
+                None => parker.park(),
+            }
         }
     }
 }
@@ -452,43 +839,151 @@ impl Controller {
 /// State tracked by the controller for each input endpoint.
 struct InputEndpointDescr {
     endpoint_name: String,
-    endpoint: Box<dyn InputEndpoint>,
+    /// Shared with [`ControllerInner::commit_inputs`], which notifies
+    /// endpoints without holding the `inputs` lock.
+    endpoint: Arc<dyn InputEndpoint>,
+
+    /// The last position reported by the endpoint, once the parser has
+    /// processed some of its input.  Shared with the input probe.
+    position: SharedPosition,
 }
 
 impl InputEndpointDescr {
-    pub fn new(endpoint_name: &str, endpoint: Box<dyn InputEndpoint>) -> Self {
+    pub fn new(
+        endpoint_name: &str,
+        endpoint: Box<dyn InputEndpoint>,
+        position: SharedPosition,
+    ) -> Self {
         Self {
             endpoint_name: endpoint_name.to_owned(),
-            endpoint,
+            endpoint: Arc::from(endpoint),
+            position,
         }
     }
 }
 
+/// Position of an input endpoint in its input stream (see
+/// [`InputConsumer::input_at`]).
+struct EndpointPosition {
+    position: Box<dyn ErasedSerialize + Send>,
+
+    /// The number of bytes pushed by the endpoint up to `position` that the
+    /// parser hasn't processed yet.
+    unparsed_bytes: u64,
+}
+
+type SharedPosition = Arc<Mutex<Option<EndpointPosition>>>;
+
 /// A lock-free queue used to send output batches from the circuit thread
 /// to output endpoint threads.  Each entry is annotated with a progress label
 /// that is equal to the number of input records fully processed by
-/// DBSP before emitting this batch of outputs.  The label increases
-/// monotonically over time.
-type BatchQueue = SegQueue<(Vec<Arc<dyn SerBatch>>, u64)>;
+/// DBSP before emitting this batch of outputs and with the number of the
+/// step that produced the batch.  Both labels increase monotonically over
+/// time.  The step is `None` for the initial snapshot of the stream (see
+/// [`OutputEndpointConfig::initial_snapshot`]), which precedes all other
+/// entries in the queue.
+type BatchQueue = SegQueue<(Vec<Arc<dyn SerBatch>>, u64, Option<Step>)>;
+
+/// Labels of a [`BatchQueue`] entry, along with the number of records in
+/// the entry.
+struct BatchLabels {
+    num_records: usize,
+    processed_records: u64,
+    step: Option<Step>,
+}
+
+/// Output batches accumulated by an endpoint configured to consolidate its
+/// outputs (see [`OutputEndpointConfig::consolidate`]).
+struct OutputConsolidator {
+    /// Output handle of the stream, used to create empty traces.
+    collection_handle: Box<dyn SerOutputBatchHandle>,
+
+    /// See [`OutputEndpointConfig::max_consolidation_delay_usecs`].
+    max_delay: Duration,
+
+    /// Updates accumulated since the last flush.
+    trace: Box<dyn SerTrace>,
+
+    /// Labels of queue entries accumulated in `trace`.
+    labels: Vec<BatchLabels>,
+
+    /// Time when accumulated updates must be flushed.
+    deadline: Option<Instant>,
+}
+
+impl OutputConsolidator {
+    fn new(collection_handle: Box<dyn SerOutputBatchHandle>, max_delay: Duration) -> Self {
+        let trace = collection_handle.new_trace();
+
+        Self {
+            collection_handle,
+            max_delay,
+            trace,
+            labels: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    fn push(&mut self, data: &[Arc<dyn SerBatch>], labels: BatchLabels) {
+        for batch in data.iter() {
+            self.trace.insert(batch.as_ref());
+        }
+        self.labels.push(labels);
+        self.deadline
+            .get_or_insert_with(|| Instant::now() + self.max_delay);
+    }
+
+    /// Time when accumulated updates must be flushed, if any.
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Returns accumulated updates as a single batch, along with the labels
+    /// of queue entries they were computed from.
+    fn flush(&mut self) -> (Arc<dyn SerBatch>, Vec<BatchLabels>) {
+        let batch = self.trace.consolidate();
+        self.trace = self.collection_handle.new_trace();
+        self.deadline = None;
+
+        (batch, take(&mut self.labels))
+    }
+}
 
 /// State tracked by the controller for each output endpoint.
 struct OutputEndpointDescr {
     /// Endpoint name.
     endpoint_name: String,
 
+    /// The output stream that the endpoint is connected to.
+    stream: Cow<'static, str>,
+
     /// FIFO queue of batches read from the stream.
     queue: Arc<BatchQueue>,
 
+    /// Set when the endpoint is disconnected to stop the endpoint thread.
+    disconnected: Arc<AtomicBool>,
+
     /// Unparker for the endpoint thread.
     unparker: Unparker,
+
+    /// See [`OutputEndpoint::commits_inputs`].
+    commits_inputs: bool,
 }
 
 impl OutputEndpointDescr {
-    pub fn new(endpoint_name: &str, unparker: Unparker) -> Self {
+    pub fn new(
+        endpoint_name: &str,
+        stream: &Cow<'static, str>,
+        unparker: Unparker,
+        commits_inputs: bool,
+    ) -> Self {
         Self {
             endpoint_name: endpoint_name.to_string(),
+            stream: stream.clone(),
             queue: Arc::new(SegQueue::new()),
+            disconnected: Arc::new(AtomicBool::new(false)),
             unparker,
+            commits_inputs,
         }
     }
 }
@@ -499,6 +994,10 @@ type StreamEndpointMap =
 struct OutputEndpoints {
     by_id: BTreeMap<EndpointId, OutputEndpointDescr>,
     by_stream: StreamEndpointMap,
+    /// Id of the next endpoint.  Endpoint ids are not reused after an
+    /// endpoint is disconnected, so that stats updates from a disconnected
+    /// endpoint cannot be attributed to a new one.
+    next_endpoint_id: EndpointId,
 }
 
 impl OutputEndpoints {
@@ -506,6 +1005,7 @@ impl OutputEndpoints {
         Self {
             by_id: BTreeMap::new(),
             by_stream: BTreeMap::new(),
+            next_endpoint_id: 0,
         }
     }
 
@@ -530,8 +1030,22 @@ impl OutputEndpoints {
             .find(|ep| ep.endpoint_name == endpoint_name)
     }
 
-    fn alloc_endpoint_id(&self) -> EndpointId {
-        self.by_id.keys().rev().next().map(|k| k + 1).unwrap_or(0)
+    fn alloc_endpoint_id(&mut self) -> EndpointId {
+        let endpoint_id = self.next_endpoint_id;
+        self.next_endpoint_id += 1;
+        endpoint_id
+    }
+
+    /// Add `stream` to the set of streams read by the circuit thread, even if
+    /// it has no endpoints.
+    fn add_stream(
+        &mut self,
+        stream: Cow<'static, str>,
+        collection_handle: Box<dyn SerOutputBatchHandle>,
+    ) {
+        self.by_stream
+            .entry(stream)
+            .or_insert_with(|| (collection_handle, BTreeSet::new()));
     }
 
     fn insert(
@@ -548,6 +1062,36 @@ impl OutputEndpoints {
             .1
             .insert(endpoint_id);
     }
+
+    /// Remove endpoint from the map.  Stops reading `stream` if this was its
+    /// last endpoint, unless `keep_stream` is `true`.
+    fn remove(
+        &mut self,
+        endpoint_id: &EndpointId,
+        keep_stream: bool,
+    ) -> Option<OutputEndpointDescr> {
+        let endpoint_descr = self.by_id.remove(endpoint_id)?;
+        if let Some((_, endpoints)) = self.by_stream.get_mut(&endpoint_descr.stream) {
+            endpoints.remove(endpoint_id);
+            if endpoints.is_empty() && !keep_stream {
+                self.by_stream.remove(&endpoint_descr.stream);
+            }
+        }
+        Some(endpoint_descr)
+    }
+}
+
+/// Tracks the delivery of output batches in exactly-once mode.
+#[derive(Default)]
+struct DeliveredSteps {
+    /// For each output endpoint, the number of consecutive steps, starting
+    /// from the step when the endpoint was connected, whose outputs the
+    /// endpoint has delivered.  A step that fails to be delivered blocks
+    /// the counter, so that the inputs of this step are never committed.
+    by_endpoint: BTreeMap<EndpointId, Step>,
+
+    /// The last value passed to [`InputEndpoint::commit`].
+    committed: Step,
 }
 
 /// Controller state sharable across threads.
@@ -558,9 +1102,30 @@ struct ControllerInner {
     status: ControllerStatus,
     state: AtomicU32,
     dump_profile_request: AtomicBool,
+    /// Pending [`Controller::checkpoint`] requests.
+    #[cfg(feature = "checkpoint")]
+    checkpoint_requests: Mutex<Vec<(PathBuf, Sender<AnyResult<()>>)>>,
+    /// Held in shared mode by input probes while pushing data to the circuit
+    /// and in exclusive mode by the circuit thread while checkpointing and,
+    /// in exactly-once mode, while executing a step.
+    input_lock: RwLock<()>,
+    /// The number of the next circuit step.  Advanced by the circuit thread
+    /// once it has queued the outputs of the step to output endpoints.
+    step: AtomicU64,
+    /// Steps delivered by output endpoints (exactly-once mode only).
+    delivered_steps: Mutex<DeliveredSteps>,
+    /// Held by the thread that notifies input endpoints about delivered steps
+    /// (see [`Self::commit_inputs`]).
+    commit_lock: Mutex<()>,
     catalog: Arc<Mutex<Catalog>>,
     inputs: Mutex<BTreeMap<EndpointId, InputEndpointDescr>>,
+    /// Id of the next input endpoint (see
+    /// [`OutputEndpoints::next_endpoint_id`]).
+    next_input_id: AtomicU64,
     outputs: ShardedLock<OutputEndpoints>,
+    /// Integrals of materialized output streams, updated by the circuit thread
+    /// after each step.
+    materialized: RwLock<BTreeMap<Cow<'static, str>, Box<dyn SerTrace>>>,
     circuit_thread_unparker: Unparker,
     backpressure_thread_unparker: Unparker,
     error_cb: Box<dyn Fn(ControllerError) + Send + Sync>,
@@ -582,9 +1147,17 @@ impl ControllerInner {
             status,
             state,
             dump_profile_request,
+            #[cfg(feature = "checkpoint")]
+            checkpoint_requests: Mutex::new(Vec::new()),
+            input_lock: RwLock::new(()),
+            step: AtomicU64::new(0),
+            delivered_steps: Mutex::new(DeliveredSteps::default()),
+            commit_lock: Mutex::new(()),
             catalog: Arc::new(Mutex::new(catalog)),
             inputs: Mutex::new(BTreeMap::new()),
+            next_input_id: AtomicU64::new(0),
             outputs: ShardedLock::new(OutputEndpoints::new()),
+            materialized: RwLock::new(BTreeMap::new()),
             circuit_thread_unparker,
             backpressure_thread_unparker,
             error_cb,
@@ -595,6 +1168,7 @@ impl ControllerInner {
         self: &Arc<Self>,
         endpoint_name: &str,
         endpoint_config: &InputEndpointConfig,
+        position: Option<InputPosition>,
     ) -> AnyResult<()> {
         let mut inputs = self.inputs.lock().unwrap();
 
@@ -602,6 +1176,14 @@ impl ControllerInner {
             Err(ControllerError::duplicate_input_endpoint(endpoint_name))?;
         }
 
+        if endpoint_config.max_records_per_second == Some(0)
+            || endpoint_config.max_bytes_per_second == Some(0)
+        {
+            Err(AnyError::msg(format!(
+                "input endpoint '{endpoint_name}': rate limit must be greater than 0"
+            )))?;
+        }
+
         // Create input pipeline, consisting of a transport endpoint, controller
         // probe, and parser.
         //
@@ -618,14 +1200,54 @@ impl ControllerInner {
             .input_collection_handle(&endpoint_config.stream)
             .ok_or_else(|| AnyError::msg(format!("unknown stream '{}'", endpoint_config.stream)))?;
 
-        let parser = format.new_parser(input_stream, &endpoint_config.format.config)?;
+        let mut parser = format.new_parser(input_stream, &endpoint_config.format.config)?;
+        if position.is_some() {
+            parser.resume();
+        }
 
-        // Create probe.
-        let endpoint_id = inputs.keys().rev().next().map(|k| k + 1).unwrap_or(0);
+        let endpoint_id = self.next_input_id.fetch_add(1, Ordering::AcqRel);
+
+        // Create dead-letter queue endpoint.
+        let dead_letter_queue = match &endpoint_config.dead_letter_queue {
+            None => None,
+            Some(dlq_config) => {
+                let transport = <dyn OutputTransport>::get_transport(&dlq_config.name)
+                    .ok_or_else(|| ControllerError::unknown_output_transport(&dlq_config.name))?;
+
+                let self_weak = Arc::downgrade(self);
+                let endpoint_name_str = endpoint_name.to_string();
+                let dlq_endpoint = transport.new_endpoint(
+                    &format!("{endpoint_name}.dead_letter_queue"),
+                    &dlq_config.config,
+                    Box::new(move |fatal: bool, e: AnyError| {
+                        if let Some(controller) = self_weak.upgrade() {
+                            controller.input_transport_error(
+                                endpoint_id,
+                                &endpoint_name_str,
+                                fatal,
+                                e.context("dead-letter queue error"),
+                            )
+                        }
+                    }),
+                )?;
+                Some(Arc::new(Mutex::new(dlq_endpoint)))
+            }
+        };
+
+        // Create probe.  Until the endpoint reports a new position, a
+        // checkpoint stores the position the endpoint resumes from.
+        let shared_position = Arc::new(Mutex::new(position.clone().map(|position| {
+            EndpointPosition {
+                position: Box::new(position.position) as Box<dyn ErasedSerialize + Send>,
+                unparsed_bytes: position.unparsed_bytes,
+            }
+        })));
         let probe = Box::new(InputProbe::new(
             endpoint_id,
             endpoint_name,
             parser,
+            shared_position.clone(),
+            dead_letter_queue,
             self.clone(),
             self.circuit_thread_unparker.clone(),
             self.backpressure_thread_unparker.clone(),
@@ -640,9 +1262,31 @@ impl ControllerInner {
         let endpoint =
             transport.new_endpoint(endpoint_name, &endpoint_config.transport.config, probe)?;
 
+        // An endpoint that acknowledges inputs on its own would lose them on
+        // a restart in exactly-once mode, while an endpoint that waits for
+        // `commit` would never acknowledge them otherwise.
+        match endpoint.exactly_once() {
+            Some(false) if self.status.global_config.exactly_once => {
+                Err(AnyError::msg(format!(
+                    "input endpoint '{endpoint_name}' is not configured for exactly-once operation, which is required in exactly-once mode"
+                )))?;
+            }
+            Some(true) if !self.status.global_config.exactly_once => {
+                Err(AnyError::msg(format!(
+                    "input endpoint '{endpoint_name}' is configured for exactly-once operation, which requires the pipeline to run in exactly-once mode"
+                )))?;
+            }
+            _ => {}
+        }
+
+        // Skip the part of the input stream processed before the checkpoint.
+        if let Some(position) = &position {
+            endpoint.seek(position)?;
+        }
+
         inputs.insert(
             endpoint_id,
-            InputEndpointDescr::new(endpoint_name, endpoint),
+            InputEndpointDescr::new(endpoint_name, endpoint, shared_position),
         );
 
         drop(inputs);
@@ -655,6 +1299,40 @@ impl ControllerInner {
         Ok(())
     }
 
+    fn disconnect_input(&self, endpoint_name: &str) -> AnyResult<()> {
+        let mut inputs = self.inputs.lock().unwrap();
+
+        let endpoint_id = inputs
+            .iter()
+            .find(|(_, ep)| ep.endpoint_name == endpoint_name)
+            .map(|(epid, _)| *epid)
+            .ok_or_else(|| ControllerError::unknown_input_endpoint(endpoint_name))?;
+
+        let ep = inputs.remove(&endpoint_id).unwrap();
+        drop(inputs);
+
+        ep.endpoint.disconnect();
+        self.status.remove_input(&endpoint_id);
+
+        self.unpark_backpressure();
+        Ok(())
+    }
+
+    fn set_input_endpoint_paused(&self, endpoint_name: &str, paused: bool) -> AnyResult<()> {
+        let inputs = self.inputs.lock().unwrap();
+
+        let endpoint_id = inputs
+            .iter()
+            .find(|(_, ep)| ep.endpoint_name == endpoint_name)
+            .map(|(epid, _)| *epid)
+            .ok_or_else(|| ControllerError::unknown_input_endpoint(endpoint_name))?;
+        self.status.set_input_endpoint_paused(&endpoint_id, paused);
+        drop(inputs);
+
+        self.unpark_backpressure();
+        Ok(())
+    }
+
     /// Unpark the circuit thread.
     fn unpark_circuit(&self) {
         self.circuit_thread_unparker.unpark();
@@ -692,6 +1370,19 @@ impl ControllerInner {
             .ok_or_else(|| ControllerError::unknown_output_stream(&endpoint_config.stream))?
             .fork();
 
+        if endpoint_config.initial_snapshot
+            && !self
+                .materialized
+                .read()
+                .unwrap()
+                .contains_key(&endpoint_config.stream)
+        {
+            Err(AnyError::msg(format!(
+                "output endpoint '{endpoint_name}' requests an initial snapshot, but stream '{}' is not materialized",
+                endpoint_config.stream
+            )))?;
+        }
+
         // Create transport endpoint.
         let transport = <dyn OutputTransport>::get_transport(&endpoint_config.transport.name)
             .ok_or_else(|| {
@@ -712,6 +1403,21 @@ impl ControllerInner {
             }),
         )?;
 
+        // An endpoint that commits input offsets on its own would commit the
+        // inputs of steps that other endpoints haven't delivered yet.
+        let commits_inputs = endpoint.commits_inputs();
+        if commits_inputs && !outputs.by_id.is_empty() {
+            Err(AnyError::msg(format!(
+                "output endpoint '{endpoint_name}' commits input offsets, which requires it to be the only output endpoint of the pipeline"
+            )))?;
+        }
+        if let Some(other) = outputs.by_id.values().find(|ep| ep.commits_inputs) {
+            Err(AnyError::msg(format!(
+                "cannot connect output endpoint '{endpoint_name}': output endpoint '{}' commits input offsets, which requires it to be the only output endpoint of the pipeline",
+                other.endpoint_name
+            )))?;
+        }
+
         // Create probe.
         let probe = Box::new(OutputProbe::new(
             endpoint_id,
@@ -726,9 +1432,50 @@ impl ControllerInner {
         let encoder = format.new_encoder(&endpoint_config.format.config, probe)?;
 
         let parker = Parker::new();
-        let endpoint_state = OutputEndpointDescr::new(endpoint_name, parker.unparker().clone());
+        let endpoint_state = OutputEndpointDescr::new(
+            endpoint_name,
+            &endpoint_config.stream,
+            parker.unparker().clone(),
+            commits_inputs,
+        );
         let queue = endpoint_state.queue.clone();
+        let disconnected = endpoint_state.disconnected.clone();
         let controller = self.clone();
+        let consolidator = endpoint_config.consolidate.then(|| {
+            OutputConsolidator::new(
+                collection_handle.fork(),
+                Duration::from_micros(endpoint_config.max_consolidation_delay_usecs),
+            )
+        });
+
+        // Initialize endpoint stats before the circuit thread can queue any
+        // batches for the endpoint.
+        self.status
+            .add_output(&endpoint_id, endpoint_name, endpoint_config);
+
+        if endpoint_config.initial_snapshot {
+            // The circuit thread updates materialized streams and queues
+            // outputs to endpoints while holding the `outputs` lock, so the
+            // snapshot reflects exactly the steps whose outputs the endpoint
+            // won't receive.
+            let snapshot = self.materialized.read().unwrap()[&endpoint_config.stream].consolidate();
+            self.status.enqueue_batch(endpoint_id, snapshot.len());
+            queue.push((
+                vec![snapshot],
+                self.status.num_total_processed_records(),
+                None,
+            ));
+        }
+
+        // Start tracking delivered steps before the circuit thread can queue
+        // any batches for the endpoint.  The circuit thread advances the step
+        // counter while holding the `outputs` lock, so the endpoint receives
+        // outputs of all steps starting from this one.
+        self.delivered_steps
+            .lock()
+            .unwrap()
+            .by_endpoint
+            .insert(endpoint_id, self.step.load(Ordering::Acquire));
 
         outputs.insert(
             endpoint_id,
@@ -746,60 +1493,201 @@ impl ControllerInner {
                 encoder,
                 parker,
                 queue,
+                disconnected,
+                consolidator,
                 controller,
             )
         });
 
         drop(outputs);
 
-        // Initialize endpoint stats.
-        self.status
-            .add_output(&endpoint_id, endpoint_name, endpoint_config);
-
         Ok(())
     }
 
-    fn output_thread_func(
-        endpoint_id: EndpointId,
-        endpoint_name: String,
-        mut encoder: Box<dyn Encoder>,
-        parker: Parker,
-        queue: Arc<BatchQueue>,
-        controller: Arc<ControllerInner>,
-    ) {
-        loop {
-            if controller.state() == PipelineState::Terminated {
-                return;
-            }
+    fn disconnect_output(&self, endpoint_name: &str) -> AnyResult<()> {
+        let mut outputs = self.outputs.write().unwrap();
 
-            // Dequeue the next output batch and push it to the encoder.
-            if let Some((data, processed_records)) = queue.pop() {
-                let num_records = data.iter().map(|b| b.len()).sum();
+        let endpoint_id = outputs
+            .by_id
+            .iter()
+            .find(|(_, ep)| ep.endpoint_name == endpoint_name)
+            .map(|(epid, _)| *epid)
+            .ok_or_else(|| ControllerError::unknown_output_endpoint(endpoint_name))?;
+
+        // Keep reading the stream if it's materialized.
+        let stream = outputs.by_id[&endpoint_id].stream.clone();
+        let materialized = self.materialized.read().unwrap().contains_key(&stream);
+        let endpoint = outputs.remove(&endpoint_id, materialized).unwrap();
+        drop(outputs);
 
-                encoder
-                    .encode(data.as_slice())
-                    .unwrap_or_else(|e| controller.encode_error(endpoint_id, &endpoint_name, e));
+        // Stop the output thread.
+        endpoint.disconnected.store(true, Ordering::Release);
+        endpoint.unparker.unpark();
 
-                // `num_records` output records have been transmitted --
-                // update output stats, wake up the circuit thread if the
-                // number of queued records drops below high water mark.
-                controller.status.output_batch(
-                    endpoint_id,
-                    processed_records,
-                    num_records,
-                    &controller.circuit_thread_unparker,
-                );
-            } else {
-                // Queue is empty -- wait for the circuit thread to wake us up when
-                // more data is available.
-                parker.park();
-            }
-        }
-    }
+        self.status.remove_output(&endpoint_id);
 
-    fn state(self: &Arc<Self>) -> PipelineState {
-        PipelineState::from_u32(self.state.load(Ordering::Acquire)).unwrap()
-    }
+        // The endpoint no longer holds back input commits in exactly-once mode.
+        self.delivered_steps
+            .lock()
+            .unwrap()
+            .by_endpoint
+            .remove(&endpoint_id);
+        self.commit_inputs();
+
+        // Wake up the circuit thread in case it is blocked waiting for this
+        // endpoint to drain its buffer.
+        self.unpark_circuit();
+        Ok(())
+    }
+
+    /// Start materializing output stream `stream`.
+    fn materialize(&self, stream: &Cow<'static, str>) -> AnyResult<()> {
+        let collection_handle = self
+            .catalog
+            .lock()
+            .unwrap()
+            .output_batch_handle(stream)
+            .ok_or_else(|| ControllerError::unknown_output_stream(stream))?
+            .fork();
+
+        let trace = collection_handle.new_trace();
+        self.outputs
+            .write()
+            .unwrap()
+            .add_stream(stream.clone(), collection_handle);
+        self.materialized
+            .write()
+            .unwrap()
+            .insert(stream.clone(), trace);
+
+        Ok(())
+    }
+
+    /// Restore the contents of materialized streams from a checkpoint.
+    #[cfg(feature = "checkpoint")]
+    fn restore_materialized(&self, states: &MaterializedStreams) -> AnyResult<()> {
+        let mut materialized = self.materialized.write().unwrap();
+        for (stream, state) in states.iter() {
+            // The stream may no longer be materialized.
+            if let Some(trace) = materialized.get_mut(stream.as_str()) {
+                trace.restore(state).map_err(|e| {
+                    AnyError::msg(format!(
+                        "failed to restore materialized stream '{stream}': {e}"
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn output_thread_func(
+        endpoint_id: EndpointId,
+        endpoint_name: String,
+        mut encoder: Box<dyn Encoder>,
+        parker: Parker,
+        queue: Arc<BatchQueue>,
+        disconnected: Arc<AtomicBool>,
+        mut consolidator: Option<OutputConsolidator>,
+        controller: Arc<ControllerInner>,
+    ) {
+        loop {
+            if controller.state() == PipelineState::Terminated
+                || disconnected.load(Ordering::Acquire)
+            {
+                return;
+            }
+
+            // Dequeue the next output batch and push it to the encoder or
+            // the consolidator.
+            if let Some((data, processed_records, step)) = queue.pop() {
+                let labels = BatchLabels {
+                    num_records: data.iter().map(|b| b.len()).sum(),
+                    processed_records,
+                    step,
+                };
+
+                match &mut consolidator {
+                    None => controller.push_output(
+                        endpoint_id,
+                        &endpoint_name,
+                        encoder.as_mut(),
+                        &data,
+                        &[labels],
+                    ),
+                    Some(consolidator) => consolidator.push(&data, labels),
+                }
+            }
+
+            // Flush consolidated outputs once their deadline expires.  Otherwise,
+            // if the queue is empty, wait for the circuit thread to wake us up when
+            // more data is available or until the deadline.
+            let deadline = consolidator.as_ref().and_then(|c| c.deadline());
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if deadline <= now => {
+                    let (batch, labels) = consolidator.as_mut().unwrap().flush();
+                    controller.push_output(
+                        endpoint_id,
+                        &endpoint_name,
+                        encoder.as_mut(),
+                        &[batch],
+                        &labels,
+                    );
+                }
+                _ if !queue.is_empty() => {}
+                Some(deadline) => parker.park_timeout(deadline - now),
+                None => parker.park(),
+            }
+        }
+    }
+
+    /// Encode `data` and send it to the output endpoint.  `data` was
+    /// computed from the queue entries labeled by `labels`.
+    fn push_output(
+        &self,
+        endpoint_id: EndpointId,
+        endpoint_name: &str,
+        encoder: &mut dyn Encoder,
+        data: &[Arc<dyn SerBatch>],
+        labels: &[BatchLabels],
+    ) {
+        let step = labels.iter().filter_map(|labels| labels.step).max();
+        encoder.consumer().batch_start(step);
+        let encoded = match encoder.encode(data) {
+            Ok(()) => true,
+            Err(e) => {
+                self.encode_error(endpoint_id, endpoint_name, e);
+                false
+            }
+        };
+        let delivered = encoder.consumer().batch_end() && encoded;
+
+        if delivered && self.status.global_config.exactly_once {
+            for step in labels.iter().filter_map(|labels| labels.step) {
+                self.output_step_delivered(endpoint_id, step);
+            }
+        }
+
+        // Update output stats, wake up the circuit thread if the number of
+        // queued records drops below high water mark.  Update the number of
+        // transmitted records first, so that it is final by the time the
+        // endpoint reports processing all inputs.
+        self.status
+            .output_records(endpoint_id, data.iter().map(|b| b.len()).sum());
+        for labels in labels.iter() {
+            self.status.output_batch(
+                endpoint_id,
+                labels.processed_records,
+                labels.num_records,
+                &self.circuit_thread_unparker,
+            );
+        }
+    }
+
+    fn state(self: &Arc<Self>) -> PipelineState {
+        PipelineState::from_u32(self.state.load(Ordering::Acquire)).unwrap()
+    }
 
     fn start(self: &Arc<Self>) {
         self.state
@@ -888,6 +1776,109 @@ impl ControllerInner {
     fn output_buffers_full(&self) -> bool {
         self.status.output_buffers_full()
     }
+
+    /// Record that output endpoint `endpoint_id` has delivered the outputs of
+    /// `step` and commit inputs whose outputs have been delivered by all
+    /// endpoints.
+    fn output_step_delivered(&self, endpoint_id: EndpointId, step: Step) {
+        let mut delivered_steps = self.delivered_steps.lock().unwrap();
+        if let Some(delivered) = delivered_steps.by_endpoint.get_mut(&endpoint_id) {
+            // Ignore steps that precede the endpoint and steps after a gap
+            // left by a failed delivery.
+            if *delivered == step {
+                *delivered += 1;
+            }
+        }
+        drop(delivered_steps);
+
+        self.commit_inputs();
+    }
+
+    /// Notify input endpoints about steps whose outputs have been delivered by
+    /// all output endpoints.
+    ///
+    /// Committing can block, e.g., waiting for a Kafka broker, so endpoints
+    /// are notified without holding `delivered_steps` or `inputs`.  Only one
+    /// thread notifies endpoints at a time, so that they observe steps in
+    /// increasing order.  A thread that finds another one committing leaves it
+    /// to commit the new step as well.
+    fn commit_inputs(&self) {
+        loop {
+            let guard = match self.commit_lock.try_lock() {
+                Ok(guard) => guard,
+                Err(_) => return,
+            };
+
+            while let Some(step) = self.next_commit() {
+                let inputs: Vec<_> = self
+                    .inputs
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(epid, ep)| (*epid, ep.endpoint_name.clone(), ep.endpoint.clone()))
+                    .collect();
+
+                for (epid, endpoint_name, endpoint) in inputs {
+                    endpoint.commit(step).unwrap_or_else(|e| {
+                        self.input_transport_error(
+                            epid,
+                            &endpoint_name,
+                            false,
+                            e.context("failed to commit input"),
+                        )
+                    });
+                }
+            }
+            drop(guard);
+
+            // Another thread may have advanced delivered steps after the last
+            // `next_commit` call, but failed to acquire the lock.
+            if !self.commit_pending() {
+                return;
+            }
+        }
+    }
+
+    /// The first step whose outputs haven't been delivered by all output
+    /// endpoints.
+    fn delivered_step(&self, delivered_steps: &DeliveredSteps) -> Step {
+        // Without output endpoints, a step is complete as soon as the circuit has
+        // processed it.
+        delivered_steps
+            .by_endpoint
+            .values()
+            .min()
+            .cloned()
+            .unwrap_or_else(|| self.step.load(Ordering::Acquire))
+    }
+
+    /// Returns the step to pass to [`InputEndpoint::commit`] and marks it
+    /// committed, or `None` if there is nothing new to commit.
+    fn next_commit(&self) -> Option<Step> {
+        let mut delivered_steps = self.delivered_steps.lock().unwrap();
+        let step = self.delivered_step(&delivered_steps);
+        if step <= delivered_steps.committed {
+            return None;
+        }
+        delivered_steps.committed = step;
+        Some(step)
+    }
+
+    fn commit_pending(&self) -> bool {
+        let delivered_steps = self.delivered_steps.lock().unwrap();
+        self.delivered_step(&delivered_steps) > delivered_steps.committed
+    }
+}
+
+/// Dead-letter queue shared by all clones of an input probe.
+type DeadLetterQueue = Arc<Mutex<Box<dyn OutputEndpoint>>>;
+
+/// A record written to the dead-letter queue.
+#[derive(Serialize)]
+struct DeadLetterRecord<'a> {
+    endpoint: &'a str,
+    record: &'a str,
+    error: &'a str,
 }
 
 /// An input probe inserted between the transport endpoint and the parser to
@@ -896,6 +1887,12 @@ struct InputProbe {
     endpoint_id: EndpointId,
     endpoint_name: String,
     parser: Box<dyn Parser>,
+    /// Endpoint position (see [`InputEndpointDescr::position`]).
+    position: SharedPosition,
+    /// The last position reported by the endpoint before the parser
+    /// processed any input; recorded in `position` once it does.
+    pending_position: Option<Box<dyn ErasedSerialize + Send>>,
+    dead_letter_queue: Option<DeadLetterQueue>,
     controller: Arc<ControllerInner>,
     circuit_thread_unparker: Unparker,
     backpressure_thread_unparker: Unparker,
@@ -906,6 +1903,8 @@ impl InputProbe {
         endpoint_id: EndpointId,
         endpoint_name: &str,
         parser: Box<dyn Parser>,
+        position: SharedPosition,
+        dead_letter_queue: Option<DeadLetterQueue>,
         controller: Arc<ControllerInner>,
         circuit_thread_unparker: Unparker,
         backpressure_thread_unparker: Unparker,
@@ -914,37 +1913,122 @@ impl InputProbe {
             endpoint_id,
             endpoint_name: endpoint_name.to_owned(),
             parser,
+            position,
+            pending_position: None,
+            dead_letter_queue,
             controller,
             circuit_thread_unparker,
             backpressure_thread_unparker,
         }
     }
-}
 
-/// `InputConsumer` interface exposed to the transport endpoint.
-impl InputConsumer for InputProbe {
-    fn input(&mut self, data: &[u8]) {
-        // println!("input consumer {} bytes", data.len());
-        // Pass input buffer to the parser.
-        match self.parser.input(data) {
-            Ok(num_records) => {
-                // Success: push data to the input handle, update stats.
-                self.parser.flush();
-                self.controller.status.input_batch(
-                    self.endpoint_id,
-                    data.len(),
-                    num_records,
-                    &self.controller.status.global_config,
-                    &self.circuit_thread_unparker,
-                    &self.backpressure_thread_unparker,
-                );
+    /// Report records rejected by the parser.
+    ///
+    /// Updates endpoint stats, notifies the error callback, and forwards
+    /// rejected records to the dead-letter queue, if any.
+    fn parse_errors(&self, errors: Vec<ParseError>) {
+        if errors.is_empty() {
+            return;
+        }
+
+        if let Some(dead_letter_queue) = &self.dead_letter_queue {
+            let mut buffer = Vec::new();
+            for error in errors.iter() {
+                let record = DeadLetterRecord {
+                    endpoint: &self.endpoint_name,
+                    record: &error.record,
+                    error: &error.description,
+                };
+                // Serializing strings into a vector cannot fail.
+                serde_json::to_writer(&mut buffer, &record).unwrap();
+                buffer.push(b'\n');
             }
-            Err(error) => {
-                self.parser.clear();
-                self.controller
-                    .parse_error(self.endpoint_id, &self.endpoint_name, error);
+
+            dead_letter_queue
+                .lock()
+                .unwrap()
+                .push_buffer(&buffer)
+                .unwrap_or_else(|e| {
+                    self.controller.input_transport_error(
+                        self.endpoint_id,
+                        &self.endpoint_name,
+                        false,
+                        e.context("failed to write to the dead-letter queue"),
+                    )
+                });
+        }
+
+        for error in errors.into_iter() {
+            self.controller.parse_error(
+                self.endpoint_id,
+                &self.endpoint_name,
+                AnyError::from(error),
+            );
+        }
+    }
+
+    /// Record the endpoint position after the parser has processed
+    /// `parsed_bytes` more bytes.  Must be called while holding the input
+    /// lock.
+    ///
+    /// A position reported before the parser has processed any input is not
+    /// recorded, so that a checkpoint taken at that point resumes the
+    /// endpoint from the start of its input stream.
+    fn update_position(&mut self, parsed_bytes: usize) {
+        let mut position = self.position.lock().unwrap();
+        if parsed_bytes == 0 && position.is_none() {
+            return;
+        }
+        let unparsed_bytes = self.parser.unparsed_bytes() as u64;
+        match (self.pending_position.take(), &mut *position) {
+            (Some(new_position), _) => {
+                *position = Some(EndpointPosition {
+                    position: new_position,
+                    unparsed_bytes,
+                })
             }
+            (None, Some(position)) => position.unparsed_bytes = unparsed_bytes,
+            (None, None) => {}
+        }
+    }
+
+    fn push(&mut self, data: &[u8], position: Option<Box<dyn ErasedSerialize + Send>>) -> Step {
+        // println!("input consumer {} bytes", data.len());
+        // Pass input buffer to the parser.  Invalid records are skipped by the
+        // parser; report them, but push valid records to the input handle.
+        let _guard = self.controller.input_lock.read().unwrap();
+        let step = self.controller.step.load(Ordering::Acquire);
+        let unparsed_bytes = self.parser.unparsed_bytes();
+        let (num_records, errors) = self.parser.input(data);
+        self.parse_errors(errors);
+
+        self.parser.flush();
+        let parsed_bytes = unparsed_bytes + data.len() - self.parser.unparsed_bytes();
+        if position.is_some() {
+            self.pending_position = position;
         }
+        self.update_position(parsed_bytes);
+        self.controller.status.input_batch(
+            self.endpoint_id,
+            data.len(),
+            num_records,
+            &self.controller.status.global_config,
+            &self.circuit_thread_unparker,
+            &self.backpressure_thread_unparker,
+        );
+
+        step
+    }
+}
+
+/// `InputConsumer` interface exposed to the transport endpoint.
+impl InputConsumer for InputProbe {
+    fn input(&mut self, data: &[u8]) -> Step {
+        self.push(data, None)
+    }
+
+    fn input_at(&mut self, data: &[u8], position: Box<dyn ErasedSerialize + Send>) -> Step {
+        self.push(data, Some(position))
     }
 
     fn eoi(&mut self) {
@@ -952,21 +2036,16 @@ impl InputConsumer for InputProbe {
         // no new data has been received, the parser may contain some partially
         // parsed data and may be waiting for, e.g., and end-of-line or
         // end-of-file to finish parsing it).
-        match self.parser.eoi() {
-            Ok(num_records) => {
-                self.parser.flush();
-                self.controller.status.eoi(
-                    self.endpoint_id,
-                    num_records,
-                    &self.circuit_thread_unparker,
-                );
-            }
-            Err(error) => {
-                self.parser.clear();
-                self.controller
-                    .error(ControllerError::parse_error(&self.endpoint_name, error));
-            }
-        }
+        let _guard = self.controller.input_lock.read().unwrap();
+        let unparsed_bytes = self.parser.unparsed_bytes();
+        let (num_records, errors) = self.parser.eoi();
+        self.parse_errors(errors);
+
+        self.parser.flush();
+        self.update_position(unparsed_bytes);
+        self.controller
+            .status
+            .eoi(self.endpoint_id, num_records, &self.circuit_thread_unparker);
     }
 
     fn error(&mut self, fatal: bool, error: AnyError) {
@@ -974,15 +2053,17 @@ impl InputConsumer for InputProbe {
             .input_transport_error(self.endpoint_id, &self.endpoint_name, fatal, error);
     }
 
-    fn fork(&self) -> Box<dyn InputConsumer> {
-        Box::new(Self::new(
+    fn fork(&self) -> AnyResult<Box<dyn InputConsumer>> {
+        Ok(Box::new(Self::new(
             self.endpoint_id,
             &self.endpoint_name,
-            self.parser.fork(),
+            self.parser.fork()?,
+            Arc::new(Mutex::new(None)),
+            self.dead_letter_queue.clone(),
             self.controller.clone(),
             self.circuit_thread_unparker.clone(),
             self.backpressure_thread_unparker.clone(),
-        ))
+        )))
     }
 }
 
@@ -993,6 +2074,8 @@ struct OutputProbe {
     endpoint_name: String,
     endpoint: Box<dyn OutputEndpoint>,
     controller: Arc<ControllerInner>,
+    /// Set when the endpoint fails to process any part of the current step.
+    batch_failed: bool,
 }
 
 impl OutputProbe {
@@ -1007,8 +2090,15 @@ impl OutputProbe {
             endpoint_name: endpoint_name.to_owned(),
             endpoint,
             controller,
+            batch_failed: false,
         }
     }
+
+    fn transport_error(&mut self, error: AnyError) {
+        self.batch_failed = true;
+        self.controller
+            .output_transport_error(self.endpoint_id, &self.endpoint_name, false, error);
+    }
 }
 
 impl OutputConsumer for OutputProbe {
@@ -1021,27 +2111,49 @@ impl OutputConsumer for OutputProbe {
                     .status
                     .output_buffer(self.endpoint_id, num_bytes);
             }
-            Err(error) => {
-                self.controller.output_transport_error(
-                    self.endpoint_id,
-                    &self.endpoint_name,
-                    false,
-                    error,
-                );
-            }
+            Err(error) => self.transport_error(error),
+        }
+    }
+
+    fn batch_start(&mut self, step: Option<Step>) {
+        self.batch_failed = false;
+        if let Err(error) = self.endpoint.batch_start(step) {
+            self.transport_error(error);
+        }
+    }
+
+    fn batch_end(&mut self) -> bool {
+        if let Err(error) = self.endpoint.batch_end() {
+            self.transport_error(error);
         }
+        !self.batch_failed
     }
 }
 
 #[cfg(test)]
 mod test {
+    #[cfg(feature = "checkpoint")]
+    use crate::test::test_circuit_from_checkpoint;
     use crate::{
         test::{generate_test_batch, test_circuit, wait, TestStruct},
-        Controller, PipelineConfig,
+        Catalog, Controller, ControllerError, OutputEndpointConfig, PipelineConfig,
     };
     use csv::{ReaderBuilder as CsvReaderBuilder, WriterBuilder as CsvWriterBuilder};
-    use std::fs::remove_file;
+    use dbsp::Runtime;
+    use std::{
+        fs::{read_to_string, remove_file, write},
+        io::Write,
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread::sleep,
+        time::Duration,
+    };
     use tempfile::NamedTempFile;
+    #[cfg(feature = "checkpoint")]
+    use tempfile::TempDir;
 
     use proptest::prelude::*;
 
@@ -1146,4 +2258,679 @@ outputs:
             assert_eq!(actual, expected);
         }
     }
+
+    #[test]
+    fn test_dead_letter_queue() {
+        let (circuit, catalog) = test_circuit(1);
+
+        let mut temp_input_file = NamedTempFile::new().unwrap();
+        let temp_output_file = NamedTempFile::new().unwrap();
+        let temp_dlq_file = NamedTempFile::new().unwrap();
+
+        let config_str = format!(
+            r#"
+inputs:
+    test_input1:
+        stream: test_input1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+        dead_letter_queue:
+            name: file
+            config:
+                path: {:?}
+outputs:
+    test_output1:
+        stream: test_output1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+        "#,
+            temp_input_file.path().to_str().unwrap(),
+            temp_dlq_file.path().to_str().unwrap(),
+            temp_output_file.path().to_str().unwrap(),
+        );
+
+        temp_input_file
+            .write_all(b"1,true,,foo\nxxx\n2,false,5,bar\n3,yyy,,baz\n")
+            .unwrap();
+
+        let config: PipelineConfig = serde_yaml::from_str(&config_str).unwrap();
+        let num_errors = Arc::new(AtomicUsize::new(0));
+        let num_errors_clone = num_errors.clone();
+
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(move |e| match e {
+                ControllerError::ParseError { .. } => {
+                    num_errors_clone.fetch_add(1, Ordering::AcqRel);
+                }
+                e => panic!("error: {e}"),
+            }),
+        )
+        .unwrap();
+
+        controller.start();
+        wait(|| controller.pipeline_complete(), None);
+        controller.stop().unwrap();
+
+        assert_eq!(num_errors.load(Ordering::Acquire), 2);
+
+        // Valid records are not affected by invalid records in the same buffer.
+        let actual: Vec<_> = CsvReaderBuilder::new()
+            .has_headers(false)
+            .from_path(temp_output_file.path())
+            .unwrap()
+            .deserialize::<(TestStruct, i32)>()
+            .map(|res| res.unwrap().0.id)
+            .collect();
+        assert_eq!(actual, vec![1, 2]);
+
+        let dlq = read_to_string(temp_dlq_file.path()).unwrap();
+        let rejected: Vec<_> = dlq
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(record["endpoint"], "test_input1");
+                record["record"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(rejected, vec!["xxx", "3,yyy,,baz"]);
+    }
+
+    fn read_output_ids(path: &Path) -> Vec<u32> {
+        let mut ids: Vec<_> = CsvReaderBuilder::new()
+            .has_headers(false)
+            .from_path(path)
+            .unwrap()
+            .deserialize::<(TestStruct, i32)>()
+            .map(|res| res.unwrap().0.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[cfg(feature = "checkpoint")]
+    #[test]
+    fn test_checkpoint() {
+        let mut temp_input_file = NamedTempFile::new().unwrap();
+        let temp_output_file1 = NamedTempFile::new().unwrap();
+        let temp_output_file2 = NamedTempFile::new().unwrap();
+        let checkpoint_dir = TempDir::new().unwrap();
+        let input_path = temp_input_file.path().to_str().unwrap().to_string();
+
+        let config = |output_path: &Path| -> PipelineConfig {
+            let config_str = format!(
+                r#"
+inputs:
+    test_input1:
+        stream: test_input1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+outputs:
+    test_output1:
+        stream: test_output1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+materialized_streams: [test_output1]
+        "#,
+                input_path,
+                output_path.to_str().unwrap(),
+            );
+            serde_yaml::from_str(&config_str).unwrap()
+        };
+
+        temp_input_file
+            .write_all(b"1,true,,foo\n2,false,5,bar\n")
+            .unwrap();
+
+        let (circuit, catalog) = test_circuit(2);
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config(temp_output_file1.path()),
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+
+        controller.start();
+        wait(|| controller.pipeline_complete(), None);
+        controller.checkpoint(checkpoint_dir.path()).unwrap();
+        controller.stop().unwrap();
+
+        assert_eq!(read_output_ids(temp_output_file1.path()), vec![1, 2]);
+
+        // Append more data and resume the pipeline from the checkpoint: only the
+        // new record must be processed.
+        temp_input_file.write_all(b"3,true,,baz\n").unwrap();
+
+        let (circuit, catalog) = test_circuit_from_checkpoint(2, checkpoint_dir.path());
+        let controller = Controller::from_checkpoint(
+            circuit,
+            catalog,
+            &config(temp_output_file2.path()),
+            checkpoint_dir.path(),
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+
+        controller.start();
+        wait(|| controller.pipeline_complete(), None);
+
+        // Materialized streams include outputs produced before the checkpoint.
+        assert_eq!(
+            controller
+                .snapshot("test_output1", None, None)
+                .unwrap()
+                .len(),
+            3
+        );
+        controller.stop().unwrap();
+
+        assert_eq!(read_output_ids(temp_output_file2.path()), vec![3]);
+    }
+
+    #[cfg(feature = "checkpoint")]
+    #[test]
+    fn test_checkpoint_directory() {
+        let input_dir = TempDir::new().unwrap();
+        let temp_output_file1 = NamedTempFile::new().unwrap();
+        let temp_output_file2 = NamedTempFile::new().unwrap();
+        let checkpoint_dir = TempDir::new().unwrap();
+
+        // A small read buffer makes the endpoint push partial records.
+        let config = |output_path: &Path| -> PipelineConfig {
+            let config_str = format!(
+                r#"
+inputs:
+    test_input1:
+        stream: test_input1
+        transport:
+            name: directory
+            config:
+                pattern: {:?}
+                consumed_files_path: {:?}
+                follow: true
+                poll_interval_ms: 10
+                buffer_size_bytes: 5
+        format:
+            name: csv
+outputs:
+    test_output1:
+        stream: test_output1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+        "#,
+                input_dir.path().join("*.csv"),
+                input_dir.path().join("consumed"),
+                output_path.to_str().unwrap(),
+            );
+            serde_yaml::from_str(&config_str).unwrap()
+        };
+        let num_output_lines =
+            |path: &Path| read_to_string(path).unwrap_or_default().lines().count();
+
+        write(
+            input_dir.path().join("1.csv"),
+            "1,true,,foo\n2,false,5,bar\n",
+        )
+        .unwrap();
+
+        let (circuit, catalog) = test_circuit(2);
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config(temp_output_file1.path()),
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+
+        controller.start();
+        wait(|| num_output_lines(temp_output_file1.path()) == 2, None);
+        controller.checkpoint(checkpoint_dir.path()).unwrap();
+
+        // Files ingested after the checkpoint are recorded as consumed, but
+        // a pipeline resumed from the checkpoint must ingest them again.
+        write(input_dir.path().join("2.csv"), "3,true,,baz\n").unwrap();
+        wait(|| num_output_lines(temp_output_file1.path()) == 3, None);
+        wait(
+            || {
+                read_to_string(input_dir.path().join("consumed"))
+                    .unwrap()
+                    .lines()
+                    .count()
+                    == 2
+            },
+            None,
+        );
+        controller.stop().unwrap();
+        assert_eq!(read_output_ids(temp_output_file1.path()), vec![1, 2, 3]);
+
+        let (circuit, catalog) = test_circuit_from_checkpoint(2, checkpoint_dir.path());
+        let controller = Controller::from_checkpoint(
+            circuit,
+            catalog,
+            &config(temp_output_file2.path()),
+            checkpoint_dir.path(),
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+
+        controller.start();
+        wait(|| num_output_lines(temp_output_file2.path()) == 1, None);
+        // Give the endpoint time to ingest files it shouldn't.
+        sleep(Duration::from_millis(100));
+        controller.stop().unwrap();
+
+        assert_eq!(read_output_ids(temp_output_file2.path()), vec![3]);
+    }
+
+    #[test]
+    fn test_exactly_once_stateful() {
+        let config: PipelineConfig =
+            serde_yaml::from_str("exactly_once: true\ninputs: {}").unwrap();
+
+        let (circuit, catalog) = test_circuit(1);
+        Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap()
+        .stop()
+        .unwrap();
+
+        let (circuit, (input, output)) = Runtime::init_circuit(1, |circuit| {
+            let (input, hinput) = circuit.add_input_zset::<TestStruct, i32>();
+            (hinput, input.integrate().output())
+        })
+        .unwrap();
+        let mut catalog = Catalog::new();
+        catalog.register_input_zset_handle("test_input1", input);
+        catalog.register_output_batch_handle("test_output1", output);
+
+        let error = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "exactly-once mode requires a stateless circuit"
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut temp_input_file = NamedTempFile::new().unwrap();
+
+        let config_str = format!(
+            r#"
+inputs:
+    test_input1:
+        stream: test_input1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+materialized_streams: [test_output1]
+        "#,
+            temp_input_file.path().to_str().unwrap(),
+        );
+        let config: PipelineConfig = serde_yaml::from_str(&config_str).unwrap();
+
+        temp_input_file
+            .write_all(b"1,true,,foo\n2,false,5,bar\n3,true,,baz\n")
+            .unwrap();
+
+        let (circuit, catalog) = test_circuit(2);
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+
+        assert!(controller.snapshot("test_output2", None, None).is_err());
+
+        controller.start();
+        wait(|| controller.pipeline_complete(), None);
+
+        assert_eq!(
+            controller
+                .snapshot("test_output1", None, None)
+                .unwrap()
+                .len(),
+            3
+        );
+
+        let lower = r#"{"id":2,"b":false,"i":null,"s":""}"#;
+        let upper = r#"{"id":3,"b":false,"i":null,"s":""}"#;
+        let snapshot = controller
+            .snapshot("test_output1", Some(lower), Some(upper))
+            .unwrap();
+        assert_eq!(snapshot.len(), 1);
+
+        let mut cursor = snapshot.cursor();
+        assert_eq!(
+            serde_json::to_string(cursor.key()).unwrap(),
+            r#"{"id":2,"b":false,"i":5,"s":"bar"}"#
+        );
+        assert_eq!(cursor.weight(), 1);
+
+        assert!(controller
+            .snapshot("test_output1", Some("not a key"), None)
+            .is_err());
+
+        controller.stop().unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_without_keys() {
+        let (circuit, (input, output)) = Runtime::init_circuit(1, |circuit| {
+            let (input, hinput) = circuit.add_input_zset::<TestStruct, i32>();
+            (hinput, input.output())
+        })
+        .unwrap();
+        let mut catalog = Catalog::new();
+        catalog.register_input_zset_handle("test_input1", input);
+        catalog.register_output_batch_handle("test_output1", output);
+
+        let config: PipelineConfig =
+            serde_yaml::from_str("inputs: {}\nmaterialized_streams: [test_output1]").unwrap();
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+
+        // Streams registered without key deserialization don't support key
+        // ranges.
+        assert!(controller
+            .snapshot("test_output1", None, None)
+            .unwrap()
+            .is_empty());
+        assert!(controller
+            .snapshot(
+                "test_output1",
+                Some(r#"{"id":2,"b":false,"i":null,"s":""}"#),
+                None
+            )
+            .is_err());
+
+        controller.stop().unwrap();
+    }
+
+    fn transmitted_records(controller: &Controller, endpoint_id: u64) -> u64 {
+        controller
+            .status()
+            .output_status()
+            .get(&endpoint_id)
+            .map(|endpoint| endpoint.transmitted_records())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_connect_disconnect() {
+        let mut temp_input_file = NamedTempFile::new().unwrap();
+        let temp_output_file1 = NamedTempFile::new().unwrap();
+        let temp_output_file2 = NamedTempFile::new().unwrap();
+
+        let config_str = format!(
+            r#"
+inputs:
+    test_input1:
+        stream: test_input1
+        transport:
+            name: file
+            config:
+                path: {:?}
+                follow: true
+        format:
+            name: csv
+        "#,
+            temp_input_file.path().to_str().unwrap(),
+        );
+        let config: PipelineConfig = serde_yaml::from_str(&config_str).unwrap();
+
+        let output_config = |output_path: &Path| -> OutputEndpointConfig {
+            let config_str = format!(
+                r#"
+stream: test_output1
+transport:
+    name: file
+    config:
+        path: {:?}
+format:
+    name: csv
+        "#,
+                output_path.to_str().unwrap(),
+            );
+            serde_yaml::from_str(&config_str).unwrap()
+        };
+
+        let (circuit, catalog) = test_circuit(2);
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+        controller.start();
+
+        // Attach an output endpoint to the running pipeline.
+        controller
+            .connect_output("test_output1", &output_config(temp_output_file1.path()))
+            .unwrap();
+        assert!(controller
+            .connect_output("test_output1", &output_config(temp_output_file2.path()))
+            .is_err());
+        assert_eq!(controller.status().output_status().len(), 1);
+
+        temp_input_file
+            .write_all(b"1,true,,foo\n2,false,5,bar\n")
+            .unwrap();
+        wait(|| transmitted_records(&controller, 0) == 2, None);
+        assert_eq!(read_output_ids(temp_output_file1.path()), vec![1, 2]);
+
+        // Replace the output endpoint with a new one with the same name.
+        controller.disconnect_output("test_output1").unwrap();
+        assert!(controller.disconnect_output("test_output1").is_err());
+        assert!(controller.status().output_status().is_empty());
+        controller
+            .connect_output("test_output1", &output_config(temp_output_file2.path()))
+            .unwrap();
+
+        temp_input_file.write_all(b"3,true,,baz\n").unwrap();
+        // Endpoint ids are not reused.
+        wait(|| transmitted_records(&controller, 1) == 1, None);
+        assert_eq!(read_output_ids(temp_output_file2.path()), vec![3]);
+        assert_eq!(read_output_ids(temp_output_file1.path()), vec![1, 2]);
+
+        controller.disconnect_input("test_input1").unwrap();
+        assert!(controller.disconnect_input("test_input1").is_err());
+        assert!(controller.status().input_status().is_empty());
+
+        controller.stop().unwrap();
+    }
+
+    #[test]
+    fn test_initial_snapshot() {
+        let mut temp_input_file = NamedTempFile::new().unwrap();
+        let temp_output_file = NamedTempFile::new().unwrap();
+
+        let config_str = format!(
+            r#"
+inputs:
+    test_input1:
+        stream: test_input1
+        transport:
+            name: file
+            config:
+                path: {:?}
+                follow: true
+        format:
+            name: csv
+materialized_streams: [test_output1]
+        "#,
+            temp_input_file.path().to_str().unwrap(),
+        );
+        let config: PipelineConfig = serde_yaml::from_str(&config_str).unwrap();
+
+        let output_config_str = format!(
+            r#"
+stream: test_output1
+transport:
+    name: file
+    config:
+        path: {:?}
+format:
+    name: csv
+consolidate: true
+initial_snapshot: true
+        "#,
+            temp_output_file.path().to_str().unwrap(),
+        );
+        let output_config: OutputEndpointConfig = serde_yaml::from_str(&output_config_str).unwrap();
+
+        let (circuit, catalog) = test_circuit(2);
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+        controller.start();
+
+        temp_input_file
+            .write_all(b"1,true,,foo\n2,false,5,bar\n")
+            .unwrap();
+        wait(
+            || controller.status().num_total_processed_records() == 2,
+            None,
+        );
+
+        // The new endpoint receives the current contents of the stream,
+        // followed by subsequent updates.
+        controller
+            .connect_output("test_output1", &output_config)
+            .unwrap();
+        wait(|| transmitted_records(&controller, 0) == 2, None);
+        assert_eq!(read_output_ids(temp_output_file.path()), vec![1, 2]);
+
+        temp_input_file.write_all(b"3,true,,baz\n").unwrap();
+        wait(|| transmitted_records(&controller, 0) == 3, None);
+        assert_eq!(read_output_ids(temp_output_file.path()), vec![1, 2, 3]);
+
+        controller.stop().unwrap();
+    }
+
+    #[test]
+    fn test_pause_input_endpoint() {
+        let mut temp_input_file1 = NamedTempFile::new().unwrap();
+        let mut temp_input_file2 = NamedTempFile::new().unwrap();
+        let temp_output_file = NamedTempFile::new().unwrap();
+
+        let config_str = format!(
+            r#"
+inputs:
+    test_input1:
+        stream: test_input1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+    test_input2:
+        stream: test_input1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+        max_records_per_second: 1000
+outputs:
+    test_output1:
+        stream: test_output1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+        "#,
+            temp_input_file1.path().to_str().unwrap(),
+            temp_input_file2.path().to_str().unwrap(),
+            temp_output_file.path().to_str().unwrap(),
+        );
+        let config: PipelineConfig = serde_yaml::from_str(&config_str).unwrap();
+
+        temp_input_file1
+            .write_all(b"1,true,,foo\n2,false,5,bar\n")
+            .unwrap();
+        temp_input_file2.write_all(b"3,true,,baz\n").unwrap();
+
+        let (circuit, catalog) = test_circuit(2);
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+
+        assert!(controller.pause_input_endpoint("test_input3").is_err());
+        controller.pause_input_endpoint("test_input2").unwrap();
+        controller.start();
+
+        // Only the first endpoint is running.
+        wait(|| transmitted_records(&controller, 0) == 2, None);
+        assert!(!controller.pipeline_complete());
+        assert_eq!(
+            controller.status().input_status()[&1]
+                .metrics
+                .total_records
+                .load(Ordering::Acquire),
+            0
+        );
+
+        controller.start_input_endpoint("test_input2").unwrap();
+        wait(|| controller.pipeline_complete(), None);
+        controller.stop().unwrap();
+
+        assert_eq!(read_output_ids(temp_output_file.path()), vec![1, 2, 3]);
+    }
 }
@@ -40,8 +40,13 @@ use std::{
         atomic::{AtomicBool, AtomicU64, Ordering},
         Mutex,
     },
+    time::{Duration, Instant},
 };
 
+/// An input endpoint is paused once it gets this far ahead of its configured
+/// rate (see [`RateLimiter`]).
+const RATE_LIMIT_BURST: Duration = Duration::from_secs(1);
+
 #[derive(Default, Serialize)]
 pub struct GlobalControllerMetrics {
     /// Total number of records currently buffered by all endpoints.
@@ -182,6 +187,32 @@ impl ControllerStatus {
         );
     }
 
+    /// Remove stats of a disconnected input endpoint.
+    pub fn remove_input(&self, endpoint_id: &EndpointId) {
+        self.inputs.write().unwrap().remove(endpoint_id);
+    }
+
+    /// Remove stats of a disconnected output endpoint.
+    pub fn remove_output(&self, endpoint_id: &EndpointId) {
+        self.outputs.write().unwrap().remove(endpoint_id);
+    }
+
+    /// Id of the input endpoint named `endpoint_name`.
+    pub fn input_endpoint_id(&self, endpoint_name: &str) -> Option<EndpointId> {
+        self.input_status()
+            .iter()
+            .find(|(_, endpoint_stats)| endpoint_stats.endpoint_name == endpoint_name)
+            .map(|(endpoint_id, _)| *endpoint_id)
+    }
+
+    /// Id of the output endpoint named `endpoint_name`.
+    pub fn output_endpoint_id(&self, endpoint_name: &str) -> Option<EndpointId> {
+        self.output_status()
+            .iter()
+            .find(|(_, endpoint_stats)| endpoint_stats.endpoint_name == endpoint_name)
+            .map(|(endpoint_id, _)| *endpoint_id)
+    }
+
     /// Total number of records currently buffered by all input endpoints.
     pub fn num_buffered_input_records(&self) -> u64 {
         self.global_metrics.num_buffered_input_records()
@@ -251,6 +282,38 @@ impl ControllerStatus {
         buffered_records >= max_buffered_records
     }
 
+    /// True if the endpoint has been paused by the user.
+    pub fn input_endpoint_paused(&self, endpoint_id: &EndpointId) -> bool {
+        match self.inputs.read().unwrap().get(endpoint_id) {
+            None => false,
+            Some(endpoint) => endpoint.paused.load(Ordering::Acquire),
+        }
+    }
+
+    /// Pause or resume the endpoint on behalf of the user.
+    pub fn set_input_endpoint_paused(&self, endpoint_id: &EndpointId, paused: bool) {
+        if let Some(endpoint) = self.inputs.read().unwrap().get(endpoint_id) {
+            endpoint.paused.store(paused, Ordering::Release);
+        }
+    }
+
+    /// If the endpoint has exceeded its `max_records_per_second` or
+    /// `max_bytes_per_second` limit, returns the time when it can be
+    /// resumed.
+    pub fn input_endpoint_throttled_until(
+        &self,
+        endpoint_id: &EndpointId,
+        now: Instant,
+    ) -> Option<Instant> {
+        self.inputs
+            .read()
+            .unwrap()
+            .get(endpoint_id)?
+            .rate_limiter
+            .as_ref()?
+            .throttled_until(now)
+    }
+
     /// Update counters after receiving a new input batch.
     ///
     /// # Arguments
@@ -263,7 +326,8 @@ impl ControllerStatus {
     ///   thread if the total number of buffered records exceeds
     ///   `min_batch_size_records`.
     /// * `backpressure_thread_unparker` - unparker used to wake up the the
-    ///   backpressure thread if the endpoint is full.
+    ///   backpressure thread if the endpoint is full or exceeds its rate
+    ///   limit.
     pub fn input_batch(
         &self,
         endpoint_id: EndpointId,
@@ -297,8 +361,15 @@ impl ControllerStatus {
         if let Some(endpoint_stats) = inputs.get(&endpoint_id) {
             let old = endpoint_stats.add_buffered(num_bytes, num_records);
 
-            if old < endpoint_stats.config.max_buffered_records
-                && old + num_records >= endpoint_stats.config.max_buffered_records
+            let throttled = endpoint_stats
+                .rate_limiter
+                .as_ref()
+                .map(|rate_limiter| rate_limiter.input_batch(num_bytes, num_records))
+                .unwrap_or(false);
+
+            if throttled
+                || (old < endpoint_stats.config.max_buffered_records
+                    && old + num_records >= endpoint_stats.config.max_buffered_records)
             {
                 backpressure_thread_unparker.unpark();
             }
@@ -351,6 +422,18 @@ impl ControllerStatus {
         }
     }
 
+    /// Update counters after a batch has been removed from the endpoint's
+    /// queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint_id` - id of the output endpoint.
+    /// * `total_processed_records` - the number of input records processed by
+    ///   the circuit before producing the batch.
+    /// * `num_records` - the number of records in the batch.
+    /// * `circuit_thread_unparker` - unparker used to wake up the circuit
+    ///   thread if the number of records buffered by the endpoint drops below
+    ///   `max_buffered_records`.
     pub fn output_batch(
         &self,
         endpoint_id: EndpointId,
@@ -368,6 +451,16 @@ impl ControllerStatus {
         };
     }
 
+    /// Update counters after sending `num_records` records to the endpoint.
+    ///
+    /// This can be smaller than the number of records dequeued by the
+    /// endpoint if the endpoint consolidates output batches.
+    pub fn output_records(&self, endpoint_id: EndpointId, num_records: usize) {
+        if let Some(endpoint_stats) = self.output_status().get(&endpoint_id) {
+            endpoint_stats.output_records(num_records);
+        };
+    }
+
     pub fn output_buffer(&self, endpoint_id: EndpointId, num_bytes: usize) {
         if let Some(endpoint_stats) = self.output_status().get(&endpoint_id) {
             endpoint_stats.output_buffer(num_bytes);
@@ -479,6 +572,15 @@ pub struct InputEndpointStatus {
 
     /// The first fatal error that occurred at the endpoint.
     pub fatal_error: Mutex<Option<String>>,
+
+    /// True if the endpoint has been paused by the user.  The endpoint
+    /// stays paused until resumed by the user, regardless of the state of
+    /// the pipeline.
+    pub paused: AtomicBool,
+
+    /// Enforces the endpoint's rate limits, if any.
+    #[serde(skip)]
+    rate_limiter: Option<RateLimiter>,
 }
 
 impl InputEndpointStatus {
@@ -488,6 +590,8 @@ impl InputEndpointStatus {
             config: config.clone(),
             metrics: Default::default(),
             fatal_error: Mutex::new(None),
+            paused: AtomicBool::new(false),
+            rate_limiter: RateLimiter::new(config),
         }
     }
 
@@ -547,6 +651,72 @@ impl InputEndpointStatus {
     }
 }
 
+/// Enforces `max_records_per_second` and `max_bytes_per_second` limits of
+/// an input endpoint.
+///
+/// This is a variant of the generic cell rate algorithm.  Each input batch
+/// pushes the "theoretical arrival time" of the next batch forward by the
+/// time it would take to receive the batch at the configured rate.  When
+/// this time gets more than [`RATE_LIMIT_BURST`] ahead of the wall clock,
+/// the endpoint is throttled until the wall clock catches up.
+struct RateLimiter {
+    max_records_per_second: Option<u64>,
+    max_bytes_per_second: Option<u64>,
+    state: Mutex<RateLimiterState>,
+}
+
+struct RateLimiterState {
+    /// Theoretical arrival time of the next input batch.
+    next_arrival: Instant,
+    throttled: bool,
+}
+
+impl RateLimiter {
+    /// Returns `None` if the config doesn't specify any rate limits.
+    fn new(config: &InputEndpointConfig) -> Option<Self> {
+        if config.max_records_per_second.is_none() && config.max_bytes_per_second.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            max_records_per_second: config.max_records_per_second,
+            max_bytes_per_second: config.max_bytes_per_second,
+            state: Mutex::new(RateLimiterState {
+                next_arrival: Instant::now(),
+                throttled: false,
+            }),
+        })
+    }
+
+    /// Account for a new input batch.  Returns `true` if the endpoint must be
+    /// throttled.
+    fn input_batch(&self, num_bytes: u64, num_records: u64) -> bool {
+        let cost = |amount: u64, rate: Option<u64>| {
+            rate.map(|rate| Duration::from_secs_f64(amount as f64 / rate as f64))
+                .unwrap_or_default()
+        };
+        let cost = cost(num_records, self.max_records_per_second)
+            .max(cost(num_bytes, self.max_bytes_per_second));
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.next_arrival = state.next_arrival.max(now) + cost;
+        if state.next_arrival > now + RATE_LIMIT_BURST {
+            state.throttled = true;
+        }
+        state.throttled
+    }
+
+    /// Returns the time when a throttled endpoint can be resumed.
+    fn throttled_until(&self, now: Instant) -> Option<Instant> {
+        let mut state = self.state.lock().unwrap();
+        if state.throttled && state.next_arrival <= now {
+            state.throttled = false;
+        }
+        state.throttled.then_some(state.next_arrival)
+    }
+}
+
 #[derive(Default, Serialize)]
 pub struct OutputEndpointMetrics {
     pub transmitted_records: AtomicU64,
@@ -609,9 +779,6 @@ impl OutputEndpointStatus {
         self.metrics
             .total_processed_input_records
             .store(total_processed_input_records, Ordering::Release);
-        self.metrics
-            .transmitted_records
-            .fetch_add(num_records as u64, Ordering::Relaxed);
 
         let old = self
             .metrics
@@ -621,6 +788,12 @@ impl OutputEndpointStatus {
         old
     }
 
+    fn output_records(&self, num_records: usize) {
+        self.metrics
+            .transmitted_records
+            .fetch_add(num_records as u64, Ordering::Relaxed);
+    }
+
     fn output_buffer(&self, num_bytes: usize) {
         self.metrics
             .transmitted_bytes
@@ -654,3 +827,39 @@ impl OutputEndpointStatus {
             .load(Ordering::Acquire)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{RateLimiter, RATE_LIMIT_BURST};
+    use crate::InputEndpointConfig;
+    use std::time::Instant;
+
+    #[test]
+    fn rate_limiter() {
+        let config: InputEndpointConfig = serde_yaml::from_str(
+            r#"
+stream: test_input1
+transport:
+    name: file
+format:
+    name: csv
+max_records_per_second: 10
+"#,
+        )
+        .unwrap();
+        let rate_limiter = RateLimiter::new(&config).unwrap();
+
+        // Half a second worth of records is within the burst limit.
+        assert!(!rate_limiter.input_batch(1_000, 5));
+        assert_eq!(rate_limiter.throttled_until(Instant::now()), None);
+
+        // Another second worth of records exceeds it.
+        assert!(rate_limiter.input_batch(1_000, 10));
+        let now = Instant::now();
+        let throttled_until = rate_limiter.throttled_until(now).unwrap();
+        assert!(throttled_until > now + RATE_LIMIT_BURST);
+
+        // The endpoint can resume once the clock catches up.
+        assert_eq!(rate_limiter.throttled_until(throttled_until), None);
+    }
+}
@@ -1,6 +1,10 @@
-use dbsp::{algebra::ZRingValue, CollectionHandle, DBData, DBWeight, InputHandle, UpsertHandle};
+use dbsp::{
+    algebra::{HasZero, ZRingValue},
+    CollectionHandle, DBData, DBWeight, InputHandle, UpsertHandle,
+};
 use erased_serde::{deserialize, Deserializer as ErasedDeserializer, Error as EError};
-use serde::Deserialize;
+use serde::{de::Error as _, Deserialize};
+use std::cmp::Ordering;
 
 /// Maximal buffer size reused across clock cycles.
 ///
@@ -163,6 +167,40 @@ pub trait DeCollectionHandle: Send {
     /// documentation for details.
     fn delete(&mut self, deserializer: &mut dyn ErasedDeserializer) -> Result<(), EError>;
 
+    /// Buffer a delete update followed by an insert update.
+    ///
+    /// `old` is interpreted the same way as by [`delete`](`Self::delete`),
+    /// `new` the same way as by [`insert`](`Self::insert`).  Both records are
+    /// deserialized before either update is buffered, so that the handle
+    /// remains unmodified if either record fails to deserialize.
+    fn replace(
+        &mut self,
+        old: &mut dyn ErasedDeserializer,
+        new: &mut dyn ErasedDeserializer,
+    ) -> Result<(), EError>;
+
+    /// Buffer an update with the given weight.
+    ///
+    /// A positive `weight` inserts `weight` copies of the record; a negative
+    /// `weight` deletes `-weight` copies, where the record is interpreted
+    /// the same way as by [`delete`](`Self::delete`).  Updates with zero
+    /// weight are ignored.
+    ///
+    /// The default implementation is suitable for handles with set or upsert
+    /// semantics, where multiple copies of an update have the same effect as
+    /// a single copy: it buffers a single insert or delete update.
+    fn update(
+        &mut self,
+        deserializer: &mut dyn ErasedDeserializer,
+        weight: i64,
+    ) -> Result<(), EError> {
+        match weight.cmp(&0) {
+            Ordering::Greater => self.insert(deserializer),
+            Ordering::Less => self.delete(deserializer),
+            Ordering::Equal => Ok(()),
+        }
+    }
+
     /// Reserve space for at least `reservation` more updates in the
     /// internal input buffer.
     ///
@@ -207,16 +245,46 @@ pub trait DeCollectionHandle: Send {
 /// The [`delete`](`Self::delete`) method of this handle deserializes value
 /// `v` type `V` and buffers a `(v, -1)` update for the underlying
 /// `CollectionHandle`.
+///
+/// The [`update`](`Self::update`) method of this handle deserializes value
+/// `v` type `V` and buffers a single `(v, weight)` update for the underlying
+/// `CollectionHandle`.  Handles created with [`new`](`Self::new`) only accept
+/// weights `+1` and `-1`; handles created with
+/// [`with_weights`](`Self::with_weights`) accept any weight representable in
+/// `R`.
 pub struct DeZSetHandle<K, R> {
     updates: Vec<(K, R)>,
     handle: CollectionHandle<K, R>,
+    weight: fn(i64) -> Option<R>,
 }
 
 impl<K, R> DeZSetHandle<K, R> {
-    pub fn new(handle: CollectionHandle<K, R>) -> Self {
+    pub fn new(handle: CollectionHandle<K, R>) -> Self
+    where
+        R: ZRingValue,
+    {
+        Self::with_weight_fn(handle, |weight| match weight {
+            1 => Some(R::one()),
+            -1 => Some(R::one().neg()),
+            0 => Some(R::zero()),
+            _ => None,
+        })
+    }
+
+    /// Create a handle whose [`update`](`DeCollectionHandle::update`) method
+    /// accepts arbitrary weights.
+    pub fn with_weights(handle: CollectionHandle<K, R>) -> Self
+    where
+        R: TryFrom<i64>,
+    {
+        Self::with_weight_fn(handle, |weight| R::try_from(weight).ok())
+    }
+
+    fn with_weight_fn(handle: CollectionHandle<K, R>, weight: fn(i64) -> Option<R>) -> Self {
         Self {
             updates: Vec::new(),
             handle,
+            weight,
         }
     }
 
@@ -246,6 +314,37 @@ where
         Ok(())
     }
 
+    fn replace(
+        &mut self,
+        old: &mut dyn ErasedDeserializer,
+        new: &mut dyn ErasedDeserializer,
+    ) -> Result<(), EError> {
+        let old = deserialize::<K>(old)?;
+        let new = deserialize::<K>(new)?;
+
+        self.updates.push((old, R::one().neg()));
+        self.updates.push((new, R::one()));
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        deserializer: &mut dyn ErasedDeserializer,
+        weight: i64,
+    ) -> Result<(), EError> {
+        let key = deserialize::<K>(deserializer)?;
+        let weight = (self.weight)(weight).ok_or_else(|| {
+            EError::custom(format!(
+                "weight {weight} is out of range for this input stream"
+            ))
+        })?;
+
+        if !weight.is_zero() {
+            self.updates.push((key, weight));
+        }
+        Ok(())
+    }
+
     fn reserve(&mut self, reservation: usize) {
         self.updates.reserve(reservation);
     }
@@ -261,7 +360,7 @@ where
     }
 
     fn fork(&self) -> Box<dyn DeCollectionHandle> {
-        Box::new(Self::new(self.handle.clone()))
+        Box::new(Self::with_weight_fn(self.handle.clone(), self.weight))
     }
 }
 
@@ -308,6 +407,19 @@ where
         Ok(())
     }
 
+    fn replace(
+        &mut self,
+        old: &mut dyn ErasedDeserializer,
+        new: &mut dyn ErasedDeserializer,
+    ) -> Result<(), EError> {
+        let old = deserialize::<K>(old)?;
+        let new = deserialize::<K>(new)?;
+
+        self.updates.push((old, false));
+        self.updates.push((new, true));
+        Ok(())
+    }
+
     fn reserve(&mut self, reservation: usize) {
         self.updates.reserve(reservation);
     }
@@ -376,6 +488,20 @@ where
         Ok(())
     }
 
+    fn replace(
+        &mut self,
+        old: &mut dyn ErasedDeserializer,
+        new: &mut dyn ErasedDeserializer,
+    ) -> Result<(), EError> {
+        let old_key = deserialize::<K>(old)?;
+        let val = deserialize::<V>(new)?;
+        let key = (self.key_func)(&val);
+
+        self.updates.push((old_key, None));
+        self.updates.push((key, Some(val)));
+        Ok(())
+    }
+
     fn reserve(&mut self, reservation: usize) {
         self.updates.reserve(reservation);
     }
@@ -544,7 +670,7 @@ mod test {
             })
             .unwrap();
 
-        let de_zset = DeZSetHandle::new(zset_input);
+        let de_zset = DeZSetHandle::with_weights(zset_input);
         let de_set = DeSetHandle::new(set_input);
         let de_map = DeMapHandle::new(map_input, |test_struct: &TestStruct| test_struct.id);
 
@@ -723,4 +849,33 @@ mod test {
 
         dbsp.kill().unwrap();
     }
+
+    #[test]
+    fn test_weighted_update() {
+        let (mut dbsp, mut input_handles, output_handles) = decollection_test_circuit(NUM_WORKERS);
+
+        let input = TestStruct {
+            id: 1,
+            s: "foo".to_string(),
+            b: true,
+            o: None,
+        };
+        let input_json = to_json_string(&input).unwrap();
+
+        // Each update is pushed as a single record, regardless of its weight.
+        for weight in [i64::MAX, 0, -3] {
+            let mut deserializer = JsonDeserializer::new(StrRead::new(&input_json));
+            let mut deserializer = <dyn ErasedDeserializer>::erase(&mut deserializer);
+            input_handles.0.update(&mut deserializer, weight).unwrap();
+        }
+        input_handles.0.flush();
+        dbsp.step().unwrap();
+
+        assert_eq!(
+            output_handles.0.consolidate(),
+            OrdZSet::from_tuples((), vec![(input, i64::MAX as isize - 3)])
+        );
+
+        dbsp.kill().unwrap();
+    }
 }
@@ -1,23 +1,124 @@
 use crate::{
-    format::{Encoder, InputFormat, OutputFormat, Parser},
+    format::{split_on_newline, Encoder, InputFormat, OutputFormat, ParseError, Parser},
     DeCollectionHandle, OutputConsumer, SerBatch,
 };
 use anyhow::{Error as AnyError, Result as AnyResult};
 use csv::{
-    byte_record_deserializer, Reader as CsvReader, ReaderBuilder as CsvReaderBuilder,
+    byte_record_deserializer, ByteRecord, ReaderBuilder as CsvReaderBuilder,
     WriterBuilder as CsvWriterBuilder,
 };
 use erased_serde::Deserializer as ErasedDeserializer;
 use serde::Deserialize;
 use serde_yaml::Value as YamlValue;
-use std::{borrow::Cow, io::Read, mem::take, sync::Arc};
+use std::{borrow::Cow, mem::take, sync::Arc};
 use utoipa::ToSchema;
 
 /// CSV format parser.
 pub struct CsvInputFormat;
 
-#[derive(Deserialize, ToSchema)]
-pub struct CsvParserConfig;
+const fn default_delimiter() -> char {
+    ','
+}
+
+const fn default_quote() -> char {
+    '"'
+}
+
+const fn default_double_quote() -> bool {
+    true
+}
+
+#[derive(Clone, Deserialize, ToSchema)]
+pub struct CsvParserConfig {
+    /// Field delimiter.  Must be an ASCII character.
+    ///
+    /// The default is `,`.
+    #[serde(default = "default_delimiter")]
+    delimiter: char,
+
+    /// Quote character.  Must be an ASCII character.
+    ///
+    /// The default is `"`.
+    #[serde(default = "default_quote")]
+    quote: char,
+
+    /// Escape character used to escape quotes inside quoted fields.
+    ///
+    /// When not specified, quotes are escaped by doubling them (see
+    /// `double_quote`).
+    #[serde(default)]
+    escape: Option<char>,
+
+    /// Interpret two consecutive quote characters inside a quoted field
+    /// as a single quote.
+    ///
+    /// The default is `true`.
+    #[serde(default = "default_double_quote")]
+    double_quote: bool,
+
+    /// Treat the first record in the input stream as a header and skip it.
+    ///
+    /// The default is `false`.
+    #[serde(default)]
+    headers: bool,
+
+    /// Ignore lines that start with this character.
+    ///
+    /// By default, comments are not supported.
+    #[serde(default)]
+    comment: Option<char>,
+
+    /// The last column of each record contains an integer weight.
+    ///
+    /// When `true`, each record is pushed to the input stream as a single
+    /// update with the specified weight: `w > 0` inserts `w` copies of the
+    /// record, `w < 0` deletes `-w` copies, and records with zero weight are
+    /// ignored.  This matches the output of the CSV encoder.  Weights other
+    /// than `1` and `-1` require a Z-set registered with
+    /// [`Catalog::register_input_weighted_zset_handle`](`crate::Catalog::register_input_weighted_zset_handle`).
+    /// When `false` (the default), every record is inserted into the input
+    /// stream.
+    #[serde(default)]
+    weight_column: bool,
+}
+
+impl CsvParserConfig {
+    /// Convert a configuration parameter to an ASCII byte.
+    fn ascii_char(param: &str, c: char) -> AnyResult<u8> {
+        if c.is_ascii() {
+            Ok(c as u8)
+        } else {
+            Err(AnyError::msg(format!(
+                "invalid CSV parser configuration: '{param}' must be an ASCII character, found '{c}'"
+            )))
+        }
+    }
+
+    /// Create a CSV reader builder for this configuration.
+    fn reader_builder(&self) -> AnyResult<CsvReaderBuilder> {
+        let mut builder = CsvReaderBuilder::new();
+
+        // We skip the header manually, since a new reader is created for
+        // every input buffer.
+        builder
+            .has_headers(false)
+            .delimiter(Self::ascii_char("delimiter", self.delimiter)?)
+            .quote(Self::ascii_char("quote", self.quote)?)
+            .escape(
+                self.escape
+                    .map(|c| Self::ascii_char("escape", c))
+                    .transpose()?,
+            )
+            .double_quote(self.double_quote)
+            .comment(
+                self.comment
+                    .map(|c| Self::ascii_char("comment", c))
+                    .transpose()?,
+            );
+
+        Ok(builder)
+    }
+}
 
 impl InputFormat for CsvInputFormat {
     fn name(&self) -> Cow<'static, str> {
@@ -27,9 +128,10 @@ impl InputFormat for CsvInputFormat {
     fn new_parser(
         &self,
         input_stream: &dyn DeCollectionHandle,
-        _config: &YamlValue,
+        config: &YamlValue,
     ) -> AnyResult<Box<dyn Parser>> {
-        Ok(Box::new(CsvParser::new(input_stream)) as Box<dyn Parser>)
+        let config = CsvParserConfig::deserialize(config)?;
+        Ok(Box::new(CsvParser::new(input_stream, config)?) as Box<dyn Parser>)
     }
 }
 
@@ -37,6 +139,8 @@ struct CsvParser {
     /// Input handle to push parsed data to.
     input_stream: Box<dyn DeCollectionHandle>,
 
+    config: CsvParserConfig,
+
     /// Since we cannot assume that the input buffer ends on line end,
     /// we save the "leftover" part of the buffer after the last new-line
     /// character and prepend it to the next input buffer.
@@ -45,83 +149,161 @@ struct CsvParser {
     /// Builder used to create a new CSV reader for each received data
     /// buffer.
     builder: CsvReaderBuilder,
+
+    /// `true` if the header record hasn't been received yet and must be
+    /// skipped.
+    skip_header: bool,
 }
 
 impl CsvParser {
-    fn new(input_stream: &dyn DeCollectionHandle) -> Self {
-        let mut builder = CsvReaderBuilder::new();
-        builder.has_headers(false);
+    fn new(input_stream: &dyn DeCollectionHandle, config: CsvParserConfig) -> AnyResult<Self> {
+        let builder = config.reader_builder()?;
 
-        Self {
+        Ok(Self {
             input_stream: input_stream.fork(),
+            skip_header: config.headers,
+            config,
             leftover: Vec::new(),
             builder,
-        }
+        })
     }
 
-    fn parse_from_reader<R>(
+    /// Parse a buffer that contains zero or more complete CSV records.
+    ///
+    /// Records that fail to parse are skipped and reported in the returned
+    /// vector of errors.
+    fn parse_from_slice(
         input_stream: &mut dyn DeCollectionHandle,
-        mut reader: CsvReader<R>,
-    ) -> AnyResult<usize>
-    where
-        R: Read,
-    {
+        builder: &CsvReaderBuilder,
+        skip_header: &mut bool,
+        weight_column: bool,
+        data: &[u8],
+    ) -> (usize, Vec<ParseError>) {
+        let mut reader = builder.from_reader(data);
+        // Offset in `data` where `reader` starts.
+        let mut base = 0;
+        let mut record = ByteRecord::new();
         let mut num_records = 0;
+        let mut errors = Vec::new();
+
+        loop {
+            let start = base + reader.position().byte() as usize;
+            let res = reader.read_byte_record(&mut record);
+            let end = base + reader.position().byte() as usize;
+
+            let res = match res {
+                Ok(false) => break,
+                Ok(true) if *skip_header => {
+                    *skip_header = false;
+                    continue;
+                }
+                Ok(true) => Self::parse_record(input_stream, weight_column, &mut record),
+                // The reader failed without consuming any input.  Report the
+                // error for the rest of the current line and resume parsing
+                // from the next line with a new reader.
+                Err(e) if end == start => {
+                    let line_end = data[start..]
+                        .iter()
+                        .position(|&c| c == b'\n')
+                        .map_or(data.len(), |pos| start + pos);
+                    errors.push(ParseError::new(&data[start..line_end], AnyError::from(e)));
+
+                    base = (line_end + 1).min(data.len());
+                    if base == data.len() {
+                        break;
+                    }
+                    reader = builder.from_reader(&data[base..]);
+                    continue;
+                }
+                Err(e) => Err(AnyError::from(e)),
+            };
 
-        for record in reader.byte_records() {
-            let record = record?;
-
-            let mut deserializer = byte_record_deserializer(&record, None);
-            let mut deserializer = <dyn ErasedDeserializer>::erase(&mut deserializer);
-            input_stream.insert(&mut deserializer).map_err(|e| {
-                AnyError::msg(format!(
-                    "failed to deserialize csv record '{record:?}': {e}"
-                ))
-            })?;
-            num_records += 1;
+            match res {
+                Ok(n) => num_records += n,
+                Err(e) => errors.push(ParseError::new(&data[start..end], e)),
+            }
         }
 
-        Ok(num_records)
+        (num_records, errors)
     }
 
-    /// Returns the index of the first character following the last newline
-    /// in `data`.
-    fn split_on_newline(data: &[u8]) -> usize {
-        let data_len = data.len();
-        let index = data
-            .iter()
-            .rev()
-            .position(|&x| x == b'\n')
-            .unwrap_or(data_len);
+    /// Push a single CSV record to the input stream.
+    ///
+    /// Returns the number of updates pushed to the stream: 0 for records with
+    /// zero weight, 1 otherwise.
+    fn parse_record(
+        input_stream: &mut dyn DeCollectionHandle,
+        weight_column: bool,
+        record: &mut ByteRecord,
+    ) -> AnyResult<usize> {
+        let weight = if weight_column {
+            let weight = Self::parse_weight(record)?;
+            record.truncate(record.len() - 1);
+            weight
+        } else {
+            1
+        };
+
+        if weight == 0 {
+            return Ok(0);
+        }
 
-        data_len - index
+        let mut deserializer = byte_record_deserializer(record, None);
+        let mut deserializer = <dyn ErasedDeserializer>::erase(&mut deserializer);
+        input_stream.update(&mut deserializer, weight)?;
+
+        Ok(1)
+    }
+
+    /// Parse the weight stored in the last column of `record`.
+    fn parse_weight(record: &ByteRecord) -> AnyResult<i64> {
+        let field = record
+            .len()
+            .checked_sub(1)
+            .and_then(|index| record.get(index))
+            .ok_or_else(|| AnyError::msg("missing weight column"))?;
+
+        std::str::from_utf8(field)
+            .ok()
+            .and_then(|field| field.trim().parse::<i64>().ok())
+            .ok_or_else(|| {
+                AnyError::msg(format!(
+                    "invalid weight '{}'",
+                    String::from_utf8_lossy(field)
+                ))
+            })
     }
 }
 
 impl Parser for CsvParser {
-    fn input(&mut self, data: &[u8]) -> AnyResult<usize> {
-        // println!("input {} bytes:\n{}\nself.leftover:\n{}", data.len(),
-        //    std::str::from_utf8(data).map(|s| s.to_string()).unwrap_or_else(|e|
-        // format!("invalid csv: {e}")),    std::str::from_utf8(&self.leftover).
-        // map(|s| s.to_string()).unwrap_or_else(|e| format!("invalid csv: {e}")));
-
-        let leftover = Self::split_on_newline(data);
-
-        // println!("leftover: {leftover}");
+    fn input(&mut self, data: &[u8]) -> (usize, Vec<ParseError>) {
+        let leftover = split_on_newline(data);
 
         if leftover == 0 {
             // `data` doesn't contain a new-line character; append it to
             // the `leftover` buffer so it gets processed with the next input
             // buffer.
             self.leftover.extend_from_slice(data);
-            Ok(0)
+            (0, Vec::new())
         } else {
-            let reader = self
-                .builder
-                .from_reader(Read::chain(&*self.leftover, &data[0..leftover]));
-
-            let res = Self::parse_from_reader(&mut *self.input_stream, reader);
-            // println!("parse returned: {res:?}");
+            let res = if self.leftover.is_empty() {
+                Self::parse_from_slice(
+                    &mut *self.input_stream,
+                    &self.builder,
+                    &mut self.skip_header,
+                    self.config.weight_column,
+                    &data[0..leftover],
+                )
+            } else {
+                self.leftover.extend_from_slice(&data[0..leftover]);
+                Self::parse_from_slice(
+                    &mut *self.input_stream,
+                    &self.builder,
+                    &mut self.skip_header,
+                    self.config.weight_column,
+                    &self.leftover,
+                )
+            };
 
             self.leftover.clear();
             self.leftover.extend_from_slice(&data[leftover..]);
@@ -130,15 +312,17 @@ impl Parser for CsvParser {
         }
     }
 
-    fn eoi(&mut self) -> AnyResult<usize> {
-        if self.leftover.is_empty() {
-            return Ok(0);
-        }
-
+    fn eoi(&mut self) -> (usize, Vec<ParseError>) {
         // Try to interpret the leftover chunk as a complete CSV line.
-        let reader = self.builder.from_reader(&*self.leftover);
-
-        Self::parse_from_reader(&mut *self.input_stream, reader)
+        let leftover = take(&mut self.leftover);
+
+        Self::parse_from_slice(
+            &mut *self.input_stream,
+            &self.builder,
+            &mut self.skip_header,
+            self.config.weight_column,
+            &leftover,
+        )
     }
 
     fn flush(&mut self) {
@@ -149,8 +333,20 @@ impl Parser for CsvParser {
         self.input_stream.clear_buffer();
     }
 
-    fn fork(&self) -> Box<dyn Parser> {
-        Box::new(Self::new(&*self.input_stream))
+    fn unparsed_bytes(&self) -> usize {
+        self.leftover.len()
+    }
+
+    fn resume(&mut self) {
+        // The header line was consumed before the checkpoint.
+        self.skip_header = false;
+    }
+
+    fn fork(&self) -> AnyResult<Box<dyn Parser>> {
+        Ok(Box::new(Self::new(
+            &*self.input_stream,
+            self.config.clone(),
+        )?))
     }
 }
 
@@ -211,6 +407,10 @@ impl CsvEncoder {
 }
 
 impl Encoder for CsvEncoder {
+    fn consumer(&mut self) -> &mut dyn OutputConsumer {
+        self.output_consumer.as_mut()
+    }
+
     fn encode(&mut self, batches: &[Arc<dyn SerBatch>]) -> AnyResult<()> {
         let buffer = take(&mut self.buffer);
         let mut writer = self.builder.from_writer(buffer);
@@ -249,3 +449,103 @@ impl Encoder for CsvEncoder {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        format::InputFormat,
+        test::{MockDeZSet, TestStruct},
+    };
+
+    fn test_data() -> Vec<TestStruct> {
+        vec![
+            TestStruct {
+                id: 1,
+                b: true,
+                i: None,
+                s: "foo".to_string(),
+            },
+            TestStruct {
+                id: 2,
+                b: false,
+                i: Some(-10),
+                s: "bar;baz".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_csv_parser_config() {
+        let zset = MockDeZSet::<TestStruct>::new();
+        let config = serde_yaml::from_str(
+            r##"
+delimiter: ";"
+quote: "'"
+headers: true
+comment: "#"
+weight_column: true
+"##,
+        )
+        .unwrap();
+        let mut parser = <dyn InputFormat>::get_format("csv")
+            .unwrap()
+            .new_parser(&zset, &config)
+            .unwrap();
+
+        let input = b"id;b;i;s;weight
+# comment
+1;true;;foo;2
+2;false;-10;'bar;baz';-1
+# another comment
+1;true;;foo;0
+";
+
+        // Feed the input in small chunks to make sure that the header is
+        // only skipped once.
+        let mut num_records = 0;
+        for chunk in input.chunks(5) {
+            let (n, errors) = parser.input(chunk);
+            assert!(errors.is_empty());
+            num_records += n;
+        }
+        let (n, errors) = parser.eoi();
+        assert!(errors.is_empty());
+        num_records += n;
+        parser.flush();
+
+        assert_eq!(num_records, 2);
+
+        let data = test_data();
+        assert_eq!(
+            zset.state().flushed,
+            vec![
+                (data[0].clone(), true),
+                (data[0].clone(), true),
+                (data[1].clone(), false)
+            ]
+        );
+
+        // Invalid records are skipped without affecting valid records in the
+        // same buffer.
+        zset.reset();
+        let (num_records, errors) =
+            parser.input(b"1;true;;foo;x\n1;true;;foo;1\nfoo;true;;foo;1\n");
+        parser.flush();
+
+        assert_eq!(num_records, 1);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].record, "1;true;;foo;x");
+        assert_eq!(errors[1].record, "foo;true;;foo;1");
+        assert_eq!(zset.state().flushed, vec![(data[0].clone(), true)]);
+    }
+
+    #[test]
+    fn test_csv_parser_invalid_config() {
+        let zset = MockDeZSet::<TestStruct>::new();
+        let config = serde_yaml::from_str("delimiter: \"é\"").unwrap();
+        assert!(<dyn InputFormat>::get_format("csv")
+            .unwrap()
+            .new_parser(&zset, &config)
+            .is_err());
+    }
+}
@@ -0,0 +1,630 @@
+use crate::{
+    format::{split_on_newline, Encoder, InputFormat, OutputFormat, ParseError, Parser},
+    DeCollectionHandle, OutputConsumer, SerBatch,
+};
+use anyhow::{Error as AnyError, Result as AnyResult};
+use erased_serde::{Deserializer as ErasedDeserializer, Serialize as ErasedSerialize};
+use serde::{Deserialize, Serialize};
+use serde_json::{value::RawValue, Deserializer as JsonDeserializer};
+use serde_yaml::Value as YamlValue;
+use std::{borrow::Cow, mem::take, sync::Arc};
+use utoipa::ToSchema;
+
+/// JSON format parser.
+pub struct JsonInputFormat;
+
+/// Representation of updates in a JSON stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonUpdateFormat {
+    /// Each line contains a single record wrapped in an insert or delete
+    /// envelope: `{"insert": {...}}` or `{"delete": {...}}`.  The envelope
+    /// may specify the number of copies of the record to insert or delete,
+    /// e.g., `{"insert": {...}, "weight": 3}`; the default is 1.  Weights
+    /// other than 1 require a Z-set registered with
+    /// [`Catalog::register_input_weighted_zset_handle`](`crate::Catalog::register_input_weighted_zset_handle`).
+    #[default]
+    InsertDelete,
+
+    /// Each line contains a single record, which is inserted into the
+    /// input stream.  Deletions cannot be expressed in this format.
+    Raw,
+
+    /// Each line contains a Debezium change event, e.g.,
+    /// `{"before": {...}, "after": {...}, "op": "u"}`, optionally wrapped in
+    /// a `{"schema": ..., "payload": {...}}` envelope.
+    ///
+    /// Create (`c`) and snapshot read (`r`) events insert the `after` record.
+    /// Delete (`d`) events delete the `before` record.  Update (`u`) events
+    /// delete the `before` record and insert the `after` record.  Deletes and
+    /// updates therefore require the source table to log complete old rows
+    /// (e.g., `REPLICA IDENTITY FULL` in Postgres).
+    Debezium,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct JsonParserConfig {
+    /// Representation of updates in the input stream.
+    ///
+    /// The default is `insert_delete`.
+    #[serde(default)]
+    update_format: JsonUpdateFormat,
+}
+
+impl InputFormat for JsonInputFormat {
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed("json")
+    }
+
+    fn new_parser(
+        &self,
+        input_stream: &dyn DeCollectionHandle,
+        config: &YamlValue,
+    ) -> AnyResult<Box<dyn Parser>> {
+        let config = JsonParserConfig::deserialize(config)?;
+        Ok(Box::new(JsonParser::new(input_stream, config.update_format)) as Box<dyn Parser>)
+    }
+}
+
+/// Insert/delete envelope used by the `insert_delete` update format.
+///
+/// Exactly one of `insert` and `delete` must be present.
+///
+/// The parser instantiates this type with `&RawValue` to extract the
+/// serialized record without knowing its type; the encoder instantiates it
+/// with `&dyn ErasedSerialize`.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InsDelUpdate<T> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    insert: Option<T>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delete: Option<T>,
+    /// Number of copies of the record to insert or delete.
+    #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")]
+    weight: u64,
+}
+
+/// Default value of `InsDelUpdate::weight`.
+const fn default_weight() -> u64 {
+    1
+}
+
+fn is_default_weight(weight: &u64) -> bool {
+    *weight == default_weight()
+}
+
+/// Debezium change event operation.
+#[derive(Clone, Copy, Debug, Deserialize)]
+enum DebeziumOp {
+    #[serde(rename = "c")]
+    Create,
+    #[serde(rename = "r")]
+    Read,
+    #[serde(rename = "u")]
+    Update,
+    #[serde(rename = "d")]
+    Delete,
+}
+
+/// Debezium change event.
+///
+/// Debezium may or may not wrap events in a `payload` envelope, depending on
+/// whether schemas are enabled in the converter; we accept both.  Other
+/// fields of the event (`source`, `ts_ms`, etc.) are ignored.
+#[derive(Deserialize)]
+struct DebeziumUpdate<'a> {
+    #[serde(borrow, default)]
+    payload: Option<Box<DebeziumUpdate<'a>>>,
+    #[serde(borrow, default)]
+    before: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    after: Option<&'a RawValue>,
+    #[serde(default)]
+    op: Option<DebeziumOp>,
+}
+
+impl<'a> DebeziumUpdate<'a> {
+    /// Unwrap the `payload` envelope if present.
+    fn unwrap_payload(self) -> Self {
+        match self.payload {
+            Some(payload) => payload.unwrap_payload(),
+            None => self,
+        }
+    }
+}
+
+/// Parser for newline-delimited JSON streams.
+struct JsonParser {
+    /// Input handle to push parsed data to.
+    input_stream: Box<dyn DeCollectionHandle>,
+
+    update_format: JsonUpdateFormat,
+
+    /// Since we cannot assume that the input buffer ends on line end,
+    /// we save the "leftover" part of the buffer after the last new-line
+    /// character and prepend it to the next input buffer.
+    leftover: Vec<u8>,
+}
+
+impl JsonParser {
+    fn new(input_stream: &dyn DeCollectionHandle, update_format: JsonUpdateFormat) -> Self {
+        Self {
+            input_stream: input_stream.fork(),
+            update_format,
+            leftover: Vec::new(),
+        }
+    }
+
+    /// Parse a buffer that contains zero or more complete lines, one record
+    /// per line.  Empty lines are ignored.
+    ///
+    /// Records that fail to parse are skipped and reported in the returned
+    /// vector of errors.
+    fn parse_lines(
+        input_stream: &mut dyn DeCollectionHandle,
+        update_format: JsonUpdateFormat,
+        data: &[u8],
+    ) -> (usize, Vec<ParseError>) {
+        let mut num_records = 0;
+        let mut errors = Vec::new();
+
+        for line in data.split(|&c| c == b'\n') {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            match Self::parse_record(input_stream, update_format, line) {
+                Ok(()) => num_records += 1,
+                Err(e) => errors.push(ParseError::new(line, e)),
+            }
+        }
+
+        (num_records, errors)
+    }
+
+    fn parse_record(
+        input_stream: &mut dyn DeCollectionHandle,
+        update_format: JsonUpdateFormat,
+        record: &[u8],
+    ) -> AnyResult<()> {
+        match update_format {
+            JsonUpdateFormat::InsertDelete => {
+                let update = serde_json::from_slice::<InsDelUpdate<&RawValue>>(record)?;
+                let weight = i64::try_from(update.weight).map_err(|_| {
+                    AnyError::msg(format!("weight {} is out of range", update.weight))
+                })?;
+                let (val, weight) = match (update.insert, update.delete) {
+                    (Some(val), None) => (val, weight),
+                    (None, Some(val)) => (val, -weight),
+                    _ => {
+                        return Err(AnyError::msg(
+                            "update must contain exactly one of 'insert' and 'delete' fields",
+                        ))
+                    }
+                };
+
+                let mut deserializer = JsonDeserializer::from_str(val.get());
+                let mut deserializer = <dyn ErasedDeserializer>::erase(&mut deserializer);
+                input_stream.update(&mut deserializer, weight)?;
+            }
+            JsonUpdateFormat::Raw => {
+                // Reject records with trailing garbage before pushing anything
+                // to the input stream.
+                let record = serde_json::from_slice::<&RawValue>(record)?;
+                let mut deserializer = JsonDeserializer::from_str(record.get());
+                input_stream.insert(&mut <dyn ErasedDeserializer>::erase(&mut deserializer))?;
+            }
+            JsonUpdateFormat::Debezium => {
+                let update = serde_json::from_slice::<DebeziumUpdate>(record)?.unwrap_payload();
+                Self::parse_debezium_update(input_stream, update)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_debezium_update(
+        input_stream: &mut dyn DeCollectionHandle,
+        update: DebeziumUpdate,
+    ) -> AnyResult<()> {
+        let op = update
+            .op
+            .ok_or_else(|| AnyError::msg("Debezium event is missing the 'op' field"))?;
+        let before = || {
+            update.before.ok_or_else(|| {
+                AnyError::msg(format!(
+                    "Debezium '{op:?}' event is missing the 'before' record"
+                ))
+            })
+        };
+        let after = || {
+            update.after.ok_or_else(|| {
+                AnyError::msg(format!(
+                    "Debezium '{op:?}' event is missing the 'after' record"
+                ))
+            })
+        };
+
+        match op {
+            DebeziumOp::Create | DebeziumOp::Read => {
+                let mut deserializer = JsonDeserializer::from_str(after()?.get());
+                input_stream.insert(&mut <dyn ErasedDeserializer>::erase(&mut deserializer))?;
+            }
+            DebeziumOp::Update => {
+                // Push both records at once, so that an invalid `after`
+                // record doesn't leave a half-applied update behind.
+                let mut before = JsonDeserializer::from_str(before()?.get());
+                let mut after = JsonDeserializer::from_str(after()?.get());
+                input_stream.replace(
+                    &mut <dyn ErasedDeserializer>::erase(&mut before),
+                    &mut <dyn ErasedDeserializer>::erase(&mut after),
+                )?;
+            }
+            DebeziumOp::Delete => {
+                let mut deserializer = JsonDeserializer::from_str(before()?.get());
+                input_stream.delete(&mut <dyn ErasedDeserializer>::erase(&mut deserializer))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Parser for JsonParser {
+    fn input(&mut self, data: &[u8]) -> (usize, Vec<ParseError>) {
+        let leftover = split_on_newline(data);
+
+        if leftover == 0 {
+            // `data` doesn't contain a new-line character; append it to
+            // the `leftover` buffer so it gets processed with the next input
+            // buffer.
+            self.leftover.extend_from_slice(data);
+            (0, Vec::new())
+        } else {
+            let res = if self.leftover.is_empty() {
+                Self::parse_lines(
+                    &mut *self.input_stream,
+                    self.update_format,
+                    &data[0..leftover],
+                )
+            } else {
+                self.leftover.extend_from_slice(&data[0..leftover]);
+                Self::parse_lines(&mut *self.input_stream, self.update_format, &self.leftover)
+            };
+
+            self.leftover.clear();
+            self.leftover.extend_from_slice(&data[leftover..]);
+
+            res
+        }
+    }
+
+    fn eoi(&mut self) -> (usize, Vec<ParseError>) {
+        // Try to interpret the leftover chunk as a complete JSON record.
+        let leftover = take(&mut self.leftover);
+        Self::parse_lines(&mut *self.input_stream, self.update_format, &leftover)
+    }
+
+    fn flush(&mut self) {
+        self.input_stream.flush();
+    }
+
+    fn clear(&mut self) {
+        self.input_stream.clear_buffer();
+    }
+
+    fn unparsed_bytes(&self) -> usize {
+        self.leftover.len()
+    }
+
+    fn fork(&self) -> AnyResult<Box<dyn Parser>> {
+        Ok(Box::new(Self::new(&*self.input_stream, self.update_format)))
+    }
+}
+
+/// JSON format encoder.
+pub struct JsonOutputFormat;
+
+const fn default_buffer_size_records() -> usize {
+    10_000
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct JsonEncoderConfig {
+    #[serde(default = "default_buffer_size_records")]
+    buffer_size_records: usize,
+}
+
+impl OutputFormat for JsonOutputFormat {
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed("json")
+    }
+
+    fn new_encoder(
+        &self,
+        config: &YamlValue,
+        consumer: Box<dyn OutputConsumer>,
+    ) -> AnyResult<Box<dyn Encoder>> {
+        let config = JsonEncoderConfig::deserialize(config)?;
+
+        Ok(Box::new(JsonEncoder::new(consumer, config)))
+    }
+}
+
+/// Encoder that outputs newline-delimited JSON records in the
+/// `insert_delete` format.
+///
+/// A record with weight `w` is encoded as a single insert (if `w > 0`) or
+/// delete (if `w < 0`) update with weight `|w|`, so that the output can be fed
+/// back to a JSON parser.
+struct JsonEncoder {
+    /// Input handle to push serialized data to.
+    output_consumer: Box<dyn OutputConsumer>,
+
+    config: JsonEncoderConfig,
+
+    buffer: Vec<u8>,
+}
+
+impl JsonEncoder {
+    fn new(output_consumer: Box<dyn OutputConsumer>, config: JsonEncoderConfig) -> Self {
+        Self {
+            output_consumer,
+            config,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl Encoder for JsonEncoder {
+    fn consumer(&mut self) -> &mut dyn OutputConsumer {
+        self.output_consumer.as_mut()
+    }
+
+    fn encode(&mut self, batches: &[Arc<dyn SerBatch>]) -> AnyResult<()> {
+        let mut buffer = take(&mut self.buffer);
+        let mut num_records = 0;
+
+        for batch in batches.iter() {
+            let mut cursor = batch.cursor();
+
+            while cursor.key_valid() {
+                let w = cursor.weight();
+                let update: InsDelUpdate<&dyn ErasedSerialize> = InsDelUpdate {
+                    insert: (w > 0).then(|| cursor.key()),
+                    delete: (w < 0).then(|| cursor.key()),
+                    weight: w.unsigned_abs(),
+                };
+
+                serde_json::to_writer(&mut buffer, &update)?;
+                buffer.push(b'\n');
+                num_records += 1;
+
+                if num_records >= self.config.buffer_size_records {
+                    self.output_consumer.push_buffer(&buffer);
+                    buffer.clear();
+                    num_records = 0;
+                }
+
+                cursor.step_key();
+            }
+        }
+
+        if num_records > 0 {
+            self.output_consumer.push_buffer(&buffer);
+            buffer.clear();
+        }
+
+        self.buffer = buffer;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        format::{InputFormat, OutputFormat},
+        seroutput::SerBatchImpl,
+        test::{MockDeZSet, TestStruct},
+        OutputConsumer, SerBatch,
+    };
+    use dbsp::{trace::Batch, OrdZSet};
+    use serde_yaml::Value as YamlValue;
+    use std::sync::{Arc, Mutex};
+
+    struct MockOutputConsumer(Arc<Mutex<Vec<u8>>>);
+
+    impl OutputConsumer for MockOutputConsumer {
+        fn push_buffer(&mut self, buffer: &[u8]) {
+            self.0.lock().unwrap().extend_from_slice(buffer);
+        }
+    }
+
+    fn test_data() -> Vec<TestStruct> {
+        vec![
+            TestStruct {
+                id: 1,
+                b: true,
+                i: None,
+                s: "foo".to_string(),
+            },
+            TestStruct {
+                id: 2,
+                b: false,
+                i: Some(-10),
+                s: "bar\nbaz".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_json_parser() {
+        let zset = MockDeZSet::<TestStruct>::new();
+        let mut parser = <dyn InputFormat>::get_format("json")
+            .unwrap()
+            .new_parser(&zset, &YamlValue::Null)
+            .unwrap();
+
+        let input = br#"{"insert": {"id": 1, "b": true, "i": null, "s": "foo"}}
+
+{"delete": {"id": 2, "b": false, "i": -10, "s": "bar\nbaz"}}
+{"insert": {"id": 1, "b": true, "i": null, "s": "foo"}}"#;
+
+        // Feed the input in small chunks to exercise the leftover logic.
+        let mut num_records = 0;
+        for chunk in input.chunks(7) {
+            let (n, errors) = parser.input(chunk);
+            assert!(errors.is_empty());
+            num_records += n;
+        }
+        let (n, errors) = parser.eoi();
+        assert!(errors.is_empty());
+        num_records += n;
+        parser.flush();
+
+        assert_eq!(num_records, 3);
+
+        let data = test_data();
+        assert_eq!(
+            zset.state().flushed,
+            vec![
+                (data[0].clone(), true),
+                (data[1].clone(), false),
+                (data[0].clone(), true)
+            ]
+        );
+
+        // Invalid records are skipped without affecting valid records in the
+        // same buffer.
+        zset.reset();
+        let (num_records, errors) = parser.input(
+            br#"{"update": 5}
+{"insert": {"id": 1, "b": true, "i": null, "s": "foo"}}
+{"insert": {"id": 1}}
+"#,
+        );
+        parser.flush();
+
+        assert_eq!(num_records, 1);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].record, r#"{"update": 5}"#);
+        assert_eq!(errors[1].record, r#"{"insert": {"id": 1}}"#);
+        assert_eq!(zset.state().flushed, vec![(data[0].clone(), true)]);
+    }
+
+    #[test]
+    fn test_json_raw_parser() {
+        let zset = MockDeZSet::<TestStruct>::new();
+        let mut parser = <dyn InputFormat>::get_format("json")
+            .unwrap()
+            .new_parser(&zset, &serde_yaml::from_str("update_format: raw").unwrap())
+            .unwrap();
+
+        let input = b"{\"id\": 1, \"b\": true, \"i\": null, \"s\": \"foo\"}\n";
+        assert_eq!(parser.input(input), (1, Vec::new()));
+        parser.flush();
+
+        assert_eq!(zset.state().flushed, vec![(test_data()[0].clone(), true)]);
+
+        // A record followed by junk is rejected as a whole.
+        zset.reset();
+        let (num_records, errors) =
+            parser.input(b"{\"id\": 1, \"b\": true, \"i\": null, \"s\": \"foo\"} junk\n");
+        parser.flush();
+
+        assert_eq!(num_records, 0);
+        assert_eq!(errors.len(), 1);
+        assert!(zset.state().flushed.is_empty());
+    }
+
+    #[test]
+    fn test_json_debezium_parser() {
+        let zset = MockDeZSet::<TestStruct>::new();
+        let mut parser = <dyn InputFormat>::get_format("json")
+            .unwrap()
+            .new_parser(
+                &zset,
+                &serde_yaml::from_str("update_format: debezium").unwrap(),
+            )
+            .unwrap();
+
+        let input = br#"{"before": null, "after": {"id": 1, "b": true, "i": null, "s": "foo"}, "op": "c", "ts_ms": 1}
+{"schema": {}, "payload": {"before": {"id": 1, "b": true, "i": null, "s": "foo"}, "after": {"id": 2, "b": false, "i": -10, "s": "bar\nbaz"}, "op": "u"}}
+{"before": {"id": 2, "b": false, "i": -10, "s": "bar\nbaz"}, "after": null, "op": "d"}
+{"after": {"id": 1, "b": true, "i": null, "s": "foo"}, "op": "r"}
+"#;
+        assert_eq!(parser.input(input), (4, Vec::new()));
+        parser.flush();
+
+        let data = test_data();
+        assert_eq!(
+            zset.state().flushed,
+            vec![
+                (data[0].clone(), true),
+                (data[0].clone(), false),
+                (data[1].clone(), true),
+                (data[1].clone(), false),
+                (data[0].clone(), true),
+            ]
+        );
+
+        // Events that lack records required by their operation are rejected.
+        zset.reset();
+        let (num_records, errors) = parser.input(
+            br#"{"before": null, "after": {"id": 1, "b": true, "i": null, "s": "foo"}, "op": "u"}
+{"after": {"id": 1, "b": true, "i": null, "s": "foo"}}
+{"before": {"id": 1, "b": true, "i": null, "s": "foo"}, "op": "t"}
+{"before": {"id": 1, "b": true, "i": null, "s": "foo"}, "after": {"id": "one"}, "op": "u"}
+"#,
+        );
+        parser.flush();
+
+        // An update with an invalid `after` record doesn't delete the
+        // `before` record either.
+        assert_eq!(num_records, 0);
+        assert_eq!(errors.len(), 4);
+        assert!(zset.state().flushed.is_empty());
+    }
+
+    #[test]
+    fn test_json_encoder() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut encoder = <dyn OutputFormat>::get_format("json")
+            .unwrap()
+            .new_encoder(
+                &YamlValue::Null,
+                Box::new(MockOutputConsumer(output.clone())),
+            )
+            .unwrap();
+
+        let data = test_data();
+        let batch = OrdZSet::from_keys((), vec![(data[0].clone(), 2), (data[1].clone(), -1)]);
+        let batch = Arc::new(SerBatchImpl::new(batch)) as Arc<dyn SerBatch>;
+        encoder.encode(&[batch]).unwrap();
+
+        // Each record is encoded as a single update that carries its weight.
+        let encoded = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert_eq!(encoded.lines().count(), 2);
+        assert!(encoded.contains(r#""weight":2"#));
+
+        // Feed the encoded data back to the parser.
+        let zset = MockDeZSet::<TestStruct>::new();
+        let mut parser = <dyn InputFormat>::get_format("json")
+            .unwrap()
+            .new_parser(&zset, &YamlValue::Null)
+            .unwrap();
+        assert_eq!(parser.input(&output.lock().unwrap()).0, 2);
+        parser.flush();
+
+        assert_eq!(
+            zset.state().flushed,
+            vec![
+                (data[0].clone(), true),
+                (data[0].clone(), true),
+                (data[1].clone(), false)
+            ]
+        );
+    }
+}
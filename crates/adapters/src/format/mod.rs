@@ -1,23 +1,56 @@
-use crate::{DeCollectionHandle, SerBatch};
-use anyhow::Result as AnyResult;
+use crate::{DeCollectionHandle, SerBatch, Step};
+use anyhow::{Error as AnyError, Result as AnyResult};
 use once_cell::sync::Lazy;
 use serde_yaml::Value as YamlValue;
-use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    error::Error as StdError,
+    fmt::{Display, Error as FmtError, Formatter},
+    sync::Arc,
+};
 
 mod csv;
+mod json;
 
 pub use self::csv::{CsvEncoderConfig, CsvParserConfig};
 use self::csv::{CsvInputFormat, CsvOutputFormat};
+pub use self::json::{JsonEncoderConfig, JsonParserConfig, JsonUpdateFormat};
+use self::json::{JsonInputFormat, JsonOutputFormat};
 
 /// Static map of supported input formats.
 // TODO: support for registering new formats at runtime in order to allow
 // external crates to implement new formats.
-static INPUT_FORMATS: Lazy<BTreeMap<&'static str, Box<dyn InputFormat>>> =
-    Lazy::new(|| BTreeMap::from([("csv", Box::new(CsvInputFormat) as Box<dyn InputFormat>)]));
+static INPUT_FORMATS: Lazy<BTreeMap<&'static str, Box<dyn InputFormat>>> = Lazy::new(|| {
+    BTreeMap::from([
+        ("csv", Box::new(CsvInputFormat) as Box<dyn InputFormat>),
+        ("json", Box::new(JsonInputFormat) as Box<dyn InputFormat>),
+    ])
+});
 
 /// Static map of supported output formats.
-static OUTPUT_FORMATS: Lazy<BTreeMap<&'static str, Box<dyn OutputFormat>>> =
-    Lazy::new(|| BTreeMap::from([("csv", Box::new(CsvOutputFormat) as Box<dyn OutputFormat>)]));
+static OUTPUT_FORMATS: Lazy<BTreeMap<&'static str, Box<dyn OutputFormat>>> = Lazy::new(|| {
+    BTreeMap::from([
+        ("csv", Box::new(CsvOutputFormat) as Box<dyn OutputFormat>),
+        ("json", Box::new(JsonOutputFormat) as Box<dyn OutputFormat>),
+    ])
+});
+
+/// Returns the index of the first character following the last newline
+/// in `data`.
+///
+/// Used by line-oriented parsers to split the input buffer into a prefix
+/// that consists of complete lines and an incomplete trailing line.
+pub(crate) fn split_on_newline(data: &[u8]) -> usize {
+    let data_len = data.len();
+    let index = data
+        .iter()
+        .rev()
+        .position(|&x| x == b'\n')
+        .unwrap_or(data_len);
+
+    data_len - index
+}
 
 /// Trait that represents a specific data format.
 ///
@@ -47,6 +80,40 @@ impl dyn InputFormat {
     }
 }
 
+/// Error parsing an individual input record.
+///
+/// Parsers report errors on a per-record basis: an invalid record gets
+/// skipped, while the parser continues processing subsequent records.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Raw contents of the rejected record.
+    pub record: String,
+
+    /// Error description.
+    pub description: String,
+}
+
+impl ParseError {
+    pub fn new(record: &[u8], error: AnyError) -> Self {
+        Self {
+            record: String::from_utf8_lossy(record).trim_end().to_string(),
+            description: error.to_string(),
+        }
+    }
+}
+
+impl StdError for ParseError {}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(
+            f,
+            "failed to parse record '{}': {}",
+            self.record, self.description
+        )
+    }
+}
+
 /// Parser that converts a raw byte stream into a stream of database records.
 pub trait Parser: Send {
     /// Push a chunk of data to the parser.
@@ -59,18 +126,21 @@ pub trait Parser: Send {
     /// that cannot be fully parsed until more data or an end-of-file
     /// notification is received.
     ///
-    /// Returns the number of records in the parsed representation or an error
-    /// if parsing fails.
-    fn input(&mut self, data: &[u8]) -> AnyResult<usize>;
+    /// A record that fails to parse does not affect other records in `data`:
+    /// the parser skips the invalid record and continues parsing.
+    ///
+    /// Returns the number of records in the parsed representation and a list
+    /// of errors for records that failed to parse.
+    fn input(&mut self, data: &[u8]) -> (usize, Vec<ParseError>);
 
     /// End-of-input-stream notification.
     ///
     /// No more data will be received from the stream.  The parser uses this
     /// notification to complete or discard any incompletely parsed records.
     ///
-    /// Returns the number of additional records pushed to the circuit or an
-    /// error if parsing fails.
-    fn eoi(&mut self) -> AnyResult<usize>;
+    /// Returns the number of additional records pushed to the circuit and a
+    /// list of errors for records that failed to parse.
+    fn eoi(&mut self) -> (usize, Vec<ParseError>);
 
     /// Flush input handles.
     ///
@@ -86,11 +156,30 @@ pub trait Parser: Send {
     /// on all input handles modified by this parser.
     fn clear(&mut self);
 
+    /// The number of bytes received by the parser but not yet parsed.
+    ///
+    /// These bytes belong to an incomplete record that will be parsed once
+    /// more data or an end-of-input notification is received.  The controller
+    /// uses this to compute the offset of the last fully processed record
+    /// in the input stream when checkpointing the pipeline.
+    fn unparsed_bytes(&self) -> usize;
+
+    /// Notify the parser that the input stream is being resumed from the
+    /// middle, e.g., when restoring the pipeline from a checkpoint.
+    ///
+    /// Invoked before any data is pushed to the parser.  The parser must not
+    /// expect any stream-level preamble, such as a header line, in the
+    /// resumed stream.
+    fn resume(&mut self) {}
+
     /// Create a new parser with the same configuration as `self`.
     ///
     /// Used by multithreaded transport endpoints to create multiple parallel
     /// input pipelines.
-    fn fork(&self) -> Box<dyn Parser>;
+    ///
+    /// Fails if the parser cannot be instantiated, e.g., because it failed to
+    /// allocate resources.
+    fn fork(&self) -> AnyResult<Box<dyn Parser>>;
 }
 
 pub trait OutputFormat: Send + Sync {
@@ -119,9 +208,24 @@ impl dyn OutputFormat {
 }
 
 pub trait Encoder: Send {
+    /// The consumer that this encoder sends encoded data to.
+    fn consumer(&mut self) -> &mut dyn OutputConsumer;
+
     fn encode(&mut self, batches: &[Arc<dyn SerBatch>]) -> AnyResult<()>;
 }
 
 pub trait OutputConsumer: Send {
     fn push_buffer(&mut self, buffer: &[u8]);
+
+    /// Start of the output of a circuit step (see
+    /// [`OutputEndpoint::batch_start`](`crate::OutputEndpoint::batch_start`)).
+    fn batch_start(&mut self, _step: Option<Step>) {}
+
+    /// End of the output of a circuit step (see
+    /// [`OutputEndpoint::batch_end`](`crate::OutputEndpoint::batch_end`)).
+    ///
+    /// Returns `true` if the output has been delivered successfully.
+    fn batch_end(&mut self) -> bool {
+        true
+    }
 }
@@ -136,16 +136,16 @@ pub use catalog::Catalog;
 pub use deinput::{
     DeCollectionHandle, DeMapHandle, DeScalarHandle, DeScalarHandleImpl, DeSetHandle, DeZSetHandle,
 };
-pub use format::{Encoder, InputFormat, OutputConsumer, OutputFormat, Parser};
-pub use seroutput::{SerBatch, SerCursor, SerOutputBatchHandle};
+pub use format::{Encoder, InputFormat, OutputConsumer, OutputFormat, ParseError, Parser};
+pub use seroutput::{SerBatch, SerCursor, SerOutputBatchHandle, SerTrace};
 
 pub use controller::{
     Controller, ControllerError, ControllerStatus, FormatConfig, GlobalPipelineConfig,
     InputEndpointConfig, OutputEndpointConfig, PipelineConfig, TransportConfig,
 };
 pub use transport::{
-    FileInputTransport, InputConsumer, InputEndpoint, InputTransport, OutputEndpoint,
-    OutputTransport,
+    FileInputTransport, InputConsumer, InputEndpoint, InputPosition, InputTransport,
+    OutputEndpoint, OutputTransport, Step,
 };
 
 #[cfg(feature = "server")]
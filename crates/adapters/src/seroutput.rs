@@ -1,10 +1,16 @@
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+#[cfg(feature = "checkpoint")]
+use dbsp::{
+    circuit::checkpoint,
+    trace::serialize::{DecodeBatch, EncodeBatch},
+};
 use dbsp::{
     trace::{Batch, BatchReader, Cursor},
     OutputHandle,
 };
 use erased_serde::Serialize as ErasedSerialize;
-use serde::Serialize;
-use std::sync::Arc;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{any::Any, sync::Arc};
 
 /// A type-erased batch whose contents can be serialized.
 ///
@@ -25,6 +31,10 @@ pub trait SerBatch: Send + Sync {
     /// Cursor over the batch.
     fn cursor<'a>(&'a self) -> Box<dyn SerCursor + 'a>;
 
+    /// Returns `self` as `&dyn Any`, so it can be downcast to the concrete
+    /// batch type.
+    fn as_any(&self) -> &dyn Any;
+
     // fn fork(&self) -> Box<dyn SerBatch>;
 }
 
@@ -123,6 +133,10 @@ where
         Box::new(SerBatchCursor::new(&*self.batch))
     }
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     /*fn fork(&self) -> Box<dyn SerBatch> {
         Box::new(Self {
             batch: self.batch.clone(),
@@ -216,6 +230,14 @@ pub trait SerOutputBatchHandle: Send + Sync {
 
     /// Returns an alias to `self`.
     fn fork(&self) -> Box<dyn SerOutputBatchHandle>;
+
+    /// Create an empty trace that can accumulate batches produced by this
+    /// handle.
+    ///
+    /// The trace supports snapshots restricted to a range of keys only if
+    /// the handle was registered with
+    /// [`Catalog::register_keyed_output_batch_handle`](`crate::Catalog::register_keyed_output_batch_handle`).
+    fn new_trace(&self) -> Box<dyn SerTrace>;
 }
 
 impl<B> SerOutputBatchHandle for OutputHandle<B>
@@ -245,4 +267,226 @@ where
     fn fork(&self) -> Box<dyn SerOutputBatchHandle> {
         Box::new(self.clone())
     }
+
+    fn new_trace(&self) -> Box<dyn SerTrace> {
+        Box::new(SerTraceImpl::<B>::new(None))
+    }
+}
+
+/// An [`OutputHandle`] whose keys can be deserialized, so that snapshots of
+/// traces created by [`SerOutputBatchHandle::new_trace`] can be restricted
+/// to a range of keys.
+pub(crate) struct KeyedOutputHandle<B>(OutputHandle<B>);
+
+impl<B> KeyedOutputHandle<B> {
+    pub(crate) fn new(handle: OutputHandle<B>) -> Self {
+        Self(handle)
+    }
+}
+
+impl<B> SerOutputBatchHandle for KeyedOutputHandle<B>
+where
+    B: Batch<Time = ()> + Send + Sync,
+    B::Key: Serialize + DeserializeOwned,
+    B::Val: Serialize,
+    B::R: Into<i64>,
+{
+    fn take_from_worker(&self, worker: usize) -> Option<Box<dyn SerBatch>> {
+        SerOutputBatchHandle::take_from_worker(&self.0, worker)
+    }
+
+    fn take_from_all(&self) -> Vec<Arc<dyn SerBatch>> {
+        SerOutputBatchHandle::take_from_all(&self.0)
+    }
+
+    fn consolidate(&self) -> Box<dyn SerBatch> {
+        SerOutputBatchHandle::consolidate(&self.0)
+    }
+
+    fn fork(&self) -> Box<dyn SerOutputBatchHandle> {
+        Box::new(Self(self.0.clone()))
+    }
+
+    fn new_trace(&self) -> Box<dyn SerTrace> {
+        let parse_key: KeyParser<B::Key> = |key| serde_json::from_str(key);
+        Box::new(SerTraceImpl::<B>::new(Some(parse_key)))
+    }
+}
+
+/// A type-erased integral of an output stream.
+///
+/// Accumulates all updates produced by an output stream in order to maintain
+/// the current contents of the stream.
+pub trait SerTrace: Send + Sync {
+    /// Add a batch of updates produced by the stream to the trace.
+    ///
+    /// Panics if `batch` was not produced by the output handle that created
+    /// this trace.
+    fn insert(&mut self, batch: &dyn SerBatch);
+
+    /// Returns the current contents of the stream.
+    ///
+    /// `lower` and `upper`, when specified, are JSON-encoded keys that
+    /// restrict the snapshot to keys in the half-open range `[lower, upper)`.
+    /// Fails if the trace doesn't support key ranges (see
+    /// [`SerOutputBatchHandle::new_trace`]).
+    fn snapshot(&self, lower: Option<&str>, upper: Option<&str>) -> AnyResult<Arc<dyn SerBatch>>;
+
+    /// Merge all updates added to the trace into a single batch.
+    ///
+    /// Unlike [`Self::snapshot`], this method doesn't copy individual tuples,
+    /// and is therefore cheaper for large traces.
+    fn consolidate(&self) -> Arc<dyn SerBatch>;
+
+    /// Serialize the contents of the trace, e.g., to store them in a
+    /// checkpoint.
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint(&self) -> AnyResult<Vec<u8>>;
+
+    /// Replace the contents of the trace with contents serialized by
+    /// [`Self::checkpoint`].
+    #[cfg(feature = "checkpoint")]
+    fn restore(&mut self, state: &[u8]) -> AnyResult<()>;
+}
+
+/// Parses a JSON-encoded key.
+type KeyParser<K> = fn(&str) -> serde_json::Result<K>;
+
+/// [`SerTrace`] implementation that stores batches of type `B`.
+struct SerTraceImpl<B>
+where
+    B: BatchReader,
+{
+    /// Batches whose sum is the current contents of the stream, in
+    /// decreasing order of size (approximately).
+    batches: Vec<Arc<B>>,
+
+    /// Parses key range bounds, if the trace supports key ranges.
+    parse_key: Option<KeyParser<B::Key>>,
+}
+
+impl<B> SerTraceImpl<B>
+where
+    B: BatchReader,
+{
+    fn new(parse_key: Option<KeyParser<B::Key>>) -> Self {
+        Self {
+            batches: Vec::new(),
+            parse_key,
+        }
+    }
+}
+
+impl<B> SerTrace for SerTraceImpl<B>
+where
+    B: Batch<Time = ()> + Send + Sync,
+    B::Key: Serialize,
+    B::Val: Serialize,
+    B::R: Into<i64>,
+{
+    fn insert(&mut self, batch: &dyn SerBatch) {
+        let batch = batch
+            .as_any()
+            .downcast_ref::<SerBatchImpl<B>>()
+            .expect("SerTrace::insert: unexpected batch type");
+        if batch.batch.is_empty() {
+            return;
+        }
+        self.batches.push(batch.batch.clone());
+
+        // Merge batches of similar sizes, so that the number of batches stays
+        // logarithmic in the size of the trace.
+        while let [.., batch1, batch2] = self.batches.as_slice() {
+            if batch2.len() * 2 < batch1.len() {
+                break;
+            }
+            let merged = Arc::new(batch1.merge(batch2));
+            self.batches.truncate(self.batches.len() - 2);
+            self.batches.push(merged);
+        }
+    }
+
+    fn snapshot(&self, lower: Option<&str>, upper: Option<&str>) -> AnyResult<Arc<dyn SerBatch>> {
+        let (lower, upper) = match self.parse_key {
+            Some(parse_key) => (
+                lower
+                    .map(parse_key)
+                    .transpose()
+                    .context("invalid lower bound")?,
+                upper
+                    .map(parse_key)
+                    .transpose()
+                    .context("invalid upper bound")?,
+            ),
+            None if lower.is_none() && upper.is_none() => (None, None),
+            None => {
+                return Err(AnyError::msg(
+                    "the stream does not support key ranges: its output handle must be registered with 'Catalog::register_keyed_output_batch_handle'",
+                ))
+            }
+        };
+
+        let mut tuples = Vec::new();
+        for batch in self.batches.iter() {
+            let mut cursor = batch.cursor();
+            if let Some(lower) = &lower {
+                cursor.seek_key(lower);
+            }
+
+            while cursor.key_valid() {
+                if matches!(&upper, Some(upper) if cursor.key() >= upper) {
+                    break;
+                }
+                while cursor.val_valid() {
+                    let weight = cursor.weight();
+                    tuples.push((
+                        B::item_from(cursor.key().clone(), cursor.val().clone()),
+                        weight,
+                    ));
+                    cursor.step_val();
+                }
+                cursor.step_key();
+            }
+        }
+
+        Ok(Arc::new(SerBatchImpl::new(B::from_tuples((), tuples))))
+    }
+
+    fn consolidate(&self) -> Arc<dyn SerBatch> {
+        Arc::new(SerBatchImpl {
+            batch: self.merged(),
+        })
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint(&self) -> AnyResult<Vec<u8>> {
+        Ok(checkpoint::encode(&EncodeBatch(&*self.merged()))?)
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn restore(&mut self, state: &[u8]) -> AnyResult<()> {
+        let DecodeBatch(batch) = checkpoint::decode::<DecodeBatch<B>>(state)?;
+        self.batches.clear();
+        if !batch.is_empty() {
+            self.batches.push(Arc::new(batch));
+        }
+        Ok(())
+    }
+}
+
+impl<B> SerTraceImpl<B>
+where
+    B: Batch<Time = ()>,
+{
+    /// Merge all batches in the trace.
+    fn merged(&self) -> Arc<B> {
+        match self.batches.as_slice() {
+            [] => Arc::new(B::empty(())),
+            [batch] => batch.clone(),
+            [batch, rest @ ..] => Arc::new(
+                rest.iter()
+                    .fold((**batch).clone(), |merged, batch| merged.merge(batch)),
+            ),
+        }
+    }
 }
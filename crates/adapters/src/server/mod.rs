@@ -1,11 +1,12 @@
 use crate::{
-    Catalog, Controller, ControllerError, HttpInputTransport, HttpOutputTransport, PipelineConfig,
+    Catalog, Controller, ControllerError, HttpInputTransport, HttpOutputTransport,
+    InputEndpointConfig, OutputConsumer, OutputEndpointConfig, OutputFormat, PipelineConfig,
 };
 use actix_web::{
     dev::{Server, ServiceFactory, ServiceRequest},
     get,
     middleware::Logger,
-    rt, web,
+    post, rt, web,
     web::Data as WebData,
     App, Error as ActixError, HttpRequest, HttpResponse, HttpServer, Responder,
 };
@@ -15,8 +16,12 @@ use clap::Parser;
 use dbsp::DBSPHandle;
 use env_logger::Env;
 use log::{error, info};
-use serde::Serialize;
-use std::{net::TcpListener, sync::Mutex};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value as YamlValue;
+use std::{
+    net::TcpListener,
+    sync::{Arc, Mutex, RwLock},
+};
 use tokio::{
     spawn,
     sync::mpsc::{channel, Receiver, Sender},
@@ -28,7 +33,9 @@ use self::prometheus::PrometheusMetrics;
 struct ServerState {
     metadata: String,
     controller: Mutex<Option<Controller>>,
-    prometheus: PrometheusMetrics,
+    /// Updated when endpoints are connected or disconnected while holding
+    /// the `controller` lock.
+    prometheus: RwLock<PrometheusMetrics>,
     /// Channel used to send a `kill` command to
     /// the self-destruct task when shutting down
     /// the server.
@@ -45,7 +52,7 @@ impl ServerState {
         Self {
             metadata: meta,
             controller: Mutex::new(Some(controller)),
-            prometheus,
+            prometheus: RwLock::new(prometheus),
             terminate_sender,
         }
     }
@@ -238,6 +245,8 @@ where
         .service(ResourceFiles::new("/static", generated))
         .service(start)
         .service(pause)
+        .service(start_input)
+        .service(pause_input)
         .service(shutdown)
         .service(status)
         .service(metrics)
@@ -245,6 +254,11 @@ where
         .service(dump_profile)
         .service(input_endpoint)
         .service(output_endpoint)
+        .service(connect_input)
+        .service(disconnect_input)
+        .service(connect_output)
+        .service(disconnect_output)
+        .service(snapshot)
         .service(kill)
 }
 
@@ -274,6 +288,44 @@ async fn pause(state: WebData<ServerState>) -> impl Responder {
     }
 }
 
+/// Resume an input endpoint paused via `/pause_input`.
+#[get("/start_input/{endpoint_name}")]
+async fn start_input(
+    state: WebData<ServerState>,
+    endpoint_name: web::Path<String>,
+) -> impl Responder {
+    match &*state.controller.lock().unwrap() {
+        Some(controller) => match controller.start_input_endpoint(&endpoint_name) {
+            Ok(()) => HttpResponse::Ok().json("Input endpoint resumed"),
+            Err(e) => HttpResponse::BadRequest().json(&ErrorResponse::new(&format!(
+                "Failed to resume input endpoint '{endpoint_name}': {e}"
+            ))),
+        },
+        None => {
+            HttpResponse::Conflict().json(&ErrorResponse::new("The pipeline has been terminated"))
+        }
+    }
+}
+
+/// Pause an input endpoint until it is resumed via `/start_input`.
+#[get("/pause_input/{endpoint_name}")]
+async fn pause_input(
+    state: WebData<ServerState>,
+    endpoint_name: web::Path<String>,
+) -> impl Responder {
+    match &*state.controller.lock().unwrap() {
+        Some(controller) => match controller.pause_input_endpoint(&endpoint_name) {
+            Ok(()) => HttpResponse::Ok().json("Input endpoint paused"),
+            Err(e) => HttpResponse::BadRequest().json(&ErrorResponse::new(&format!(
+                "Failed to pause input endpoint '{endpoint_name}': {e}"
+            ))),
+        },
+        None => {
+            HttpResponse::Conflict().json(&ErrorResponse::new("The pipeline has been terminated"))
+        }
+    }
+}
+
 #[get("/status")]
 async fn status(state: WebData<ServerState>) -> impl Responder {
     match &*state.controller.lock().unwrap() {
@@ -293,7 +345,7 @@ async fn status(state: WebData<ServerState>) -> impl Responder {
 #[get("/metrics")]
 async fn metrics(state: WebData<ServerState>) -> impl Responder {
     match &*state.controller.lock().unwrap() {
-        Some(controller) => match state.prometheus.metrics(controller) {
+        Some(controller) => match state.prometheus.read().unwrap().metrics(controller) {
             Ok(metrics) => HttpResponse::Ok()
                 .content_type(mime::TEXT_PLAIN)
                 .body(metrics),
@@ -380,6 +432,330 @@ async fn output_endpoint(req: HttpRequest, stream: web::Payload) -> impl Respond
     }
 }
 
+/// Connect a new input endpoint to the pipeline.
+///
+/// The request body contains endpoint configuration in JSON format (see
+/// [`InputEndpointConfig`]).
+#[post("/connect_input/{endpoint_name}")]
+async fn connect_input(
+    state: WebData<ServerState>,
+    endpoint_name: web::Path<String>,
+    config: web::Json<InputEndpointConfig>,
+) -> impl Responder {
+    match &*state.controller.lock().unwrap() {
+        Some(controller) => {
+            if let Err(e) = controller.connect_input(&endpoint_name, &config) {
+                return HttpResponse::BadRequest().json(&ErrorResponse::new(&format!(
+                    "Failed to connect input endpoint '{endpoint_name}': {e}"
+                )));
+            }
+
+            let status = controller.status();
+            let endpoint_id = status.input_endpoint_id(&endpoint_name).unwrap();
+            let result = state
+                .prometheus
+                .write()
+                .unwrap()
+                .add_input_endpoint(endpoint_id, &status.input_status()[&endpoint_id]);
+
+            match result {
+                Ok(()) => HttpResponse::Ok().json("Input endpoint connected"),
+                Err(e) => HttpResponse::InternalServerError().json(&ErrorResponse::new(&format!(
+                    "Failed to register metrics for input endpoint '{endpoint_name}': {e}"
+                ))),
+            }
+        }
+        None => {
+            HttpResponse::Conflict().json(&ErrorResponse::new("The pipeline has been terminated"))
+        }
+    }
+}
+
+/// Disconnect an input endpoint from the pipeline.
+#[post("/disconnect_input/{endpoint_name}")]
+async fn disconnect_input(
+    state: WebData<ServerState>,
+    endpoint_name: web::Path<String>,
+) -> impl Responder {
+    match &*state.controller.lock().unwrap() {
+        Some(controller) => {
+            let endpoint_id = controller.status().input_endpoint_id(&endpoint_name);
+            if let Err(e) = controller.disconnect_input(&endpoint_name) {
+                return HttpResponse::BadRequest().json(&ErrorResponse::new(&format!(
+                    "Failed to disconnect input endpoint '{endpoint_name}': {e}"
+                )));
+            }
+
+            let result = match endpoint_id {
+                Some(endpoint_id) => state
+                    .prometheus
+                    .write()
+                    .unwrap()
+                    .remove_input_endpoint(endpoint_id),
+                None => Ok(()),
+            };
+
+            match result {
+                Ok(()) => HttpResponse::Ok().json("Input endpoint disconnected"),
+                Err(e) => HttpResponse::InternalServerError().json(&ErrorResponse::new(&format!(
+                    "Failed to unregister metrics for input endpoint '{endpoint_name}': {e}"
+                ))),
+            }
+        }
+        None => {
+            HttpResponse::Conflict().json(&ErrorResponse::new("The pipeline has been terminated"))
+        }
+    }
+}
+
+/// Connect a new output endpoint to the pipeline.
+///
+/// The request body contains endpoint configuration in JSON format (see
+/// [`OutputEndpointConfig`]).
+#[post("/connect_output/{endpoint_name}")]
+async fn connect_output(
+    state: WebData<ServerState>,
+    endpoint_name: web::Path<String>,
+    config: web::Json<OutputEndpointConfig>,
+) -> impl Responder {
+    match &*state.controller.lock().unwrap() {
+        Some(controller) => {
+            if let Err(e) = controller.connect_output(&endpoint_name, &config) {
+                return HttpResponse::BadRequest().json(&ErrorResponse::new(&format!(
+                    "Failed to connect output endpoint '{endpoint_name}': {e}"
+                )));
+            }
+
+            let status = controller.status();
+            let endpoint_id = status.output_endpoint_id(&endpoint_name).unwrap();
+            let result = state
+                .prometheus
+                .write()
+                .unwrap()
+                .add_output_endpoint(endpoint_id, &status.output_status()[&endpoint_id]);
+
+            match result {
+                Ok(()) => HttpResponse::Ok().json("Output endpoint connected"),
+                Err(e) => HttpResponse::InternalServerError().json(&ErrorResponse::new(&format!(
+                    "Failed to register metrics for output endpoint '{endpoint_name}': {e}"
+                ))),
+            }
+        }
+        None => {
+            HttpResponse::Conflict().json(&ErrorResponse::new("The pipeline has been terminated"))
+        }
+    }
+}
+
+/// Disconnect an output endpoint from the pipeline.
+#[post("/disconnect_output/{endpoint_name}")]
+async fn disconnect_output(
+    state: WebData<ServerState>,
+    endpoint_name: web::Path<String>,
+) -> impl Responder {
+    match &*state.controller.lock().unwrap() {
+        Some(controller) => {
+            let endpoint_id = controller.status().output_endpoint_id(&endpoint_name);
+            if let Err(e) = controller.disconnect_output(&endpoint_name) {
+                return HttpResponse::BadRequest().json(&ErrorResponse::new(&format!(
+                    "Failed to disconnect output endpoint '{endpoint_name}': {e}"
+                )));
+            }
+
+            let result = match endpoint_id {
+                Some(endpoint_id) => state
+                    .prometheus
+                    .write()
+                    .unwrap()
+                    .remove_output_endpoint(endpoint_id),
+                None => Ok(()),
+            };
+
+            match result {
+                Ok(()) => HttpResponse::Ok().json("Output endpoint disconnected"),
+                Err(e) => HttpResponse::InternalServerError().json(&ErrorResponse::new(&format!(
+                    "Failed to unregister metrics for output endpoint '{endpoint_name}': {e}"
+                ))),
+            }
+        }
+        None => {
+            HttpResponse::Conflict().json(&ErrorResponse::new("The pipeline has been terminated"))
+        }
+    }
+}
+
+/// Query parameters of the `/snapshot` endpoint.
+#[derive(Deserialize)]
+struct SnapshotQuery {
+    /// Output format name; defaults to `csv`.
+    #[serde(default = "default_snapshot_format")]
+    format: String,
+    /// JSON-encoded inclusive lower bound on keys.
+    lower: Option<String>,
+    /// JSON-encoded exclusive upper bound on keys.
+    upper: Option<String>,
+}
+
+fn default_snapshot_format() -> String {
+    "csv".to_string()
+}
+
+/// Output consumer that accumulates encoded snapshot data in memory.
+struct SnapshotBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl OutputConsumer for SnapshotBuffer {
+    fn push_buffer(&mut self, buffer: &[u8]) {
+        self.0.lock().unwrap().extend_from_slice(buffer);
+    }
+}
+
+/// Return the current contents of a materialized output stream, optionally
+/// restricted to a range of keys, encoded using the specified output format.
+#[get("/snapshot/{stream_name}")]
+async fn snapshot(
+    state: WebData<ServerState>,
+    stream_name: web::Path<String>,
+    query: web::Query<SnapshotQuery>,
+) -> impl Responder {
+    let batch = match &*state.controller.lock().unwrap() {
+        Some(controller) => {
+            match controller.snapshot(&stream_name, query.lower.as_deref(), query.upper.as_deref())
+            {
+                Ok(batch) => batch,
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(&ErrorResponse::new(&format!(
+                        "Failed to retrieve snapshot of stream '{stream_name}': {e}"
+                    )))
+                }
+            }
+        }
+        None => {
+            return HttpResponse::Conflict()
+                .json(&ErrorResponse::new("The pipeline has been terminated"))
+        }
+    };
+
+    let format = match <dyn OutputFormat>::get_format(&query.format) {
+        Some(format) => format,
+        None => {
+            return HttpResponse::BadRequest().json(&ErrorResponse::new(&format!(
+                "Unknown output format '{}'",
+                query.format
+            )))
+        }
+    };
+
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let result = format
+        .new_encoder(&YamlValue::Null, Box::new(SnapshotBuffer(buffer.clone())))
+        .and_then(|mut encoder| encoder.encode(&[batch]));
+
+    match result {
+        Ok(()) => {
+            let data = std::mem::take(&mut *buffer.lock().unwrap());
+            HttpResponse::Ok().body(data)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(&ErrorResponse::new(&format!(
+            "Failed to encode snapshot of stream '{stream_name}': {e}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "server")]
+mod test {
+    use super::{build_app, PrometheusMetrics, ServerState};
+    use crate::{
+        test::{test_circuit, wait},
+        Controller, PipelineConfig,
+    };
+    use actix_web::{http::StatusCode, web::Data as WebData, App};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[actix_web::test]
+    async fn test_snapshot() {
+        let mut input_file = NamedTempFile::new().unwrap();
+        input_file
+            .write_all(b"1,true,,foo\n2,false,5,bar\n3,true,,baz\n")
+            .unwrap();
+
+        let config_str = format!(
+            r#"
+inputs:
+    test_input1:
+        stream: test_input1
+        transport:
+            name: file
+            config:
+                path: {:?}
+        format:
+            name: csv
+materialized_streams: [test_output1]
+"#,
+            input_file.path().to_str().unwrap(),
+        );
+        let config: PipelineConfig = serde_yaml::from_str(&config_str).unwrap();
+
+        let (circuit, catalog) = test_circuit(2);
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}")),
+        )
+        .unwrap();
+        controller.start();
+        wait(|| controller.pipeline_complete(), None);
+
+        let prometheus = PrometheusMetrics::new(&controller).unwrap();
+        let state = WebData::new(ServerState::new(
+            controller,
+            prometheus,
+            "metadata".to_string(),
+            None,
+        ));
+        let server = actix_test::start(move || build_app(App::new(), state.clone()));
+
+        let mut resp = server.get("/snapshot/test_output1").send().await.unwrap();
+        assert!(resp.status().is_success());
+        let body = resp.body().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&body).lines().count(), 3);
+
+        let mut resp = server
+            .get("/snapshot/test_output1")
+            .query(&[
+                ("lower", r#"{"id":2,"b":false,"i":null,"s":""}"#),
+                ("upper", r#"{"id":3,"b":false,"i":null,"s":""}"#),
+            ])
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+        let body = resp.body().await.unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&body).lines().collect::<Vec<_>>(),
+            vec!["2,false,5,bar,1"]
+        );
+
+        let resp = server
+            .get("/snapshot/test_output1")
+            .query(&[("lower", "not a key")])
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = server.get("/snapshot/test_output2").send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = server.get("/shutdown").send().await.unwrap();
+        assert!(resp.status().is_success());
+    }
+}
+
 #[cfg(test)]
 #[cfg(feature = "with-kafka")]
 #[cfg(feature = "server")]
@@ -406,7 +782,9 @@ mod test_with_kafka {
         strategy::{Strategy, ValueTree},
         test_runner::TestRunner,
     };
+    use serde_json::json;
     use std::{pin::Pin, sync::Arc, thread::sleep, time::Duration};
+    use tempfile::NamedTempFile;
 
     #[actix_web::test]
     async fn test_server() {
@@ -525,6 +903,44 @@ outputs:
         let resp = server.get("/metadata").send().await.unwrap();
         assert!(resp.status().is_success());
 
+        // Attach and detach an output endpoint.
+        println!("/connect_output");
+        let debug_output_file = NamedTempFile::new().unwrap();
+        let resp = server
+            .post("/connect_output/test_output_debug")
+            .send_json(&json!({
+                "stream": "test_output1",
+                "transport": {
+                    "name": "file",
+                    "config": { "path": debug_output_file.path() }
+                },
+                "format": { "name": "csv" }
+            }))
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+
+        let resp = server.get("/metrics").send().await.unwrap();
+        assert!(resp.status().is_success());
+
+        println!("/disconnect_output");
+        let resp = server
+            .post("/disconnect_output/test_output_debug")
+            .send()
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+
+        let resp = server
+            .post("/disconnect_output/test_output_debug")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = server.get("/metrics").send().await.unwrap();
+        assert!(resp.status().is_success());
+
         // Pause command; send more data, receive none.
         println!("/pause");
         let resp = server.get("/pause").send().await.unwrap();
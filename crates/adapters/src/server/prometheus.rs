@@ -65,6 +65,16 @@ impl PrometheusMetrics {
         Ok(())
     }
 
+    /// Unregister the metrics of a disconnected input endpoint.
+    pub(crate) fn remove_input_endpoint(&mut self, endpoint_id: EndpointId) -> AnyResult<()> {
+        if let Some(input_metrics) = self.input_metrics.remove(&endpoint_id) {
+            for gauge in input_metrics.gauges() {
+                self.registry.unregister(Box::new(gauge.clone()))?;
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn update_input_metrics(
         &self,
         endpoint_id: EndpointId,
@@ -127,6 +137,16 @@ impl PrometheusMetrics {
         Ok(())
     }
 
+    /// Unregister the metrics of a disconnected output endpoint.
+    pub(crate) fn remove_output_endpoint(&mut self, endpoint_id: EndpointId) -> AnyResult<()> {
+        if let Some(output_metrics) = self.output_metrics.remove(&endpoint_id) {
+            for gauge in output_metrics.gauges() {
+                self.registry.unregister(Box::new(gauge.clone()))?;
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn update_output_metrics(
         &self,
         endpoint_id: EndpointId,
@@ -196,6 +216,19 @@ struct InputMetrics {
     num_parse_errors: IntGauge,
 }
 
+impl InputMetrics {
+    fn gauges(&self) -> [&IntGauge; 6] {
+        [
+            &self.total_bytes,
+            &self.total_records,
+            &self.buffered_bytes,
+            &self.buffered_records,
+            &self.num_transport_errors,
+            &self.num_parse_errors,
+        ]
+    }
+}
+
 struct OutputMetrics {
     transmitted_bytes: IntGauge,
     transmitted_records: IntGauge,
@@ -204,3 +237,16 @@ struct OutputMetrics {
     num_transport_errors: IntGauge,
     num_encode_errors: IntGauge,
 }
+
+impl OutputMetrics {
+    fn gauges(&self) -> [&IntGauge; 6] {
+        [
+            &self.transmitted_bytes,
+            &self.transmitted_records,
+            &self.buffered_records,
+            &self.buffered_batches,
+            &self.num_transport_errors,
+            &self.num_encode_errors,
+        ]
+    }
+}
@@ -67,7 +67,7 @@ impl<T> MockDeZSet<T> {
 
 impl<T> DeCollectionHandle for MockDeZSet<T>
 where
-    T: for<'de> Deserialize<'de> + Send + 'static,
+    T: for<'de> Deserialize<'de> + Clone + Send + 'static,
 {
     fn insert(&mut self, deserializer: &mut dyn ErasedDeserializer) -> Result<(), EError> {
         let val = deserialize::<T>(deserializer)?;
@@ -81,6 +81,34 @@ where
         Ok(())
     }
 
+    fn replace(
+        &mut self,
+        old: &mut dyn ErasedDeserializer,
+        new: &mut dyn ErasedDeserializer,
+    ) -> Result<(), EError> {
+        let old = deserialize::<T>(old)?;
+        let new = deserialize::<T>(new)?;
+        let mut state = self.0.lock().unwrap();
+        state.buffered.push((old, false));
+        state.buffered.push((new, true));
+        Ok(())
+    }
+
+    /// Buffers `|weight|` copies of the update, so that tests can inspect
+    /// weights without a separate representation.
+    fn update(
+        &mut self,
+        deserializer: &mut dyn ErasedDeserializer,
+        weight: i64,
+    ) -> Result<(), EError> {
+        let val = deserialize::<T>(deserializer)?;
+        let mut state = self.0.lock().unwrap();
+        for _ in 0..weight.unsigned_abs() {
+            state.buffered.push((val.clone(), weight > 0));
+        }
+        Ok(())
+    }
+
     fn reserve(&mut self, _reservation: usize) {}
 
     fn flush(&mut self) {
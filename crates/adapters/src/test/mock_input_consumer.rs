@@ -1,4 +1,7 @@
-use crate::{controller::FormatConfig, DeCollectionHandle, InputConsumer, InputFormat, Parser};
+use crate::{
+    controller::FormatConfig, DeCollectionHandle, InputConsumer, InputFormat, ParseError, Parser,
+    Step,
+};
 use anyhow::{Error as AnyError, Result as AnyResult};
 use std::sync::{Arc, Mutex, MutexGuard};
 
@@ -16,7 +19,7 @@ pub struct MockInputConsumerState {
     pub endpoint_error: Option<AnyError>,
 
     /// The last result returned by the parser.
-    pub parser_result: Option<AnyResult<usize>>,
+    pub parser_result: Option<(usize, Vec<ParseError>)>,
 
     /// Parser to push data to.
     parser: Box<dyn Parser>,
@@ -88,22 +91,23 @@ impl MockInputConsumer {
 }
 
 impl InputConsumer for MockInputConsumer {
-    fn input(&mut self, data: &[u8]) {
+    fn input(&mut self, data: &[u8]) -> Step {
         // println!("input");
         let mut state = self.state();
 
         state.data.extend_from_slice(data);
         let parser_result = state.parser.input(data);
         // println!("parser returned '{:?}'", state.parser_result);
-        if let Err(e) = &parser_result {
+        for e in parser_result.1.iter() {
             if let Some(error_cb) = &mut state.error_cb {
-                error_cb(e);
+                error_cb(&AnyError::from(e.clone()));
             } else {
                 panic!("mock_input_consumer: parse error '{e}'");
             }
         }
         state.parser_result = Some(parser_result);
         state.parser.flush();
+        0
     }
 
     fn error(&mut self, _fatal: bool, error: AnyError) {
@@ -121,7 +125,7 @@ impl InputConsumer for MockInputConsumer {
         self.state().eoi = true;
     }
 
-    fn fork(&self) -> Box<dyn InputConsumer> {
-        Box::new(self.clone())
+    fn fork(&self) -> AnyResult<Box<dyn InputConsumer>> {
+        Ok(Box::new(self.clone()))
     }
 }
@@ -1,10 +1,11 @@
 //! Test framework for the `adapters` crate.
 
 use crate::{controller::InputEndpointConfig, Catalog, InputEndpoint, InputTransport};
-use dbsp::{DBSPHandle, Runtime};
+use dbsp::{DBSPHandle, RootCircuit, Runtime};
 use log::{Log, Metadata, Record};
 use serde::Deserialize;
 use std::{
+    path::Path,
     thread::sleep,
     time::{Duration, Instant},
 };
@@ -76,7 +77,7 @@ pub fn mock_input_pipeline<T>(
     config: InputEndpointConfig,
 ) -> (Box<dyn InputEndpoint>, MockInputConsumer, MockDeZSet<T>)
 where
-    T: for<'de> Deserialize<'de> + Send + 'static,
+    T: for<'de> Deserialize<'de> + Clone + Send + 'static,
 {
     let input_handle = <MockDeZSet<T>>::new();
 
@@ -98,17 +99,37 @@ where
 /// the output.
 // TODO: parameterize with the number (and types?) of input and output streams.
 pub fn test_circuit(workers: usize) -> (DBSPHandle, Catalog) {
-    let (circuit, (input, output)) = Runtime::init_circuit(workers, |circuit| {
+    test_circuit_inner(workers, None)
+}
+
+/// Create the circuit returned by [`test_circuit`] and restore its state from
+/// a checkpoint in `path`.
+#[cfg(feature = "checkpoint")]
+pub fn test_circuit_from_checkpoint(workers: usize, path: &Path) -> (DBSPHandle, Catalog) {
+    test_circuit_inner(workers, Some(path))
+}
+
+fn test_circuit_inner(workers: usize, checkpoint: Option<&Path>) -> (DBSPHandle, Catalog) {
+    let constructor = |circuit: &mut RootCircuit| {
         let (input, hinput) = circuit.add_input_zset::<TestStruct, i32>();
 
         let houtput = input.output();
         (hinput, houtput)
-    })
+    };
+
+    let (circuit, (input, output)) = match checkpoint {
+        None => Runtime::init_circuit(workers, constructor),
+        #[cfg(feature = "checkpoint")]
+        Some(path) => Runtime::init_circuit_from_checkpoint(workers, path, constructor),
+        // Only `test_circuit_from_checkpoint` passes a checkpoint.
+        #[cfg(not(feature = "checkpoint"))]
+        Some(_) => unreachable!(),
+    }
     .unwrap();
 
     let mut catalog = Catalog::new();
     catalog.register_input_zset_handle("test_input1", input);
-    catalog.register_output_batch_handle("test_output1", output);
+    catalog.register_keyed_output_batch_handle("test_output1", output);
 
     (circuit, catalog)
 }
@@ -0,0 +1,698 @@
+use super::{InputConsumer, InputEndpoint, InputPosition, InputTransport, Step};
+use crate::PipelineState;
+use anyhow::{Error as AnyError, Result as AnyResult};
+use crossbeam::sync::{Parker, Unparker};
+use glob::{glob, Pattern};
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml::Value as YamlValue;
+use std::{
+    borrow::Cow,
+    collections::{HashSet, VecDeque},
+    fs::{metadata, read_to_string, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn},
+    time::Duration,
+};
+use utoipa::ToSchema;
+
+/// Default value of `DirectoryInputConfig::poll_interval_ms`.
+const fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+/// `InputTransport` implementation that reads files matching a glob pattern.
+pub struct DirectoryInputTransport;
+
+impl InputTransport for DirectoryInputTransport {
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed("directory")
+    }
+
+    fn new_endpoint(
+        &self,
+        _name: &str,
+        config: &YamlValue,
+        consumer: Box<dyn InputConsumer>,
+    ) -> AnyResult<Box<dyn InputEndpoint>> {
+        let config = DirectoryInputConfig::deserialize(config)?;
+        let ep = DirectoryInputEndpoint::new(config, consumer)?;
+        Ok(Box::new(ep))
+    }
+}
+
+/// Order in which [`DirectoryInputConfig::pattern`] files are ingested.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub enum FileOrder {
+    /// Order by file path.
+    #[serde(rename = "name")]
+    Name,
+
+    /// Order by modification time, oldest first.  Files with the same
+    /// modification time are ordered by path.
+    #[serde(rename = "mtime")]
+    Mtime,
+}
+
+impl Default for FileOrder {
+    fn default() -> Self {
+        Self::Name
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DirectoryInputConfig {
+    /// Glob pattern that selects files to ingest, e.g., `/data/inbox/*.csv`.
+    ///
+    /// The endpoint may start reading a file as soon as it appears, so files
+    /// must be moved into place atomically, e.g., by writing them under a
+    /// name that doesn't match the pattern and renaming them once complete.
+    pattern: String,
+
+    /// Order in which files are ingested.
+    ///
+    /// Files discovered by a scan of the directory are ingested after all
+    /// files discovered by previous scans.  The default is `name`.
+    #[serde(default)]
+    order: FileOrder,
+
+    /// Path to the file that tracks consumed files.
+    ///
+    /// The endpoint appends the path of each file to this file once the file
+    /// has been ingested and skips files listed in it, including after a
+    /// restart.  The file is created if it does not exist.  A file that was
+    /// partially ingested when the pipeline stopped is ingested again from
+    /// the beginning.  A pipeline resumed from a checkpoint instead skips the
+    /// files and the part of the current file consumed when the checkpoint
+    /// was taken, regardless of the contents of this file.
+    consumed_files_path: String,
+
+    /// Enable exactly-once mode.
+    ///
+    /// When `true`, a file is recorded as consumed only after the outputs of
+    /// the circuit steps that processed it have been delivered by all output
+    /// endpoints.  This mode requires the pipeline to run in
+    /// [exactly-once mode](`crate::GlobalPipelineConfig::exactly_once`).
+    /// Otherwise, a file is recorded as consumed as soon as all of its
+    /// contents have been pushed to the circuit.
+    #[serde(default)]
+    exactly_once: bool,
+
+    /// Enable directory watching.
+    ///
+    /// When `false`, the endpoint ingests all files that match `pattern`,
+    /// outputs an [`eoi`](`InputConsumer::eoi`) message and stops.  When
+    /// `true`, the endpoint keeps scanning for new files.
+    #[serde(default)]
+    follow: bool,
+
+    /// Interval between scans of the directory when there are no more files
+    /// to ingest, in milliseconds.  The default is 1000.
+    #[serde(default = "default_poll_interval_ms")]
+    poll_interval_ms: u64,
+
+    /// Read buffer size.
+    ///
+    /// Default: when this parameter is not specified, a platform-specific
+    /// default is used.
+    buffer_size_bytes: Option<usize>,
+}
+
+/// Position of the endpoint, reported to the consumer with each input buffer
+/// (see [`InputConsumer::input_at`]).
+#[derive(Clone, Serialize, Deserialize)]
+struct DirectoryPosition {
+    /// Files whose contents have been pushed to the consumer, including the
+    /// files listed in `consumed_files_path` when the endpoint was created,
+    /// in the order read.
+    #[serde(
+        serialize_with = "serialize_consumed",
+        deserialize_with = "deserialize_consumed"
+    )]
+    consumed: Arc<Vec<PathBuf>>,
+
+    /// File being read and the offset of the end of the data pushed from it.
+    current: Option<(PathBuf, u64)>,
+}
+
+fn serialize_consumed<S>(consumed: &Arc<Vec<PathBuf>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    consumed.as_slice().serialize(serializer)
+}
+
+fn deserialize_consumed<'de, D>(deserializer: D) -> Result<Arc<Vec<PathBuf>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::deserialize(deserializer).map(Arc::new)
+}
+
+/// State shared by the endpoint and its worker thread.
+struct DirectoryInputEndpointInner {
+    config: DirectoryInputConfig,
+    status: AtomicU32,
+
+    /// `consumed_files_path` opened for appending.
+    consumed_files: Mutex<File>,
+
+    /// Files read but not yet recorded as consumed in exactly-once mode,
+    /// along with the last circuit step that processes their contents, in
+    /// the order read.
+    uncommitted: Mutex<VecDeque<(Step, PathBuf)>>,
+
+    /// Position requested via [`InputEndpoint::seek`], applied by the worker
+    /// thread before reading more data.
+    seek_position: Mutex<Option<DirectoryPosition>>,
+}
+
+impl DirectoryInputEndpointInner {
+    /// Append `path` to the list of consumed files.
+    fn record_consumed(&self, path: &Path) -> AnyResult<()> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| AnyError::msg(format!("invalid file name '{}'", path.display())))?;
+
+        let mut consumed_files = self.consumed_files.lock().unwrap();
+        writeln!(consumed_files, "{path}")?;
+        consumed_files.sync_data()?;
+        Ok(())
+    }
+
+    /// Record files processed by steps `0..step` as consumed.
+    fn commit(&self, step: Step) -> AnyResult<()> {
+        let mut uncommitted = self.uncommitted.lock().unwrap();
+        while let Some((file_step, _)) = uncommitted.front() {
+            if *file_step >= step {
+                break;
+            }
+            let (_, path) = uncommitted.pop_front().unwrap();
+            self.record_consumed(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Returns files that match the pattern and are not in `seen`, in the
+    /// order in which they should be ingested.
+    fn scan(&self, seen: &HashSet<PathBuf>) -> AnyResult<VecDeque<PathBuf>> {
+        let mut files = Vec::new();
+        for path in glob(&self.config.pattern)? {
+            let path = path?;
+            if seen.contains(&path) || !path.is_file() {
+                continue;
+            }
+            let mtime = match self.config.order {
+                FileOrder::Name => None,
+                FileOrder::Mtime => Some(metadata(&path)?.modified()?),
+            };
+            files.push((mtime, path));
+        }
+        files.sort();
+
+        Ok(files.into_iter().map(|(_, path)| path).collect())
+    }
+
+    fn open(&self, path: &Path) -> AnyResult<BufReader<File>> {
+        let file = File::open(path).map_err(|e| {
+            AnyError::msg(format!(
+                "Failed to open input file '{}': {e}",
+                path.display()
+            ))
+        })?;
+        Ok(match self.config.buffer_size_bytes {
+            Some(buffer_size) if buffer_size > 0 => BufReader::with_capacity(buffer_size, file),
+            _ => BufReader::new(file),
+        })
+    }
+
+    /// Called once all contents of `path` have been pushed to the circuit;
+    /// `step` is the last step that processes them.
+    fn file_complete(&self, path: PathBuf, step: Step) -> AnyResult<()> {
+        if self.config.exactly_once {
+            self.uncommitted.lock().unwrap().push_back((step, path));
+            Ok(())
+        } else {
+            self.record_consumed(&path)
+        }
+    }
+}
+
+struct DirectoryInputEndpoint {
+    inner: Arc<DirectoryInputEndpointInner>,
+    unparker: Unparker,
+}
+
+impl DirectoryInputEndpoint {
+    fn new(config: DirectoryInputConfig, consumer: Box<dyn InputConsumer>) -> AnyResult<Self> {
+        Pattern::new(&config.pattern).map_err(|e| {
+            AnyError::msg(format!("invalid file pattern '{}': {e}", config.pattern))
+        })?;
+
+        let consumed: Vec<PathBuf> = match read_to_string(&config.consumed_files_path) {
+            Ok(consumed_files) => consumed_files.lines().map(PathBuf::from).collect(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(AnyError::msg(format!(
+                    "Failed to read consumed files from '{}': {e}",
+                    config.consumed_files_path
+                )))
+            }
+        };
+
+        let consumed_files = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.consumed_files_path)
+            .map_err(|e| {
+                AnyError::msg(format!(
+                    "Failed to open '{}': {e}",
+                    config.consumed_files_path
+                ))
+            })?;
+
+        let inner = Arc::new(DirectoryInputEndpointInner {
+            config,
+            status: AtomicU32::new(PipelineState::Paused as u32),
+            consumed_files: Mutex::new(consumed_files),
+            uncommitted: Mutex::new(VecDeque::new()),
+            seek_position: Mutex::new(None),
+        });
+
+        let parker = Parker::new();
+        let unparker = parker.unparker().clone();
+        let worker_inner = inner.clone();
+        let _worker = spawn(move || Self::worker_thread(worker_inner, consumer, parker, consumed));
+
+        Ok(Self { inner, unparker })
+    }
+
+    fn worker_thread(
+        endpoint: Arc<DirectoryInputEndpointInner>,
+        mut consumer: Box<dyn InputConsumer>,
+        parker: Parker,
+        consumed: Vec<PathBuf>,
+    ) {
+        // Files consumed or opened so far.
+        let mut seen: HashSet<PathBuf> = consumed.iter().cloned().collect();
+        // Files whose contents have been pushed to the consumer.
+        let mut consumed = Arc::new(consumed);
+        // Files discovered by the last scan that haven't been opened yet.
+        let mut queue = VecDeque::new();
+        // File being read and the offset of the end of the data pushed from it.
+        let mut reader: Option<(PathBuf, BufReader<File>, u64)> = None;
+        // Last byte pushed to the consumer.
+        let mut last_byte = b'\n';
+        // Step that processes the last input buffer.
+        let mut step = 0;
+
+        loop {
+            match PipelineState::from_u32(endpoint.status.load(Ordering::Acquire)) {
+                Some(PipelineState::Paused) => parker.park(),
+                Some(PipelineState::Running) => {
+                    if let Some(position) = endpoint.seek_position.lock().unwrap().take() {
+                        seen = position.consumed.iter().cloned().collect();
+                        consumed = position.consumed;
+                        queue.clear();
+                        reader = None;
+                        if let Some((path, offset)) = position.current {
+                            seen.insert(path.clone());
+                            let file_reader = endpoint.open(&path).and_then(|mut file_reader| {
+                                file_reader.seek(SeekFrom::Start(offset))?;
+                                Ok(file_reader)
+                            });
+                            match file_reader {
+                                Ok(file_reader) => reader = Some((path, file_reader, offset)),
+                                Err(e) => {
+                                    consumer.error(true, e);
+                                    return;
+                                }
+                            }
+                        }
+                        // The position points to the end of a complete record.
+                        last_byte = b'\n';
+                    }
+
+                    if reader.is_none() {
+                        if queue.is_empty() {
+                            match endpoint.scan(&seen) {
+                                Ok(files) => queue = files,
+                                Err(e) => {
+                                    consumer.error(true, e);
+                                    return;
+                                }
+                            }
+                        }
+                        match queue.pop_front() {
+                            Some(path) => {
+                                // The file may have been removed since the scan.
+                                // Skip it for now; it will be picked up again
+                                // after a restart if it reappears.
+                                seen.insert(path.clone());
+                                match endpoint.open(&path) {
+                                    Ok(file_reader) => reader = Some((path, file_reader, 0)),
+                                    Err(e) => consumer.error(false, e),
+                                }
+                            }
+                            None if endpoint.config.follow => {
+                                sleep(Duration::from_millis(endpoint.config.poll_interval_ms))
+                            }
+                            None => {
+                                consumer.eoi();
+                                return;
+                            }
+                        }
+                        continue;
+                    }
+
+                    let (path, file_reader, offset) = reader.as_mut().unwrap();
+
+                    match file_reader.fill_buf() {
+                        Err(e) => {
+                            consumer.error(
+                                true,
+                                AnyError::msg(format!(
+                                    "Failed to read input file '{}': {e}",
+                                    path.display()
+                                )),
+                            );
+                            return;
+                        }
+                        Ok(data) if data.is_empty() => {
+                            let (path, _, _) = reader.take().unwrap();
+                            Arc::make_mut(&mut consumed).push(path.clone());
+
+                            // Make sure that records don't span files.
+                            if last_byte != b'\n' {
+                                let position = DirectoryPosition {
+                                    consumed: consumed.clone(),
+                                    current: None,
+                                };
+                                step = consumer.input_at(b"\n", Box::new(position));
+                                last_byte = b'\n';
+                            }
+
+                            if let Err(e) = endpoint.file_complete(path, step) {
+                                consumer.error(true, e);
+                                return;
+                            }
+                        }
+                        Ok(data) => {
+                            let len = data.len();
+                            *offset += len as u64;
+                            let position = DirectoryPosition {
+                                consumed: consumed.clone(),
+                                current: Some((path.clone(), *offset)),
+                            };
+                            step = consumer.input_at(data, Box::new(position));
+                            last_byte = data[len - 1];
+                            file_reader.consume(len);
+                        }
+                    }
+                }
+                Some(PipelineState::Terminated) => return,
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+impl InputEndpoint for DirectoryInputEndpoint {
+    fn pause(&self) -> AnyResult<()> {
+        // Notify worker thread via the status flag.  The worker may
+        // send another buffer downstream before the flag takes effect.
+        self.inner
+            .status
+            .store(PipelineState::Paused as u32, Ordering::Release);
+        Ok(())
+    }
+
+    fn start(&self) -> AnyResult<()> {
+        self.inner
+            .status
+            .store(PipelineState::Running as u32, Ordering::Release);
+
+        // Wake up the worker if it's paused.
+        self.unparker.unpark();
+        Ok(())
+    }
+
+    fn disconnect(&self) {
+        self.inner
+            .status
+            .store(PipelineState::Terminated as u32, Ordering::Release);
+
+        // Wake up the worker if it's paused.
+        self.unparker.unpark();
+    }
+
+    fn commit(&self, step: Step) -> AnyResult<()> {
+        if self.inner.config.exactly_once {
+            self.inner.commit(step)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn exactly_once(&self) -> Option<bool> {
+        Some(self.inner.config.exactly_once)
+    }
+
+    fn seek(&self, position: &InputPosition) -> AnyResult<()> {
+        let mut directory_position: DirectoryPosition =
+            serde_json::from_value(position.position.clone()).map_err(|e| {
+                AnyError::msg(format!(
+                    "invalid directory position {}: {e}",
+                    position.position
+                ))
+            })?;
+
+        // Push unparsed bytes again.
+        match &mut directory_position.current {
+            Some((_, offset)) if *offset >= position.unparsed_bytes => {
+                *offset -= position.unparsed_bytes
+            }
+            _ if position.unparsed_bytes == 0 => {}
+            _ => {
+                return Err(AnyError::msg(format!(
+                    "invalid directory position {}: fewer than {} bytes before the position",
+                    position.position, position.unparsed_bytes
+                )))
+            }
+        }
+
+        *self.inner.seek_position.lock().unwrap() = Some(directory_position);
+        Ok(())
+    }
+}
+
+impl Drop for DirectoryInputEndpoint {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{mock_input_pipeline, wait, MockDeZSet};
+    use serde::{Deserialize, Serialize};
+    use std::{
+        fs::{read_to_string, remove_file, rename, write},
+        path::Path,
+        thread::sleep,
+        time::Duration,
+    };
+    use tempfile::tempdir;
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+    struct TestStruct {
+        s: String,
+        b: bool,
+        i: i64,
+    }
+
+    fn flushed_ids(zset: &MockDeZSet<TestStruct>) -> Vec<i64> {
+        let mut ids = ingested_ids(zset);
+        ids.sort();
+        ids
+    }
+
+    // Returns ids in the order in which they were ingested.
+    fn ingested_ids(zset: &MockDeZSet<TestStruct>) -> Vec<i64> {
+        zset.state().flushed.iter().map(|(val, _)| val.i).collect()
+    }
+
+    // Returns the configuration of an endpoint that ingests `*.csv` files in
+    // `dir` and records consumed files in `dir/consumed`, with additional
+    // transport `options`.
+    fn directory_config(dir: &Path, options: &[&str]) -> String {
+        let options: String = options
+            .iter()
+            .map(|option| format!("        {option}\n"))
+            .collect();
+        format!(
+            r#"
+stream: test_input
+transport:
+    name: directory
+    config:
+        pattern: {:?}
+        consumed_files_path: {:?}
+{options}format:
+    name: csv
+"#,
+            dir.join("*.csv"),
+            dir.join("consumed"),
+        )
+    }
+
+    #[test]
+    fn test_directory() {
+        let dir = tempdir().unwrap();
+        let consumed_files_path = dir.path().join("consumed");
+
+        let config_str = format!(
+            r#"
+stream: test_input
+transport:
+    name: directory
+    config:
+        pattern: {:?}
+        consumed_files_path: {:?}
+        follow: true
+        poll_interval_ms: 10
+format:
+    name: csv
+"#,
+            dir.path().join("*.csv"),
+            consumed_files_path,
+        );
+
+        write(dir.path().join("1.csv"), "foo,true,1\nbar,false,2\n").unwrap();
+        // Records don't span files, even if a file doesn't end with a newline.
+        write(dir.path().join("2.csv"), "foo,true,3").unwrap();
+        write(dir.path().join("3.txt"), "foo,true,4\n").unwrap();
+
+        let (endpoint, _consumer, zset) =
+            mock_input_pipeline::<TestStruct>(serde_yaml::from_str(&config_str).unwrap());
+        assert_eq!(endpoint.exactly_once(), Some(false));
+        endpoint.start().unwrap();
+        wait(|| zset.state().flushed.len() == 3, None);
+        assert_eq!(flushed_ids(&zset), vec![1, 2, 3]);
+
+        // Files that appear later are picked up by the next scan.
+        let tmp_path = dir.path().join("4.tmp");
+        write(&tmp_path, "foo,true,5\n").unwrap();
+        rename(&tmp_path, dir.path().join("4.csv")).unwrap();
+        wait(|| zset.state().flushed.len() == 4, None);
+        assert_eq!(flushed_ids(&zset), vec![1, 2, 3, 5]);
+
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        wait(
+            || {
+                read_to_string(&consumed_files_path)
+                    .unwrap()
+                    .lines()
+                    .count()
+                    == 3
+            },
+            None,
+        );
+        assert_eq!(
+            read_to_string(&consumed_files_path).unwrap(),
+            format!("{}\n{}\n{}\n", path("1.csv"), path("2.csv"), path("4.csv"))
+        );
+
+        endpoint.disconnect();
+        drop(endpoint);
+
+        // A new endpoint skips files that have already been consumed.
+        write(dir.path().join("0.csv"), "foo,true,6\n").unwrap();
+        let (endpoint, _consumer, zset) =
+            mock_input_pipeline::<TestStruct>(serde_yaml::from_str(&config_str).unwrap());
+        endpoint.start().unwrap();
+        wait(|| zset.state().flushed.len() == 1, None);
+        sleep(Duration::from_millis(100));
+        assert_eq!(flushed_ids(&zset), vec![6]);
+    }
+
+    #[test]
+    fn test_directory_order() {
+        let dir = tempdir().unwrap();
+
+        // Some file systems store modification times with a granularity of
+        // one second.
+        write(dir.path().join("b.csv"), "foo,true,1\n").unwrap();
+        sleep(Duration::from_millis(1100));
+        write(dir.path().join("c.csv"), "foo,true,3\n").unwrap();
+        sleep(Duration::from_millis(1100));
+        write(dir.path().join("a.csv"), "foo,true,2\n").unwrap();
+
+        for (order, expected) in [("name", vec![2, 1, 3]), ("mtime", vec![1, 3, 2])] {
+            let _ = remove_file(dir.path().join("consumed"));
+            let config_str = directory_config(dir.path(), &[&format!("order: {order}")]);
+
+            let (endpoint, consumer, zset) =
+                mock_input_pipeline::<TestStruct>(serde_yaml::from_str(&config_str).unwrap());
+            endpoint.start().unwrap();
+            wait(|| consumer.state().eoi, None);
+            assert_eq!(ingested_ids(&zset), expected);
+        }
+    }
+
+    #[test]
+    fn test_directory_exactly_once() {
+        let dir = tempdir().unwrap();
+        let consumed_files_path = dir.path().join("consumed");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let config_str = directory_config(dir.path(), &["exactly_once: true"]);
+
+        write(dir.path().join("1.csv"), "foo,true,1\n").unwrap();
+        write(dir.path().join("2.csv"), "foo,true,2\n").unwrap();
+
+        let (endpoint, consumer, zset) =
+            mock_input_pipeline::<TestStruct>(serde_yaml::from_str(&config_str).unwrap());
+        assert_eq!(endpoint.exactly_once(), Some(true));
+        endpoint.start().unwrap();
+        wait(|| consumer.state().eoi, None);
+        assert_eq!(flushed_ids(&zset), vec![1, 2]);
+
+        // Files are only recorded as consumed once the steps that processed
+        // them are committed.  The mock consumer processes all inputs in
+        // step 0.
+        assert_eq!(read_to_string(&consumed_files_path).unwrap(), "");
+        endpoint.commit(0).unwrap();
+        assert_eq!(read_to_string(&consumed_files_path).unwrap(), "");
+
+        // A restart before the commit ingests the files again.
+        endpoint.disconnect();
+        drop(endpoint);
+
+        let (endpoint, consumer, zset) =
+            mock_input_pipeline::<TestStruct>(serde_yaml::from_str(&config_str).unwrap());
+        endpoint.start().unwrap();
+        wait(|| consumer.state().eoi, None);
+        assert_eq!(flushed_ids(&zset), vec![1, 2]);
+
+        endpoint.commit(1).unwrap();
+        assert_eq!(
+            read_to_string(&consumed_files_path).unwrap(),
+            format!("{}\n{}\n", path("1.csv"), path("2.csv"))
+        );
+        endpoint.disconnect();
+        drop(endpoint);
+
+        // Committed files are skipped after a restart.
+        write(dir.path().join("3.csv"), "foo,true,3\n").unwrap();
+        let (endpoint, consumer, zset) =
+            mock_input_pipeline::<TestStruct>(serde_yaml::from_str(&config_str).unwrap());
+        endpoint.start().unwrap();
+        wait(|| consumer.state().eoi, None);
+        assert_eq!(flushed_ids(&zset), vec![3]);
+    }
+}
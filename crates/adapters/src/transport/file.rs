@@ -1,20 +1,26 @@
-use super::{InputConsumer, InputEndpoint, InputTransport, OutputEndpoint, OutputTransport};
-use crate::PipelineState;
+use super::{
+    InputConsumer, InputEndpoint, InputPosition, InputTransport, OutputEndpoint, OutputTransport,
+};
+use crate::{PipelineState, Step};
 use anyhow::{Error as AnyError, Result as AnyResult};
 use crossbeam::sync::{Parker, Unparker};
+use log::error;
 use num_traits::FromPrimitive;
 use serde::Deserialize;
 use serde_yaml::Value as YamlValue;
 use std::{
     borrow::Cow,
-    fs::File,
-    io::{BufRead, BufReader, Write},
+    cmp::max,
+    ffi::OsStr,
+    fs::{hard_link, read_dir, remove_file, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom, Write},
+    path::Path,
     sync::{
         atomic::{AtomicU32, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     thread::{sleep, spawn},
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use utoipa::ToSchema;
 
@@ -65,6 +71,9 @@ pub struct FileInputConfig {
 struct FileInputEndpoint {
     config: FileInputConfig,
     status: Arc<AtomicU32>,
+    /// Offset requested via [`InputEndpoint::seek`], applied by the worker
+    /// thread before reading more data.
+    seek_offset: Arc<Mutex<Option<u64>>>,
     unparker: Option<Unparker>,
 }
 
@@ -73,6 +82,7 @@ impl FileInputEndpoint {
         Self {
             config,
             status: Arc::new(AtomicU32::new(PipelineState::Paused as u32)),
+            seek_offset: Arc::new(Mutex::new(None)),
             unparker: None,
         }
     }
@@ -92,8 +102,11 @@ impl FileInputEndpoint {
         let parker = Parker::new();
         self.unparker = Some(parker.unparker().clone());
         let status = self.status.clone();
+        let seek_offset = self.seek_offset.clone();
         let follow = self.config.follow;
-        let _worker = spawn(move || Self::worker_thread(reader, consumer, parker, status, follow));
+        let _worker = spawn(move || {
+            Self::worker_thread(reader, consumer, parker, status, seek_offset, follow)
+        });
         Ok(())
     }
 
@@ -108,12 +121,23 @@ impl FileInputEndpoint {
         mut consumer: Box<dyn InputConsumer>,
         parker: Parker,
         status: Arc<AtomicU32>,
+        seek_offset: Arc<Mutex<Option<u64>>>,
         follow: bool,
     ) {
+        // Offset of the end of the data pushed to the consumer so far, reported
+        // to the consumer as the endpoint's position.
+        let mut offset = 0;
         loop {
             match PipelineState::from_u32(status.load(Ordering::Acquire)) {
                 Some(PipelineState::Paused) => parker.park(),
                 Some(PipelineState::Running) => {
+                    if let Some(new_offset) = seek_offset.lock().unwrap().take() {
+                        if let Err(e) = reader.seek(SeekFrom::Start(new_offset)) {
+                            consumer.error(true, AnyError::from(e));
+                            return;
+                        }
+                        offset = new_offset;
+                    }
                     let data = reader.fill_buf();
                     match data {
                         Err(e) => {
@@ -130,8 +154,9 @@ impl FileInputEndpoint {
                         }
                         Ok(data) => {
                             // println!("read {} bytes from file", data.len());
-                            consumer.input(data);
                             let len = data.len();
+                            offset += len as u64;
+                            consumer.input_at(data, Box::new(offset));
                             reader.consume(len);
                         }
                     }
@@ -168,6 +193,23 @@ impl InputEndpoint for FileInputEndpoint {
         // Wake up the worker if it's paused.
         self.unpark();
     }
+
+    fn seek(&self, position: &InputPosition) -> AnyResult<()> {
+        let offset = position.position.as_u64().ok_or_else(|| {
+            AnyError::msg(format!(
+                "invalid file position {}: expected a byte offset",
+                position.position
+            ))
+        })?;
+        let offset = offset.checked_sub(position.unparsed_bytes).ok_or_else(|| {
+            AnyError::msg(format!(
+                "invalid file position {}: fewer than {} bytes before the position",
+                position.position, position.unparsed_bytes
+            ))
+        })?;
+        *self.seek_offset.lock().unwrap() = Some(offset);
+        Ok(())
+    }
 }
 
 impl Drop for FileInputEndpoint {
@@ -200,39 +242,409 @@ impl OutputTransport for FileOutputTransport {
 #[derive(Deserialize, ToSchema)]
 pub struct FileOutputConfig {
     /// File path.
+    ///
+    /// When file rotation or atomic mode is enabled, this is a template for
+    /// the names of output files.  The file name (but not the directory)
+    /// can contain the following placeholders:
+    ///
+    /// * `{seq}` - sequence number of the file.  Required.  When the
+    ///   endpoint is created, numbering resumes after the highest sequence
+    ///   number of existing files that match the template.
+    /// * `{timestamp}` - time when the file was created, in milliseconds
+    ///   since the UNIX epoch.  Optional.
+    ///
+    /// Existing files are never overwritten in this mode: if a file with
+    /// the next name already exists, e.g., because it was created by
+    /// another writer, the endpoint skips to the next sequence number.
+    /// Without rotation, the file at `path` is truncated if it exists.
     path: String,
+
+    /// Start a new file once the current file reaches this size.
+    ///
+    /// Rotation is checked at the end of every step, and the output of a
+    /// step is never split across files, so files can exceed this size.
+    max_file_size_bytes: Option<u64>,
+
+    /// Start a new file at the end of the first step completed after the
+    /// current file has been open for this many seconds.
+    max_file_age_secs: Option<u64>,
+
+    /// Start a new file after writing the outputs of this many steps to
+    /// the current file.
+    max_steps_per_file: Option<u64>,
+
+    /// Enable atomic mode.
+    ///
+    /// When `true`, each file is written under a temporary name, obtained by
+    /// appending `.tmp` to the file name, and moved to its final name once
+    /// the file is complete, so that readers never observe partially written
+    /// files.
+    /// Unless other rotation options are specified, the output of each step
+    /// is written to a separate file.
+    #[serde(default)]
+    atomic: bool,
 }
 
-struct FileOutputEndpoint {
+impl FileOutputConfig {
+    /// `true` if the endpoint may write more than one file.
+    fn rotation_enabled(&self) -> bool {
+        self.atomic
+            || self.max_file_size_bytes.is_some()
+            || self.max_file_age_secs.is_some()
+            || self.max_steps_per_file.is_some()
+    }
+
+    fn validate(&self) -> AnyResult<()> {
+        if self.rotation_enabled() {
+            // Timestamps alone don't make file names unique, since several
+            // files can be created within the same millisecond.
+            if !self.path.contains("{seq}") {
+                return Err(AnyError::msg(format!(
+                    "output file path '{}' must contain a '{{seq}}' placeholder when file rotation or atomic mode is enabled",
+                    self.path
+                )));
+            }
+
+            let dir = Path::new(&self.path).parent().unwrap_or(Path::new(""));
+            let dir = dir.to_string_lossy();
+            if dir.contains("{seq}") || dir.contains("{timestamp}") {
+                return Err(AnyError::msg(format!(
+                    "output file path '{}' can only contain placeholders in the file name",
+                    self.path
+                )));
+            }
+        }
+
+        for (name, value) in [
+            ("max_file_size_bytes", self.max_file_size_bytes),
+            ("max_file_age_secs", self.max_file_age_secs),
+            ("max_steps_per_file", self.max_steps_per_file),
+        ] {
+            if value == Some(0) {
+                return Err(AnyError::msg(format!("'{name}' must be greater than 0")));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An output file written by [`FileOutputEndpoint`].
+struct OutputFile {
     file: File,
+
+    /// Path to rename the file to once it is complete, in atomic mode.
+    final_path: Option<String>,
+
+    /// Path the file is written to.
+    path: String,
+
+    /// Number of bytes written to the file.
+    bytes: u64,
+
+    /// Number of steps written to the file.
+    steps: u64,
+
+    /// Time when the file was created.
+    created: Instant,
+}
+
+impl OutputFile {
+    fn new(file: File, path: String, final_path: Option<String>) -> Self {
+        Self {
+            file,
+            final_path,
+            path,
+            bytes: 0,
+            steps: 0,
+            created: Instant::now(),
+        }
+    }
+
+    /// Create a file at `path`, truncating the existing file if any.
+    fn create(path: String) -> AnyResult<Self> {
+        let file = File::create(&path)
+            .map_err(|e| AnyError::msg(format!("Failed to create output file '{path}': {e}")))?;
+
+        Ok(Self::new(file, path, None))
+    }
+
+    /// Create a new file at `path` or, in atomic mode, at its temporary path.
+    ///
+    /// Returns `None` if the file already exists or, in atomic mode, if
+    /// either path exists.
+    fn create_new(path: String, atomic: bool) -> AnyResult<Option<Self>> {
+        let (path, final_path) = if atomic {
+            (format!("{path}.tmp"), Some(path))
+        } else {
+            (path, None)
+        };
+
+        if let Some(final_path) = &final_path {
+            if Path::new(final_path).exists() {
+                return Ok(None);
+            }
+        }
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => Ok(Some(Self::new(file, path, final_path))),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(None),
+            Err(e) => Err(AnyError::msg(format!(
+                "Failed to create output file '{path}': {e}"
+            ))),
+        }
+    }
+
+    /// Make sure all data is written to disk and, in atomic mode, move the
+    /// file to its final path.
+    ///
+    /// The file is moved by creating a hard link at the final path, which,
+    /// unlike renaming the file, fails instead of replacing a file created
+    /// at that path in the meantime.
+    fn close(self) -> AnyResult<()> {
+        if let Some(final_path) = &self.final_path {
+            self.file.sync_all()?;
+            hard_link(&self.path, final_path).map_err(|e| {
+                AnyError::msg(format!(
+                    "Failed to move output file '{}' to '{final_path}': {e}",
+                    self.path
+                ))
+            })?;
+            remove_file(&self.path).map_err(|e| {
+                AnyError::msg(format!(
+                    "Failed to remove temporary output file '{}': {e}",
+                    self.path
+                ))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `s` into its longest prefix of decimal digits and the rest of the
+/// string, or returns `None` if `s` doesn't start with a digit.
+fn split_digits(s: &str) -> Option<(&str, &str)> {
+    let len = s.bytes().take_while(u8::is_ascii_digit).count();
+    (len > 0).then(|| s.split_at(len))
+}
+
+/// Matches file name `name` against file name template `template` (see
+/// [`FileOutputConfig::path`]) and returns the value of its `{seq}`
+/// placeholder.
+fn match_seq(mut template: &str, mut name: &str) -> Option<u64> {
+    let mut seq = None;
+
+    while !template.is_empty() {
+        if let Some(rest) = template.strip_prefix("{seq}") {
+            let (digits, tail) = split_digits(name)?;
+            seq = Some(digits.parse().ok()?);
+            template = rest;
+            name = tail;
+        } else if let Some(rest) = template.strip_prefix("{timestamp}") {
+            name = split_digits(name)?.1;
+            template = rest;
+        } else {
+            let c = template.chars().next().unwrap();
+            name = name.strip_prefix(c)?;
+            template = &template[c.len_utf8()..];
+        }
+    }
+
+    if name.is_empty() {
+        seq
+    } else {
+        None
+    }
+}
+
+/// Returns the sequence number that follows the highest sequence number of
+/// existing files that match `template`, including temporary files left
+/// behind in atomic mode.
+fn resume_seq(template: &str) -> AnyResult<u64> {
+    let template = Path::new(template);
+    let dir = match template.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name_template = template
+        .file_name()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| {
+            AnyError::msg(format!("invalid output file path '{}'", template.display()))
+        })?;
+
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        // Creating the first file will report the error.
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => {
+            return Err(AnyError::msg(format!(
+                "Failed to read output directory '{}': {e}",
+                dir.display()
+            )))
+        }
+    };
+
+    let mut next_seq = 0;
+    for entry in entries {
+        let file_name = entry?.file_name();
+        let seq = file_name.to_str().and_then(|name| {
+            match_seq(name_template, name).or_else(|| {
+                name.strip_suffix(".tmp")
+                    .and_then(|name| match_seq(name_template, name))
+            })
+        });
+        if let Some(seq) = seq {
+            next_seq = max(next_seq, seq + 1);
+        }
+    }
+
+    Ok(next_seq)
+}
+
+struct FileOutputEndpoint {
+    config: FileOutputConfig,
+
+    /// Currently open file.  Without rotation, the file is created when the
+    /// endpoint is created and is never closed; otherwise, it is created
+    /// lazily when the first buffer is written to it.
+    file: Option<OutputFile>,
+
+    /// Sequence number of the next file.  Sequence numbers of files that
+    /// already exist are skipped.
+    next_seq: u64,
+
+    /// `true` between [`OutputEndpoint::batch_start`] and
+    /// [`OutputEndpoint::batch_end`] calls.
+    in_batch: bool,
 }
 
 impl FileOutputEndpoint {
     fn new(config: FileOutputConfig) -> AnyResult<Self> {
-        let file = File::create(&config.path).map_err(|e| {
-            AnyError::msg(format!(
-                "Failed to create output file '{}': {e}",
-                config.path
-            ))
-        })?;
-        Ok(Self { file })
+        config.validate()?;
+
+        let (file, next_seq) = if config.rotation_enabled() {
+            (None, resume_seq(&config.path)?)
+        } else {
+            (Some(OutputFile::create(config.path.clone())?), 0)
+        };
+
+        Ok(Self {
+            config,
+            file,
+            next_seq,
+            in_batch: false,
+        })
+    }
+
+    /// Returns the current file, creating a new one if necessary.
+    fn file(&mut self) -> AnyResult<&mut OutputFile> {
+        // Every iteration tries a new sequence number, so the loop terminates
+        // once all existing files are skipped.
+        while self.file.is_none() {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let path = self
+                .config
+                .path
+                .replace("{seq}", &self.next_seq.to_string())
+                .replace("{timestamp}", &timestamp.to_string());
+            self.next_seq += 1;
+
+            self.file = OutputFile::create_new(path, self.config.atomic)?;
+        }
+
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// Called after all outputs of a step have been written.  Closes the
+    /// current file if it is due for rotation.
+    fn step_complete(&mut self) -> AnyResult<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        file.steps += 1;
+
+        let config = &self.config;
+        let max_steps_per_file = match config.max_steps_per_file {
+            None if config.atomic
+                && config.max_file_size_bytes.is_none()
+                && config.max_file_age_secs.is_none() =>
+            {
+                Some(1)
+            }
+            max_steps_per_file => max_steps_per_file,
+        };
+
+        let rotate = config
+            .max_file_size_bytes
+            .map_or(false, |max| file.bytes >= max)
+            || config.max_file_age_secs.map_or(false, |max| {
+                file.created.elapsed() >= Duration::from_secs(max)
+            })
+            || max_steps_per_file.map_or(false, |max| file.steps >= max);
+
+        if rotate {
+            self.file.take().unwrap().close()?;
+        }
+        Ok(())
     }
 }
 
 impl OutputEndpoint for FileOutputEndpoint {
     fn push_buffer(&mut self, buffer: &[u8]) -> AnyResult<()> {
-        self.file.write_all(buffer)?;
+        let file = self.file()?;
+        file.file.write_all(buffer)?;
+        file.bytes += buffer.len() as u64;
+
+        // Buffers pushed outside of a batch are treated as separate steps.
+        if !self.in_batch {
+            self.step_complete()?;
+        }
         Ok(())
     }
+
+    fn batch_start(&mut self, _step: Option<Step>) -> AnyResult<()> {
+        self.in_batch = true;
+        Ok(())
+    }
+
+    fn batch_end(&mut self) -> AnyResult<()> {
+        self.in_batch = false;
+        self.step_complete()
+    }
+}
+
+impl Drop for FileOutputEndpoint {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            if let Err(e) = file.close() {
+                error!("{e}");
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::test::{mock_input_pipeline, wait};
+    use super::FileOutputTransport;
+    use crate::{
+        test::{mock_input_pipeline, wait},
+        OutputEndpoint, OutputTransport,
+    };
+    use anyhow::Result as AnyResult;
     use csv::WriterBuilder as CsvWriterBuilder;
     use serde::{Deserialize, Serialize};
-    use std::{io::Write, thread::sleep, time::Duration};
-    use tempfile::NamedTempFile;
+    use std::{
+        fs::{read_dir, read_to_string, write},
+        io::Write,
+        thread::sleep,
+        time::Duration,
+    };
+    use tempfile::{tempdir, NamedTempFile};
 
     #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
     struct TestStruct {
@@ -369,7 +781,7 @@ format:
             || {
                 let state = consumer.state();
                 // println!("result: {:?}", state.parser_result);
-                state.parser_result.is_some() && state.parser_result.as_ref().unwrap().is_err()
+                state.parser_result.is_some() && !state.parser_result.as_ref().unwrap().1.is_empty()
             },
             None,
         );
@@ -379,4 +791,156 @@ format:
 
         endpoint.disconnect();
     }
+
+    fn output_endpoint(config_str: &str) -> AnyResult<Box<dyn OutputEndpoint>> {
+        FileOutputTransport.new_endpoint(
+            "test_output",
+            &serde_yaml::from_str(config_str).unwrap(),
+            Box::new(|_, _| {}),
+        )
+    }
+
+    fn write_step(endpoint: &mut dyn OutputEndpoint, data: &[u8]) {
+        endpoint.batch_start(Some(0)).unwrap();
+        endpoint.push_buffer(data).unwrap();
+        endpoint.batch_end().unwrap();
+    }
+
+    #[test]
+    fn test_file_output_rotation() {
+        let dir = tempdir().unwrap();
+        let path = |seq: u32| dir.path().join(format!("out-{seq}.csv"));
+
+        assert!(output_endpoint(&format!(
+            "{{path: {:?}, max_steps_per_file: 2}}",
+            dir.path().join("out.csv")
+        ))
+        .is_err());
+        assert!(output_endpoint(&format!(
+            "{{path: {:?}, max_steps_per_file: 2}}",
+            dir.path().join("out-{timestamp}.csv")
+        ))
+        .is_err());
+        assert!(output_endpoint(&format!(
+            "{{path: {:?}, max_steps_per_file: 0}}",
+            dir.path().join("out-{seq}.csv")
+        ))
+        .is_err());
+
+        let mut endpoint = output_endpoint(&format!(
+            "{{path: {:?}, max_file_size_bytes: 4}}",
+            dir.path().join("out-{seq}.csv")
+        ))
+        .unwrap();
+
+        // Files are rotated at step boundaries only.
+        write_step(endpoint.as_mut(), b"ab\n");
+        write_step(endpoint.as_mut(), b"cd\n");
+        write_step(endpoint.as_mut(), b"ef\n");
+        assert_eq!(read_to_string(path(0)).unwrap(), "ab\ncd\n");
+        assert_eq!(read_to_string(path(1)).unwrap(), "ef\n");
+        assert!(!path(2).exists());
+    }
+
+    #[test]
+    fn test_file_output_atomic() {
+        let dir = tempdir().unwrap();
+        let path = |seq: u32| dir.path().join(format!("out-{seq}.csv"));
+        let tmp_path = |seq: u32| dir.path().join(format!("out-{seq}.csv.tmp"));
+
+        let mut endpoint = output_endpoint(&format!(
+            "{{path: {:?}, atomic: true}}",
+            dir.path().join("out-{seq}.csv")
+        ))
+        .unwrap();
+
+        // Each step is written to a separate file, which only becomes
+        // visible once the step is complete.
+        endpoint.batch_start(Some(0)).unwrap();
+        endpoint.push_buffer(b"ab\n").unwrap();
+        endpoint.push_buffer(b"cd\n").unwrap();
+        assert!(!path(0).exists());
+        assert!(tmp_path(0).exists());
+        endpoint.batch_end().unwrap();
+        assert_eq!(read_to_string(path(0)).unwrap(), "ab\ncd\n");
+        assert!(!tmp_path(0).exists());
+
+        // Steps without outputs don't create files.
+        endpoint.batch_start(Some(0)).unwrap();
+        endpoint.batch_end().unwrap();
+
+        write_step(endpoint.as_mut(), b"ef\n");
+        assert_eq!(read_to_string(path(1)).unwrap(), "ef\n");
+        assert!(!path(2).exists());
+    }
+
+    #[test]
+    fn test_file_output_resume() {
+        let dir = tempdir().unwrap();
+        let tmp_path = dir.path().join("out-3-1000.csv.tmp");
+        let config = format!(
+            "{{path: {:?}, atomic: true}}",
+            dir.path().join("out-{seq}-{timestamp}.csv")
+        );
+        let files = |prefix: &str| -> Vec<String> {
+            read_dir(dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .filter(|name| name.starts_with(prefix))
+                .collect()
+        };
+
+        let mut endpoint = output_endpoint(&config).unwrap();
+        write_step(endpoint.as_mut(), b"ab\n");
+        drop(endpoint);
+        assert_eq!(files("out-0-").len(), 1);
+
+        // A temporary file left behind by a writer that crashed and files
+        // that don't match the template.
+        write(&tmp_path, b"partial\n").unwrap();
+        write(dir.path().join("out-10.csv"), b"").unwrap();
+        write(dir.path().join("other-20-1000.csv"), b"").unwrap();
+
+        // Numbering resumes after the highest existing sequence number.
+        let mut endpoint = output_endpoint(&config).unwrap();
+        write_step(endpoint.as_mut(), b"cd\n");
+        drop(endpoint);
+
+        let new_files = files("out-4-");
+        assert_eq!(new_files.len(), 1);
+        assert_eq!(
+            read_to_string(dir.path().join(&new_files[0])).unwrap(),
+            "cd\n"
+        );
+        assert_eq!(read_to_string(&tmp_path).unwrap(), "partial\n");
+    }
+
+    #[test]
+    fn test_file_output_clash() {
+        let dir = tempdir().unwrap();
+        let path = |seq: u32| dir.path().join(format!("out-{seq}.csv"));
+
+        let mut endpoint = output_endpoint(&format!(
+            "{{path: {:?}, max_steps_per_file: 1}}",
+            dir.path().join("out-{seq}.csv")
+        ))
+        .unwrap();
+
+        // Files created by another writer after the endpoint was created are
+        // skipped rather than overwritten.
+        write(path(0), b"other\n").unwrap();
+        write_step(endpoint.as_mut(), b"ab\n");
+        assert_eq!(read_to_string(path(0)).unwrap(), "other\n");
+        assert_eq!(read_to_string(path(1)).unwrap(), "ab\n");
+
+        let mut endpoint = output_endpoint(&format!(
+            "{{path: {:?}, atomic: true}}",
+            dir.path().join("out-{seq}.csv")
+        ))
+        .unwrap();
+        write(path(2), b"other\n").unwrap();
+        write_step(endpoint.as_mut(), b"cd\n");
+        assert_eq!(read_to_string(path(2)).unwrap(), "other\n");
+        assert_eq!(read_to_string(path(3)).unwrap(), "cd\n");
+    }
 }
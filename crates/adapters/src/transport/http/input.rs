@@ -196,6 +196,16 @@ impl InputEndpoint for HttpInputEndpoint {
             .state
             .store(PipelineState::Terminated as u32, Ordering::Release);
         self.notify_sockets();
+
+        // Release the endpoint name, so that it can be reused by a new
+        // endpoint.
+        let mut endpoint_map = INPUT_HTTP_ENDPOINTS.write().unwrap();
+        if endpoint_map
+            .get(self.name())
+            .map_or(false, |ep| Arc::ptr_eq(&ep.inner, &self.inner))
+        {
+            endpoint_map.remove(self.name());
+        }
     }
 }
 
@@ -14,12 +14,15 @@ use serde_yaml::Value as YamlValue;
 use std::{
     borrow::Cow,
     collections::{BTreeMap, HashSet},
-    sync::{Arc, RwLock},
+    sync::{Arc, RwLock, Weak},
 };
 use utoipa::ToSchema;
 
 /// Global map of output HTTP endpoints.
-static OUTPUT_HTTP_ENDPOINTS: Lazy<RwLock<BTreeMap<String, HttpOutputEndpoint>>> =
+///
+/// The map holds weak references, so that the name of an endpoint can be
+/// reused once the endpoint has been disconnected and dropped.
+static OUTPUT_HTTP_ENDPOINTS: Lazy<RwLock<BTreeMap<String, Weak<HttpOutputEndpointInner>>>> =
     Lazy::new(|| RwLock::new(BTreeMap::new()));
 
 /// `OutputTransport` implementation that sends data to websockets.
@@ -52,7 +55,8 @@ impl HttpOutputTransport {
             .read()
             .unwrap()
             .get(endpoint_name)
-            .map(Clone::clone)
+            .and_then(Weak::upgrade)
+            .map(|inner| HttpOutputEndpoint { inner })
             .ok_or_else(|| anyhow!("unknown HTTP output endpoint '{endpoint_name}'"))?;
         if endpoint.num_sockets() >= MAX_SOCKETS_PER_ENDPOINT {
             return Err(anyhow!(
@@ -112,7 +116,7 @@ impl HttpOutputEndpoint {
     ) -> AnyResult<Self> {
         let mut endpoint_map = OUTPUT_HTTP_ENDPOINTS.write().unwrap();
 
-        if endpoint_map.contains_key(name) {
+        if endpoint_map.get(name).and_then(Weak::upgrade).is_some() {
             return Err(anyhow!(format!(
                 "duplicate HTTP output endpoint name '{name}'"
             )));
@@ -122,7 +126,7 @@ impl HttpOutputEndpoint {
             inner: Arc::new(HttpOutputEndpointInner::new(name, async_error_callback)),
         };
 
-        endpoint_map.insert(name.to_string(), endpoint.clone());
+        endpoint_map.insert(name.to_string(), Arc::downgrade(&endpoint.inner));
         Ok(endpoint)
     }
 
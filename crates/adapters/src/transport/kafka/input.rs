@@ -1,19 +1,23 @@
 use super::{refine_kafka_error, KafkaLogLevel};
-use crate::{InputConsumer, InputEndpoint, InputTransport, PipelineState};
+use crate::{InputConsumer, InputEndpoint, InputPosition, InputTransport, PipelineState, Step};
 use anyhow::{Error as AnyError, Result as AnyResult};
 use log::debug;
 use num_traits::FromPrimitive;
+use once_cell::sync::Lazy;
 use rdkafka::{
     config::{FromClientConfigAndContext, RDKafkaLogLevel},
-    consumer::{BaseConsumer, Consumer, ConsumerContext, Rebalance, RebalanceProtocol},
+    consumer::{
+        BaseConsumer, CommitMode, Consumer, ConsumerContext, ConsumerGroupMetadata, Rebalance,
+        RebalanceProtocol,
+    },
     error::{KafkaError, KafkaResult},
-    ClientConfig, ClientContext, Message,
+    ClientConfig, ClientContext, Message, Offset, TopicPartitionList,
 };
 use serde::Deserialize;
 use serde_yaml::Value as YamlValue;
 use std::{
     borrow::Cow,
-    collections::BTreeMap,
+    collections::{BTreeMap, VecDeque},
     env,
     sync::{
         atomic::{AtomicU32, Ordering},
@@ -38,6 +42,51 @@ const fn default_group_join_timeout_secs() -> u32 {
     10
 }
 
+const fn default_max_uncommitted_messages() -> usize {
+    100_000
+}
+
+/// Kafka input endpoints in exactly-once mode indexed by consumer group id.
+///
+/// Transactional output endpoints use this registry to find the endpoint
+/// whose offsets they commit as part of their transactions (see
+/// [`KafkaOutputConfig::input_group_id`](`super::KafkaOutputConfig`)).
+static EXACTLY_ONCE_ENDPOINTS: Lazy<Mutex<BTreeMap<String, Weak<KafkaInputEndpointInner>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Returns the offsets of the messages processed by steps `0..=step` that
+/// the exactly-once input endpoint in consumer group `group_id` has received,
+/// along with the metadata of the consumer group, in the form expected by
+/// `Producer::send_offsets_to_transaction`.
+///
+/// Returns `None` if there are no offsets to commit.
+pub(super) fn transaction_offsets(
+    group_id: &str,
+    step: Step,
+) -> AnyResult<Option<(TopicPartitionList, ConsumerGroupMetadata)>> {
+    let endpoint = EXACTLY_ONCE_ENDPOINTS
+        .lock()
+        .unwrap()
+        .get(group_id)
+        .and_then(Weak::upgrade)
+        .ok_or_else(|| {
+            AnyError::msg(format!(
+                "there is no Kafka input endpoint with 'enable.auto.commit' set to 'false' in consumer group '{group_id}'"
+            ))
+        })?;
+
+    let offsets = endpoint.offsets(step + 1, false)?;
+    if offsets.count() == 0 {
+        return Ok(None);
+    }
+    let metadata = endpoint.kafka_consumer.group_metadata().ok_or_else(|| {
+        AnyError::msg(format!(
+            "failed to retrieve the metadata of consumer group '{group_id}'"
+        ))
+    })?;
+    Ok(Some((offsets, metadata)))
+}
+
 /// `InputTransport` implementation that reads data from one or more
 /// Kafka topics.
 pub struct KafkaInputTransport;
@@ -68,8 +117,21 @@ pub struct KafkaInputConfig {
     /// used to configure the Kafka consumer.  Not all options are valid with
     /// this Kafka adapter:
     ///
-    /// * "enable.auto.commit", if present, must be set to "false",
-    /// * "enable.auto.offset.store", if present, must be set to "false"
+    /// * "enable.auto.commit", if present, must be set to "true", except in
+    ///   exactly-once mode (see below),
+    /// * "enable.auto.offset.store", if present, must be set to the same value
+    ///   as "enable.auto.commit".
+    ///
+    /// Setting "enable.auto.commit" to "false" enables exactly-once mode, in
+    /// which the endpoint commits the offset of a message only after the
+    /// outputs of the circuit step that processed the message have been
+    /// delivered by all output endpoints.  This mode requires the pipeline
+    /// to run in [exactly-once mode](`crate::GlobalPipelineConfig::exactly_once`)
+    /// and each message to contain only complete records.  Conversely, a
+    /// pipeline in exactly-once mode rejects endpoints that commit offsets
+    /// automatically.  A transactional Kafka output endpoint can commit the
+    /// offsets atomically with its outputs (see
+    /// [`KafkaOutputConfig`](`super::KafkaOutputConfig`)).
     #[serde(flatten)]
     kafka_options: BTreeMap<String, String>,
 
@@ -86,6 +148,17 @@ pub struct KafkaInputConfig {
     /// consumer group during initialization.
     #[serde(default = "default_group_join_timeout_secs")]
     group_join_timeout_secs: u32,
+
+    /// Maximum number of received messages whose offsets have not been
+    /// committed yet in exactly-once mode.
+    ///
+    /// When this limit is reached, the endpoint stops reading from Kafka until
+    /// the outputs of the steps that processed these messages are delivered
+    /// and their offsets are committed.
+    ///
+    /// Defaults to 100,000.
+    #[serde(default = "default_max_uncommitted_messages")]
+    max_uncommitted_messages: usize,
 }
 
 // The auto-derived implementation gets confused by the flattened
@@ -115,6 +188,19 @@ impl<'s> ToSchema<'s> for KafkaInputConfig {
                         .format(Some(SchemaFormat::KnownFormat(KnownFormat::Int32)))
                         .description(Some("Maximum timeout in seconds to wait for the endpoint to join the Kafka consumer group during initialization.")),
                 )
+                .property(
+                    "max_uncommitted_messages",
+                    ObjectBuilder::new()
+                        .schema_type(SchemaType::Integer)
+                        .format(Some(SchemaFormat::KnownFormat(KnownFormat::Int64)))
+                        .description(Some(r#"Maximum number of received messages whose offsets have not been committed yet in exactly-once mode.
+
+When this limit is reached, the endpoint stops reading from Kafka until
+the outputs of the steps that processed these messages are delivered
+and their offsets are committed.
+
+Defaults to 100,000."#)),
+                )
                 .additional_properties(Some(
                         ObjectBuilder::new()
                         .schema_type(SchemaType::String)
@@ -148,6 +234,15 @@ impl KafkaInputConfig {
             .or_insert_with(|| val.to_string());
     }
 
+    /// `true` if the endpoint is configured to commit offsets explicitly
+    /// (exactly-once mode).
+    fn manual_commit(&self) -> bool {
+        self.kafka_options
+            .get("enable.auto.commit")
+            .map(String::as_str)
+            == Some("false")
+    }
+
     /// Validate configuration, set default option values required by this
     /// adapter.
     fn validate(&mut self) -> AnyResult<()> {
@@ -156,10 +251,18 @@ impl KafkaInputConfig {
             &env::var("REDPANDA_BROKERS").unwrap_or_else(|_| "localhost".to_string()),
         );
 
-        // Commit automatically.
-        // See https://docs.confluent.io/platform/current/clients/consumer.html#offset-management
-        self.enforce_option("enable.auto.commit", "true")?;
-        self.enforce_option("enable.auto.offset.store", "true")?;
+        if self.manual_commit() {
+            // Exactly-once mode: offsets are committed explicitly by the endpoint.
+            self.enforce_option("enable.auto.offset.store", "false")?;
+            if self.max_uncommitted_messages == 0 {
+                Err(AnyError::msg("'max_uncommitted_messages' must be positive"))?;
+            }
+        } else {
+            // Commit automatically.
+            // See https://docs.confluent.io/platform/current/clients/consumer.html#offset-management
+            self.enforce_option("enable.auto.commit", "true")?;
+            self.enforce_option("enable.auto.offset.store", "true")?;
+        }
 
         let group_id = format!(
             "{}",
@@ -214,7 +317,7 @@ impl ConsumerContext for KafkaInputContext {
         // println!("Rebalance: {rebalance:?}");
         if matches!(rebalance, Rebalance::Assign(_)) {
             if let Some(endpoint) = self.endpoint.lock().unwrap().upgrade() {
-                if endpoint.state() == PipelineState::Running {
+                if endpoint.effective_state() == PipelineState::Running {
                     // TODO: handle errors by storing them inside `endpoint`
                     // for later processing in `poll`.
                     let _ = endpoint.resume_partitions();
@@ -228,9 +331,50 @@ impl ConsumerContext for KafkaInputContext {
     }
 }
 
+/// Position of the endpoint, reported to the consumer with each payload (see
+/// [`InputConsumer::input_at`]): the offset of the next message to read from
+/// each partition, indexed by topic and partition.
+type KafkaPosition = BTreeMap<String, BTreeMap<i32, i64>>;
+
+/// A partition the endpoint must resume from `offset` (see
+/// [`InputEndpoint::seek`]).
+struct PendingSeek {
+    offset: i64,
+
+    /// `true` once the consumer has been repositioned to `offset`.
+    done: bool,
+}
+
+/// A message whose offset has not been committed yet.
+struct UncommittedMessage {
+    /// The circuit step that processes the message.
+    step: Step,
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
 struct KafkaInputEndpointInner {
     state: AtomicU32,
     kafka_consumer: BaseConsumer<KafkaInputContext>,
+
+    /// Commit offsets explicitly when notified by the controller (exactly-once
+    /// mode).
+    manual_commit: bool,
+
+    /// Messages received but not yet committed in exactly-once mode, in the
+    /// order received.
+    uncommitted: Mutex<VecDeque<UncommittedMessage>>,
+
+    /// See [`KafkaInputConfig::max_uncommitted_messages`].
+    max_uncommitted_messages: usize,
+
+    /// Consumer group id.
+    group_id: String,
+
+    /// Position requested via [`InputEndpoint::seek`], applied by the worker
+    /// thread.
+    seek_position: Mutex<Option<KafkaPosition>>,
 }
 
 impl KafkaInputEndpointInner {
@@ -258,10 +402,30 @@ impl KafkaInputEndpointInner {
         let endpoint = Arc::new(Self {
             state: AtomicU32::new(PipelineState::Paused as u32),
             kafka_consumer,
+            manual_commit: config.manual_commit(),
+            uncommitted: Mutex::new(VecDeque::new()),
+            max_uncommitted_messages: config.max_uncommitted_messages,
+            group_id: config.kafka_options.get("group.id").unwrap().clone(),
+            seek_position: Mutex::new(None),
         });
 
         *endpoint.kafka_consumer.context().endpoint.lock().unwrap() = Arc::downgrade(&endpoint);
 
+        if endpoint.manual_commit {
+            let mut endpoints = EXACTLY_ONCE_ENDPOINTS.lock().unwrap();
+            if endpoints
+                .get(&endpoint.group_id)
+                .and_then(Weak::upgrade)
+                .is_some()
+            {
+                return Err(AnyError::msg(format!(
+                    "consumer group '{}' is already used by another Kafka input endpoint with 'enable.auto.commit' set to 'false'",
+                    endpoint.group_id
+                )));
+            }
+            endpoints.insert(endpoint.group_id.clone(), Arc::downgrade(&endpoint));
+        }
+
         // Subscibe consumer to `topics`.
         endpoint
             .kafka_consumer
@@ -316,6 +480,20 @@ impl KafkaInputEndpointInner {
         self.state.store(state as u32, Ordering::Release);
     }
 
+    /// The state the consumer should be in: paused while the endpoint is
+    /// running but has too many uncommitted messages (backpressure).
+    fn effective_state(&self) -> PipelineState {
+        match self.state() {
+            PipelineState::Running
+                if self.manual_commit
+                    && self.uncommitted.lock().unwrap().len() >= self.max_uncommitted_messages =>
+            {
+                PipelineState::Paused
+            }
+            state => state,
+        }
+    }
+
     /// Pause all partitions assigned to the consumer.
     fn pause_partitions(&self) -> KafkaResult<()> {
         // println!("pause");
@@ -337,11 +515,77 @@ impl KafkaInputEndpointInner {
         refine_kafka_error(self.kafka_consumer.client(), e)
     }
 
+    /// Next offset to read from each partition after the messages processed
+    /// by steps `0..step`.  Removes these messages from `self.uncommitted` if
+    /// `remove` is `true`.
+    fn offsets(&self, step: Step, remove: bool) -> KafkaResult<TopicPartitionList> {
+        let mut offsets = BTreeMap::new();
+
+        let mut uncommitted = self.uncommitted.lock().unwrap();
+        let num_messages = uncommitted
+            .iter()
+            .take_while(|message| message.step < step)
+            .count();
+        for message in uncommitted.range(..num_messages) {
+            offsets.insert(
+                (message.topic.as_str(), message.partition),
+                message.offset + 1,
+            );
+        }
+
+        let mut topic_partitions = TopicPartitionList::new();
+        for ((topic, partition), offset) in offsets.into_iter() {
+            topic_partitions.add_partition_offset(topic, partition, Offset::Offset(offset))?;
+        }
+
+        if remove {
+            uncommitted.drain(..num_messages);
+        }
+        Ok(topic_partitions)
+    }
+
+    /// Commit offsets of all messages processed by steps `0..step`.
+    ///
+    /// If a transactional output endpoint has already committed these offsets
+    /// as part of its transaction, this is a no-op on the broker.
+    fn commit(&self, step: Step) -> AnyResult<()> {
+        let topic_partitions = self.offsets(step, true)?;
+        if topic_partitions.count() == 0 {
+            return Ok(());
+        }
+
+        self.kafka_consumer
+            .commit(&topic_partitions, CommitMode::Sync)
+            .map_err(|e| self.refine_error(e).1)
+    }
+
     fn worker_thread(endpoint: Arc<KafkaInputEndpointInner>, mut consumer: Box<dyn InputConsumer>) {
         let mut actual_state = PipelineState::Paused;
+        // Step that processes the last received payload.  Messages without
+        // payload are committed along with it.
+        let mut step = 0;
+        // Position after the last received message.
+        let mut position = KafkaPosition::new();
+        // Partitions that must be repositioned before ingesting their messages.
+        let mut pending_seeks: BTreeMap<(String, i32), PendingSeek> = BTreeMap::new();
         loop {
+            if let Some(seek_position) = endpoint.seek_position.lock().unwrap().take() {
+                for (topic, partitions) in seek_position.iter() {
+                    for (partition, offset) in partitions.iter() {
+                        pending_seeks.insert(
+                            (topic.clone(), *partition),
+                            PendingSeek {
+                                offset: *offset,
+                                done: false,
+                            },
+                        );
+                    }
+                }
+                position = seek_position;
+            }
+
             // endpoint.debug_consumer();
-            match endpoint.state() {
+            match endpoint.effective_state() {
                 PipelineState::Paused if actual_state != PipelineState::Paused => {
                     actual_state = PipelineState::Paused;
                     if let Err(e) = endpoint.pause_partitions() {
@@ -384,8 +628,59 @@ impl KafkaInputEndpointInner {
                     // println!("received {} bytes", message.payload().unwrap().len());
                     // message.payload().map(|payload| consumer.input(payload));
 
+                    // Partitions are assigned to the consumer dynamically, so
+                    // we reposition a partition once we receive a message from
+                    // it, skipping messages received before that.
+                    let key = (message.topic().to_string(), message.partition());
+                    if let Some(pending) = pending_seeks.get_mut(&key) {
+                        if message.offset() == pending.offset
+                            || (pending.done && message.offset() > pending.offset)
+                        {
+                            // Messages between the requested offset and this
+                            // one may have been removed by the broker.
+                            pending_seeks.remove(&key);
+                        } else {
+                            if !pending.done {
+                                match endpoint.kafka_consumer.seek(
+                                    message.topic(),
+                                    message.partition(),
+                                    Offset::Offset(pending.offset),
+                                    POLL_TIMEOUT,
+                                ) {
+                                    Ok(()) => pending.done = true,
+                                    Err(e) => {
+                                        let (fatal, e) = endpoint.refine_error(e);
+                                        consumer.error(fatal, e);
+                                        if fatal {
+                                            return;
+                                        }
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+                    }
+
+                    position
+                        .entry(key.0)
+                        .or_default()
+                        .insert(key.1, message.offset() + 1);
+
                     if let Some(payload) = message.payload() {
-                        consumer.input(payload);
+                        step = consumer.input_at(payload, Box::new(position.clone()));
+                    }
+
+                    if endpoint.manual_commit {
+                        endpoint
+                            .uncommitted
+                            .lock()
+                            .unwrap()
+                            .push_back(UncommittedMessage {
+                                step,
+                                topic: message.topic().to_string(),
+                                partition: message.partition(),
+                                offset: message.offset(),
+                            });
                     }
                 }
             }
@@ -409,10 +704,46 @@ impl InputEndpoint for KafkaInputEndpoint {
     fn disconnect(&self) {
         self.0.set_state(PipelineState::Terminated);
     }
+
+    fn commit(&self, step: Step) -> AnyResult<()> {
+        if self.0.manual_commit {
+            self.0.commit(step)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn exactly_once(&self) -> Option<bool> {
+        Some(self.0.manual_commit)
+    }
+
+    fn seek(&self, position: &InputPosition) -> AnyResult<()> {
+        if position.unparsed_bytes > 0 {
+            return Err(AnyError::msg(
+                "cannot resume from the middle of a record that spans multiple Kafka messages",
+            ));
+        }
+        let kafka_position: KafkaPosition = serde_json::from_value(position.position.clone())
+            .map_err(|e| {
+                AnyError::msg(format!("invalid Kafka position {}: {e}", position.position))
+            })?;
+        *self.0.seek_position.lock().unwrap() = Some(kafka_position);
+        Ok(())
+    }
 }
 
 impl Drop for KafkaInputEndpoint {
     fn drop(&mut self) {
         self.disconnect();
+
+        if self.0.manual_commit {
+            let mut endpoints = EXACTLY_ONCE_ENDPOINTS.lock().unwrap();
+            if endpoints
+                .get(&self.0.group_id)
+                .map_or(false, |endpoint| endpoint.ptr_eq(&Arc::downgrade(&self.0)))
+            {
+                endpoints.remove(&self.0.group_id);
+            }
+        }
     }
 }
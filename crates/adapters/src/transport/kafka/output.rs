@@ -1,5 +1,5 @@
-use super::KafkaLogLevel;
-use crate::{OutputEndpoint, OutputTransport};
+use super::{input::transaction_offsets, KafkaLogLevel};
+use crate::{OutputEndpoint, OutputTransport, Step};
 use anyhow::{Error as AnyError, Result as AnyResult};
 use crossbeam::sync::{Parker, Unparker};
 use log::debug;
@@ -21,6 +21,9 @@ use utoipa::{
 
 const OUTPUT_POLLING_INTERVAL: Duration = Duration::from_millis(100);
 
+/// Timeout for transactional operations in exactly-once mode.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(60);
+
 /// `OutputTransport` implementation that writes to a Kafka topic.
 pub struct KafkaOutputTransport;
 
@@ -53,6 +56,10 @@ pub struct KafkaOutputConfig {
     ///
     /// See [`librdkafka` options](https://github.com/edenhill/librdkafka/blob/master/CONFIGURATION.md)
     /// used to configure the Kafka producer.
+    ///
+    /// Setting "transactional.id" enables exactly-once mode, in which the
+    /// output of each circuit step is written in a separate Kafka transaction.
+    /// The transactional id must remain the same across pipeline restarts.
     #[serde(flatten)]
     kafka_options: BTreeMap<String, String>,
 
@@ -77,6 +84,19 @@ pub struct KafkaOutputConfig {
     /// Defaults to 1000.
     #[serde(default = "default_max_inflight_messages")]
     max_inflight_messages: u32,
+
+    /// Consumer group of a Kafka input endpoint in exactly-once mode whose
+    /// offsets are committed as part of each transaction.
+    ///
+    /// Requires "transactional.id".  Each transaction commits the offsets of
+    /// the input messages processed by the circuit steps whose outputs it
+    /// contains, using `send_offsets_to_transaction`, so that the outputs
+    /// of a step and the consumption of its inputs become visible
+    /// atomically.  The offsets are committed regardless of whether other
+    /// output endpoints have delivered their outputs, so the controller
+    /// rejects this option unless this is the only output endpoint of the
+    /// pipeline.
+    input_group_id: Option<String>,
 }
 
 impl KafkaOutputConfig {
@@ -94,6 +114,12 @@ impl KafkaOutputConfig {
             "bootstrap.servers",
             &env::var("REDPANDA_BROKERS").unwrap_or_else(|_| "localhost".to_string()),
         );
+
+        if self.input_group_id.is_some() && !self.kafka_options.contains_key("transactional.id") {
+            Err(AnyError::msg(
+                "'input_group_id' requires a transactional producer ('transactional.id')",
+            ))?;
+        }
         Ok(())
     }
 }
@@ -129,6 +155,21 @@ unacknowledged messages reaches this limit, sending of a new message
 blocks until additional acknowledgements arrive from the broker.
 
 Defaults to 1000."#)),
+                )
+                .property(
+                    "input_group_id",
+                    ObjectBuilder::new()
+                        .schema_type(SchemaType::String)
+                        .description(Some(r#"Consumer group of a Kafka input endpoint in exactly-once mode whose offsets are committed as part of each transaction.
+
+Requires "transactional.id".  Each transaction commits the offsets of
+the input messages processed by the circuit steps whose outputs it
+contains, using `send_offsets_to_transaction`, so that the outputs
+of a step and the consumption of its inputs become visible
+atomically.  The offsets are committed regardless of whether other
+output endpoints have delivered their outputs, so the controller
+rejects this option unless this is the only output endpoint of the
+pipeline."#)),
                 )
                 .additional_properties(Some(
                         ObjectBuilder::new()
@@ -189,6 +230,18 @@ struct KafkaOutputEndpoint {
     topic: String,
     max_inflight_messages: u32,
     parker: Parker,
+
+    /// Write each step in a separate transaction (exactly-once mode).
+    transactional: bool,
+
+    /// Set when a message in the current transaction could not be sent.
+    transaction_failed: bool,
+
+    /// See [`KafkaOutputConfig::input_group_id`].
+    input_group_id: Option<String>,
+
+    /// The last step whose outputs are included in the current transaction.
+    step: Option<Step>,
 }
 
 impl KafkaOutputEndpoint {
@@ -218,11 +271,22 @@ impl KafkaOutputEndpoint {
         // Create Kafka producer.
         let kafka_producer = ThreadedProducer::from_config_and_context(&client_config, context)?;
 
+        let transactional = config.kafka_options.contains_key("transactional.id");
+        if transactional {
+            // Fences off any previous instance of the producer with the same
+            // transactional id and aborts its incomplete transactions.
+            kafka_producer.init_transactions(TRANSACTION_TIMEOUT)?;
+        }
+
         Ok(Self {
             kafka_producer,
             topic: config.topic,
             max_inflight_messages: config.max_inflight_messages,
             parker,
+            transactional,
+            transaction_failed: false,
+            input_group_id: config.input_group_id,
+            step: None,
         })
     }
 }
@@ -242,9 +306,61 @@ impl OutputEndpoint for KafkaOutputEndpoint {
         }
 
         let record = <BaseRecord<(), [u8], ()>>::to(&self.topic).payload(buffer);
-        self.kafka_producer
-            .send(record)
-            .map_err(|(err, _record)| err)?;
+        self.kafka_producer.send(record).map_err(|(err, _record)| {
+            self.transaction_failed = true;
+            err
+        })?;
         Ok(())
     }
+
+    fn batch_start(&mut self, step: Option<Step>) -> AnyResult<()> {
+        if self.transactional {
+            self.transaction_failed = false;
+            self.step = step;
+            self.kafka_producer.begin_transaction()?;
+        }
+        Ok(())
+    }
+
+    fn batch_end(&mut self) -> AnyResult<()> {
+        if !self.transactional {
+            return Ok(());
+        }
+
+        if self.transaction_failed {
+            self.kafka_producer.abort_transaction(TRANSACTION_TIMEOUT)?;
+            return Err(AnyError::msg(
+                "failed to send one or more messages; transaction aborted",
+            ));
+        }
+
+        // Commit input offsets in the same transaction.
+        if let (Some(group_id), Some(step)) = (&self.input_group_id, self.step) {
+            let result = transaction_offsets(group_id, step).and_then(|offsets| {
+                if let Some((offsets, group_metadata)) = offsets {
+                    self.kafka_producer.send_offsets_to_transaction(
+                        &offsets,
+                        &group_metadata,
+                        TRANSACTION_TIMEOUT,
+                    )?;
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                let _ = self.kafka_producer.abort_transaction(TRANSACTION_TIMEOUT);
+                return Err(e.context("failed to add input offsets to transaction"));
+            }
+        }
+
+        // Flushes all outstanding messages and waits for them to be acknowledged.
+        if let Err(e) = self.kafka_producer.commit_transaction(TRANSACTION_TIMEOUT) {
+            let _ = self.kafka_producer.abort_transaction(TRANSACTION_TIMEOUT);
+            return Err(AnyError::from(e).context("failed to commit transaction"));
+        }
+        Ok(())
+    }
+
+    fn commits_inputs(&self) -> bool {
+        self.input_group_id.is_some()
+    }
 }
@@ -8,7 +8,10 @@ use crate::{
 };
 use log::LevelFilter;
 use proptest::prelude::*;
-use std::{thread::sleep, time::Duration};
+use std::{
+    thread::sleep,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 /// Wait to receive all records in `data` in the same order.
 fn wait_for_output_ordered(zset: &MockDeZSet<TestStruct>, data: &[Vec<TestStruct>]) {
@@ -211,4 +214,128 @@ outputs:
         println!("Delete Kafka resources");
         drop(kafka_resources);
     }
+
+    #[test]
+    fn proptest_kafka_exactly_once(data in generate_test_batches(100, 1000)) {
+        let _ = log::set_logger(&TEST_LOGGER);
+        log::set_max_level(LevelFilter::Debug);
+
+        let kafka_resources = KafkaResources::create_topics(&[("exactly_once_test_input_topic", 1), ("exactly_once_test_output_topic", 1)]);
+
+        // Committed offsets outlive the topics, so use a fresh consumer group
+        // for each test case.
+        let group_id = format!(
+            "exactly_once_test_group_{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        );
+
+        let config_str = format!(r#"
+exactly_once: true
+inputs:
+    test_input1:
+        stream: test_input1
+        transport:
+            name: kafka
+            config:
+                bootstrap.servers: "localhost"
+                auto.offset.reset: "earliest"
+                enable.auto.commit: "false"
+                group.id: "{group_id}"
+                topics: [exactly_once_test_input_topic]
+                log_level: debug
+        format:
+            name: csv
+outputs:
+    test_output2:
+        stream: test_output1
+        transport:
+            name: kafka
+            config:
+                bootstrap.servers: "localhost"
+                topic: exactly_once_test_output_topic
+                transactional.id: "exactly_once_test_output"
+                input_group_id: "{group_id}"
+        format:
+            name: csv
+"#);
+        let config: PipelineConfig = serde_yaml::from_str(&config_str).unwrap();
+
+        let buffer_consumer = BufferConsumer::new("exactly_once_test_output_topic");
+        let producer = TestProducer::new();
+
+        println!("Test: process data and commit input offsets");
+        let (circuit, catalog) = test_circuit(4);
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}"))
+        ).unwrap();
+
+        producer.send_to_topic(&data, "exactly_once_test_input_topic");
+        controller.start();
+        buffer_consumer.wait_for_output_unordered(&data);
+
+        // Give the endpoint time to commit input offsets.
+        sleep(Duration::from_millis(1000));
+        controller.stop().unwrap();
+        buffer_consumer.clear();
+
+        println!("Test: restart the pipeline; committed inputs must not be replayed");
+        let (circuit, catalog) = test_circuit(4);
+        let controller = Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}"))
+        ).unwrap();
+
+        producer.send_to_topic(&data, "exactly_once_test_input_topic");
+        controller.start();
+        buffer_consumer.wait_for_output_unordered(&data);
+
+        sleep(Duration::from_millis(1000));
+        assert_eq!(buffer_consumer.len(), data.iter().map(Vec::len).sum::<usize>());
+
+        drop(buffer_consumer);
+        controller.stop().unwrap();
+
+        println!("Test: exactly-once input endpoint requires exactly-once mode");
+        let config: PipelineConfig = serde_yaml::from_str(
+            &config_str.replace("exactly_once: true", "exactly_once: false")
+        ).unwrap();
+        let (circuit, catalog) = test_circuit(4);
+        assert!(Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}"))
+        ).is_err());
+
+        println!("Test: an endpoint that commits input offsets must be the only output endpoint");
+        let config: PipelineConfig = serde_yaml::from_str(&format!(r#"{config_str}
+    test_output3:
+        stream: test_output1
+        transport:
+            name: kafka
+            config:
+                bootstrap.servers: "localhost"
+                topic: exactly_once_test_output_topic
+        format:
+            name: csv
+"#)).unwrap();
+        let (circuit, catalog) = test_circuit(4);
+        assert!(Controller::with_config(
+            circuit,
+            catalog,
+            &config,
+            Box::new(|e| panic!("error: {e}"))
+        ).is_err());
+
+        println!("Delete Kafka resources");
+        drop(kafka_resources);
+    }
 }
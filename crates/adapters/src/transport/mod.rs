@@ -1,9 +1,13 @@
 use anyhow::{Error as AnyError, Result as AnyResult};
+use erased_serde::Serialize as ErasedSerialize;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 use serde_yaml::Value as YamlValue;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 
+mod directory;
 mod file;
 
 #[cfg(feature = "server")]
@@ -12,6 +16,7 @@ mod http;
 #[cfg(feature = "with-kafka")]
 mod kafka;
 
+pub use directory::{DirectoryInputConfig, DirectoryInputTransport, FileOrder};
 pub use file::{FileInputConfig, FileInputTransport, FileOutputConfig, FileOutputTransport};
 
 #[cfg(feature = "server")]
@@ -22,11 +27,39 @@ pub use kafka::{
     KafkaInputConfig, KafkaInputTransport, KafkaLogLevel, KafkaOutputConfig, KafkaOutputTransport,
 };
 
+/// Sequence number of a circuit step.
+///
+/// Steps are numbered from 0 in the order in which the controller executes
+/// them.
+pub type Step = u64;
+
+/// Position of an input endpoint in its input stream, stored in checkpoints.
+///
+/// [`InputEndpoint::seek`] uses it to resume reading the stream where the
+/// checkpoint left off.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputPosition {
+    /// The last position reported by the endpoint via
+    /// [`InputConsumer::input_at`], serialized as JSON.  Its format is defined
+    /// by the transport, e.g., a byte offset for a file or per-partition
+    /// offsets for Kafka.
+    pub position: JsonValue,
+
+    /// The number of bytes at the end of the data pushed up to `position` that
+    /// the parser hasn't processed, because they form an incomplete record.
+    /// The endpoint must push them again when resuming from `position`.
+    pub unparsed_bytes: u64,
+}
+
 /// Static map of supported input transports.
 // TODO: support for registering new transports at runtime in order to allow
 // external crates to implement new transports.
 static INPUT_TRANSPORT: Lazy<BTreeMap<&'static str, Box<dyn InputTransport>>> = Lazy::new(|| {
     BTreeMap::from([
+        (
+            "directory",
+            Box::new(DirectoryInputTransport) as Box<dyn InputTransport>,
+        ),
         (
             "file",
             Box::new(FileInputTransport) as Box<dyn InputTransport>,
@@ -108,7 +141,7 @@ impl dyn InputTransport {
 
 /// Input transport endpoint receives a stream of bytes via the underlying
 /// data transport protocol and pushes it to the associated [`InputConsumer`].
-pub trait InputEndpoint: Send {
+pub trait InputEndpoint: Send + Sync {
     /// Pause the endpoint.
     ///
     /// The endpoint must stop pushing data downstream.  This method may
@@ -129,6 +162,44 @@ pub trait InputEndpoint: Send {
     /// data buffers may be pushed downstream before the endpoint gets
     /// disconnected.
     fn disconnect(&self);
+
+    /// Resume reading the input stream from `position`, recorded in a
+    /// checkpoint (see [`InputConsumer::input_at`]).
+    ///
+    /// Invoked before the endpoint is started for the first time.
+    /// Transports that cannot replay their input from an earlier position
+    /// keep the default implementation, which fails.
+    fn seek(&self, position: &InputPosition) -> AnyResult<()> {
+        Err(AnyError::msg(format!(
+            "endpoint does not support resuming from position {}",
+            position.position
+        )))
+    }
+
+    /// Notify the endpoint that all outputs produced by steps `0..step` have
+    /// been durably delivered to all output endpoints.
+    ///
+    /// The endpoint can acknowledge the inputs processed by these steps (see
+    /// [`InputConsumer::input`]) to the data source, e.g., by committing
+    /// their offsets, so that they will not be replayed after a restart.
+    /// Only invoked in exactly-once mode (see
+    /// [`GlobalPipelineConfig::exactly_once`](`crate::GlobalPipelineConfig::exactly_once`)).
+    fn commit(&self, _step: Step) -> AnyResult<()> {
+        Ok(())
+    }
+
+    /// Returns `Some(true)` if the endpoint is configured to acknowledge
+    /// inputs only when notified via [`Self::commit`], `Some(false)` if it
+    /// acknowledges them on its own (e.g., by committing offsets
+    /// automatically), and `None` if the endpoint works the same way in both
+    /// modes.
+    ///
+    /// The controller refuses to connect an endpoint whose setting disagrees
+    /// with
+    /// [`GlobalPipelineConfig::exactly_once`](`crate::GlobalPipelineConfig::exactly_once`).
+    fn exactly_once(&self) -> Option<bool> {
+        None
+    }
 }
 
 /// Input stream consumer.
@@ -138,7 +209,24 @@ pub trait InputEndpoint: Send {
 // TODO: `input_owned`.
 pub trait InputConsumer: Send {
     /// Push a chunk of data to the consumer.
-    fn input(&mut self, data: &[u8]);
+    ///
+    /// Returns the number of the circuit step that will process `data`.  In
+    /// exactly-once mode, this is precisely the step that will consume the
+    /// data; otherwise it is a best-effort estimate.
+    fn input(&mut self, data: &[u8]) -> Step;
+
+    /// Push a chunk of data to the consumer along with the position of the
+    /// endpoint in its input stream after this chunk.
+    ///
+    /// Works like [`Self::input`], but the consumer records `position`
+    /// atomically with pushing `data` to the circuit, so that a checkpoint
+    /// can resume the endpoint from the position of the last chunk processed
+    /// by the circuit (see [`InputEndpoint::seek`]).  Endpoints that support
+    /// seeking must push all data using this method.  The default
+    /// implementation ignores `position`.
+    fn input_at(&mut self, data: &[u8], _position: Box<dyn ErasedSerialize + Send>) -> Step {
+        self.input(data)
+    }
 
     /// Endpoint failed.
     ///
@@ -153,8 +241,9 @@ pub trait InputConsumer: Send {
     /// Create a new consumer instance.
     ///
     /// Used by multithreaded transport endpoints to create multiple parallel
-    /// input pipelines.
-    fn fork(&self) -> Box<dyn InputConsumer>;
+    /// input pipelines.  Positions reported to a fork via [`Self::input_at`]
+    /// are not stored in checkpoints.
+    fn fork(&self) -> AnyResult<Box<dyn InputConsumer>>;
 }
 
 /// Trait that represents a specific data transport.
@@ -208,4 +297,36 @@ impl dyn OutputTransport {
 
 pub trait OutputEndpoint: Send {
     fn push_buffer(&mut self, buffer: &[u8]) -> AnyResult<()>;
+
+    /// Notifies the endpoint that the buffers that follow, up to the next
+    /// [`Self::batch_end`] call, contain the output of a single circuit step.
+    ///
+    /// `step` is the last circuit step whose outputs are included in the
+    /// batch (a batch may combine the outputs of several steps if the endpoint
+    /// consolidates its outputs), or `None` if the batch only contains the
+    /// initial snapshot of the stream.
+    fn batch_start(&mut self, _step: Option<Step>) -> AnyResult<()> {
+        Ok(())
+    }
+
+    /// Notifies the endpoint that all buffers of the current step have been
+    /// pushed.
+    ///
+    /// Endpoints that support exactly-once delivery must not return until
+    /// the buffers received since the last [`Self::batch_start`] call have been
+    /// durably delivered, or fail without delivering any of them.
+    fn batch_end(&mut self) -> AnyResult<()> {
+        Ok(())
+    }
+
+    /// Returns `true` if the endpoint acknowledges the inputs of the steps
+    /// whose outputs it delivers on its own, e.g., by committing input offsets
+    /// as part of an output transaction.
+    ///
+    /// Such an endpoint acknowledges inputs regardless of whether other output
+    /// endpoints have delivered their outputs, so the controller only allows
+    /// it as the only output endpoint of the pipeline.
+    fn commits_inputs(&self) -> bool {
+        false
+    }
 }
@@ -527,6 +527,37 @@ where
     }
 }
 
+#[cfg(feature = "checkpoint")]
+impl<K, V, R, O> bincode::Encode for HashedKVBatch<K, V, R, O>
+where
+    K: DBData,
+    V: DBData,
+    R: DBWeight,
+    O: OrdOffset,
+{
+    fn encode<E: bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> core::result::Result<(), bincode::error::EncodeError> {
+        dbsp::trace::serialize::encode_batch(self, encoder)
+    }
+}
+
+#[cfg(feature = "checkpoint")]
+impl<K, V, R, O> bincode::Decode for HashedKVBatch<K, V, R, O>
+where
+    K: DBData,
+    V: DBData,
+    R: DBWeight,
+    O: OrdOffset,
+{
+    fn decode<D: bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> Result<Self, bincode::error::DecodeError> {
+        dbsp::trace::serialize::decode_batch(decoder)
+    }
+}
+
 impl<K, V, R, O> Batch for HashedKVBatch<K, V, R, O>
 where
     K: DBData,
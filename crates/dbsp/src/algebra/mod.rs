@@ -528,6 +528,16 @@ pub trait Semigroup<V> {
     }
 }
 
+/// A [`Semigroup`] whose operation is commutative, i.e.,
+/// `combine(x, y) == combine(y, x)`.
+///
+/// This is a marker trait: the compiler cannot check commutativity, so
+/// implementers must make sure that the property holds.  Aggregators with a
+/// commutative semigroup can be evaluated by combining partial aggregates
+/// computed in different workers in arbitrary order (see
+/// [`Stream::aggregate_two_phase`](`crate::Stream::aggregate_two_phase`)).
+pub trait CommutativeSemigroup<V>: Semigroup<V> {}
+
 /// Trait [`Semigroup`] implementation for types that
 /// implement [`SemigroupValue`].
 ///
@@ -545,6 +555,8 @@ where
     }
 }
 
+impl<V> CommutativeSemigroup<V> for DefaultSemigroup<V> where V: SemigroupValue {}
+
 /// [`Semigroup`] implementation that panics with "not implemented"
 /// message.
 // TODO: this is a temporary thing that can be used with aggregation operators,
@@ -0,0 +1,290 @@
+//! Checkpointing the state of a circuit.
+//!
+//! A checkpoint of a circuit consists of the serialized states of all of its
+//! stateful operators (see
+//! [`Operator::checkpoint`](`crate::circuit::operator_traits::Operator::checkpoint`)),
+//! indexed by the global ids of the corresponding nodes.  A checkpoint can
+//! only be restored into a circuit with identical structure, e.g., a circuit
+//! created by the same constructor function.
+//!
+//! [`DBSPHandle::checkpoint`](`crate::DBSPHandle::checkpoint`) stores
+//! checkpoints in a checkpoint directory.  Each checkpoint is written to a
+//! staging subdirectory, which is renamed to `checkpoint-<seq>` once all of its
+//! files have been written and synced to disk.  The rename is the commit point
+//! of the checkpoint: a crash before the rename leaves the previous checkpoint
+//! intact, and [`latest`] never returns a partially written checkpoint.
+//!
+//! Operators whose state is already stored in files, e.g., traces that spill
+//! batches to disk, can add these files to the checkpoint directory (see
+//! [`current_dir`]) instead of serializing their contents.
+
+use crate::circuit::GlobalNodeId;
+use bincode::{
+    config::standard,
+    decode_from_slice, encode_to_vec,
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::{Display, Error as FmtError, Formatter},
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Prefix of the names of committed checkpoints in a checkpoint directory.
+const CHECKPOINT_PREFIX: &str = "checkpoint-";
+
+/// Suffix of checkpoints that are still being written.
+const STAGING_SUFFIX: &str = ".tmp";
+
+thread_local! {
+    // Directory of the checkpoint written or restored by the current thread.
+    static CURRENT_DIR: RefCell<Option<PathBuf>> = RefCell::new(None);
+}
+
+/// Checkpoint error.
+#[derive(Debug)]
+pub enum Error {
+    /// Error serializing the state of an operator.
+    Encode {
+        node_id: GlobalNodeId,
+        error: EncodeError,
+    },
+    /// Error deserializing the state of an operator.
+    Decode {
+        node_id: GlobalNodeId,
+        error: DecodeError,
+    },
+    /// The checkpoint contains state for a node that does not exist in the
+    /// circuit, which indicates that the checkpoint was taken from a circuit
+    /// with a different structure.
+    UnknownNode { node_id: GlobalNodeId },
+    /// The checkpoint was taken from a circuit with a different number of
+    /// worker threads.
+    WorkerMismatch { expected: usize, found: usize },
+    /// The checkpoint file is corrupted.
+    InvalidCheckpoint { error: DecodeError },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Self::Encode { node_id, error } => {
+                write!(
+                    f,
+                    "failed to checkpoint the state of node '{node_id}': {error}"
+                )
+            }
+            Self::Decode { node_id, error } => {
+                write!(
+                    f,
+                    "failed to restore the state of node '{node_id}': {error}"
+                )
+            }
+            Self::UnknownNode { node_id } => {
+                write!(f, "checkpoint contains state for unknown node '{node_id}'")
+            }
+            Self::WorkerMismatch { expected, found } => {
+                write!(
+                    f,
+                    "checkpoint was taken with {found} worker(s), but the circuit has {expected} worker(s)"
+                )
+            }
+            Self::InvalidCheckpoint { error } => {
+                write!(f, "invalid checkpoint: {error}")
+            }
+        }
+    }
+}
+
+/// Serialized state of all stateful operators in a circuit.
+///
+/// Created by [`CircuitHandle::checkpoint`](`crate::CircuitHandle::checkpoint`)
+/// and consumed by [`CircuitHandle::restore`](`crate::CircuitHandle::restore`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub struct CircuitCheckpoint {
+    nodes: BTreeMap<GlobalNodeId, Vec<u8>>,
+}
+
+impl CircuitCheckpoint {
+    /// Create an empty checkpoint.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the state of node `node_id` to the checkpoint.
+    pub fn insert(&mut self, node_id: GlobalNodeId, state: Vec<u8>) {
+        self.nodes.insert(node_id, state);
+    }
+
+    /// Serialized state of node `node_id`, if any.
+    pub fn get(&self, node_id: &GlobalNodeId) -> Option<&[u8]> {
+        self.nodes.get(node_id).map(Vec::as_slice)
+    }
+
+    /// Iterate over the ids of all nodes in the checkpoint.
+    pub fn node_ids(&self) -> impl Iterator<Item = &GlobalNodeId> {
+        self.nodes.keys()
+    }
+
+    /// The number of nodes in the checkpoint.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the checkpoint does not contain any operator state.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// Contents of a per-worker checkpoint file written by
+/// [`DBSPHandle::checkpoint`](`crate::DBSPHandle::checkpoint`).
+#[derive(Encode, Decode)]
+pub(crate) struct WorkerCheckpoint {
+    /// The number of workers in the runtime that created the checkpoint.
+    pub num_workers: u64,
+    pub circuit: CircuitCheckpoint,
+}
+
+/// Serialize `value` using the encoding used by operators to checkpoint their
+/// state.
+pub fn encode<T>(value: &T) -> Result<Vec<u8>, EncodeError>
+where
+    T: Encode,
+{
+    encode_to_vec(value, standard())
+}
+
+/// Deserialize a value serialized with [`encode`].
+pub fn decode<T>(bytes: &[u8]) -> Result<T, DecodeError>
+where
+    T: Decode,
+{
+    decode_from_slice(bytes, standard()).map(|(value, _)| value)
+}
+
+/// Directory of the checkpoint that the current thread is writing or
+/// restoring, if any.
+///
+/// Set while [`DBSPHandle::checkpoint`](`crate::DBSPHandle::checkpoint`)
+/// collects the state of operators and while
+/// [`Runtime::init_circuit_from_checkpoint`](`crate::Runtime::init_circuit_from_checkpoint`)
+/// restores it.  An operator can add files to this directory and only
+/// serialize their names.  File names must be unique across all workers and
+/// files must be synced to disk before the operator returns its state.  The
+/// directory may not exist on other hosts of a multihost runtime.
+pub fn current_dir() -> Option<PathBuf> {
+    CURRENT_DIR.with(|dir| dir.borrow().clone())
+}
+
+/// Calls `f` with `path` as the [`current_dir`] of the current thread.
+#[cfg(feature = "checkpoint")]
+pub(crate) fn with_dir<F, T>(path: &Path, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let previous = CURRENT_DIR.with(|dir| dir.replace(Some(path.to_path_buf())));
+    let result = f();
+    CURRENT_DIR.with(|dir| *dir.borrow_mut() = previous);
+    result
+}
+
+/// Sequence number of the committed checkpoint `name`, if `name` is the name
+/// of a committed checkpoint.
+fn checkpoint_seq(name: &str) -> Option<u64> {
+    name.strip_prefix(CHECKPOINT_PREFIX)?.parse().ok()
+}
+
+/// Returns the path to the most recent committed checkpoint in checkpoint
+/// directory `dir_path` or `None` if the directory does not contain any
+/// checkpoints.
+pub fn latest<P: AsRef<Path>>(dir_path: P) -> io::Result<Option<PathBuf>> {
+    let dir_path = dir_path.as_ref();
+    if !dir_path.exists() {
+        return Ok(None);
+    }
+
+    let mut latest = None;
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        if let Some(seq) = entry.file_name().to_str().and_then(checkpoint_seq) {
+            if latest.map_or(true, |(latest_seq, _)| seq > latest_seq) {
+                latest = Some((seq, entry.path()));
+            }
+        }
+    }
+
+    Ok(latest.map(|(_, path)| path))
+}
+
+/// Write `bytes` to file `path` and sync it to disk.
+///
+/// Use this function to add files to a checkpoint (see
+/// [`DBSPHandle::checkpoint_with`](`crate::DBSPHandle::checkpoint_with`)).
+pub fn write_file<P: AsRef<Path>>(path: P, bytes: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+/// Sync the contents of directory `path`, i.e., the names of the files in
+/// it, to disk.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    // Directories cannot be opened as files on Windows, where renames are
+    // durable once they return.
+    if cfg!(unix) {
+        File::open(path)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Create a new checkpoint in checkpoint directory `dir_path`.
+///
+/// Creates an empty staging directory, calls `write` to populate it and
+/// commits the checkpoint by renaming the staging directory after the
+/// previous checkpoint.  Removes older checkpoints and leftovers of failed
+/// checkpoints once the new checkpoint has been committed.
+#[cfg(feature = "checkpoint")]
+pub(crate) fn commit<F, E>(dir_path: &Path, write: F) -> Result<PathBuf, E>
+where
+    F: FnOnce(&Path) -> Result<(), E>,
+    E: From<io::Error>,
+{
+    fs::create_dir_all(dir_path)?;
+
+    let seq = latest(dir_path)?
+        .and_then(|path| checkpoint_seq(path.file_name()?.to_str()?))
+        .map_or(0, |seq| seq + 1);
+    let name = format!("{CHECKPOINT_PREFIX}{seq}");
+    let path = dir_path.join(&name);
+    let staging_path = dir_path.join(format!("{name}{STAGING_SUFFIX}"));
+
+    if staging_path.exists() {
+        fs::remove_dir_all(&staging_path)?;
+    }
+    fs::create_dir(&staging_path)?;
+    write(&staging_path)?;
+    sync_dir(&staging_path)?;
+
+    fs::rename(&staging_path, &path)?;
+    sync_dir(dir_path)?;
+
+    // The new checkpoint is durable; everything else can go.
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        if entry.file_name() != name.as_str()
+            && entry
+                .file_name()
+                .to_str()
+                .map_or(false, |name| name.starts_with(CHECKPOINT_PREFIX))
+        {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+
+    Ok(path)
+}
@@ -26,6 +26,8 @@
 //! });
 //! ```
 
+#[cfg(feature = "checkpoint")]
+use crate::circuit::checkpoint::{CircuitCheckpoint, Error as CheckpointError};
 use crate::{
     circuit::{
         cache::{CircuitCache, CircuitStoreMarker},
@@ -45,6 +47,9 @@ use crate::{
     time::{Timestamp, UnitTimestamp},
     Runtime,
 };
+use bincode::error::{DecodeError, EncodeError};
+#[cfg(feature = "checkpoint")]
+use std::collections::BTreeSet;
 use std::{
     borrow::Cow,
     cell::{Ref, RefCell, RefMut, UnsafeCell},
@@ -333,11 +338,51 @@ pub trait Node {
 
     fn fixedpoint(&self, scope: Scope) -> bool;
 
+    /// Returns `true` if the node retains state across clock cycles (see
+    /// [`Operator::is_stateful()`](super::operator_traits::Operator::is_stateful)).
+    ///
+    /// Returns `false` for subcircuits.  Nodes inside subcircuits are
+    /// inspected individually.
+    fn is_stateful(&self) -> bool {
+        false
+    }
+
+    /// Serialize the state of the node (see
+    /// [`Operator::checkpoint()`](super::operator_traits::Operator::checkpoint)).
+    ///
+    /// Returns `None` for stateless nodes and subcircuits.  Nodes inside
+    /// subcircuits are checkpointed individually.
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        Ok(None)
+    }
+
+    /// Restore the state of the node from a checkpoint (see
+    /// [`Operator::restore()`](super::operator_traits::Operator::restore)).
+    fn restore(&mut self, _state: &[u8]) -> Result<(), DecodeError> {
+        Ok(())
+    }
+
+    /// Restore the state of the node from checkpoints taken in a runtime
+    /// with a different number of workers (see
+    /// [`Operator::restore_rescaled()`](super::operator_traits::Operator::restore_rescaled)).
+    fn restore_rescaled(
+        &mut self,
+        _states: &[&[u8]],
+        _worker: usize,
+        _num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        Ok(())
+    }
+
     fn map_nodes_recursive(&self, _f: &mut dyn FnMut(&dyn Node)) {}
+
+    fn map_nodes_recursive_mut(&self, _f: &mut dyn FnMut(&mut dyn Node)) {}
 }
 
 /// Id of an operator, guaranteed to be unique within a circuit.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, bincode::Decode, bincode::Encode,
+)]
 #[repr(transparent)]
 pub struct NodeId(usize);
 
@@ -370,7 +415,7 @@ impl Display for NodeId {
 /// circuit or a sub-circuit nested inside the top-level circuit will have a
 /// path of length 1, e.g., `[5]`, an operator inside the nested circuit
 /// will have a path of length 2, e.g., `[5, 1]`, etc.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, bincode::Decode, bincode::Encode)]
 #[repr(transparent)]
 pub struct GlobalNodeId(Vec<NodeId>);
 
@@ -1611,6 +1656,14 @@ where
         }
     }
 
+    /// Recursively apply `f` to all nodes in `self` and its children.
+    pub(crate) fn map_nodes_recursive_mut(&self, f: &mut dyn FnMut(&mut dyn Node)) {
+        for node in self.inner_mut().nodes.iter_mut() {
+            f(node.as_mut());
+            node.map_nodes_recursive_mut(f);
+        }
+    }
+
     fn clear(&mut self) {
         self.inner_mut().clear();
     }
@@ -2570,6 +2623,27 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         self.operator.fixedpoint(scope)
     }
+
+    fn is_stateful(&self) -> bool {
+        self.operator.is_stateful()
+    }
+
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        self.operator.checkpoint()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.operator.restore(state)
+    }
+
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        self.operator.restore_rescaled(states, worker, num_workers)
+    }
 }
 
 struct SourceNode<C, O, Op> {
@@ -2646,6 +2720,27 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         self.operator.fixedpoint(scope)
     }
+
+    fn is_stateful(&self) -> bool {
+        self.operator.is_stateful()
+    }
+
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        self.operator.checkpoint()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.operator.restore(state)
+    }
+
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        self.operator.restore_rescaled(states, worker, num_workers)
+    }
 }
 
 struct UnaryNode<C, I, O, Op> {
@@ -2728,6 +2823,27 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         self.operator.fixedpoint(scope)
     }
+
+    fn is_stateful(&self) -> bool {
+        self.operator.is_stateful()
+    }
+
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        self.operator.checkpoint()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.operator.restore(state)
+    }
+
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        self.operator.restore_rescaled(states, worker, num_workers)
+    }
 }
 
 struct SinkNode<C, I, Op> {
@@ -2803,6 +2919,27 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         self.operator.fixedpoint(scope)
     }
+
+    fn is_stateful(&self) -> bool {
+        self.operator.is_stateful()
+    }
+
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        self.operator.checkpoint()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.operator.restore(state)
+    }
+
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        self.operator.restore_rescaled(states, worker, num_workers)
+    }
 }
 
 struct BinaryNode<C, I1, I2, O, Op> {
@@ -2918,6 +3055,27 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         self.operator.fixedpoint(scope)
     }
+
+    fn is_stateful(&self) -> bool {
+        self.operator.is_stateful()
+    }
+
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        self.operator.checkpoint()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.operator.restore(state)
+    }
+
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        self.operator.restore_rescaled(states, worker, num_workers)
+    }
 }
 
 struct TernaryNode<C, I1, I2, I3, O, Op> {
@@ -3037,6 +3195,27 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         self.operator.fixedpoint(scope)
     }
+
+    fn is_stateful(&self) -> bool {
+        self.operator.is_stateful()
+    }
+
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        self.operator.checkpoint()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.operator.restore(state)
+    }
+
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        self.operator.restore_rescaled(states, worker, num_workers)
+    }
 }
 
 struct QuaternaryNode<C, I1, I2, I3, I4, O, Op> {
@@ -3174,6 +3353,27 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         self.operator.fixedpoint(scope)
     }
+
+    fn is_stateful(&self) -> bool {
+        self.operator.is_stateful()
+    }
+
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        self.operator.checkpoint()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.operator.restore(state)
+    }
+
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        self.operator.restore_rescaled(states, worker, num_workers)
+    }
 }
 
 struct NaryNode<C, I, O, Op>
@@ -3296,6 +3496,27 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         self.operator.fixedpoint(scope)
     }
+
+    fn is_stateful(&self) -> bool {
+        self.operator.is_stateful()
+    }
+
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        self.operator.checkpoint()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.operator.restore(state)
+    }
+
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        self.operator.restore_rescaled(states, worker, num_workers)
+    }
 }
 
 // The output half of a feedback node.  We implement a feedback node using a
@@ -3393,6 +3614,29 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         unsafe { (*self.operator.get()).fixedpoint(scope) }
     }
+
+    fn is_stateful(&self) -> bool {
+        unsafe { (*self.operator.get()).is_stateful() }
+    }
+
+    // The input half of the feedback node shares the operator with this node
+    // and does not checkpoint it separately.
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        unsafe { (*self.operator.get()).checkpoint() }
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        unsafe { (*self.operator.get()).restore(state) }
+    }
+
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        unsafe { (*self.operator.get()).restore_rescaled(states, worker, num_workers) }
+    }
 }
 
 /// The input half of a feedback node
@@ -3612,6 +3856,10 @@ where
     fn map_nodes_recursive(&self, f: &mut dyn FnMut(&dyn Node)) {
         self.circuit.map_nodes_recursive(f);
     }
+
+    fn map_nodes_recursive_mut(&self, f: &mut dyn FnMut(&mut dyn Node)) {
+        self.circuit.map_nodes_recursive_mut(f);
+    }
 }
 
 /// Top-level circuit with executor.
@@ -3677,6 +3925,139 @@ impl CircuitHandle {
     pub fn unregister_scheduler_event_handler(&self, name: &str) -> bool {
         self.circuit.unregister_scheduler_event_handler(name)
     }
+
+    /// Returns `true` if no operator in the circuit, including operators in
+    /// nested circuits, retains state across clock cycles (see
+    /// [`Operator::is_stateful()`](super::operator_traits::Operator::is_stateful)).
+    pub fn is_stateless(&self) -> bool {
+        let mut stateless = true;
+        self.circuit
+            .map_nodes_recursive(&mut |node: &dyn Node| stateless &= !node.is_stateful());
+        stateless
+    }
+
+    /// Serialize the state of all stateful operators in the circuit.
+    ///
+    /// Must be called between clock cycles, i.e., not from within an
+    /// operator.  The resulting checkpoint can be loaded into a circuit with
+    /// identical structure using [`Self::restore`].
+    #[cfg(feature = "checkpoint")]
+    pub fn checkpoint(&self) -> Result<CircuitCheckpoint, CheckpointError> {
+        let mut checkpoint = CircuitCheckpoint::new();
+        let mut result = Ok(());
+
+        self.circuit
+            .map_nodes_recursive_mut(&mut |node: &mut dyn Node| {
+                if result.is_err() {
+                    return;
+                }
+                match node.checkpoint() {
+                    Ok(Some(state)) => checkpoint.insert(node.global_id().clone(), state),
+                    Ok(None) => {}
+                    Err(error) => {
+                        result = Err(CheckpointError::Encode {
+                            node_id: node.global_id().clone(),
+                            error,
+                        })
+                    }
+                }
+            });
+
+        result.map(|()| checkpoint)
+    }
+
+    /// Restore the state of the circuit from a checkpoint created by
+    /// [`Self::checkpoint`].
+    ///
+    /// Must be called before the first clock cycle of the circuit.  Fails if
+    /// the checkpoint contains state for nodes that do not exist in this
+    /// circuit.
+    #[cfg(feature = "checkpoint")]
+    pub fn restore(&self, checkpoint: &CircuitCheckpoint) -> Result<(), CheckpointError> {
+        let mut restored = BTreeSet::new();
+        let mut result = Ok(());
+
+        self.circuit
+            .map_nodes_recursive_mut(&mut |node: &mut dyn Node| {
+                if result.is_err() {
+                    return;
+                }
+                if let Some(state) = checkpoint.get(node.global_id()) {
+                    restored.insert(node.global_id().clone());
+                    if let Err(error) = node.restore(state) {
+                        result = Err(CheckpointError::Decode {
+                            node_id: node.global_id().clone(),
+                            error,
+                        });
+                    }
+                }
+            });
+        result?;
+
+        match checkpoint
+            .node_ids()
+            .find(|node_id| !restored.contains(*node_id))
+        {
+            Some(node_id) => Err(CheckpointError::UnknownNode {
+                node_id: node_id.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Restore the state of the circuit from checkpoints created by
+    /// [`Self::checkpoint`] in all workers of a runtime with a different
+    /// number of workers.
+    ///
+    /// `worker` is the index of the current worker and `num_workers` is the
+    /// number of workers in the new runtime.  The state of each operator is
+    /// redistributed across workers by key (see
+    /// [`Operator::restore_rescaled`](`crate::circuit::operator_traits::Operator::restore_rescaled`)).
+    /// Fails if the circuit contains operators whose state cannot be
+    /// redistributed, or if the checkpoints contain state for nodes that do
+    /// not exist in this circuit.
+    #[cfg(feature = "checkpoint")]
+    pub fn restore_rescaled(
+        &self,
+        checkpoints: &[CircuitCheckpoint],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), CheckpointError> {
+        let mut restored = BTreeSet::new();
+        let mut result = Ok(());
+
+        self.circuit
+            .map_nodes_recursive_mut(&mut |node: &mut dyn Node| {
+                if result.is_err() {
+                    return;
+                }
+                let states: Vec<&[u8]> = checkpoints
+                    .iter()
+                    .filter_map(|checkpoint| checkpoint.get(node.global_id()))
+                    .collect();
+                if !states.is_empty() {
+                    restored.insert(node.global_id().clone());
+                    if let Err(error) = node.restore_rescaled(&states, worker, num_workers) {
+                        result = Err(CheckpointError::Decode {
+                            node_id: node.global_id().clone(),
+                            error,
+                        });
+                    }
+                }
+            });
+        result?;
+
+        match checkpoints
+            .iter()
+            .flat_map(CircuitCheckpoint::node_ids)
+            .find(|node_id| !restored.contains(*node_id))
+        {
+            Some(node_id) => Err(CheckpointError::UnknownNode {
+                node_id: node_id.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1,16 +1,36 @@
+#[cfg(feature = "checkpoint")]
+use crate::circuit::{
+    checkpoint::{self, Error as CheckpointError, WorkerCheckpoint},
+    GlobalNodeId,
+};
 use crate::{
-    circuit::runtime::RuntimeHandle, profile::Profiler, Error as DBSPError, RootCircuit, Runtime,
-    RuntimeError, SchedulerError,
+    circuit::{
+        checkpoint::CircuitCheckpoint,
+        network::{ControlEvent, Network},
+        runtime::{Layout, RuntimeHandle},
+    },
+    profile::Profiler,
+    Error as DBSPError, RootCircuit, Runtime, RuntimeError,
 };
+use bincode::{config::standard, decode_from_slice, encode_to_vec, Decode, Encode};
 use crossbeam::channel::{bounded, Receiver, Sender, TryRecvError};
+#[cfg(feature = "checkpoint")]
+use std::io;
 use std::{
     fs,
     fs::create_dir_all,
     path::{Path, PathBuf},
+    sync::Arc,
     thread::Result as ThreadResult,
     time::Instant,
 };
 
+/// Path to the checkpoint file of worker `worker` in checkpoint `path`.
+#[cfg(feature = "checkpoint")]
+fn checkpoint_file(path: &Path, worker: usize) -> PathBuf {
+    path.join(format!("{worker}.checkpoint"))
+}
+
 impl Runtime {
     /// Instantiate a circuit in a multithreaded runtime.
     ///
@@ -38,6 +58,187 @@ impl Runtime {
         F: FnOnce(&mut RootCircuit) -> T + Clone + Send + 'static,
         T: Clone + Send + 'static,
     {
+        Self::init_circuit_inner(Layout::new_solo(nworkers), None, constructor)
+    }
+
+    /// Instantiate a circuit in a runtime that spans multiple hosts.
+    ///
+    /// Must be invoked on the coordinator, i.e., host 0 in `layout`, while
+    /// every other host in the layout invokes [`Self::serve_multihost`] with
+    /// the same layout (except for `local_host`) and a `constructor` that
+    /// builds an identical circuit.  Waits for all hosts to come up and
+    /// returns a [`DBSPHandle`] that controls workers on all hosts: each
+    /// command issued via the handle is executed by all workers in the
+    /// layout.
+    ///
+    /// Input and output handles returned by `constructor` on the coordinator
+    /// only reach the workers on the coordinator.  Inputs are distributed to
+    /// other hosts by sharding operators, e.g., [`Stream::join`](`crate::Stream::join`).
+    /// Use [`Stream::gather`](`crate::Stream::gather`) to collect outputs at
+    /// worker 0 before attaching an output handle to them.
+    ///
+    /// Restoring a multihost circuit from a checkpoint is not supported.
+    pub fn init_circuit_multihost<F, T>(
+        layout: Layout,
+        constructor: F,
+    ) -> Result<(DBSPHandle, T), DBSPError>
+    where
+        F: FnOnce(&mut RootCircuit) -> T + Clone + Send + 'static,
+        T: Clone + Send + 'static,
+    {
+        assert!(layout.is_coordinator());
+
+        let (mut handle, res) = Self::init_circuit_inner(layout, None, constructor)?;
+        handle.network = handle
+            .runtime
+            .as_ref()
+            .and_then(|runtime| runtime.runtime().network().cloned());
+
+        Ok((handle, res))
+    }
+
+    /// Run the workers of a host other than the coordinator in a runtime that
+    /// spans multiple hosts.
+    ///
+    /// Instantiates the circuit built by `constructor` in the workers of the
+    /// current host in `layout` and executes commands issued by the
+    /// coordinator (see [`Self::init_circuit_multihost`]) until the
+    /// coordinator kills the circuit or disconnects.
+    pub fn serve_multihost<F, T>(layout: Layout, constructor: F) -> Result<(), DBSPError>
+    where
+        F: FnOnce(&mut RootCircuit) -> T + Clone + Send + 'static,
+        T: Clone + Send + 'static,
+    {
+        assert!(layout.is_multihost() && !layout.is_coordinator());
+
+        let (mut handle, _) = Self::init_circuit_inner(layout, None, constructor)?;
+        let network = handle
+            .runtime
+            .as_ref()
+            .unwrap()
+            .runtime()
+            .network()
+            .unwrap()
+            .clone();
+
+        loop {
+            match network.recv_control() {
+                Ok(ControlEvent::Message { host: 0, payload }) => {
+                    let mut responses = Vec::new();
+                    let result = decode_control(0, &payload).and_then(|command| {
+                        handle.broadcast_command(command, |response| responses.push(response))
+                    });
+
+                    let (reply, error) = match result {
+                        Ok(()) => (Ok(responses), None),
+                        Err(error) => (Err(error.to_string()), Some(error)),
+                    };
+                    network.send_control(0, encode_control(&reply)?)?;
+
+                    if let Some(error) = error {
+                        return Err(error);
+                    }
+                }
+                // Ignore disconnects by hosts other than the coordinator: the
+                // coordinator will notice them and shut down.
+                Ok(ControlEvent::Message { .. }) => {}
+                Ok(ControlEvent::Disconnected { host, .. }) if host != 0 => {}
+                Ok(ControlEvent::Disconnected { .. }) | Err(_) => break,
+            }
+        }
+
+        // Worker failures are reported to the coordinator as responses to its
+        // commands, so there is nothing left to report here.
+        let _ = handle.kill();
+        Ok(())
+    }
+
+    /// Instantiate a circuit in a multithreaded runtime and restore its state
+    /// from a checkpoint.
+    ///
+    /// Works like [`Self::init_circuit`], but additionally restores the state
+    /// of all stateful operators in each worker from the latest checkpoint
+    /// created by [`DBSPHandle::checkpoint`] in checkpoint directory
+    /// `dir_path`.  The checkpoint must have been taken from a circuit built
+    /// by the same `constructor`.
+    ///
+    /// If the checkpoint was taken with a different number of workers, the
+    /// state of each operator is redistributed across the new set of workers
+    /// by key (see
+    /// [`CircuitHandle::restore_rescaled`](`crate::CircuitHandle::restore_rescaled`)).
+    /// This fails for circuits with operators whose state is not partitioned
+    /// by key, e.g., [`Stream::integrate`](`crate::Stream::integrate`), and
+    /// for circuits that use
+    /// [`Stream::shard_split`](`crate::Stream::shard_split`).
+    #[cfg(feature = "checkpoint")]
+    pub fn init_circuit_from_checkpoint<F, T, P>(
+        nworkers: usize,
+        dir_path: P,
+        constructor: F,
+    ) -> Result<(DBSPHandle, T), DBSPError>
+    where
+        F: FnOnce(&mut RootCircuit) -> T + Clone + Send + 'static,
+        T: Clone + Send + 'static,
+        P: AsRef<Path>,
+    {
+        let path = checkpoint::latest(&dir_path)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no checkpoint found in '{}'", dir_path.as_ref().display()),
+            )
+        })?;
+
+        let mut checkpoints = Vec::new();
+        let mut expected = None;
+
+        // The number of workers that took the checkpoint is recorded in each
+        // file; read files until we have seen all of them.
+        while checkpoints.len() < expected.unwrap_or(1) {
+            let worker = checkpoints.len();
+            let bytes = fs::read(checkpoint_file(&path, worker))?;
+            let WorkerCheckpoint {
+                num_workers,
+                circuit,
+            } = checkpoint::decode(&bytes)
+                .map_err(|error| CheckpointError::InvalidCheckpoint { error })?;
+
+            let num_workers = num_workers as usize;
+            match expected {
+                None => expected = Some(num_workers),
+                Some(expected) if expected != num_workers => {
+                    return Err(DBSPError::Checkpoint(CheckpointError::WorkerMismatch {
+                        expected,
+                        found: num_workers,
+                    }));
+                }
+                Some(_) => {}
+            }
+            checkpoints.push(circuit);
+        }
+
+        Self::init_circuit_inner(
+            Layout::new_solo(nworkers),
+            Some((path, Arc::new(checkpoints))),
+            constructor,
+        )
+    }
+
+    fn init_circuit_inner<F, T>(
+        layout: Layout,
+        restore_from: Option<(PathBuf, Arc<Vec<CircuitCheckpoint>>)>,
+        constructor: F,
+    ) -> Result<(DBSPHandle, T), DBSPError>
+    where
+        F: FnOnce(&mut RootCircuit) -> T + Clone + Send + 'static,
+        T: Clone + Send + 'static,
+    {
+        // Checkpoints are only created with the `checkpoint` feature.
+        #[cfg(not(feature = "checkpoint"))]
+        debug_assert!(restore_from.is_none());
+
+        let local_workers = layout.local_workers();
+        let nworkers = local_workers.len();
+
         // When a worker finishes building the circuit, it sends completion status back
         // to us via this channel.  The function returns after receiving a
         // notification from each worker.
@@ -52,8 +253,8 @@ impl Runtime {
         let (status_senders, status_receivers): (Vec<_>, Vec<_>) =
             (0..nworkers).map(|_| bounded(1)).unzip();
 
-        let runtime = Self::run(nworkers, move || {
-            let worker_index = Runtime::worker_index();
+        let runtime = Self::run_with_layout(layout, move || {
+            let worker_index = Runtime::local_worker_index();
 
             // Drop all but one channels.  This makes sure that if one of the worker panics
             // or exits, its channel will become disconnected.
@@ -65,6 +266,25 @@ impl Runtime {
                 let profiler = Profiler::new(circuit);
                 let res = constructor(circuit);
                 (res, profiler)
+            })
+            .map_err(DBSPError::Scheduler)
+            .and_then(|(circuit, res)| {
+                #[cfg(feature = "checkpoint")]
+                if let Some((path, checkpoints)) = &restore_from {
+                    let num_workers = Runtime::runtime().unwrap().num_workers();
+                    checkpoint::with_dir(path, || {
+                        if checkpoints.len() == num_workers {
+                            circuit.restore(&checkpoints[Runtime::worker_index()])
+                        } else {
+                            circuit.restore_rescaled(
+                                checkpoints,
+                                Runtime::worker_index(),
+                                num_workers,
+                            )
+                        }
+                    })?;
+                }
+                Ok((circuit, res))
             }) {
                 Ok((circuit, (res, profiler))) => {
                     if init_sender.send(Ok(res)).is_err() {
@@ -86,7 +306,10 @@ impl Runtime {
                 match command_receiver.try_recv() {
                     Ok(Command::Step) => {
                         //moregc = true;
-                        let status = circuit.step().map(|_| Response::Unit);
+                        let status = circuit
+                            .step()
+                            .map(|_| Response::Unit)
+                            .map_err(DBSPError::Scheduler);
                         // Send response.
                         if status_sender.send(status).is_err() {
                             return;
@@ -107,6 +330,24 @@ impl Runtime {
                             return;
                         }
                     }
+                    Ok(Command::IsStateless) => {
+                        if status_sender
+                            .send(Ok(Response::Stateless(circuit.is_stateless())))
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    #[cfg(feature = "checkpoint")]
+                    Ok(Command::Checkpoint(path)) => {
+                        let status =
+                            checkpoint::with_dir(Path::new(&path), || circuit.checkpoint())
+                                .map(Response::Checkpoint)
+                                .map_err(DBSPError::Checkpoint);
+                        if status_sender.send(status).is_err() {
+                            return;
+                        }
+                    }
                     // Nothing to do: do some housekeeping and relinquish the CPU if there's none
                     // left.
                     Err(TryRecvError::Empty) => {
@@ -122,7 +363,7 @@ impl Runtime {
                     }
                 }
             }
-        });
+        })?;
 
         // Receive initialization status from all workers.
 
@@ -130,13 +371,11 @@ impl Runtime {
 
         for (worker, receiver) in init_receivers.iter().enumerate() {
             match receiver.recv() {
-                Ok(Err(scheduler_error)) => {
-                    init_status.push(Err(DBSPError::Scheduler(scheduler_error)))
-                }
+                Ok(Err(error)) => init_status.push(Err(error)),
                 Ok(Ok(ret)) => init_status.push(Ok(ret)),
-                Err(_) => {
-                    init_status.push(Err(DBSPError::Runtime(RuntimeError::WorkerPanic(worker))))
-                }
+                Err(_) => init_status.push(Err(DBSPError::Runtime(RuntimeError::WorkerPanic(
+                    local_workers.start + worker,
+                )))),
             }
         }
 
@@ -158,16 +397,49 @@ impl Runtime {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Encode, Decode)]
 enum Command {
     Step,
     EnableProfiler,
     DumpProfile,
+    IsStateless,
+    /// Checkpoint the circuit; the argument is the path to the checkpoint
+    /// being written (see [`checkpoint::current_dir`]).
+    #[cfg(feature = "checkpoint")]
+    Checkpoint(String),
 }
 
+#[derive(Encode, Decode)]
 enum Response {
     Unit,
     Profile(String),
+    Stateless(bool),
+    #[cfg(feature = "checkpoint")]
+    Checkpoint(CircuitCheckpoint),
+}
+
+/// Reply of a host to a command from the coordinator: responses of all of its
+/// workers or an error.
+type HostReply = Result<Vec<Response>, String>;
+
+fn encode_control<T: Encode>(value: &T) -> Result<Vec<u8>, DBSPError> {
+    encode_to_vec(value, standard()).map_err(|error| {
+        DBSPError::Runtime(RuntimeError::HostFailure {
+            host: 0,
+            error: error.to_string(),
+        })
+    })
+}
+
+fn decode_control<T: Decode>(host: usize, bytes: &[u8]) -> Result<T, DBSPError> {
+    decode_from_slice(bytes, standard())
+        .map(|(value, _)| value)
+        .map_err(|error| {
+            DBSPError::Runtime(RuntimeError::HostFailure {
+                host,
+                error: error.to_string(),
+            })
+        })
 }
 
 /// A handle to control the execution of a circuit in a multithreaded runtime.
@@ -176,22 +448,29 @@ pub struct DBSPHandle {
     // Time when the handle was created.
     start_time: Instant,
     runtime: Option<RuntimeHandle>,
+    // The number of workers across all hosts.
+    num_workers: usize,
+    // Connections to other hosts, if this handle is the coordinator of a
+    // multihost runtime.
+    network: Option<Arc<Network>>,
     // Channels used to send commands to workers.
     command_senders: Vec<Sender<Command>>,
     // Channels used to receive command completion status from
     // workers.
-    status_receivers: Vec<Receiver<Result<Response, SchedulerError>>>,
+    status_receivers: Vec<Receiver<Result<Response, DBSPError>>>,
 }
 
 impl DBSPHandle {
     fn new(
         runtime: RuntimeHandle,
         command_senders: Vec<Sender<Command>>,
-        status_receivers: Vec<Receiver<Result<Response, SchedulerError>>>,
+        status_receivers: Vec<Receiver<Result<Response, DBSPError>>>,
     ) -> Self {
         Self {
             start_time: Instant::now(),
+            num_workers: runtime.runtime().num_workers(),
             runtime: Some(runtime),
+            network: None,
             command_senders,
             status_receivers,
         }
@@ -211,6 +490,21 @@ impl DBSPHandle {
             return Err(DBSPError::Runtime(RuntimeError::Killed));
         }
 
+        // Forward the command to other hosts.  They must receive it before we
+        // wait for local workers, which may need to exchange data with them.
+        if let Some(network) = self.network.clone() {
+            let payload = encode_control(&command)?;
+            for host in 1..self.runtime.as_ref().unwrap().runtime().layout().n_hosts() {
+                if let Err(error) = network.send_control(host, payload.clone()) {
+                    let _ = self.kill_inner();
+                    return Err(DBSPError::Runtime(RuntimeError::HostFailure {
+                        host,
+                        error: error.to_string(),
+                    }));
+                }
+            }
+        }
+
         // Send command.
         for (worker, sender) in self.command_senders.iter().enumerate() {
             if matches!(sender.send(command.clone()), Err(_)) {
@@ -229,17 +523,54 @@ impl DBSPHandle {
                 }
                 Ok(Err(e)) => {
                     let _ = self.kill_inner();
-                    return Err(DBSPError::Scheduler(e));
+                    return Err(e);
                 }
                 Ok(Ok(resp)) => handler(resp),
             }
         }
 
+        // Receive responses from other hosts.
+        if let Some(network) = self.network.clone() {
+            let nhosts = self.runtime.as_ref().unwrap().runtime().layout().n_hosts();
+            let mut replies: Vec<Option<Vec<Response>>> = (0..nhosts).map(|_| None).collect();
+
+            for _ in 1..nhosts {
+                let result = match network.recv_control() {
+                    Ok(ControlEvent::Message { host, payload }) => {
+                        decode_control::<HostReply>(host, &payload)
+                            .and_then(|reply| {
+                                reply.map_err(|error| {
+                                    DBSPError::Runtime(RuntimeError::HostFailure { host, error })
+                                })
+                            })
+                            .map(|responses| replies[host] = Some(responses))
+                    }
+                    Ok(ControlEvent::Disconnected { host, error }) => {
+                        Err(DBSPError::Runtime(RuntimeError::HostFailure {
+                            host,
+                            error,
+                        }))
+                    }
+                    Err(_) => unreachable!("network receive threads hold the control channel"),
+                };
+
+                if let Err(error) = result {
+                    let _ = self.kill_inner();
+                    return Err(error);
+                }
+            }
+
+            for response in replies.into_iter().flatten().flatten() {
+                handler(response);
+            }
+        }
+
         Ok(())
     }
 
+    /// Returns the number of workers running the circuit, across all hosts.
     pub fn num_workers(&self) -> usize {
-        self.status_receivers.len()
+        self.num_workers
     }
 
     /// Evaluate the circuit for one clock cycle.
@@ -285,6 +616,96 @@ impl DBSPHandle {
         Ok(dir_path)
     }
 
+    /// Returns `true` if the circuit has no stateful operators in any worker
+    /// (see [`Operator::is_stateful`](`crate::circuit::operator_traits::Operator::is_stateful`)).
+    ///
+    /// The output of a stateless circuit at each step depends only on the
+    /// inputs of the step.
+    pub fn is_stateless(&mut self) -> Result<bool, DBSPError> {
+        let mut stateless = true;
+        self.broadcast_command(Command::IsStateless, |resp| {
+            if let Response::Stateless(worker_stateless) = resp {
+                stateless &= worker_stateless;
+            }
+        })?;
+        Ok(stateless)
+    }
+
+    /// Checkpoint the state of the circuit to the specified checkpoint
+    /// directory.
+    ///
+    /// Creates `dir_path` if it doesn't exist.  Writes a new checkpoint
+    /// containing the state of all stateful operators in each worker thread
+    /// and replaces the previous checkpoint in `dir_path`, if any.  The
+    /// checkpoint captures the state of the circuit after the last completed
+    /// [`Self::step`].  Use [`Runtime::init_circuit_from_checkpoint`] to
+    /// instantiate a circuit from the checkpoint.
+    ///
+    /// The checkpoint is committed atomically (see
+    /// [`checkpoint`](`crate::circuit::checkpoint`)): if this method fails or
+    /// the process crashes while taking the checkpoint, `dir_path` still
+    /// contains the previous checkpoint.
+    ///
+    /// Traces that spill batches to disk add their batch files to the
+    /// checkpoint instead of serializing them.  The files are hard-linked
+    /// if `dir_path` is on the same file system as the spill directory and
+    /// copied otherwise.
+    #[cfg(feature = "checkpoint")]
+    pub fn checkpoint<P: AsRef<Path>>(&mut self, dir_path: P) -> Result<(), DBSPError> {
+        self.checkpoint_with(dir_path, |_| Ok(()))
+    }
+
+    /// Checkpoint the state of the circuit along with additional state
+    /// supplied by the caller.
+    ///
+    /// Works like [`Self::checkpoint`], but calls `write_extra` with the path
+    /// of the new checkpoint before committing it.  `write_extra` can add
+    /// files to the checkpoint (see
+    /// [`checkpoint::write_file`](`crate::circuit::checkpoint::write_file`)),
+    /// which get committed atomically with the state of the circuit.  Use
+    /// [`checkpoint::latest`](`crate::circuit::checkpoint::latest`) to locate
+    /// them when restoring from the checkpoint.
+    #[cfg(feature = "checkpoint")]
+    pub fn checkpoint_with<P, F>(&mut self, dir_path: P, write_extra: F) -> Result<(), DBSPError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&Path) -> io::Result<()>,
+    {
+        checkpoint::commit(dir_path.as_ref(), |path| {
+            // Workers may add files to the checkpoint, so they need its path.
+            let path_str = path.to_str().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid checkpoint path '{}'", path.display()),
+                )
+            })?;
+
+            let mut checkpoints = Vec::with_capacity(self.num_workers());
+            self.broadcast_command(Command::Checkpoint(path_str.to_string()), |resp| {
+                if let Response::Checkpoint(checkpoint) = resp {
+                    checkpoints.push(checkpoint);
+                }
+            })?;
+
+            let num_workers = checkpoints.len() as u64;
+            for (worker, circuit) in checkpoints.into_iter().enumerate() {
+                let bytes = checkpoint::encode(&WorkerCheckpoint {
+                    num_workers,
+                    circuit,
+                })
+                .map_err(|error| CheckpointError::Encode {
+                    node_id: GlobalNodeId::root(),
+                    error,
+                })?;
+                checkpoint::write_file(checkpoint_file(path, worker), &bytes)?;
+            }
+
+            write_extra(path).map_err(DBSPError::from)
+        })?;
+
+        Ok(())
+    }
+
     /// Terminate the execution of the circuit, exiting all worker threads.
     ///
     /// If one or more of the worker threads panics, returns the argument the
@@ -312,7 +733,22 @@ impl Drop for DBSPHandle {
 
 #[cfg(test)]
 mod tests {
-    use crate::{operator::Generator, Circuit, Error as DBSPError, Runtime, RuntimeError};
+    #[cfg(feature = "checkpoint")]
+    use crate::{
+        circuit::{checkpoint, Layout},
+        CheckpointError, Circuit, CollectionHandle, OrdIndexedZSet, OrdZSet, OutputHandle,
+        RootCircuit,
+    };
+    use crate::{
+        operator::{FilterMap, Generator},
+        Error as DBSPError, Runtime, RuntimeError,
+    };
+    #[cfg(feature = "checkpoint")]
+    use std::{
+        fs::{create_dir_all, read_dir, remove_dir_all},
+        net::TcpListener,
+        thread::spawn,
+    };
 
     // Panic during initialization in worker thread.
     #[test]
@@ -419,4 +855,315 @@ mod tests {
 
         handle.step().unwrap();
     }
+
+    #[test]
+    fn test_is_stateless() {
+        let (mut handle, _) = Runtime::init_circuit(4, |circuit| {
+            let (input, input_handle) = circuit.add_input_zset::<u64, isize>();
+            input.map(|x| x + 1).output();
+            input_handle
+        })
+        .unwrap();
+        assert!(handle.is_stateless().unwrap());
+        handle.kill().unwrap();
+
+        // The integral keeps state across clock cycles.
+        let (mut handle, _) = Runtime::init_circuit(4, |circuit| {
+            let (input, input_handle) = circuit.add_input_zset::<u64, isize>();
+            input.map(|x| x + 1).integrate().output();
+            input_handle
+        })
+        .unwrap();
+        assert!(!handle.is_stateless().unwrap());
+        handle.kill().unwrap();
+    }
+
+    #[cfg(feature = "checkpoint")]
+    type CheckpointTestHandles = (
+        CollectionHandle<u64, isize>,
+        OutputHandle<OrdZSet<u64, isize>>,
+        OutputHandle<OrdZSet<(u64, u64), isize>>,
+    );
+
+    // Circuit with an integral and an incremental join, both of which keep
+    // state across clock cycles.
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint_test_circuit(circuit: &mut RootCircuit) -> CheckpointTestHandles {
+        let (input, input_handle) = circuit.add_input_zset::<u64, isize>();
+        let integral = input.integrate().output();
+        let indexed = input.index_with(|x| (*x % 3, *x));
+        let join = indexed.join(&indexed, |_k, x, y| (*x, *y)).output();
+
+        (input_handle, integral, join)
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint_test_step(
+        handle: &mut crate::DBSPHandle,
+        (input, integral, join): &CheckpointTestHandles,
+        inputs: &[u64],
+    ) -> (OrdZSet<u64, isize>, OrdZSet<(u64, u64), isize>) {
+        for x in inputs {
+            input.push(*x, 1);
+        }
+        handle.step().unwrap();
+        (integral.consolidate(), join.consolidate())
+    }
+
+    // Checkpoint the circuit, kill it, restore it from the checkpoint, and
+    // check that it produces the same outputs as a circuit that was never
+    // stopped.
+    #[cfg(feature = "checkpoint")]
+    #[test]
+    fn test_checkpoint1() {
+        test_checkpoint(1);
+    }
+
+    #[cfg(feature = "checkpoint")]
+    #[test]
+    fn test_checkpoint4() {
+        test_checkpoint(4);
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn test_checkpoint(nworkers: usize) {
+        let checkpoint_dir = std::env::temp_dir().join(format!("test_checkpoint{nworkers}"));
+        let _ = remove_dir_all(&checkpoint_dir);
+
+        let (mut reference, reference_handles) =
+            Runtime::init_circuit(nworkers, checkpoint_test_circuit).unwrap();
+        let (mut handle, handles) =
+            Runtime::init_circuit(nworkers, checkpoint_test_circuit).unwrap();
+
+        let batches: [&[u64]; 3] = [&[1, 2, 3, 4, 5], &[6, 7], &[1, 8, 9, 10]];
+
+        assert_eq!(
+            checkpoint_test_step(&mut reference, &reference_handles, batches[0]),
+            checkpoint_test_step(&mut handle, &handles, batches[0])
+        );
+
+        // Leftovers of a checkpoint that was never committed must be ignored
+        // and cleaned up.
+        let staging_dir = checkpoint_dir.join("checkpoint-0.tmp");
+        create_dir_all(&staging_dir).unwrap();
+        std::fs::write(staging_dir.join("0.checkpoint"), b"garbage").unwrap();
+
+        handle.checkpoint(&checkpoint_dir).unwrap();
+        handle.checkpoint(&checkpoint_dir).unwrap();
+        handle.kill().unwrap();
+
+        // Only the latest checkpoint is retained.
+        assert_eq!(read_dir(&checkpoint_dir).unwrap().count(), 1);
+        assert_eq!(
+            checkpoint::latest(&checkpoint_dir).unwrap(),
+            Some(checkpoint_dir.join("checkpoint-1"))
+        );
+
+        let (mut handle, handles) = Runtime::init_circuit_from_checkpoint(
+            nworkers,
+            &checkpoint_dir,
+            checkpoint_test_circuit,
+        )
+        .unwrap();
+
+        for batch in &batches[1..] {
+            assert_eq!(
+                checkpoint_test_step(&mut reference, &reference_handles, batch),
+                checkpoint_test_step(&mut handle, &handles, batch)
+            );
+        }
+
+        handle.kill().unwrap();
+        reference.kill().unwrap();
+
+        // The state of the integral is not partitioned by key and cannot be
+        // migrated to a different number of workers.
+        let err = Runtime::init_circuit_from_checkpoint(
+            nworkers + 1,
+            &checkpoint_dir,
+            checkpoint_test_circuit,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            DBSPError::Checkpoint(CheckpointError::Decode { .. })
+        ));
+
+        remove_dir_all(&checkpoint_dir).unwrap();
+    }
+
+    // Restore a checkpoint taken with `old_workers` workers into a runtime
+    // with `new_workers` workers.
+    #[cfg(feature = "checkpoint")]
+    fn test_rescale(old_workers: usize, new_workers: usize) {
+        let checkpoint_dir =
+            std::env::temp_dir().join(format!("test_rescale{old_workers}_{new_workers}"));
+        let _ = remove_dir_all(&checkpoint_dir);
+
+        let (mut reference, reference_handles) =
+            Runtime::init_circuit(old_workers, multihost_test_circuit).unwrap();
+        let (mut handle, handles) =
+            Runtime::init_circuit(old_workers, multihost_test_circuit).unwrap();
+
+        let batches: [&[u64]; 3] = [&[1, 2, 3, 4, 5, 11, 12, 13], &[6, 7, 14], &[1, 8, 9, 10]];
+
+        assert_eq!(
+            multihost_test_step(&mut reference, &reference_handles, batches[0]),
+            multihost_test_step(&mut handle, &handles, batches[0])
+        );
+
+        handle.checkpoint(&checkpoint_dir).unwrap();
+        handle.kill().unwrap();
+
+        let (mut handle, handles) = Runtime::init_circuit_from_checkpoint(
+            new_workers,
+            &checkpoint_dir,
+            multihost_test_circuit,
+        )
+        .unwrap();
+
+        for batch in &batches[1..] {
+            assert_eq!(
+                multihost_test_step(&mut reference, &reference_handles, batch),
+                multihost_test_step(&mut handle, &handles, batch)
+            );
+        }
+
+        handle.kill().unwrap();
+        reference.kill().unwrap();
+        remove_dir_all(&checkpoint_dir).unwrap();
+    }
+
+    #[cfg(feature = "checkpoint")]
+    #[test]
+    fn test_rescale_up() {
+        test_rescale(2, 3);
+    }
+
+    #[cfg(feature = "checkpoint")]
+    #[test]
+    fn test_rescale_down() {
+        test_rescale(4, 1);
+    }
+
+    // Circuit that splits the values of each key across workers.
+    #[cfg(feature = "checkpoint")]
+    fn split_test_circuit(circuit: &mut RootCircuit) -> CollectionHandle<u64, isize> {
+        let (input, input_handle) = circuit.add_input_zset::<u64, isize>();
+        input
+            .index_with(|x| (x % 2, *x))
+            .shard_split(2)
+            .integrate_trace();
+        input_handle
+    }
+
+    // Traces of a circuit that uses `shard_split` are not partitioned by key
+    // and cannot be migrated to a different number of workers.
+    #[cfg(feature = "checkpoint")]
+    #[test]
+    fn test_rescale_split() {
+        let checkpoint_dir = std::env::temp_dir().join("test_rescale_split");
+        let _ = remove_dir_all(&checkpoint_dir);
+
+        let (mut handle, input) = Runtime::init_circuit(2, split_test_circuit).unwrap();
+        for x in 0..10 {
+            input.push(x, 1);
+        }
+        handle.step().unwrap();
+        handle.checkpoint(&checkpoint_dir).unwrap();
+        handle.kill().unwrap();
+
+        let err = Runtime::init_circuit_from_checkpoint(3, &checkpoint_dir, split_test_circuit)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DBSPError::Checkpoint(CheckpointError::Decode { .. })
+        ));
+
+        // The same number of workers does not require rescaling.
+        let (handle, _input) =
+            Runtime::init_circuit_from_checkpoint(2, &checkpoint_dir, split_test_circuit).unwrap();
+        handle.kill().unwrap();
+
+        remove_dir_all(&checkpoint_dir).unwrap();
+    }
+
+    #[cfg(feature = "checkpoint")]
+    type MultihostTestHandles = (
+        CollectionHandle<u64, isize>,
+        OutputHandle<OrdZSet<(u64, u64), isize>>,
+        OutputHandle<OrdIndexedZSet<u64, isize, isize>>,
+    );
+
+    // Circuit with a join and an aggregate, which shard their inputs across
+    // all workers, with outputs gathered at worker 0.
+    #[cfg(feature = "checkpoint")]
+    fn multihost_test_circuit(circuit: &mut RootCircuit) -> MultihostTestHandles {
+        let (input, input_handle) = circuit.add_input_zset::<u64, isize>();
+        let indexed = input.index_with(|x| (*x % 7, *x));
+        let join = indexed
+            .join(&indexed, |_k, x, y| (*x, *y))
+            .gather(0)
+            .output();
+        let sums = indexed
+            .aggregate_linear(|_k, x| *x as isize)
+            .gather(0)
+            .output();
+
+        (input_handle, join, sums)
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn multihost_test_step(
+        handle: &mut crate::DBSPHandle,
+        (input, join, sums): &MultihostTestHandles,
+        inputs: &[u64],
+    ) -> (
+        OrdZSet<(u64, u64), isize>,
+        OrdIndexedZSet<u64, isize, isize>,
+    ) {
+        for x in inputs {
+            input.push(*x, 1);
+        }
+        handle.step().unwrap();
+        (join.consolidate(), sums.consolidate())
+    }
+
+    // Run a circuit on two hosts connected over the loopback interface and
+    // check that it produces the same outputs as a circuit running on a
+    // single host with the same total number of workers.
+    #[cfg(feature = "checkpoint")]
+    #[test]
+    fn test_multihost() {
+        let hosts: Vec<_> = (0..2)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect::<Vec<_>>()
+            .iter()
+            .map(|listener| listener.local_addr().unwrap())
+            .collect();
+
+        let remote_layout = Layout::new_multihost(hosts.clone(), 2, 1);
+        let remote = spawn(move || Runtime::serve_multihost(remote_layout, multihost_test_circuit));
+
+        let (mut handle, handles) = Runtime::init_circuit_multihost(
+            Layout::new_multihost(hosts, 2, 0),
+            multihost_test_circuit,
+        )
+        .unwrap();
+        let (mut reference, reference_handles) =
+            Runtime::init_circuit(4, multihost_test_circuit).unwrap();
+        assert_eq!(handle.num_workers(), 4);
+
+        let batches: [&[u64]; 3] = [&[1, 2, 3, 4, 5, 6, 7, 8, 9], &[10, 11], &[1, 12, 13, 14]];
+        for batch in batches {
+            assert_eq!(
+                multihost_test_step(&mut reference, &reference_handles, batch),
+                multihost_test_step(&mut handle, &handles, batch)
+            );
+        }
+
+        handle.kill().unwrap();
+        reference.kill().unwrap();
+        remote.join().unwrap().unwrap();
+    }
 }
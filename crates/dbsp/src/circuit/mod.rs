@@ -7,23 +7,28 @@
 
 mod activations;
 mod dbsp_handle;
+mod network;
 
 pub(crate) mod runtime;
 
 #[macro_use]
 pub mod metadata;
 pub mod cache;
+pub mod checkpoint;
 pub mod circuit_builder;
 pub mod operator_traits;
 pub mod schedule;
 pub mod trace;
 
 pub use activations::{Activations, Activator};
+pub use checkpoint::{CircuitCheckpoint, Error as CheckpointError};
 pub use circuit_builder::{
     ChildCircuit, Circuit, CircuitHandle, ExportId, ExportStream, FeedbackConnector, GlobalNodeId,
     NodeId, OwnershipPreference, RootCircuit, Scope, Stream, WithClock,
 };
 pub use dbsp_handle::DBSPHandle;
-pub use runtime::{Error as RuntimeError, LocalStore, LocalStoreMarker, Runtime, RuntimeHandle};
+pub use runtime::{
+    Error as RuntimeError, Layout, LocalStore, LocalStoreMarker, Runtime, RuntimeHandle,
+};
 
 pub use schedule::Error as SchedulerError;
@@ -0,0 +1,334 @@
+//! TCP transport for runtimes that span multiple hosts.
+//!
+//! Every host in a multihost [`Layout`] opens one TCP connection to each of
+//! its peers and sends all of its messages for that peer over this
+//! connection.  Conversely, the host accepts one connection from each peer
+//! and runs a thread per incoming connection that dispatches received
+//! messages.  Messages are encoded with `bincode` and prefixed with their
+//! length.
+//!
+//! The transport carries two kinds of traffic:
+//!
+//! * Exchange traffic: values sent between workers on different hosts by an
+//!   exchange operator, and acknowledgements that the receiving worker has
+//!   consumed them.  Received values are dispatched to the exchange by id.
+//!   Values that arrive before the local workers have created the exchange
+//!   are buffered until it is registered.
+//!
+//! * Control traffic: commands sent by the coordinator to other hosts and
+//!   their responses.  These are queued for the thread that controls the
+//!   local workers.
+
+use crate::circuit::runtime::Layout;
+use bincode::{config::standard, decode_from_slice, encode_to_vec, Decode, Encode};
+use crossbeam::channel::{unbounded, Receiver, RecvError, Sender};
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread::{sleep, Builder},
+    time::{Duration, Instant},
+};
+
+/// How long to wait for all peers to come up.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Delay between attempts to connect to a peer or to accept a connection.
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A message exchanged between hosts.
+#[derive(Encode, Decode)]
+enum Message {
+    /// A value sent by worker `sender` to worker `receiver` via exchange
+    /// `exchange_id`.
+    Data {
+        exchange_id: usize,
+        sender: usize,
+        receiver: usize,
+        payload: Vec<u8>,
+    },
+    /// A receiver has consumed the value sent to it by worker `sender` via
+    /// exchange `exchange_id`.
+    Ack { exchange_id: usize, sender: usize },
+    /// A command from the coordinator or a response to it.
+    Control(Vec<u8>),
+}
+
+/// The receiving end of an exchange, which handles exchange traffic from
+/// remote workers.
+pub(crate) trait ExchangeEndpoint: Send + Sync {
+    /// Delivers a value sent by remote worker `sender` to local worker
+    /// `receiver`.
+    fn deliver(&self, sender: usize, receiver: usize, payload: &[u8]);
+
+    /// Notifies local worker `sender` that a remote receiver has consumed the
+    /// value it sent.
+    fn acknowledge(&self, sender: usize);
+}
+
+enum Endpoint {
+    Registered(Arc<dyn ExchangeEndpoint>),
+    /// Messages received before the exchange was registered.
+    Pending(Vec<Message>),
+}
+
+type Endpoints = Mutex<HashMap<usize, Endpoint>>;
+
+/// Control traffic received from a peer.
+pub(crate) enum ControlEvent {
+    /// Control message from `host`.
+    Message { host: usize, payload: Vec<u8> },
+    /// The connection from `host` was closed or failed.
+    Disconnected { host: usize, error: String },
+}
+
+/// Connections from the current host to all other hosts in a layout.
+pub(crate) struct Network {
+    /// Outgoing connections, indexed by host; `None` for the local host.
+    outgoing: Vec<Option<Mutex<BufWriter<TcpStream>>>>,
+    endpoints: Arc<Endpoints>,
+    control: Receiver<ControlEvent>,
+}
+
+impl Debug for Network {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Network")
+            .field("peers", &self.outgoing.iter().flatten().count())
+            .finish()
+    }
+}
+
+impl Network {
+    /// Connects to all peers in `layout`, waiting for them to start up if
+    /// necessary.
+    pub(crate) fn connect(layout: &Layout) -> io::Result<Self> {
+        let hosts = layout.hosts();
+        let local_host = layout.local_host();
+
+        // Bind before connecting to peers, so that they can connect to us while
+        // we are connecting to them.
+        let listener = TcpListener::bind(hosts[local_host])?;
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+
+        let mut outgoing = Vec::with_capacity(hosts.len());
+        for (host, address) in hosts.iter().enumerate() {
+            if host == local_host {
+                outgoing.push(None);
+            } else {
+                let mut stream = connect_with_retry(address, deadline)?;
+                stream.set_nodelay(true)?;
+                stream.write_all(&(local_host as u64).to_le_bytes())?;
+                outgoing.push(Some(Mutex::new(BufWriter::new(stream))));
+            }
+        }
+
+        let endpoints: Arc<Endpoints> = Arc::new(Mutex::new(HashMap::new()));
+        let (control_sender, control) = unbounded();
+
+        for (host, stream) in accept_peers(&listener, hosts.len() - 1, deadline)? {
+            let endpoints = endpoints.clone();
+            let control_sender = control_sender.clone();
+
+            Builder::new()
+                .name(format!("dbsp-network-{host}"))
+                .spawn(move || {
+                    let error = match receive_loop(stream, &endpoints, &control_sender, host) {
+                        Ok(()) => "connection closed".to_string(),
+                        Err(error) => error.to_string(),
+                    };
+                    let _ = control_sender.send(ControlEvent::Disconnected { host, error });
+                })?;
+        }
+
+        Ok(Self {
+            outgoing,
+            endpoints,
+            control,
+        })
+    }
+
+    /// Registers the receiving end of exchange `exchange_id` and delivers any
+    /// messages for it that arrived earlier.
+    pub(crate) fn register(&self, exchange_id: usize, endpoint: Arc<dyn ExchangeEndpoint>) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+
+        if let Some(Endpoint::Pending(messages)) =
+            endpoints.insert(exchange_id, Endpoint::Registered(endpoint.clone()))
+        {
+            for message in messages {
+                dispatch(endpoint.as_ref(), message);
+            }
+        }
+    }
+
+    /// Sends value `payload` from local worker `sender` to remote worker
+    /// `receiver` on `host`.
+    pub(crate) fn send_data(
+        &self,
+        host: usize,
+        exchange_id: usize,
+        sender: usize,
+        receiver: usize,
+        payload: Vec<u8>,
+    ) -> io::Result<()> {
+        self.send(
+            host,
+            &Message::Data {
+                exchange_id,
+                sender,
+                receiver,
+                payload,
+            },
+        )
+    }
+
+    /// Notifies remote worker `sender` on `host` that its value has been
+    /// consumed.
+    pub(crate) fn send_ack(
+        &self,
+        host: usize,
+        exchange_id: usize,
+        sender: usize,
+    ) -> io::Result<()> {
+        self.send(
+            host,
+            &Message::Ack {
+                exchange_id,
+                sender,
+            },
+        )
+    }
+
+    /// Sends a control message to `host`.
+    pub(crate) fn send_control(&self, host: usize, payload: Vec<u8>) -> io::Result<()> {
+        self.send(host, &Message::Control(payload))
+    }
+
+    /// Waits for the next control event from any peer.
+    pub(crate) fn recv_control(&self) -> Result<ControlEvent, RecvError> {
+        self.control.recv()
+    }
+
+    /// Closes all outgoing connections.  Peers observe this as a disconnect.
+    pub(crate) fn shutdown(&self) {
+        for stream in self.outgoing.iter().flatten() {
+            let stream = stream.lock().unwrap();
+            let _ = stream.get_ref().shutdown(Shutdown::Write);
+        }
+    }
+
+    fn send(&self, host: usize, message: &Message) -> io::Result<()> {
+        let bytes = encode_to_vec(message, standard())
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+
+        let mut stream = self.outgoing[host]
+            .as_ref()
+            .expect("cannot send a message to the local host")
+            .lock()
+            .unwrap();
+        stream.write_all(&(bytes.len() as u64).to_le_bytes())?;
+        stream.write_all(&bytes)?;
+        stream.flush()
+    }
+}
+
+fn connect_with_retry(address: &SocketAddr, deadline: Instant) -> io::Result<TcpStream> {
+    loop {
+        match TcpStream::connect(address) {
+            Ok(stream) => return Ok(stream),
+            Err(error) if Instant::now() >= deadline => return Err(error),
+            Err(_) => sleep(CONNECT_RETRY_INTERVAL),
+        }
+    }
+}
+
+/// Accepts connections from `npeers` peers and reads the index of the peer
+/// from each connection.
+fn accept_peers(
+    listener: &TcpListener,
+    npeers: usize,
+    deadline: Instant,
+) -> io::Result<Vec<(usize, TcpStream)>> {
+    listener.set_nonblocking(true)?;
+
+    let mut streams = Vec::with_capacity(npeers);
+    while streams.len() < npeers {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                stream.set_nonblocking(false)?;
+                let mut host = [0; 8];
+                stream.read_exact(&mut host)?;
+                streams.push((u64::from_le_bytes(host) as usize, stream));
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        ErrorKind::TimedOut,
+                        "timed out waiting for peers to connect",
+                    ));
+                }
+                sleep(CONNECT_RETRY_INTERVAL);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(streams)
+}
+
+/// Reads messages from the connection from `host` until the peer closes it.
+fn receive_loop(
+    stream: TcpStream,
+    endpoints: &Endpoints,
+    control_sender: &Sender<ControlEvent>,
+    host: usize,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut buffer = Vec::new();
+
+    loop {
+        let mut len = [0; 8];
+        match reader.read_exact(&mut len) {
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+
+        buffer.resize(u64::from_le_bytes(len) as usize, 0);
+        reader.read_exact(&mut buffer)?;
+        let (message, _) = decode_from_slice(&buffer, standard())
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+
+        let exchange_id = match message {
+            Message::Control(payload) => {
+                let _ = control_sender.send(ControlEvent::Message { host, payload });
+                continue;
+            }
+            Message::Data { exchange_id, .. } | Message::Ack { exchange_id, .. } => exchange_id,
+        };
+
+        match endpoints
+            .lock()
+            .unwrap()
+            .entry(exchange_id)
+            .or_insert_with(|| Endpoint::Pending(Vec::new()))
+        {
+            Endpoint::Registered(endpoint) => dispatch(endpoint.as_ref(), message),
+            Endpoint::Pending(messages) => messages.push(message),
+        }
+    }
+}
+
+fn dispatch(endpoint: &dyn ExchangeEndpoint, message: Message) {
+    match message {
+        Message::Data {
+            sender,
+            receiver,
+            payload,
+            ..
+        } => endpoint.deliver(sender, receiver, &payload),
+        Message::Ack { sender, .. } => endpoint.acknowledge(sender),
+        Message::Control(_) => unreachable!(),
+    }
+}
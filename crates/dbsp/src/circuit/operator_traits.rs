@@ -7,6 +7,7 @@ use crate::circuit::{
     metadata::{OperatorLocation, OperatorMeta},
     OwnershipPreference, Scope,
 };
+use bincode::error::{DecodeError, EncodeError};
 use std::borrow::Cow;
 
 /// Minimal requirements for values exchanged by operators.
@@ -194,6 +195,62 @@ pub trait Operator: 'static {
     /// of the fixed point computation, but not as part of an integrator circuit
     /// ([`Stream::integrate`](`crate::circuit::Stream::integrate`)).
     fn fixedpoint(&self, scope: Scope) -> bool;
+
+    /// Returns `true` if the operator retains state across clock cycles.
+    ///
+    /// Stateful operators must also implement [`Self::checkpoint`] and
+    /// [`Self::restore`].  The default implementation returns `false`.
+    fn is_stateful(&self) -> bool {
+        false
+    }
+
+    /// Serialize the state of the operator.
+    ///
+    /// Invoked between clock cycles of the root circuit when taking a
+    /// checkpoint of the circuit (see
+    /// [`DBSPHandle::checkpoint`](`crate::DBSPHandle::checkpoint`)).
+    /// Operators that retain state across clock cycles, e.g.,
+    /// [`Z1`](`crate::operator::Z1`), must return a serialized representation
+    /// of this state that can be loaded back using [`Self::restore`].
+    ///
+    /// The default implementation returns `Ok(None)`, which indicates that
+    /// the operator is stateless.
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        Ok(None)
+    }
+
+    /// Restore the state of the operator from a checkpoint.
+    ///
+    /// Invoked on a freshly constructed operator before the first clock
+    /// cycle with the state returned by [`Self::checkpoint`] for the same
+    /// operator in a circuit with identical structure.
+    fn restore(&mut self, _state: &[u8]) -> Result<(), DecodeError> {
+        Ok(())
+    }
+
+    /// Restore the state of the operator from checkpoints taken in a runtime
+    /// with a different number of workers.
+    ///
+    /// `states` contains the states returned by [`Self::checkpoint`] for this
+    /// operator in all workers of the old runtime.  The operator must combine
+    /// them and retain only the part of the state that belongs to worker
+    /// `worker` out of `num_workers` in the new runtime, assuming that the
+    /// state is partitioned by key in the same way as
+    /// [`Stream::shard`](`crate::circuit::Stream::shard`) partitions its
+    /// output.
+    ///
+    /// The default implementation fails, since in general the state of an
+    /// operator cannot be redistributed across workers.
+    fn restore_rescaled(
+        &mut self,
+        _states: &[&[u8]],
+        _worker: usize,
+        _num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        Err(DecodeError::Other(
+            "operator state cannot be migrated to a different number of workers",
+        ))
+    }
 }
 
 /// A source operator that injects data from the outside world or from the
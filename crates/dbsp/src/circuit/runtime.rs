@@ -1,12 +1,16 @@
 //! A multithreaded runtime for evaluating DBSP circuits in a data-parallel
 //! fashion.
 
+use super::network::Network;
 use crossbeam::channel::bounded;
 use crossbeam_utils::sync::{Parker, Unparker};
 use std::{
     cell::{Cell, RefCell},
     fmt,
     fmt::{Debug, Display, Error as FmtError, Formatter},
+    io,
+    net::SocketAddr,
+    ops::Range,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -19,6 +23,11 @@ use typedmap::{TypedDashMap, TypedMapKey};
 pub enum Error {
     WorkerPanic(usize),
     Killed,
+    /// A remote host in a multihost runtime failed or disconnected.
+    HostFailure {
+        host: usize,
+        error: String,
+    },
 }
 
 impl Display for Error {
@@ -28,6 +37,9 @@ impl Display for Error {
                 write!(f, "worker thread '{worker}' panicked")
             }
             Self::Killed => f.write_str("circuit killed by the user"),
+            Self::HostFailure { host, error } => {
+                write!(f, "host '{host}' failed: {error}")
+            }
         }
     }
 }
@@ -61,24 +73,154 @@ pub struct LocalStoreMarker;
 /// Local data store shared by all workers in a runtime.
 pub type LocalStore = TypedDashMap<LocalStoreMarker>;
 
+/// Assignment of worker threads to hosts.
+///
+/// A runtime can run all of its workers in the current process
+/// ([`Layout::Solo`]) or span multiple hosts or processes
+/// ([`Layout::Multihost`]).  In the latter case, every host runs the same
+/// number of workers.  Workers are numbered globally: host `i` runs workers
+/// `i * workers_per_host .. (i + 1) * workers_per_host`.  Host 0 is the
+/// coordinator, which controls the circuit via a
+/// [`DBSPHandle`](`crate::DBSPHandle`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// All workers run in the current process.
+    Solo { n_workers: usize },
+    /// Workers run on multiple hosts that communicate over TCP.
+    Multihost {
+        /// Addresses on which the hosts listen for connections from their
+        /// peers, in host order.
+        hosts: Vec<SocketAddr>,
+        /// The number of worker threads on each host.
+        workers_per_host: usize,
+        /// Index of the current host in `hosts`.
+        local_host: usize,
+    },
+}
+
+impl Layout {
+    /// Layout of a runtime with `n_workers` worker threads in the current
+    /// process.
+    pub fn new_solo(n_workers: usize) -> Self {
+        assert_ne!(n_workers, 0);
+        Self::Solo { n_workers }
+    }
+
+    /// Layout of a runtime that runs `workers_per_host` worker threads on
+    /// each of `hosts`.  `local_host` is the index of the current host in
+    /// `hosts`.
+    ///
+    /// Runtimes that span multiple hosts require the `checkpoint` feature,
+    /// which makes data exchanged between hosts serializable.
+    pub fn new_multihost(
+        hosts: Vec<SocketAddr>,
+        workers_per_host: usize,
+        local_host: usize,
+    ) -> Self {
+        assert_ne!(workers_per_host, 0);
+        assert!(local_host < hosts.len());
+        Self::Multihost {
+            hosts,
+            workers_per_host,
+            local_host,
+        }
+    }
+
+    /// Returns the total number of workers across all hosts.
+    pub fn n_workers(&self) -> usize {
+        match self {
+            Self::Solo { n_workers } => *n_workers,
+            Self::Multihost {
+                hosts,
+                workers_per_host,
+                ..
+            } => hosts.len() * workers_per_host,
+        }
+    }
+
+    /// Returns the number of hosts in the layout.
+    pub fn n_hosts(&self) -> usize {
+        match self {
+            Self::Solo { .. } => 1,
+            Self::Multihost { hosts, .. } => hosts.len(),
+        }
+    }
+
+    /// Returns the index of the current host.
+    pub fn local_host(&self) -> usize {
+        match self {
+            Self::Solo { .. } => 0,
+            Self::Multihost { local_host, .. } => *local_host,
+        }
+    }
+
+    /// Returns the global indexes of the workers that run on the current host.
+    pub fn local_workers(&self) -> Range<usize> {
+        match self {
+            Self::Solo { n_workers } => 0..*n_workers,
+            Self::Multihost {
+                workers_per_host,
+                local_host,
+                ..
+            } => local_host * workers_per_host..(local_host + 1) * workers_per_host,
+        }
+    }
+
+    /// Returns the index of the host that runs `worker`.
+    pub fn host_of(&self, worker: usize) -> usize {
+        debug_assert!(worker < self.n_workers());
+        match self {
+            Self::Solo { .. } => 0,
+            Self::Multihost {
+                workers_per_host, ..
+            } => worker / workers_per_host,
+        }
+    }
+
+    /// `true` if `worker` runs on the current host.
+    pub fn is_local(&self, worker: usize) -> bool {
+        self.local_workers().contains(&worker)
+    }
+
+    /// `true` if the layout spans more than one host.
+    pub fn is_multihost(&self) -> bool {
+        self.n_hosts() > 1
+    }
+
+    /// `true` if the current host is the coordinator, i.e., host 0.
+    pub fn is_coordinator(&self) -> bool {
+        self.local_host() == 0
+    }
+
+    /// Addresses of all hosts in the layout.
+    pub(crate) fn hosts(&self) -> &[SocketAddr] {
+        match self {
+            Self::Solo { .. } => &[],
+            Self::Multihost { hosts, .. } => hosts,
+        }
+    }
+}
+
 struct RuntimeInner {
-    nworkers: usize,
+    layout: Layout,
     store: LocalStore,
+    network: Option<Arc<Network>>,
 }
 
 impl Debug for RuntimeInner {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("RuntimeInner")
-            .field("nworkers", &self.nworkers)
+            .field("layout", &self.layout)
             .finish()
     }
 }
 
 impl RuntimeInner {
-    fn new(nworkers: usize) -> Self {
+    fn new(layout: Layout, network: Option<Arc<Network>>) -> Self {
         Self {
-            nworkers,
+            layout,
             store: TypedDashMap::new(),
+            network,
         }
     }
 }
@@ -138,10 +280,54 @@ impl Runtime {
     where
         F: FnOnce() + Clone + Send + 'static,
     {
-        let runtime = Self(Arc::new(RuntimeInner::new(workers)));
+        Self::spawn_workers(
+            Self(Arc::new(RuntimeInner::new(Layout::new_solo(workers), None))),
+            circuit,
+        )
+    }
+
+    /// Create a runtime with the given `layout` and run a user-provided
+    /// closure in each of its local worker threads.
+    ///
+    /// Works like [`Self::run`], but only spawns the workers that belong to
+    /// the current host in `layout`.  For a multihost layout, connects to all
+    /// other hosts in the layout first, waiting for them to come up.  This
+    /// function must be invoked on every host in the layout, with the same
+    /// `hosts` and `workers_per_host`.
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] for a multihost layout if
+    /// the `checkpoint` feature is disabled.
+    pub fn run_with_layout<F>(layout: Layout, circuit: F) -> io::Result<RuntimeHandle>
+    where
+        F: FnOnce() + Clone + Send + 'static,
+    {
+        if cfg!(not(feature = "checkpoint")) && layout.is_multihost() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "multihost runtimes require the `checkpoint` feature",
+            ));
+        }
+
+        let network = if layout.is_multihost() {
+            Some(Arc::new(Network::connect(&layout)?))
+        } else {
+            None
+        };
+
+        Ok(Self::spawn_workers(
+            Self(Arc::new(RuntimeInner::new(layout, network))),
+            circuit,
+        ))
+    }
 
-        let mut handles = Vec::with_capacity(workers);
-        handles.extend((0..workers).map(|worker_index| {
+    fn spawn_workers<F>(runtime: Self, circuit: F) -> RuntimeHandle
+    where
+        F: FnOnce() + Clone + Send + 'static,
+    {
+        let local_workers = runtime.layout().local_workers();
+
+        let mut handles = Vec::with_capacity(local_workers.len());
+        handles.extend(local_workers.clone().map(|worker_index| {
             let runtime = runtime.clone();
             let build_circuit = circuit.clone();
 
@@ -172,7 +358,7 @@ impl Runtime {
             (join_handle, init_receiver)
         }));
 
-        let mut workers = Vec::with_capacity(workers);
+        let mut workers = Vec::with_capacity(local_workers.len());
         workers.extend(handles.into_iter().map(|(handle, recv)| {
             let (unparker, kill_signal) = recv.recv().unwrap();
             WorkerHandle::new(handle, unparker, kill_signal)
@@ -198,17 +384,45 @@ impl Runtime {
     /// Returns 0-based index of the current worker thread within its
     /// runtime.  For threads that run without a runtime, this method
     /// returns `0`.
+    ///
+    /// In a multihost runtime, this is the global index of the worker across
+    /// all hosts (see [`Layout`]).
     pub fn worker_index() -> usize {
         WORKER_INDEX.with(|index| index.get())
     }
 
+    /// Returns 0-based index of the current worker thread among the workers
+    /// running on the current host.
+    ///
+    /// Same as [`Self::worker_index`], except in a multihost runtime.
+    pub fn local_worker_index() -> usize {
+        let worker_index = Self::worker_index();
+
+        Self::runtime().map_or(worker_index, |runtime| {
+            worker_index - runtime.layout().local_workers().start
+        })
+    }
+
     fn inner(&self) -> &RuntimeInner {
         &self.0
     }
 
     /// Returns the number of workers in this runtime.
+    ///
+    /// In a multihost runtime, this is the number of workers across all
+    /// hosts.
     pub fn num_workers(&self) -> usize {
-        self.inner().nworkers
+        self.inner().layout.n_workers()
+    }
+
+    /// Returns the layout of this runtime.
+    pub fn layout(&self) -> &Layout {
+        &self.inner().layout
+    }
+
+    /// Returns connections to other hosts in a multihost runtime.
+    pub(crate) fn network(&self) -> Option<&Arc<Network>> {
+        self.inner().network.as_ref()
     }
 
     /// Returns reference to the data store shared by all workers within the
@@ -230,7 +444,7 @@ impl Runtime {
     /// same across all worker threads.  Repeated calls to this function
     /// with the same worker index generate numbers 0, 1, 2, ...
     pub fn sequence_next(&self, worker_index: usize) -> usize {
-        debug_assert!(worker_index < self.inner().nworkers);
+        debug_assert!(worker_index < self.num_workers());
         let mut entry = self
             .local_store()
             .entry(WorkerId(worker_index))
@@ -334,6 +548,13 @@ impl RuntimeHandle {
             .into_iter()
             .map(|h| h.join_handle.join())
             .collect();
+
+        // Close connections to other hosts, if any, so that they notice that
+        // this host has exited.
+        if let Some(network) = self.runtime.network() {
+            network.shutdown();
+        }
+
         results.into_iter().collect::<ThreadResult<()>>()
     }
 }
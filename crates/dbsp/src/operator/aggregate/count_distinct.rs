@@ -0,0 +1,133 @@
+use crate::{
+    algebra::{CommutativeSemigroup, MonoidValue, Semigroup},
+    operator::aggregate::Aggregator,
+    trace::Cursor,
+    DBData, Timestamp,
+};
+use bincode::{Decode, Encode};
+use size_of::SizeOf;
+use std::{cmp::Ordering, marker::PhantomData};
+
+/// An [aggregator](`crate::operator::Aggregator`) that counts distinct
+/// values with non-zero weight.
+///
+/// The accumulator of this aggregator stores all distinct values, so that
+/// accumulators computed over overlapping subsets of values (e.g., by
+/// different workers or for overlapping time windows) can be combined
+/// precisely.
+#[derive(Clone)]
+pub struct CountDistinct;
+
+/// Accumulator of the [`CountDistinct`] aggregator: the set of distinct
+/// values, in ascending order.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, SizeOf, Encode, Decode)]
+pub struct DistinctValues<V>(Vec<V>);
+
+impl<V> DistinctValues<V> {
+    /// Returns the number of distinct values.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns distinct values in ascending order.
+    pub fn values(&self) -> &[V] {
+        &self.0
+    }
+}
+
+/// Set union over [`DistinctValues`].
+#[derive(Clone)]
+pub struct DistinctSemigroup<V>(PhantomData<V>);
+
+impl<V> Semigroup<DistinctValues<V>> for DistinctSemigroup<V>
+where
+    V: Ord + Clone,
+{
+    fn combine(left: &DistinctValues<V>, right: &DistinctValues<V>) -> DistinctValues<V> {
+        let (mut left, mut right) = (left.0.iter().peekable(), right.0.iter().peekable());
+        let mut result = Vec::with_capacity(left.len() + right.len());
+
+        loop {
+            let next = match (left.peek(), right.peek()) {
+                (Some(l), Some(r)) => match l.cmp(r) {
+                    Ordering::Less => left.next(),
+                    Ordering::Greater => right.next(),
+                    Ordering::Equal => {
+                        right.next();
+                        left.next()
+                    }
+                },
+                (Some(_), None) => left.next(),
+                (None, Some(_)) => right.next(),
+                (None, None) => break,
+            };
+            result.extend(next.cloned());
+        }
+
+        DistinctValues(result)
+    }
+}
+
+impl<V> CommutativeSemigroup<DistinctValues<V>> for DistinctSemigroup<V> where V: Ord + Clone {}
+
+impl<V, T, R> Aggregator<V, T, R> for CountDistinct
+where
+    V: DBData,
+    T: Timestamp,
+    R: MonoidValue,
+{
+    type Accumulator = DistinctValues<V>;
+    type Output = usize;
+    type Semigroup = DistinctSemigroup<V>;
+
+    fn aggregate<'s, C>(&self, cursor: &mut C) -> Option<Self::Accumulator>
+    where
+        C: Cursor<'s, V, (), T, R>,
+    {
+        let mut values = Vec::new();
+
+        while cursor.key_valid() {
+            let mut weight = R::zero();
+
+            cursor.map_times(|_t, w| weight.add_assign_by_ref(w));
+
+            if !weight.is_zero() {
+                values.push(cursor.key().clone());
+            }
+
+            cursor.step_key();
+        }
+
+        (!values.is_empty()).then_some(DistinctValues(values))
+    }
+
+    fn finalize(&self, accumulator: Self::Accumulator) -> Self::Output {
+        accumulator.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DistinctSemigroup, DistinctValues};
+    use crate::algebra::Semigroup;
+
+    #[test]
+    fn union() {
+        let left = DistinctValues(vec![1, 3, 5, 7]);
+        let right = DistinctValues(vec![2, 3, 7, 8]);
+
+        assert_eq!(
+            DistinctSemigroup::combine(&left, &right),
+            DistinctValues(vec![1, 2, 3, 5, 7, 8])
+        );
+        assert_eq!(
+            DistinctSemigroup::combine(&left, &DistinctValues::default()),
+            left
+        );
+    }
+}
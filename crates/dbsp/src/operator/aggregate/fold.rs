@@ -15,6 +15,10 @@ use std::{convert::identity, marker::PhantomData};
 /// applying a user-provided `output` function to the final value of
 /// the accumulator.
 ///
+/// Folds are not linear (see
+/// [`Aggregator::is_linear`](`crate::operator::Aggregator::is_linear`))
+/// unless declared so using [`Self::linear`].
+///
 /// # Type arguments
 ///
 /// * `A` - accumulator
@@ -26,6 +30,7 @@ pub struct Fold<A, S, SF, OF> {
     init: A,
     step: SF,
     output: OF,
+    linear: bool,
     phantom: PhantomData<S>,
 }
 
@@ -41,6 +46,7 @@ impl<A, S, SF> Fold<A, S, SF, fn(A) -> A> {
             init,
             step,
             output: identity,
+            linear: false,
             phantom: PhantomData,
         }
     }
@@ -54,9 +60,24 @@ impl<A, S, SF, OF> Fold<A, S, SF, OF> {
             init,
             step,
             output,
+            linear: false,
             phantom: PhantomData,
         }
     }
+
+    /// Declares the fold linear in the weights of its inputs.
+    ///
+    /// This is the case when folding values with weights `w1` and `w2`
+    /// separately and combining the results using semigroup `S` yields the
+    /// same accumulator as folding them with weight `w1 + w2`, including
+    /// for negative weights, e.g., for sums computed by the step function
+    /// `|acc, v, w| *acc += *v * w`.  Linear folds can be evaluated by
+    /// [`Stream::aggregate_two_phase`](`crate::Stream::aggregate_two_phase`)
+    /// without sharding their input first.
+    pub fn linear(mut self) -> Self {
+        self.linear = true;
+        self
+    }
 }
 
 impl<V, T, R, A, S, O, SF, OF> Aggregator<V, T, R> for Fold<A, S, SF, OF>
@@ -98,4 +119,8 @@ where
     fn finalize(&self, acc: Self::Accumulator) -> Self::Output {
         (self.output)(acc)
     }
+
+    fn is_linear(&self) -> bool {
+        self.linear
+    }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    algebra::{MonoidValue, Semigroup},
+    algebra::{CommutativeSemigroup, MonoidValue, Semigroup},
     operator::aggregate::Aggregator,
     trace::Cursor,
     DBData, Timestamp,
@@ -23,6 +23,8 @@ where
     }
 }
 
+impl<V> CommutativeSemigroup<V> for MaxSemigroup<V> where V: Ord + Clone {}
+
 impl<V, T, R> Aggregator<V, T, R> for Max
 where
     V: DBData,
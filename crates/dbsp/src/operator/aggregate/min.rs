@@ -1,5 +1,5 @@
 use crate::{
-    algebra::{MonoidValue, Semigroup},
+    algebra::{CommutativeSemigroup, MonoidValue, Semigroup},
     operator::aggregate::Aggregator,
     trace::Cursor,
     DBData, Timestamp,
@@ -25,6 +25,8 @@ where
         min(left, right).clone()
     }
 }
+
+impl<V> CommutativeSemigroup<V> for MinSemigroup<V> where V: Ord + Clone {}
 impl<V, T, R> Aggregator<V, T, R> for Min
 where
     V: DBData,
@@ -27,14 +27,22 @@ use crate::{
 
 // Some standard aggregators.
 mod average;
+mod count_distinct;
 mod fold;
 mod max;
 mod min;
+mod percentile;
+mod two_phase;
 
 pub use average::Avg;
+pub use count_distinct::{CountDistinct, DistinctSemigroup, DistinctValues};
 pub use fold::Fold;
 pub use max::{Max, MaxSemigroup};
 pub use min::{Min, MinSemigroup};
+pub use percentile::{
+    ApproxPercentile, Median, Percentile, QuantileSketch, QuantileSketchSemigroup, ValueCounts,
+    ValueCountsSemigroup,
+};
 
 /// A trait for aggregator objects.  An aggregator summarizes the contents
 /// of a Z-set into a single value.
@@ -84,12 +92,13 @@ pub trait Aggregator<K, T, R>: Clone + 'static {
     /// meaning that computing the aggregate piecewise and combining
     /// the results using `Self::Semigroup` should yield the same value as
     /// aggregating the entire input using `Self::aggregate`.
-    // TODO: We currently only use this with `radix_tree`, which only
-    // requires the semigroup structure (i.e., associativity).  In the future
-    // we will also use this in computing regular aggregates by combining
-    // per-worker aggregates computes over arbitrary subsets of values,
-    // which additionally requires commutativity.  Do we want to introduce
-    // the `CommutativeSemigroup` trait?
+    ///
+    /// Aggregators whose semigroup is additionally commutative (see
+    /// [`CommutativeSemigroup`](`crate::algebra::CommutativeSemigroup`)) can
+    /// be evaluated using [`Stream::aggregate_split`], which combines
+    /// per-worker aggregates computed over arbitrary subsets of values, and
+    /// [`Stream::aggregate_two_phase`], which additionally requires the
+    /// aggregator to be linear (see [`Self::is_linear`]) to take effect.
     type Semigroup: Semigroup<Self::Accumulator>;
 
     /// Aggregate type produced by this aggregator.
@@ -112,6 +121,21 @@ pub trait Aggregator<K, T, R>: Clone + 'static {
     /// Compute the final value of the aggregate.
     fn finalize(&self, accumulator: Self::Accumulator) -> Self::Output;
 
+    /// Returns `true` if the aggregator is linear in the weights of its
+    /// inputs.
+    ///
+    /// A linear aggregator can be applied to any Z-set, including Z-sets
+    /// with negative weights, and combining its aggregates of any two
+    /// Z-sets using `Self::Semigroup` yields its aggregate of their sum.
+    /// For example, sums and counts are linear, while `Min` and `Max` are
+    /// not: the maximum of a Z-set that retracts a value is not defined by
+    /// the maximum of the retracted value alone.
+    ///
+    /// The default implementation conservatively returns `false`.
+    fn is_linear(&self) -> bool {
+        false
+    }
+
     /// Applies `aggregate` to `cursor` followed by `finalize`.
     fn aggregate_and_finalize<'s, C>(&self, cursor: &mut C) -> Option<Self::Output>
     where
@@ -153,6 +177,10 @@ where
     fn finalize(&self, accumulator: Self::Accumulator) -> Self::Output {
         accumulator
     }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
 }
 
 impl<C, Z> Stream<C, Z>
@@ -212,6 +240,20 @@ where
 
     /// Like [`Self::aggregate`], but can return any batch type.
     pub fn aggregate_generic<A, O>(&self, aggregator: A) -> Stream<C, O>
+    where
+        Z: IndexedZSet + Send,
+        A: Aggregator<Z::Val, <C as WithClock>::Time, Z::R>,
+        O: Batch<Key = Z::Key, Val = A::Output, Time = ()>,
+        O::R: ZRingValue,
+    {
+        self.shard()
+            .aggregate_unsharded::<A, O>(aggregator)
+            .mark_sharded()
+    }
+
+    // Incrementally aggregates the stream within each worker, without
+    // re-sharding it first.
+    fn aggregate_unsharded<A, O>(&self, aggregator: A) -> Stream<C, O>
     where
         Z: IndexedZSet + Send,
         A: Aggregator<Z::Val, <C as WithClock>::Time, Z::R>,
@@ -219,7 +261,7 @@ where
         O::R: ZRingValue,
     {
         let circuit = self.circuit();
-        let stream = self.shard();
+        let stream = self;
 
         // We construct the following circuit.  See `AggregateIncremental` documentation
         // for details.
@@ -236,11 +278,10 @@ where
         circuit
             .add_binary_operator(
                 AggregateIncremental::new(aggregator, circuit.clone()),
-                &stream,
+                stream,
                 &stream.trace::<Spine<<<C as WithClock>::Time as Timestamp>::OrdValBatch<Z::Key, Z::Val, Z::R>>>(),
             )
             .upsert::<O>()
-            .mark_sharded()
     }
 
     /// A version of [`Self::aggregate`] optimized for linear
@@ -0,0 +1,508 @@
+use crate::{
+    algebra::{CommutativeSemigroup, Semigroup, F64},
+    operator::aggregate::Aggregator,
+    trace::Cursor,
+    DBData, DBWeight, Timestamp,
+};
+use bincode::{Decode, Encode};
+use num::ToPrimitive;
+use size_of::SizeOf;
+use std::{cmp::Ordering, collections::BTreeMap, marker::PhantomData};
+
+/// An [aggregator](`crate::operator::Aggregator`) that computes the exact
+/// `p`-th percentile of a Z-set.
+///
+/// Uses the nearest-rank definition of percentile: the result is the
+/// smallest value `v` in the Z-set such that at least `p * n` values are
+/// less than or equal to `v`, where `n` is the total number of values.  Each
+/// value is counted as many times as its weight.  Values with negative
+/// weights are ignored.
+///
+/// The accumulator of this aggregator stores all distinct values along with
+/// their weights (see [`ValueCounts`]).  Use [`ApproxPercentile`] to
+/// compute percentiles of numeric values over large inputs in bounded space.
+#[derive(Clone)]
+pub struct Percentile {
+    p: f64,
+}
+
+impl Percentile {
+    /// Create a `Percentile` aggregator for percentile `p`, which must be
+    /// between `0.0` and `1.0`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` is not in `[0.0, 1.0]`.
+    pub fn new(p: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&p),
+            "percentile must be between 0.0 and 1.0, found {p}"
+        );
+        Self { p }
+    }
+}
+
+/// An [aggregator](`crate::operator::Aggregator`) that computes the exact
+/// median of a Z-set.
+///
+/// Equivalent to `Percentile::new(0.5)`: for a Z-set with an even number
+/// of values, returns the lower of the two middle values.
+#[derive(Clone)]
+pub struct Median;
+
+/// Accumulator of the [`Percentile`] and [`Median`] aggregators: distinct
+/// values in ascending order with their weights.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, SizeOf, Encode, Decode)]
+pub struct ValueCounts<V>(Vec<(V, u64)>);
+
+impl<V> ValueCounts<V> {
+    /// Returns the total weight of all values.
+    pub fn total(&self) -> u64 {
+        self.0.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Returns the `p`-th percentile of the values using the nearest-rank
+    /// method, or `None` if there are no values.
+    pub fn percentile(&self, p: f64) -> Option<&V> {
+        let total = self.total();
+        let rank = ((p * total as f64).ceil() as u64).clamp(1, total.max(1));
+
+        let mut seen = 0;
+        for (value, count) in self.0.iter() {
+            seen += count;
+            if seen >= rank {
+                return Some(value);
+            }
+        }
+
+        None
+    }
+}
+
+/// Multiset union over [`ValueCounts`].
+#[derive(Clone)]
+pub struct ValueCountsSemigroup<V>(PhantomData<V>);
+
+impl<V> Semigroup<ValueCounts<V>> for ValueCountsSemigroup<V>
+where
+    V: Ord + Clone,
+{
+    fn combine(left: &ValueCounts<V>, right: &ValueCounts<V>) -> ValueCounts<V> {
+        let (mut left, mut right) = (left.0.iter().peekable(), right.0.iter().peekable());
+        let mut result = Vec::with_capacity(left.len() + right.len());
+
+        loop {
+            match (left.peek(), right.peek()) {
+                (Some((l, lcount)), Some((r, rcount))) => match l.cmp(r) {
+                    Ordering::Less => result.extend(left.next().cloned()),
+                    Ordering::Greater => result.extend(right.next().cloned()),
+                    Ordering::Equal => {
+                        result.push((l.clone(), lcount + rcount));
+                        left.next();
+                        right.next();
+                    }
+                },
+                (Some(_), None) => result.extend(left.next().cloned()),
+                (None, Some(_)) => result.extend(right.next().cloned()),
+                (None, None) => break,
+            }
+        }
+
+        ValueCounts(result)
+    }
+}
+
+impl<V> CommutativeSemigroup<ValueCounts<V>> for ValueCountsSemigroup<V> where V: Ord + Clone {}
+
+/// Collects values with positive weights under cursor.
+fn value_counts<'s, V, T, R, C>(cursor: &mut C) -> Option<ValueCounts<V>>
+where
+    V: DBData,
+    T: Timestamp,
+    R: DBWeight + ToPrimitive,
+    C: Cursor<'s, V, (), T, R>,
+{
+    let mut values = Vec::new();
+
+    while cursor.key_valid() {
+        let mut weight = R::zero();
+
+        cursor.map_times(|_t, w| weight.add_assign_by_ref(w));
+
+        match weight.to_u64() {
+            Some(count) if count > 0 => values.push((cursor.key().clone(), count)),
+            _ => {}
+        }
+
+        cursor.step_key();
+    }
+
+    (!values.is_empty()).then_some(ValueCounts(values))
+}
+
+impl<V, T, R> Aggregator<V, T, R> for Percentile
+where
+    V: DBData,
+    T: Timestamp,
+    R: DBWeight + ToPrimitive,
+{
+    type Accumulator = ValueCounts<V>;
+    type Output = V;
+    type Semigroup = ValueCountsSemigroup<V>;
+
+    fn aggregate<'s, C>(&self, cursor: &mut C) -> Option<Self::Accumulator>
+    where
+        C: Cursor<'s, V, (), T, R>,
+    {
+        value_counts(cursor)
+    }
+
+    fn finalize(&self, accumulator: Self::Accumulator) -> Self::Output {
+        accumulator.percentile(self.p).unwrap().clone()
+    }
+}
+
+impl<V, T, R> Aggregator<V, T, R> for Median
+where
+    V: DBData,
+    T: Timestamp,
+    R: DBWeight + ToPrimitive,
+{
+    type Accumulator = ValueCounts<V>;
+    type Output = V;
+    type Semigroup = ValueCountsSemigroup<V>;
+
+    fn aggregate<'s, C>(&self, cursor: &mut C) -> Option<Self::Accumulator>
+    where
+        C: Cursor<'s, V, (), T, R>,
+    {
+        value_counts(cursor)
+    }
+
+    fn finalize(&self, accumulator: Self::Accumulator) -> Self::Output {
+        accumulator.percentile(0.5).unwrap().clone()
+    }
+}
+
+/// An [aggregator](`crate::operator::Aggregator`) that computes an
+/// approximate `p`-th percentile of numeric values.
+///
+/// Values are summarized in a [`QuantileSketch`], which maps each value to a
+/// bucket of values within the same relative distance from each other (as in
+/// [DDSketch](https://arxiv.org/abs/1908.10693)).  The result is guaranteed
+/// to be within `relative_accuracy` of the exact nearest-rank percentile
+/// computed by [`Percentile`], e.g., a relative accuracy of `0.01`
+/// guarantees that the result is within 1% of the exact value.  The size of
+/// the sketch is proportional to the logarithm of the ratio between the
+/// largest and the smallest absolute values in the input and does not depend
+/// on the number of values.  For instance, with 1% relative accuracy, a
+/// sketch of latencies between a microsecond and an hour has fewer than 1100
+/// buckets.
+///
+/// Sketches are combined by adding up bucket counts, so the result is the
+/// same regardless of how the input is partitioned, e.g., among workers or
+/// time windows.
+///
+/// Values are converted to `f64` using [`ToPrimitive`].  Values that cannot be
+/// converted and values with negative weights are ignored.
+#[derive(Clone)]
+pub struct ApproxPercentile {
+    p: f64,
+    ln_gamma: f64,
+}
+
+impl ApproxPercentile {
+    /// Create an `ApproxPercentile` aggregator for percentile `p` with the
+    /// specified relative accuracy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` is not in `[0.0, 1.0]` or `relative_accuracy` is not in
+    /// `(0.0, 1.0)`.
+    pub fn new(p: f64, relative_accuracy: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&p),
+            "percentile must be between 0.0 and 1.0, found {p}"
+        );
+        assert!(
+            relative_accuracy > 0.0 && relative_accuracy < 1.0,
+            "relative accuracy must be between 0.0 and 1.0, found {relative_accuracy}"
+        );
+
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        Self {
+            p,
+            ln_gamma: gamma.ln(),
+        }
+    }
+
+    /// Returns the index of the bucket that contains absolute value `x`.
+    fn bucket(&self, x: f64) -> i32 {
+        (x.ln() / self.ln_gamma).ceil() as i32
+    }
+
+    /// Returns the representative absolute value of bucket `index`, i.e.,
+    /// the value with the smallest relative distance from all values in the
+    /// bucket.
+    fn bucket_value(&self, index: i32) -> f64 {
+        let gamma = self.ln_gamma.exp();
+        2.0 * (index as f64 * self.ln_gamma).exp() / (gamma + 1.0)
+    }
+}
+
+/// Accumulator of the [`ApproxPercentile`] aggregator.
+///
+/// Counts values in logarithmically sized buckets, separately for positive
+/// and negative values.  Sketches can only be combined if they were computed
+/// with the same relative accuracy.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, SizeOf, Encode, Decode)]
+pub struct QuantileSketch {
+    /// Buckets of negative values, indexed by the bucket of the absolute
+    /// value.
+    negative: ValueCounts<i32>,
+    zeros: u64,
+    positive: ValueCounts<i32>,
+}
+
+impl QuantileSketch {
+    /// Returns the number of values in the sketch.
+    pub fn count(&self) -> u64 {
+        self.negative.total() + self.zeros + self.positive.total()
+    }
+
+    fn percentile(&self, aggregator: &ApproxPercentile) -> Option<f64> {
+        let total = self.count();
+        let rank = ((aggregator.p * total as f64).ceil() as u64).clamp(1, total.max(1));
+        let mut seen = 0;
+
+        // Negative values in ascending order.
+        for (index, count) in self.negative.0.iter().rev() {
+            seen += count;
+            if seen >= rank {
+                return Some(-aggregator.bucket_value(*index));
+            }
+        }
+
+        seen += self.zeros;
+        if seen >= rank {
+            return Some(0.0);
+        }
+
+        for (index, count) in self.positive.0.iter() {
+            seen += count;
+            if seen >= rank {
+                return Some(aggregator.bucket_value(*index));
+            }
+        }
+
+        None
+    }
+}
+
+/// Adds up bucket counts of [`QuantileSketch`]es.
+#[derive(Clone)]
+pub struct QuantileSketchSemigroup;
+
+impl Semigroup<QuantileSketch> for QuantileSketchSemigroup {
+    fn combine(left: &QuantileSketch, right: &QuantileSketch) -> QuantileSketch {
+        QuantileSketch {
+            negative: ValueCountsSemigroup::combine(&left.negative, &right.negative),
+            zeros: left.zeros + right.zeros,
+            positive: ValueCountsSemigroup::combine(&left.positive, &right.positive),
+        }
+    }
+}
+
+impl CommutativeSemigroup<QuantileSketch> for QuantileSketchSemigroup {}
+
+impl<V, T, R> Aggregator<V, T, R> for ApproxPercentile
+where
+    V: DBData + ToPrimitive,
+    T: Timestamp,
+    R: DBWeight + ToPrimitive,
+{
+    type Accumulator = QuantileSketch;
+    type Output = F64;
+    type Semigroup = QuantileSketchSemigroup;
+
+    fn aggregate<'s, C>(&self, cursor: &mut C) -> Option<Self::Accumulator>
+    where
+        C: Cursor<'s, V, (), T, R>,
+    {
+        let mut negative = BTreeMap::new();
+        let mut zeros = 0;
+        let mut positive = BTreeMap::new();
+
+        while cursor.key_valid() {
+            let mut weight = R::zero();
+
+            cursor.map_times(|_t, w| weight.add_assign_by_ref(w));
+
+            match (weight.to_u64(), cursor.key().to_f64()) {
+                (Some(count), Some(x)) if count > 0 && !x.is_nan() => {
+                    if x > 0.0 {
+                        *positive.entry(self.bucket(x)).or_insert(0) += count;
+                    } else if x < 0.0 {
+                        *negative.entry(self.bucket(-x)).or_insert(0) += count;
+                    } else {
+                        zeros += count;
+                    }
+                }
+                _ => {}
+            }
+
+            cursor.step_key();
+        }
+
+        let sketch = QuantileSketch {
+            negative: ValueCounts(negative.into_iter().collect()),
+            zeros,
+            positive: ValueCounts(positive.into_iter().collect()),
+        };
+
+        (sketch.count() > 0).then_some(sketch)
+    }
+
+    fn finalize(&self, accumulator: Self::Accumulator) -> Self::Output {
+        F64::new(accumulator.percentile(self).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ApproxPercentile, Median, Percentile, QuantileSketch, QuantileSketchSemigroup, ValueCounts,
+        ValueCountsSemigroup,
+    };
+    use crate::{
+        algebra::Semigroup,
+        operator::{aggregate::Aggregator, CountDistinct},
+        trace::{Batch, BatchReader},
+        OrdIndexedZSet, OrdZSet, Runtime,
+    };
+    use std::fmt::Debug;
+
+    fn sketch(aggregator: &ApproxPercentile, values: &[i64]) -> QuantileSketch {
+        let values = OrdZSet::<i64, isize>::from_keys((), values.iter().map(|v| (*v, 1)).collect());
+        <ApproxPercentile as Aggregator<i64, (), isize>>::aggregate(
+            aggregator,
+            &mut values.cursor(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn nearest_rank() {
+        let values = ValueCounts(vec![(10, 1), (20, 2), (30, 1), (40, 1)]);
+
+        assert_eq!(values.percentile(0.0), Some(&10));
+        assert_eq!(values.percentile(0.2), Some(&10));
+        assert_eq!(values.percentile(0.5), Some(&20));
+        assert_eq!(values.percentile(0.6), Some(&20));
+        assert_eq!(values.percentile(0.7), Some(&30));
+        assert_eq!(values.percentile(1.0), Some(&40));
+        assert_eq!(ValueCounts::<i32>::default().percentile(0.5), None);
+    }
+
+    #[test]
+    fn union() {
+        let left = ValueCounts(vec![(1, 1), (3, 2)]);
+        let right = ValueCounts(vec![(2, 1), (3, 1), (4, 5)]);
+
+        assert_eq!(
+            ValueCountsSemigroup::combine(&left, &right),
+            ValueCounts(vec![(1, 1), (2, 1), (3, 3), (4, 5)])
+        );
+    }
+
+    #[test]
+    fn approx_accuracy() {
+        let values: Vec<i64> = (-200..=1000).collect();
+        let exact = ValueCounts(values.iter().map(|v| (*v, 1)).collect());
+
+        for p in [0.0, 0.01, 0.1, 0.25, 0.5, 0.9, 0.99, 1.0] {
+            let aggregator = ApproxPercentile::new(p, 0.01);
+            let approx = aggregator
+                .finalize(sketch(&aggregator, &values))
+                .into_inner();
+            let expected = *exact.percentile(p).unwrap() as f64;
+
+            assert!(
+                (approx - expected).abs() <= 0.01 * expected.abs(),
+                "p: {p}, expected: {expected}, approx: {approx}"
+            );
+        }
+    }
+
+    #[test]
+    fn approx_combine() {
+        let aggregator = ApproxPercentile::new(0.5, 0.05);
+        let values: Vec<i64> = (-100..10_000).step_by(7).collect();
+        let (left, right) = values.split_at(values.len() / 3);
+
+        assert_eq!(
+            QuantileSketchSemigroup::combine(
+                &sketch(&aggregator, left),
+                &sketch(&aggregator, right)
+            ),
+            sketch(&aggregator, &values)
+        );
+    }
+
+    fn check<V: Debug + PartialEq>(
+        actual: &OrdIndexedZSet<usize, V, isize>,
+        expected: &OrdIndexedZSet<usize, V, isize>,
+    ) {
+        assert_eq!(actual, expected)
+    }
+
+    // Compare incremental aggregation against aggregating the integral of
+    // the input in each step.
+    #[test]
+    fn incremental() {
+        let (mut dbsp, mut input_handle) = Runtime::init_circuit(4, |circuit| {
+            let (input, input_handle) = circuit.add_input_indexed_zset::<usize, i64, isize>();
+            let integral = input.integrate();
+
+            input.aggregate(Percentile::new(0.9)).gather(0).apply2(
+                &integral
+                    .stream_aggregate(Percentile::new(0.9))
+                    .differentiate()
+                    .gather(0),
+                check,
+            );
+            input.aggregate(Median).gather(0).apply2(
+                &integral.stream_aggregate(Median).differentiate().gather(0),
+                check,
+            );
+            input.aggregate(CountDistinct).gather(0).apply2(
+                &integral
+                    .stream_aggregate(CountDistinct)
+                    .differentiate()
+                    .gather(0),
+                check,
+            );
+
+            input_handle
+        })
+        .unwrap();
+
+        for step in 0..10i64 {
+            let mut tuples: Vec<_> = (0..100)
+                .map(|i| (i as usize % 7, ((i * step) % 31, 1 + i as isize % 2)))
+                .collect();
+            if step > 0 {
+                // Retract some of the values inserted in the previous step.
+                tuples.extend(
+                    (0..100)
+                        .step_by(3)
+                        .map(|i| (i as usize % 7, ((i * (step - 1)) % 31, -1))),
+                );
+            }
+            input_handle.append(&mut tuples);
+            dbsp.step().unwrap();
+        }
+
+        dbsp.kill().unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+//! Two-phase aggregation.
+//!
+//! Instead of sharding all input tuples by key, each worker first aggregates
+//! the tuples it holds into partial accumulators, which are then sharded by
+//! key and combined using the semigroup of the aggregator.
+//!
+//! Each partial accumulator is paired with the total weight of the tuples it
+//! aggregates, so that a key whose values cancel out across workers, e.g.,
+//! because a value was inserted in one worker and retracted in another,
+//! disappears from the output.
+
+use crate::{
+    algebra::{
+        AddByRef, CommutativeSemigroup, HasOne, HasZero, IndexedZSet, Semigroup, ZRingValue,
+    },
+    circuit::{Circuit, Stream, WithClock},
+    operator::aggregate::Aggregator,
+    trace::Cursor,
+    DBTimestamp, DBWeight, OrdIndexedZSet, Runtime, Timestamp,
+};
+use std::{marker::PhantomData, ops::Neg};
+
+impl<C, Z> Stream<C, Z>
+where
+    C: Circuit,
+    <C as WithClock>::Time: DBTimestamp,
+    Z: Clone + 'static,
+{
+    /// Incremental aggregation operator that pre-aggregates values locally in
+    /// each worker.
+    ///
+    /// Computes the same result as [`Self::aggregate`].  However, instead of
+    /// sending all input tuples to the worker that owns their key, each worker
+    /// aggregates the tuples it holds into a partial accumulator per key,
+    /// regardless of how the input is partitioned across workers.
+    /// Only changes to partial accumulators are exchanged among workers and
+    /// combined using the [`Semigroup`](`Aggregator::Semigroup`) of the
+    /// aggregator.  This reduces the amount of data exchanged among workers
+    /// by orders of magnitude when the input contains many values per key,
+    /// e.g., when aggregating a large input over a small number of groups.
+    /// On the other hand, it adds the cost of maintaining partial aggregates
+    /// in every worker, so it does not pay off for inputs with few values
+    /// per key.
+    ///
+    /// Partial accumulators are combined in arbitrary order; hence the
+    /// aggregator must declare its semigroup commutative by implementing
+    /// [`CommutativeSemigroup`].
+    ///
+    /// The input of a worker can contain a retraction of a value whose
+    /// insertion was processed by another worker, so partial accumulators
+    /// are only computed for aggregators that are linear in the weights of
+    /// their inputs (see [`Aggregator::is_linear`]).  For other aggregators,
+    /// and when the circuit runs in a single worker, this operator is
+    /// equivalent to [`Self::aggregate`].  Use [`Self::aggregate_split`] to
+    /// evaluate such aggregators in two phases.
+    #[allow(clippy::type_complexity)]
+    pub fn aggregate_two_phase<A>(
+        &self,
+        aggregator: A,
+    ) -> Stream<C, OrdIndexedZSet<Z::Key, A::Output, Z::R>>
+    where
+        Z: IndexedZSet + Send,
+        A: Aggregator<Z::Val, <C as WithClock>::Time, Z::R>,
+        A::Semigroup: CommutativeSemigroup<A::Accumulator>,
+        Z::R: ZRingValue,
+    {
+        let num_workers = Runtime::runtime()
+            .map(|runtime| runtime.num_workers())
+            .unwrap_or(1);
+
+        if num_workers == 1 || !aggregator.is_linear() {
+            self.aggregate(aggregator)
+        } else {
+            self.aggregate_partials(aggregator)
+        }
+    }
+
+    /// Incremental aggregation operator for inputs with hot keys.
+    ///
+    /// Computes the same result as [`Self::aggregate`], but does not require
+    /// all values of a key to be processed by the same worker.  Instead, the
+    /// input is sharded using [`Self::shard_split`], which distributes the
+    /// values of each key across up to `fanout` workers.  The workers then
+    /// evaluate the aggregate in two phases like
+    /// [`Self::aggregate_two_phase`].  Since there are at most `fanout`
+    /// partial accumulators per key, the second phase is cheap even for keys
+    /// with many values.
+    ///
+    /// Unlike [`Self::aggregate_two_phase`], this operator does not require
+    /// the aggregator to be linear, since [`Self::shard_split`] sends all
+    /// insertions and retractions of a value to the same worker.
+    ///
+    /// Use [`Self::shard_stats`] to find out whether the input is skewed
+    /// enough to benefit from this operator.
+    #[allow(clippy::type_complexity)]
+    pub fn aggregate_split<A>(
+        &self,
+        aggregator: A,
+        fanout: usize,
+    ) -> Stream<C, OrdIndexedZSet<Z::Key, A::Output, Z::R>>
+    where
+        Z: IndexedZSet + Send,
+        A: Aggregator<Z::Val, <C as WithClock>::Time, Z::R>,
+        A::Semigroup: CommutativeSemigroup<A::Accumulator>,
+        Z::R: ZRingValue,
+    {
+        self.shard_split(fanout).aggregate_partials(aggregator)
+    }
+
+    // Aggregates the stream into partial accumulators in each worker, then
+    // shards partial accumulators by key and combines them.
+    #[allow(clippy::type_complexity)]
+    fn aggregate_partials<A>(
+        &self,
+        aggregator: A,
+    ) -> Stream<C, OrdIndexedZSet<Z::Key, A::Output, Z::R>>
+    where
+        Z: IndexedZSet + Send,
+        A: Aggregator<Z::Val, <C as WithClock>::Time, Z::R>,
+        Z::R: ZRingValue,
+    {
+        self.aggregate_unsharded::<_, OrdIndexedZSet<Z::Key, (A::Accumulator, Z::R), Z::R>>(
+            Partial(aggregator.clone()),
+        )
+        .aggregate(Combine {
+            aggregator,
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// Computes the accumulator of `A` without finalizing it, along with the
+/// total weight of the aggregated values.
+#[derive(Clone)]
+struct Partial<A>(A);
+
+/// Semigroup over the outputs of [`Partial`]: combines accumulators using
+/// `S` and adds up weights.
+#[derive(Clone)]
+struct PartialSemigroup<S>(PhantomData<S>);
+
+impl<S, A, R> Semigroup<(A, R)> for PartialSemigroup<S>
+where
+    S: Semigroup<A>,
+    R: AddByRef,
+{
+    fn combine((left, left_weight): &(A, R), (right, right_weight): &(A, R)) -> (A, R) {
+        (
+            S::combine(left, right),
+            left_weight.add_by_ref(right_weight),
+        )
+    }
+}
+
+impl<V, T, R, A> Aggregator<V, T, R> for Partial<A>
+where
+    R: DBWeight,
+    A: Aggregator<V, T, R>,
+{
+    type Accumulator = (A::Accumulator, R);
+    type Output = (A::Accumulator, R);
+    type Semigroup = PartialSemigroup<A::Semigroup>;
+
+    fn aggregate<'s, C>(&self, cursor: &mut C) -> Option<Self::Accumulator>
+    where
+        C: Cursor<'s, V, (), T, R>,
+    {
+        let mut weight = R::zero();
+        while cursor.key_valid() {
+            cursor.map_times(|_t, w| weight.add_assign_by_ref(w));
+            cursor.step_key();
+        }
+        cursor.rewind_keys();
+
+        self.0
+            .aggregate(cursor)
+            .map(|accumulator| (accumulator, weight))
+    }
+
+    fn finalize(&self, accumulator: Self::Accumulator) -> Self::Output {
+        accumulator
+    }
+}
+
+/// Combines partial accumulators computed by [`Partial`] and finalizes the
+/// result.
+///
+/// The same accumulator can be computed by several workers, in which case it
+/// occurs with weight greater than one and is combined with itself the
+/// corresponding number of times.  Returns `None` if the total weight of the
+/// values aggregated by all partial accumulators is zero.
+#[derive(Clone)]
+struct Combine<A, V> {
+    aggregator: A,
+    phantom: PhantomData<V>,
+}
+
+impl<V, T, R, A> Aggregator<(A::Accumulator, R), T, R> for Combine<A, V>
+where
+    V: Clone + 'static,
+    T: Timestamp,
+    R: DBWeight + ZRingValue,
+    A: Aggregator<V, T, R>,
+{
+    type Accumulator = A::Accumulator;
+    type Output = A::Output;
+    type Semigroup = A::Semigroup;
+
+    fn aggregate<'s, C>(&self, cursor: &mut C) -> Option<Self::Accumulator>
+    where
+        C: Cursor<'s, (A::Accumulator, R), (), T, R>,
+    {
+        let minus_one = R::one().neg();
+        let mut result: Option<A::Accumulator> = None;
+        let mut total_weight = R::zero();
+
+        while cursor.key_valid() {
+            let mut weight = R::zero();
+            cursor.map_times(|_t, w| weight.add_assign_by_ref(w));
+
+            let (partial, partial_weight) = cursor.key();
+            while !weight.le0() {
+                result = Some(match result {
+                    None => partial.clone(),
+                    Some(acc) => {
+                        <A::Semigroup as Semigroup<A::Accumulator>>::combine(&acc, partial)
+                    }
+                });
+                total_weight.add_assign_by_ref(partial_weight);
+                weight.add_assign_by_ref(&minus_one);
+            }
+
+            cursor.step_key();
+        }
+
+        if total_weight.is_zero() {
+            None
+        } else {
+            result
+        }
+    }
+
+    fn finalize(&self, accumulator: Self::Accumulator) -> Self::Output {
+        self.aggregator.finalize(accumulator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        algebra::DefaultSemigroup,
+        operator::{CountDistinct, Fold, Max},
+        trace::Batch,
+        DBData, OrdIndexedZSet, RootCircuit, Runtime, Stream,
+    };
+
+    // Returns the input of each worker at `step`.  Key 0 is hot: it has many
+    // more values than the other keys.  Insertions and retractions of the
+    // same value are sent to different workers, so the input of individual
+    // workers contains negative weights.
+    fn two_phase_input(step: isize, workers: usize) -> Vec<OrdIndexedZSet<usize, isize, isize>> {
+        let mut tuples: Vec<((usize, isize), isize)> =
+            (0..500).map(|n| ((0, n + step * 500), 1)).collect();
+
+        if step == 0 {
+            // Keys 1..=10 only get values in the first step.
+            tuples.extend((1..=10).flat_map(|k| (0..5).map(move |n| ((k, n), 1))));
+            // Key 11 is used to retract the maximal value of a key.
+            tuples.extend((0..10).map(|n| ((11, n), 1)));
+        } else {
+            // Retract some of the values of key 0 inserted at the previous
+            // step, a whole key, and the maximal value of key 11.
+            tuples.extend((400..500).map(|n| ((0, n + (step - 1) * 500), -1)));
+            tuples.extend((0..5).map(|n| ((step as usize, n), -1)));
+            tuples.push(((11, 10 - step), -1));
+        }
+
+        let mut inputs = vec![Vec::new(); workers];
+        for ((k, v), w) in tuples {
+            let worker = (v as usize + usize::from(w < 0)) % workers;
+            inputs[worker].push(((k, v), w));
+        }
+
+        inputs
+            .into_iter()
+            .map(|tuples| OrdIndexedZSet::from_tuples((), tuples))
+            .collect()
+    }
+
+    fn check<V>(
+        actual: &Stream<RootCircuit, OrdIndexedZSet<usize, V, isize>>,
+        expected: &Stream<RootCircuit, OrdIndexedZSet<usize, V, isize>>,
+    ) where
+        V: DBData,
+    {
+        actual.gather(0).apply2(
+            &expected.gather(0),
+            |actual: &OrdIndexedZSet<usize, V, isize>, expected| assert_eq!(actual, expected),
+        );
+    }
+
+    #[test]
+    fn test_two_phase() {
+        for workers in [1, 2, 4] {
+            let (mut dbsp, input_handle) = Runtime::init_circuit(workers, |circuit| {
+                // The input is neither sharded nor marked as sharded.
+                let (input, input_handle) =
+                    circuit.add_input_stream::<OrdIndexedZSet<usize, isize, isize>>();
+
+                let sum = <Fold<_, DefaultSemigroup<_>, _, _>>::new(
+                    0,
+                    |acc: &mut isize, v: &isize, w: isize| *acc += *v * w,
+                )
+                .linear();
+                // Workers often compute identical partial counts.
+                let count = <Fold<_, DefaultSemigroup<_>, _, _>>::new(
+                    0,
+                    |acc: &mut isize, _v: &isize, w: isize| *acc += w,
+                )
+                .linear();
+
+                // Build two-phase aggregates before the reference aggregates
+                // shard the input.
+                let sum_two_phase = input.aggregate_two_phase(sum.clone());
+                let count_two_phase = input.aggregate_two_phase(count.clone());
+                let max_two_phase = input.aggregate_two_phase(Max);
+                let count_distinct_two_phase = input.aggregate_two_phase(CountDistinct);
+                let sum_split = input.aggregate_split(sum.clone(), 4);
+                let max_split = input.aggregate_split(Max, 4);
+                let count_distinct_split = input.aggregate_split(CountDistinct, 4);
+
+                let expected_sum = input.aggregate(sum);
+                let expected_count = input.aggregate(count);
+                let expected_max = input.aggregate(Max);
+                let expected_count_distinct = input.aggregate(CountDistinct);
+
+                check(&sum_two_phase, &expected_sum);
+                check(&count_two_phase, &expected_count);
+                check(&max_two_phase, &expected_max);
+                check(&count_distinct_two_phase, &expected_count_distinct);
+                check(&sum_split, &expected_sum);
+                check(&max_split, &expected_max);
+                check(&count_distinct_split, &expected_count_distinct);
+
+                input_handle
+            })
+            .unwrap();
+
+            for step in 0..5 {
+                for (worker, input) in two_phase_input(step, workers).into_iter().enumerate() {
+                    input_handle.set_for_worker(worker, input);
+                }
+                dbsp.step().unwrap();
+            }
+
+            dbsp.kill().unwrap();
+        }
+    }
+}
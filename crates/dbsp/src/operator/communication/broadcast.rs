@@ -0,0 +1,58 @@
+//! Operator that replicates batches across all worker threads.
+
+use crate::{
+    circuit::GlobalNodeId,
+    circuit_cache_key,
+    operator::communication::exchange::new_exchange_operators,
+    trace::{Batch, Spine, Trace},
+    Circuit, Runtime, Stream,
+};
+use std::panic::Location;
+
+circuit_cache_key!(BroadcastId<C, D>(GlobalNodeId => Stream<C, D>));
+
+impl<C, B> Stream<C, B>
+where
+    C: Circuit,
+    B: Batch<Time = ()> + Send,
+{
+    /// Send each input batch to all workers.
+    ///
+    /// The output stream in every worker contains the union of all input
+    /// batches across all workers.  This is useful to join a small stream of
+    /// changes against a sharded trace using a predicate that does not
+    /// respect the sharding of the trace, e.g., in
+    /// [`Stream::join_range`](`crate::Stream::join_range`).
+    #[track_caller]
+    pub fn broadcast(&self) -> Stream<C, B> {
+        let location = Location::caller();
+
+        match Runtime::runtime() {
+            Some(runtime) if runtime.num_workers() > 1 => {
+                let num_workers = runtime.num_workers();
+
+                self.circuit()
+                    .cache_get_or_insert_with(
+                        BroadcastId::new(self.origin_node_id().clone()),
+                        move || {
+                            let (sender, receiver) = new_exchange_operators(
+                                &runtime,
+                                Runtime::worker_index(),
+                                Some(location),
+                                move |batch: B, batches: &mut Vec<B>| {
+                                    batches.extend((0..num_workers).map(|_| batch.clone()));
+                                },
+                                |trace: &mut Spine<B>, batch: B| trace.insert(batch),
+                            );
+
+                            self.circuit()
+                                .add_exchange(sender, receiver, self)
+                                .consolidate()
+                        },
+                    )
+                    .clone()
+            }
+            _ => self.clone(),
+        }
+    }
+}
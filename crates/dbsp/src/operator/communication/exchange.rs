@@ -1,6 +1,11 @@
 //! Exchange operators implement a N-to-N communication pattern where
 //! each participant sends exactly one value to and receives exactly one
 //! value from each peer at every clock cycle.
+//!
+//! In a multihost runtime, values sent to workers on other hosts are
+//! serialized and transmitted over the network.  Multihost runtimes require
+//! the `checkpoint` feature, which makes exchanged values serializable (see
+//! [`Serializable`]).
 
 // TODO: We may want to generalize these operators to implement N-to-M
 // communication, including 1-to-N and N-to-1.
@@ -8,11 +13,16 @@
 use crate::{
     circuit::{
         metadata::OperatorLocation,
+        network::{ExchangeEndpoint, Network},
         operator_traits::{Operator, SinkOperator, SourceOperator},
+        runtime::Layout,
         OwnershipPreference, Runtime, Scope,
     },
     circuit_cache_key,
+    trace::Serializable,
 };
+#[cfg(feature = "checkpoint")]
+use bincode::{config::standard, decode_from_slice, encode_to_vec};
 use crossbeam_utils::CachePadded;
 use once_cell::sync::OnceCell;
 use std::{
@@ -42,6 +52,11 @@ circuit_cache_key!(local ExchangeId<T>(usize => Arc<Exchange<T>>));
 /// The send operation can only proceed when all peers have retrieved data
 /// produced at the previous round.  Likewise, the receive operation can proceed
 /// once all incoming values are ready for the current round.
+///
+/// In a multihost runtime, only mailboxes of local receivers are used.  Values
+/// for remote receivers are sent over the network as soon as they are written,
+/// and the remote receiver acknowledges each value once it has consumed it,
+/// which frees up the sender's slot.
 pub(crate) struct Exchange<T> {
     /// The number of communicating peers.
     npeers: usize,
@@ -61,6 +76,42 @@ pub(crate) struct Exchange<T> {
     sender_counters: Vec<CachePadded<AtomicUsize>>,
     /// Callback invoked when all `npeers` mailboxes are available.
     sender_callbacks: Vec<OnceCell<Box<dyn Fn() + Send + Sync>>>,
+    /// Connection to workers on other hosts in a multihost runtime.
+    remote: Option<RemoteExchange<T>>,
+}
+
+/// State used by an [`Exchange`] to communicate with workers on other hosts.
+struct RemoteExchange<T> {
+    exchange_id: usize,
+    layout: Layout,
+    network: Arc<Network>,
+    encode: fn(&T) -> Vec<u8>,
+    decode: fn(&[u8]) -> T,
+}
+
+#[cfg(feature = "checkpoint")]
+fn encode_value<T: Serializable>(value: &T) -> Vec<u8> {
+    encode_to_vec(value, standard())
+        .unwrap_or_else(|error| panic!("failed to encode exchanged value: {error}"))
+}
+
+#[cfg(feature = "checkpoint")]
+fn decode_value<T: Serializable>(bytes: &[u8]) -> T {
+    decode_from_slice(bytes, standard())
+        .unwrap_or_else(|error| panic!("failed to decode exchanged value: {error}"))
+        .0
+}
+
+// Without the `checkpoint` feature, runtimes cannot span multiple hosts (see
+// `Runtime::run_with_layout`), so values are never sent over the network.
+#[cfg(not(feature = "checkpoint"))]
+fn encode_value<T>(_value: &T) -> Vec<u8> {
+    unreachable!("multihost runtimes require the `checkpoint` feature")
+}
+
+#[cfg(not(feature = "checkpoint"))]
+fn decode_value<T>(_bytes: &[u8]) -> T {
+    unreachable!("multihost runtimes require the `checkpoint` feature")
 }
 
 impl<T> Exchange<T>
@@ -68,7 +119,7 @@ where
     T: Send + 'static,
 {
     /// Create a new exchange operator for `npeers` communicating threads.
-    fn new(npeers: usize) -> Self {
+    fn new(npeers: usize, remote: Option<RemoteExchange<T>>) -> Self {
         Self {
             npeers,
             mailboxes: (0..npeers * npeers).map(|_| Mutex::new(None)).collect(),
@@ -80,21 +131,79 @@ where
                 .map(|_| CachePadded::new(AtomicUsize::new(npeers)))
                 .collect(),
             sender_callbacks: (0..npeers).map(|_| OnceCell::new()).collect(),
+            remote,
         }
     }
 
     /// Create a new `Exchange` instance if an instance with the same id
     /// (created by another thread) does not yet exist within `runtime`.
     /// The number of peers will be set to `runtime.num_workers()`.
-    pub(crate) fn with_runtime(runtime: &Runtime, exchange_id: usize) -> Arc<Self> {
+    ///
+    /// In a multihost runtime, the new instance is registered with the
+    /// network to receive values sent by workers on other hosts.
+    pub(crate) fn with_runtime(runtime: &Runtime, exchange_id: usize) -> Arc<Self>
+    where
+        T: Serializable,
+    {
         runtime
             .local_store()
             .entry(ExchangeId::new(exchange_id))
-            .or_insert_with(|| Arc::new(Exchange::new(runtime.num_workers())))
+            .or_insert_with(|| {
+                let remote = runtime.network().map(|network| RemoteExchange {
+                    exchange_id,
+                    layout: runtime.layout().clone(),
+                    network: network.clone(),
+                    encode: encode_value::<T>,
+                    decode: decode_value::<T>,
+                });
+                let exchange = Arc::new(Exchange::new(runtime.num_workers(), remote));
+
+                if let Some(network) = runtime.network() {
+                    network.register(exchange_id, exchange.clone());
+                }
+                exchange
+            })
             .value()
             .clone()
     }
 
+    /// Returns the connection to `worker` if it runs on another host.
+    fn remote_peer(&self, worker: usize) -> Option<(&RemoteExchange<T>, usize)> {
+        self.remote
+            .as_ref()
+            .filter(|remote| !remote.layout.is_local(worker))
+            .map(|remote| (remote, remote.layout.host_of(worker)))
+    }
+
+    /// Record that a new value is available to `receiver`.
+    fn notify_receiver(&self, receiver: usize) {
+        let old_counter = self.receiver_counters[receiver].fetch_add(1, Ordering::AcqRel);
+        if old_counter >= self.npeers - 1 {
+            // This can be a spurious callback (see detailed comment in `try_receive_all`)
+            // below.
+            if let Some(cb) = self.receiver_callbacks[receiver].get() {
+                cb()
+            }
+        }
+    }
+
+    /// Record that one of `sender`'s outgoing mailboxes is free.
+    fn notify_sender(&self, sender: usize) {
+        let old_counter = self.sender_counters[sender].fetch_add(1, Ordering::AcqRel);
+        if old_counter >= self.npeers - 1 {
+            // This can be a spurious callback if the following thread interleaving occurs:
+            // 1. Another receiver increments the sender's counter to `npeers`.
+            // 2. The sender starts transmitting messages, writing `receiver`'s mailbox
+            // first    (counter drops to `npeers-1`)
+            // 3. `receiver` is unblocked and retrieves its message, bumping the counter
+            //    back to `npeers` and generating a spurious sender callback in the
+            // following    line.
+            if let Some(cb) = self.sender_callbacks[sender].get() {
+                cb()
+            }
+        }
+    }
+
     /// Returns a reference to a mailbox for the sender/receiver pair.
     fn mailbox(&self, sender: usize, receiver: usize) -> &Mutex<Option<T>> {
         debug_assert!(sender < self.npeers);
@@ -134,15 +243,19 @@ where
         }
 
         for receiver in 0..self.npeers {
-            *self.mailbox(sender, receiver).lock().unwrap() = data.next();
-            self.sender_counters[sender].fetch_sub(1, Ordering::AcqRel);
-            let old_counter = self.receiver_counters[receiver].fetch_add(1, Ordering::AcqRel);
-            if old_counter >= self.npeers - 1 {
-                // This can be a spurious callback (see detailed comment in `try_receive_all`)
-                // below.
-                if let Some(cb) = self.receiver_callbacks[receiver].get() {
-                    cb()
-                }
+            if let Some((remote, host)) = self.remote_peer(receiver) {
+                let payload = (remote.encode)(&data.next().unwrap());
+                self.sender_counters[sender].fetch_sub(1, Ordering::AcqRel);
+                remote
+                    .network
+                    .send_data(host, remote.exchange_id, sender, receiver, payload)
+                    .unwrap_or_else(|error| {
+                        panic!("failed to send exchanged value to host {host}: {error}")
+                    });
+            } else {
+                *self.mailbox(sender, receiver).lock().unwrap() = data.next();
+                self.sender_counters[sender].fetch_sub(1, Ordering::AcqRel);
+                self.notify_receiver(receiver);
             }
         }
         true
@@ -181,18 +294,15 @@ where
                 .unwrap();
             cb(data);
             self.receiver_counters[receiver].fetch_sub(1, Ordering::Release);
-            let old_counter = self.sender_counters[sender].fetch_add(1, Ordering::AcqRel);
-            if old_counter >= self.npeers - 1 {
-                // This can be a spurious callback if the following thread interleaving occurs:
-                // 1. Another receiver increments the sender's counter to `npeers`.
-                // 2. The sender starts transmitting messages, writing `receiver`'s mailbox
-                // first    (counter drops to `npeers-1`)
-                // 3. `receiver` is unblocked and retrieves its message, bumping the counter
-                //    back to `npeers` and generating a spurious sender callback in the
-                // following    line.
-                if let Some(cb) = self.sender_callbacks[sender].get() {
-                    cb()
-                }
+            if let Some((remote, host)) = self.remote_peer(sender) {
+                remote
+                    .network
+                    .send_ack(host, remote.exchange_id, sender)
+                    .unwrap_or_else(|error| {
+                        panic!("failed to acknowledge exchanged value to host {host}: {error}")
+                    });
+            } else {
+                self.notify_sender(sender);
             }
         }
 
@@ -250,6 +360,21 @@ where
     }
 }
 
+impl<T> ExchangeEndpoint for Exchange<T>
+where
+    T: Send + 'static,
+{
+    fn deliver(&self, sender: usize, receiver: usize, payload: &[u8]) {
+        let remote = self.remote.as_ref().unwrap();
+        *self.mailbox(sender, receiver).lock().unwrap() = Some((remote.decode)(payload));
+        self.notify_receiver(receiver);
+    }
+
+    fn acknowledge(&self, sender: usize) {
+        self.notify_sender(sender);
+    }
+}
+
 /// Operator that partitions incoming data across all workers.
 ///
 /// This operator works in tandem with [`ExchangeReceiver`], which reassembles
@@ -384,7 +509,7 @@ pub struct ExchangeSender<D, T, L> {
 
 impl<D, T, L> ExchangeSender<D, T, L>
 where
-    T: Send + 'static,
+    T: Send + Serializable + 'static,
 {
     fn new(
         runtime: &Runtime,
@@ -488,7 +613,7 @@ pub struct ExchangeReceiver<T, L> {
 
 impl<T, L> ExchangeReceiver<T, L>
 where
-    T: Send + 'static,
+    T: Send + Serializable + 'static,
 {
     fn new(
         runtime: &Runtime,
@@ -577,7 +702,8 @@ where
 /// # Type arguments
 /// * `TI` - Type of values in the input stream consumed by `ExchangeSender`.
 /// * `TO` - Type of values in the output stream produced by `ExchangeReceiver`.
-/// * `TE` - Type of values sent across workers.
+/// * `TE` - Type of values sent across workers.  Values sent to workers on
+///   other hosts in a multihost runtime are serialized using `bincode`.
 /// * `PL` - Type of closure that splits a value of type `TI` into
 ///   `runtime.num_workers()` values of type `TE`.
 /// * `I` - Iterator returned by `PL`.
@@ -592,7 +718,7 @@ pub fn new_exchange_operators<TI, TO, TE, PL, CL>(
 ) -> (ExchangeSender<TI, TE, PL>, ExchangeReceiver<TE, CL>)
 where
     TO: Default + Clone,
-    TE: Send + 'static,
+    TE: Send + Serializable + 'static,
     PL: FnMut(TI, &mut Vec<TE>) + 'static,
     CL: Fn(&mut TO, TE) + 'static,
 {
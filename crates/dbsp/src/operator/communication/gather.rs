@@ -5,6 +5,7 @@ use crate::{
         GlobalNodeId, OwnershipPreference, Scope,
     },
     circuit_cache_key,
+    operator::communication::new_exchange_operators,
     trace::{spine_fueled::Spine, Batch, Trace},
     Circuit, Runtime, Stream,
 };
@@ -37,6 +38,9 @@ where
     /// The output stream in `receiver_worker` will contain a union of all
     /// input batches across all workers. The output streams in all other
     /// workers will contain empty batches.
+    ///
+    /// In a multihost runtime, batches are sent to `receiver_worker` over the
+    /// network using an exchange operator.
     #[track_caller]
     pub fn gather(&self, receiver_worker: usize) -> Stream<C, B>
     where
@@ -59,6 +63,25 @@ where
                             GatherId::new((self.origin_node_id().clone(), receiver_worker)),
                             move || {
                                 let current_worker = Runtime::worker_index();
+
+                                if runtime.layout().is_multihost() {
+                                    let (sender, receiver) = new_exchange_operators(
+                                        &runtime,
+                                        current_worker,
+                                        Some(location),
+                                        move |batch: B, batches: &mut Vec<B>| {
+                                            batches.extend((0..workers).map(|_| B::empty(())));
+                                            batches[receiver_worker] = batch;
+                                        },
+                                        |trace: &mut Spine<B>, batch: B| trace.insert(batch),
+                                    );
+
+                                    return self
+                                        .circuit()
+                                        .add_exchange(sender, receiver, self)
+                                        .consolidate();
+                                }
+
                                 let gather_id = runtime.sequence_next(current_worker);
 
                                 let gather = runtime
@@ -1,6 +1,11 @@
+mod broadcast;
 mod exchange;
 mod gather;
 mod shard;
 
 pub(crate) use exchange::Exchange;
 pub use exchange::{new_exchange_operators, ExchangeReceiver, ExchangeSender};
+pub(crate) use shard::key_shard;
+#[cfg(feature = "checkpoint")]
+pub(crate) use shard::split_sharded;
+pub use shard::ShardStats;
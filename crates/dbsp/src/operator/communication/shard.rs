@@ -1,9 +1,6 @@
 //! Operators to shard batches across multiple worker threads based on keys
 //! and to gather sharded batches in one worker.
 
-// TODOs:
-// - different sharding modes.
-
 use crate::{
     circuit::GlobalNodeId,
     circuit_cache_key, default_hash,
@@ -11,17 +8,207 @@ use crate::{
     trace::{cursor::Cursor, Batch, BatchReader, Builder, Spine, Trace},
     Circuit, Runtime, Stream,
 };
-use std::{hash::Hash, panic::Location};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    hash::Hash,
+    panic::Location,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 circuit_cache_key!(ShardId<C, D>((GlobalNodeId, ShardingPolicy) => Stream<C, D>));
+circuit_cache_key!(ShardStatsId<K>(GlobalNodeId => ShardStats<K>));
+circuit_cache_key!(local SplitShardedId(usize => Arc<AtomicBool>));
 
-// An attempt to future-proof the design for when we support multiple sharding
-// disciplines.
-#[derive(Hash, PartialEq, Eq)]
-pub struct ShardingPolicy;
+/// Strategy used to assign tuples to workers.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ShardingPolicy {
+    /// All tuples with the same key are assigned to the same worker based on
+    /// the hash of the key.
+    Key,
+    /// Tuples with the same key are split across up to `fanout` workers
+    /// based on the hash of the value (see [`Stream::shard_split`]).
+    Split { fanout: usize },
+}
 
 fn sharding_policy<C>(_circuit: &C) -> ShardingPolicy {
-    ShardingPolicy
+    ShardingPolicy::Key
+}
+
+/// Returns the worker that `key` is assigned to by [`Stream::shard`] in a
+/// runtime with `workers` workers.
+pub(crate) fn key_shard<K>(key: &K, workers: usize) -> usize
+where
+    K: Hash,
+{
+    default_hash(key) as usize % workers
+}
+
+fn split_sharded_flag(runtime: &Runtime) -> Arc<AtomicBool> {
+    runtime
+        .local_store()
+        .entry(SplitShardedId::new(Runtime::worker_index()))
+        .or_insert_with(|| Arc::new(AtomicBool::new(false)))
+        .value()
+        .clone()
+}
+
+/// Returns a flag that is set once [`Stream::shard_split`] splits the values
+/// of a key across workers in the circuit of the current worker, or `None`
+/// outside of a multithreaded runtime.
+///
+/// The state of operators in such a circuit is not partitioned by key, which
+/// rules out redistributing it across a different number of workers.
+#[cfg(feature = "checkpoint")]
+pub(crate) fn split_sharded() -> Option<Arc<AtomicBool>> {
+    Runtime::runtime().map(|runtime| split_sharded_flag(&runtime))
+}
+
+/// A batch is considered skewed when one worker receives more than
+/// `SKEW_FACTOR` times its fair share of the tuples in the batch.
+const SKEW_FACTOR: usize = 2;
+
+/// Smaller batches are not checked for skew.
+const MIN_SKEWED_BATCH_SIZE: usize = 128;
+
+/// The maximal number of hot keys tracked by [`ShardStats`].
+const MAX_HOT_KEYS: usize = 16;
+
+/// Statistics collected by the [`Stream::shard`] operator in one worker to
+/// detect skew, i.e., uneven distribution of tuples across workers.
+///
+/// Each worker counts the tuples it sends to every peer.  In addition, when a
+/// batch sent by the worker is skewed, the worker identifies the hot keys,
+/// i.e., keys that account for more than a fair share of the batch.  Hot keys
+/// are reported by [`ShardStats::hot_keys`] to help diagnose the skew.
+///
+/// Use [`Stream::shard_stats`] to obtain the statistics for a stream.
+#[derive(Clone)]
+pub struct ShardStats<K>(Rc<RefCell<ShardStatsInner<K>>>);
+
+struct ShardStatsInner<K> {
+    // The number of tuples sent to each worker.
+    tuples: Vec<u64>,
+    // The number of batches in which skew was detected.
+    skewed_batches: u64,
+    // Estimated number of tuples for each hot key.
+    hot_keys: BTreeMap<K, u64>,
+}
+
+impl<K> ShardStats<K>
+where
+    K: Ord + Clone,
+{
+    fn new(workers: usize) -> Self {
+        Self(Rc::new(RefCell::new(ShardStatsInner {
+            tuples: vec![0; workers],
+            skewed_batches: 0,
+            hot_keys: BTreeMap::new(),
+        })))
+    }
+
+    /// Returns the number of tuples sent by the current worker to each
+    /// worker.
+    pub fn tuples_per_worker(&self) -> Vec<u64> {
+        self.0.borrow().tuples.clone()
+    }
+
+    /// Returns the ratio of the largest number of tuples sent to a single
+    /// worker to the average across workers.
+    ///
+    /// `1.0` indicates perfectly uniform distribution, while the number of
+    /// workers indicates that all tuples were sent to the same worker.
+    pub fn skew(&self) -> f64 {
+        let inner = self.0.borrow();
+        let total: u64 = inner.tuples.iter().sum();
+
+        if total == 0 {
+            1.0
+        } else {
+            let max = inner.tuples.iter().max().copied().unwrap_or_default();
+            max as f64 * inner.tuples.len() as f64 / total as f64
+        }
+    }
+
+    /// Returns the number of skewed batches sent by the current worker.
+    pub fn skewed_batches(&self) -> u64 {
+        self.0.borrow().skewed_batches
+    }
+
+    /// Returns hot keys detected in skewed batches along with the number of
+    /// their tuples in those batches, hottest keys first.
+    pub fn hot_keys(&self) -> Vec<(K, u64)> {
+        let mut hot_keys: Vec<_> = self
+            .0
+            .borrow()
+            .hot_keys
+            .iter()
+            .map(|(key, tuples)| (key.clone(), *tuples))
+            .collect();
+        hot_keys.sort_by(|(_, tuples1), (_, tuples2)| tuples2.cmp(tuples1));
+        hot_keys
+    }
+
+    /// Clears all statistics.
+    pub fn reset(&self) {
+        let mut inner = self.0.borrow_mut();
+        inner.tuples.iter_mut().for_each(|tuples| *tuples = 0);
+        inner.skewed_batches = 0;
+        inner.hot_keys.clear();
+    }
+
+    /// Updates statistics after partitioning `batch` into `shards`.
+    fn update<IB, OB>(&self, batch: &IB, shards: &[OB])
+    where
+        IB: BatchReader<Key = K>,
+        OB: BatchReader,
+    {
+        let mut inner = self.0.borrow_mut();
+        let workers = shards.len();
+
+        let mut max_shard = 0;
+        for (tuples, shard) in inner.tuples.iter_mut().zip(shards) {
+            *tuples += shard.len() as u64;
+            max_shard = max_shard.max(shard.len());
+        }
+
+        let total = batch.len();
+        if total < MIN_SKEWED_BATCH_SIZE || max_shard * workers <= SKEW_FACTOR * total {
+            return;
+        }
+
+        inner.skewed_batches += 1;
+
+        // Find keys with more tuples than a worker's fair share of the batch.
+        let fair_share = total / workers;
+        let mut cursor = batch.cursor();
+        while cursor.key_valid() {
+            let mut tuples = 0;
+            while cursor.val_valid() {
+                tuples += 1;
+                cursor.step_val();
+            }
+            if tuples > fair_share {
+                *inner.hot_keys.entry(cursor.key().clone()).or_insert(0) += tuples as u64;
+            }
+            cursor.step_key();
+        }
+
+        // Only keep the hottest keys.
+        while inner.hot_keys.len() > MAX_HOT_KEYS {
+            let coldest = inner
+                .hot_keys
+                .iter()
+                .min_by_key(|(_, tuples)| **tuples)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            inner.hot_keys.remove(&coldest);
+        }
+    }
 }
 
 impl<C, IB> Stream<C, IB>
@@ -84,7 +271,10 @@ where
     /// workers.  This limits the scalability since a slow worker (e.g., running
     /// on a busy CPU core or sharing the core with other workers) or uneven
     /// sharding can slow down the whole system and reduce gains from
-    /// parallelization.
+    /// parallelization.  Use [`Self::shard_stats`] to detect uneven
+    /// sharding.  Operators that can combine results computed over subsets
+    /// of the values of a key can use [`Self::shard_split`] instead to spread
+    /// large keys across workers.
     #[track_caller]
     pub fn shard(&self) -> Stream<C, IB>
     where
@@ -123,6 +313,12 @@ where
                             sharding_policy(self.circuit()),
                         )),
                         move || {
+                            let stats = ShardStats::new(num_workers);
+                            self.circuit().cache_insert(
+                                ShardStatsId::new(self.origin_node_id().clone()),
+                                stats.clone(),
+                            );
+
                             // As a minor optimization, we reuse this array across all invocations
                             // of the sharding operator.
                             let mut builders = Vec::with_capacity(runtime.num_workers());
@@ -131,7 +327,14 @@ where
                                 Runtime::worker_index(),
                                 Some(location),
                                 move |batch: IB, batches: &mut Vec<OB>| {
-                                    Self::shard_batch(&batch, num_workers, &mut builders, batches);
+                                    Self::shard_batch(
+                                        &batch,
+                                        num_workers,
+                                        &mut builders,
+                                        batches,
+                                        |shard, _| shard,
+                                    );
+                                    stats.update(&batch, batches);
                                 },
                                 |trace: &mut Spine<OB>, batch: OB| trace.insert(batch),
                             );
@@ -161,14 +364,110 @@ where
         })
     }
 
-    // Partitions the batch into `nshards` partitions based on the hash of the key.
-    fn shard_batch<OB>(
+    /// Returns statistics collected by the operator that shards this stream
+    /// (see [`ShardStats`]).
+    ///
+    /// Returns `None` if the stream has not been sharded, e.g., because the
+    /// circuit runs in a single worker or no operator has sharded this stream
+    /// yet.
+    pub fn shard_stats(&self) -> Option<ShardStats<IB::Key>> {
+        self.circuit()
+            .cache_get(&ShardStatsId::new(self.origin_node_id().clone()))
+    }
+
+    /// Shard batches across worker threads, splitting the values of each key
+    /// across up to `fanout` workers.
+    ///
+    /// Unlike [`Self::shard`], which sends all tuples with the same key to the
+    /// same worker, this operator assigns each `(key, value)` pair to one of
+    /// `fanout` consecutive workers, starting from the worker that
+    /// [`Self::shard`] assigns the key to, based on the hash of the value.
+    /// All keys are split this way, not just the hot keys reported by
+    /// [`ShardStats::hot_keys`]: the set of hot keys changes over time and
+    /// differs between workers, while the assignment of a tuple must only
+    /// depend on the key and the value, so that a retraction always reaches
+    /// the same worker as the original insertion.  Splitting a key that is
+    /// not hot does not affect correctness, but increases the number of
+    /// partial results per key.
+    ///
+    /// The output of this operator is not sharded by key; hence it can only
+    /// be consumed by operators that can combine results computed over
+    /// arbitrary subsets of the values of a key, e.g., linear operators and
+    /// [`Stream::aggregate_split`].
+    ///
+    /// A `fanout` of `1` is equivalent to [`Self::shard`].
+    ///
+    /// # Limitations
+    ///
+    /// The state of operators that consume the output of this operator is
+    /// not partitioned by key and cannot be migrated to a runtime with a
+    /// different number of workers.  Hence
+    /// [`Runtime::init_circuit_from_checkpoint`] fails to rescale any circuit
+    /// that uses this operator.
+    #[track_caller]
+    pub fn shard_split(&self, fanout: usize) -> Stream<C, IB>
+    where
+        IB: Batch + Send,
+        IB::Val: Hash,
+    {
+        assert_ne!(fanout, 0);
+        let location = Location::caller();
+
+        match Runtime::runtime() {
+            Some(runtime) if runtime.num_workers() > 1 && fanout > 1 => {
+                let num_workers = runtime.num_workers();
+                let fanout = fanout.min(num_workers);
+                let policy = ShardingPolicy::Split { fanout };
+
+                split_sharded_flag(&runtime).store(true, Ordering::Release);
+
+                self.circuit()
+                    .cache_get_or_insert_with(
+                        ShardId::new((self.origin_node_id().clone(), policy)),
+                        move || {
+                            let mut builders = Vec::with_capacity(num_workers);
+                            let (sender, receiver) = new_exchange_operators(
+                                &runtime,
+                                Runtime::worker_index(),
+                                Some(location),
+                                move |batch: IB, batches: &mut Vec<IB>| {
+                                    Self::shard_batch(
+                                        &batch,
+                                        num_workers,
+                                        &mut builders,
+                                        batches,
+                                        |shard, val| {
+                                            (shard + default_hash(val) as usize % fanout)
+                                                % num_workers
+                                        },
+                                    );
+                                },
+                                |trace: &mut Spine<IB>, batch: IB| trace.insert(batch),
+                            );
+
+                            self.circuit()
+                                .add_exchange(sender, receiver, self)
+                                .consolidate()
+                        },
+                    )
+                    .clone()
+            }
+            _ => self.shard(),
+        }
+    }
+
+    // Partitions the batch into `nshards` partitions.  `assign` maps the
+    // shard of each key, determined by the hash of the key, and a value of
+    // this key to the shard of the `(key, value)` pair.
+    fn shard_batch<OB, F>(
         batch: &IB,
         shards: usize,
         builders: &mut Vec<OB::Builder>,
         outputs: &mut Vec<OB>,
+        mut assign: F,
     ) where
         OB: Batch<Key = IB::Key, Val = IB::Val, Time = (), R = IB::R>,
+        F: FnMut(usize, &IB::Val) -> usize,
     {
         builders.clear();
 
@@ -182,8 +481,9 @@ where
         let mut cursor = batch.cursor();
 
         while cursor.key_valid() {
-            let batch_index = default_hash(cursor.key()) as usize % shards;
+            let shard = key_shard(cursor.key(), shards);
             while cursor.val_valid() {
+                let batch_index = assign(shard, cursor.val());
                 builders[batch_index].push((
                     OB::item_from(cursor.key().clone(), cursor.val().clone()),
                     cursor.weight(),
@@ -305,4 +605,80 @@ mod tests {
 
         hruntime.join().unwrap();
     }
+
+    // 1000 values of key 0 and a single value for every other key.
+    fn skewed_data(worker_index: usize, num_workers: usize) -> OrdIndexedZSet<usize, usize, isize> {
+        let tuples: Vec<_> = (0..1000)
+            .filter(|n| n % num_workers == worker_index)
+            .flat_map(|n| vec![((0, n), 1), ((n + 1, n), 1)])
+            .collect();
+        <OrdIndexedZSet<usize, usize, isize>>::from_tuples((), tuples)
+    }
+
+    #[test]
+    fn test_shard_stats() {
+        let hruntime = Runtime::run(4, || {
+            let (circuit, input) = RootCircuit::build(move |circuit| {
+                let input = circuit.add_source(Generator::new(|| {
+                    let worker_index = Runtime::worker_index();
+                    let num_workers = Runtime::runtime().unwrap().num_workers();
+                    skewed_data(worker_index, num_workers)
+                }));
+                input.shard();
+                input
+            })
+            .unwrap();
+
+            circuit.step().unwrap();
+
+            let stats = input.shard_stats().unwrap();
+            assert_eq!(stats.tuples_per_worker().iter().sum::<u64>(), 500);
+            assert!(stats.skew() > 2.0);
+            assert_eq!(stats.skewed_batches(), 1);
+            assert_eq!(stats.hot_keys(), vec![(0, 250)]);
+
+            stats.reset();
+            assert_eq!(stats.skewed_batches(), 0);
+            assert_eq!(stats.hot_keys(), vec![]);
+        });
+
+        hruntime.join().unwrap();
+    }
+
+    #[test]
+    fn test_shard_split() {
+        let hruntime = Runtime::run(4, || {
+            let circuit = RootCircuit::build(move |circuit| {
+                let input = circuit.add_source(Generator::new(|| {
+                    let worker_index = Runtime::worker_index();
+                    let num_workers = Runtime::runtime().unwrap().num_workers();
+                    skewed_data(worker_index, num_workers)
+                }));
+                let split = input.shard_split(4);
+
+                // The hot key is spread across all workers.
+                split.inspect(|batch: &OrdIndexedZSet<usize, usize, isize>| {
+                    assert!(batch.len() < 1000);
+                });
+
+                split
+                    .gather(0)
+                    .inspect(|batch: &OrdIndexedZSet<usize, usize, isize>| {
+                        if Runtime::worker_index() == 0 {
+                            assert_eq!(batch, &skewed_data(0, 1))
+                        } else {
+                            assert_eq!(batch.len(), 0);
+                        }
+                    });
+            })
+            .unwrap()
+            .0;
+
+            for _ in 0..3 {
+                circuit.step().unwrap();
+            }
+        });
+
+        hruntime.join().unwrap();
+    }
 }
@@ -518,6 +518,11 @@ where
 /// `T::default()`).  The handle is then used to write new values
 /// to the mailboxes, which will be consumed at the next
 /// logical clock tick.
+///
+/// In a multihost runtime, the handle only manages mailboxes of workers
+/// on the current host, and worker indexes passed to its methods are
+/// relative to the current host (see
+/// [`Runtime::local_worker_index`](`crate::Runtime::local_worker_index`)).
 #[derive(Clone)]
 pub struct InputHandle<T>(Arc<InputHandleInternal<T>>);
 
@@ -535,7 +540,9 @@ where
                     .local_store()
                     .entry(InputId::new(input_id))
                     .or_insert_with(|| {
-                        Self(Arc::new(InputHandleInternal::new(runtime.num_workers())))
+                        Self(Arc::new(InputHandleInternal::new(
+                            runtime.layout().local_workers().len(),
+                        )))
                     })
                     .value()
                     .clone()
@@ -905,7 +912,7 @@ where
 {
     fn new(input_func: F) -> (Self, InputHandle<IT>) {
         let handle = InputHandle::new();
-        let mailbox = handle.mailbox(Runtime::worker_index()).clone();
+        let mailbox = handle.mailbox(Runtime::local_worker_index()).clone();
 
         let input = Self {
             mailbox,
@@ -8,6 +8,7 @@ use crate::{
         z1::{DelayedFeedback, DelayedNestedFeedback},
         Plus,
     },
+    trace::Serializable,
     NumEntries,
 };
 use size_of::SizeOf;
@@ -27,6 +28,7 @@ where
         + HasZero
         + SizeOf
         + NumEntries
+        + Serializable
         + 'static,
 {
     /// Integrate the input stream.
@@ -13,15 +13,20 @@
 //! * For each `((k1, v1), w1)` in `z1` and `((k2, v2), w2)` in `z2` where `k2 ∈
 //!   join_range(k1)`, add all values in `join_func(k1,v1,k2,v2)` to the output
 //!   batch with weight `w1 * w2`.
+//!
+//! [`Stream::stream_join_range`] and its variants join the pair of batches
+//! received at each timestamp.  [`Stream::join_range`] and its variants are
+//! their incremental versions: they maintain traces of both inputs and
+//! output changes to the range-join of the accumulated collections.
 
 use crate::{
-    algebra::{MulByRef, ZRingValue},
+    algebra::{IndexedZSet, MulByRef, ZRingValue},
     circuit::{
         operator_traits::{BinaryOperator, Operator},
         Circuit, Scope, Stream,
     },
     trace::{cursor::Cursor, Batch, BatchReader},
-    DBData, OrdIndexedZSet, OrdZSet,
+    DBData, OrdIndexedZSet, OrdZSet, RootCircuit,
 };
 use std::{borrow::Cow, marker::PhantomData};
 
@@ -107,6 +112,109 @@ where
     }
 }
 
+impl<I1> Stream<RootCircuit, I1>
+where
+    I1: IndexedZSet + Send,
+    I1::R: ZRingValue,
+{
+    /// Incremental range-join of two streams into an `OrdZSet`.
+    ///
+    /// Given streams `self` and `other` of changes to relations `A` and `B`
+    /// respectively, computes a stream of changes to the range-join of `A`
+    /// and `B` (see module documentation for the definition of the
+    /// range-join operator and its arguments):
+    ///
+    /// ```text
+    /// delta(A >< B) = a >< z^-1(B) + A >< b
+    /// ```
+    ///
+    /// Both inputs are sharded across workers and their traces are maintained
+    /// locally by each worker.  Since keys that match each other are not
+    /// necessarily assigned to the same worker, changes to each input are
+    /// [broadcast](`Stream::broadcast`) to all workers, which join them
+    /// against their local shard of the other trace.
+    ///
+    /// The first term seeks the range of each changed key of `A` in the trace
+    /// of `B`.  The second term scans the keys in each worker's shard of `A`
+    /// to find the ones whose ranges contain changed keys of `B`; this scan
+    /// only happens at timestamps when `B` changes.
+    #[track_caller]
+    pub fn join_range<RF, JF, It, I2>(
+        &self,
+        other: &Stream<RootCircuit, I2>,
+        range_func: RF,
+        join_func: JF,
+    ) -> Stream<RootCircuit, OrdZSet<It::Item, I1::R>>
+    where
+        I2: IndexedZSet<R = I1::R> + Send,
+        RF: Fn(&I1::Key) -> (I2::Key, I2::Key) + Clone + 'static,
+        JF: Fn(&I1::Key, &I1::Val, &I2::Key, &I2::Val) -> It + Clone + 'static,
+        It: IntoIterator + 'static,
+        It::Item: DBData,
+    {
+        self.join_range_generic(other, range_func, move |k1, v1, k2, v2| {
+            join_func(k1, v1, k2, v2).into_iter().map(|k| (k, ()))
+        })
+    }
+
+    /// Incremental range-join of two streams into an `OrdIndexedZSet`.
+    ///
+    /// Like [`Self::join_range`], but the `join_func` closure returns an
+    /// iterator over `(key, value)` pairs used to assemble the output
+    /// indexed Z-set.
+    #[track_caller]
+    pub fn join_range_index<RF, JF, It, K, V, I2>(
+        &self,
+        other: &Stream<RootCircuit, I2>,
+        range_func: RF,
+        join_func: JF,
+    ) -> Stream<RootCircuit, OrdIndexedZSet<K, V, I1::R>>
+    where
+        I2: IndexedZSet<R = I1::R> + Send,
+        RF: Fn(&I1::Key) -> (I2::Key, I2::Key) + Clone + 'static,
+        JF: Fn(&I1::Key, &I1::Val, &I2::Key, &I2::Val) -> It + Clone + 'static,
+        K: DBData,
+        V: DBData,
+        It: IntoIterator<Item = (K, V)> + 'static,
+    {
+        self.join_range_generic(other, range_func, join_func)
+    }
+
+    /// Like [`Self::join_range`], but can return any indexed Z-set type.
+    #[track_caller]
+    pub fn join_range_generic<RF, JF, It, I2, O>(
+        &self,
+        other: &Stream<RootCircuit, I2>,
+        range_func: RF,
+        join_func: JF,
+    ) -> Stream<RootCircuit, O>
+    where
+        I2: IndexedZSet<R = I1::R> + Send,
+        O: IndexedZSet<R = I1::R>,
+        RF: Fn(&I1::Key) -> (I2::Key, I2::Key) + Clone + 'static,
+        JF: Fn(&I1::Key, &I1::Val, &I2::Key, &I2::Val) -> It + Clone + 'static,
+        It: IntoIterator<Item = (O::Key, O::Val)> + 'static,
+    {
+        let left = self.shard();
+        let right = other.shard();
+
+        // delta(A >< B) = a >< z^-1(B) + A >< b, where each worker joins the
+        // changes to one input received from all workers with its own shard of
+        // the other input.
+        self.broadcast()
+            .stream_join_range_generic(
+                &right.integrate_trace().delay_trace(),
+                range_func.clone(),
+                join_func.clone(),
+            )
+            .plus(&left.integrate_trace().stream_join_range_generic(
+                &other.broadcast(),
+                range_func,
+                join_func,
+            ))
+    }
+}
+
 pub struct StreamJoinRange<RF, JF, It, I1, I2, O> {
     range_func: RF,
     join_func: JF,
@@ -152,6 +260,13 @@ where
 {
     fn eval(&mut self, i1: &I1, i2: &I2) -> O {
         let mut tuples = Vec::new();
+
+        // Avoid scanning `i1` when there is nothing to join it with, which is
+        // common when `i1` is a trace.
+        if i1.is_empty() || i2.is_empty() {
+            return O::from_tuples((), tuples);
+        }
+
         let mut i1_cursor = i1.cursor();
         let mut i2_cursor = i2.cursor();
 
@@ -198,7 +313,7 @@ where
 
 #[cfg(test)]
 mod test {
-    use crate::{operator::Generator, zset, Circuit, RootCircuit};
+    use crate::{operator::Generator, zset, Circuit, RootCircuit, Runtime};
 
     #[test]
     fn stream_join_range_test() {
@@ -297,4 +412,80 @@ mod test {
             circuit.step().unwrap();
         }
     }
+
+    #[test]
+    fn join_range_test() {
+        let circuit = RootCircuit::build(move |circuit| {
+            let mut input1 = vec![
+                zset! {
+                    (1, 'a') => 1,
+                    (1, 'b') => 2,
+                    (2, 'c') => 3,
+                    (3, 'e') => 5,
+                },
+                zset! {(1, 'a') => -1, (5, 'x') => 1},
+                zset! {},
+                zset! {(4, 'n') => 2},
+                zset! {(2, 'c') => -3},
+            ]
+            .into_iter();
+            let mut input2 = vec![
+                zset! {
+                    (2, 'g') => 3,
+                    (3, 'i') => 5,
+                },
+                zset! {(1, 'b') => 1},
+                zset! {(4, 'm') => 1, (6, 'y') => 1},
+                zset! {},
+                zset! {(3, 'i') => -5},
+            ]
+            .into_iter();
+
+            let index1 = circuit
+                .add_source(Generator::new(move || input1.next().unwrap()))
+                .index();
+            let index2 = circuit
+                .add_source(Generator::new(move || input2.next().unwrap()))
+                .index();
+
+            // The integral of the incremental join must be equal to the join of
+            // integrals.  Both are collected in worker 0 when running in a
+            // multithreaded runtime.
+            let incremental = index1
+                .join_range(
+                    &index2,
+                    |&k| (k - 1, k + 2),
+                    |&k1, &v1, &k2, &v2| Some(((k1, v1), (k2, v2))),
+                )
+                .integrate()
+                .gather(0);
+            let expected = index1.integrate().gather(0).stream_join_range(
+                &index2.integrate().gather(0),
+                |&k| (k - 1, k + 2),
+                |&k1, &v1, &k2, &v2| Some(((k1, v1), (k2, v2))),
+            );
+            incremental.apply2(&expected, |o1, o2| assert_eq!(o1, o2));
+        })
+        .unwrap()
+        .0;
+
+        for _ in 0..5 {
+            circuit.step().unwrap();
+        }
+    }
+
+    fn do_join_range_test_mt(workers: usize) {
+        let hruntime = Runtime::run(workers, || {
+            join_range_test();
+        });
+
+        hruntime.join().unwrap();
+    }
+
+    #[test]
+    fn join_range_test_mt() {
+        do_join_range_test_mt(1);
+        do_join_range_test_mt(2);
+        do_join_range_test_mt(4);
+    }
 }
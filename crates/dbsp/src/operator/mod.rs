@@ -30,12 +30,16 @@ mod semijoin;
 mod stream_fold;
 mod sum;
 pub mod time_series;
+mod topk;
 mod trace;
 mod z1;
 
 #[cfg(feature = "with-csv")]
 pub use self::csv::CsvSource;
-pub use aggregate::{Aggregator, Avg, Fold, Max, MaxSemigroup, Min, MinSemigroup};
+pub use aggregate::{
+    Aggregator, ApproxPercentile, Avg, CountDistinct, Fold, Max, MaxSemigroup, Median, Min,
+    MinSemigroup, Percentile,
+};
 pub use apply::Apply;
 pub use condition::Condition;
 pub use delta0::Delta0;
@@ -126,6 +126,12 @@ impl<T> OutputHandleInternal<T> {
 /// leaving the mailbox empty.  If the value is not read, it gets
 /// overwritten at the next clock cycle (i.e., during the next call to
 /// `step`).
+///
+/// In a multihost runtime, the handle only receives values produced by
+/// workers on the current host, and worker indexes passed to its methods
+/// are relative to the current host.  To read the complete contents of a
+/// stream at the coordinator, collect it at worker 0 first using
+/// [`Stream::gather`](`crate::Stream::gather`).
 #[derive(Clone)]
 pub struct OutputHandle<T>(Arc<OutputHandleInternal<T>>);
 
@@ -143,7 +149,9 @@ where
                     .local_store()
                     .entry(OutputId::new(output_id))
                     .or_insert_with(|| {
-                        Self(Arc::new(OutputHandleInternal::new(runtime.num_workers())))
+                        Self(Arc::new(OutputHandleInternal::new(
+                            runtime.layout().local_workers().len(),
+                        )))
                     })
                     .value()
                     .clone()
@@ -240,7 +248,7 @@ where
 {
     fn new() -> (Self, OutputHandle<T>) {
         let handle = OutputHandle::new();
-        let mailbox = handle.mailbox(Runtime::worker_index()).clone();
+        let mailbox = handle.mailbox(Runtime::local_worker_index()).clone();
 
         let output = Self { mailbox };
 
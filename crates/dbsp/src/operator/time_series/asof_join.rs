@@ -0,0 +1,495 @@
+//! As-of join of partitioned time series.
+
+use crate::{
+    algebra::{HasOne, HasZero, IndexedZSet, ZRingValue},
+    circuit::{
+        operator_traits::{Operator, QuaternaryOperator},
+        OwnershipPreference, Scope,
+    },
+    operator::{
+        time_series::{
+            lag::{is_positive, map_batch, Descending},
+            OrdPartitionedIndexedZSet, PartitionedBatchReader, PartitionedIndexedZSet,
+        },
+        trace::{DelayedTraceId, IntegrateTraceId, UntimedTraceAppend, Z1Trace},
+    },
+    trace::{Batch, BatchReader, Cursor, Spine},
+    Circuit, DBData, OrdIndexedZSet, RootCircuit, Stream,
+};
+use std::{borrow::Cow, cmp::max, marker::PhantomData, ops::Neg};
+
+pub type OrdAsofJoinStream<PK, TS, V1, V2, R> =
+    Stream<RootCircuit, OrdPartitionedIndexedZSet<PK, TS, (V1, Option<V2>), R>>;
+
+/// Returns the timestamps of all records in `batch`, labeled with the side of
+/// the join the batch belongs to.
+///
+/// All timestamps get weight 1, so that updates that cancel each other out in
+/// the input still mark the timestamp as affected.
+fn affected_times<B, TS, V>(batch: &B, from_right: bool) -> OrdIndexedZSet<B::Key, (TS, bool), B::R>
+where
+    B: PartitionedIndexedZSet<TS, V>,
+    B::R: ZRingValue,
+    TS: DBData,
+{
+    let mut tuples = Vec::with_capacity(batch.len());
+    let mut cursor = batch.cursor();
+    while cursor.key_valid() {
+        while cursor.val_valid() {
+            tuples.push((
+                (cursor.key().clone(), (cursor.val().0.clone(), from_right)),
+                B::R::one(),
+            ));
+            cursor.step_val();
+        }
+        cursor.step_key();
+    }
+    OrdIndexedZSet::from_tuples((), tuples)
+}
+
+impl<B> Stream<RootCircuit, B> {
+    /// As-of join of two partitioned time series.
+    ///
+    /// Matches each record `(ts, v1)` in `self` with the latest record
+    /// `(ts2, v2)` in the same partition of `other` such that `ts2 <= ts`.
+    /// Records of `other` with the same timestamp are ordered by value, so
+    /// the largest one is considered the latest.  Each `(timestamp, value)`
+    /// pair with a positive weight in `other` counts as one record.
+    ///
+    /// For each record in `self`, outputs `(ts, (v1, Some(v2)))`, or
+    /// `(ts, (v1, None))` if there are no matching records in `other`, with
+    /// the weight of the record in `self`.  Use `filter` on the output to
+    /// obtain inner join semantics.
+    ///
+    /// This operator is incremental: an update to `self` only affects the
+    /// output for its own timestamp.  An update to `other` with timestamp `ts`
+    /// affects records in `self` with timestamps from `ts` up to the next
+    /// timestamp in `other`, whose outputs are retracted and re-emitted with
+    /// the new match.  This includes late updates with timestamps below
+    /// previously received ones.
+    ///
+    /// # Example
+    ///
+    /// Enrich each trade with the most recent quote for the same symbol:
+    ///
+    /// ```text
+    /// trades: symbol => (time, price)
+    /// quotes: symbol => (time, quote)
+    /// trades.asof_join(&quotes): symbol => (time, (price, Option<quote>))
+    /// ```
+    pub fn asof_join<TS, V1, V2, B2>(
+        &self,
+        other: &Stream<RootCircuit, B2>,
+    ) -> OrdAsofJoinStream<B::Key, TS, V1, V2, B::R>
+    where
+        B: PartitionedIndexedZSet<TS, V1>,
+        B::R: ZRingValue,
+        B2: PartitionedIndexedZSet<TS, V2, Key = B::Key, R = B::R>,
+        TS: DBData,
+        V1: DBData,
+        V2: DBData,
+    {
+        // ```
+        //         ┌───┐            affected                                    output
+        //  self   │map├──┐  ┌────┐ times   ┌─────────────┐  ┌──────────────────────────────►
+        // ──┬────►└───┘  └─►│plus├────────►│             │  │
+        //   │     ┌───┐  ┌─►└────┘         │             │  │  ┌──────────────────┐ output_trace
+        //   │  ┌─►│map├──┘                 │             ├──┴─►│UntimedTraceAppend├──────┐
+        //   │  │  └───┘    ┌───────────────┤  AsofJoin   │     └──────────────────┘      │
+        //   └──┼──────────►│integrate_trace│             │              ▲                │
+        // other│           └───────────────┤             │            ┌─┴──┐             │
+        // ─────┤  ┌───┐    ┌───────────────┤             │◄───────────┤Z^-1│◄────────────┘
+        //      └─►│map├───►│integrate_trace│             │            └────┘
+        //         └───┘    └───────────────┴─────────────┘
+        // ```
+        self.circuit().region("asof_join", || {
+            let circuit = self.circuit();
+            let left = self.shard();
+            let right = other.shard();
+
+            let left_trace = left.integrate_trace();
+
+            // Right records of each partition in reverse order, used to find
+            // the latest record preceding a given timestamp.
+            let right_trace = right
+                .apply_named("ReversePartitions", |batch: &B2| {
+                    map_batch::<_, OrdIndexedZSet<B::Key, Descending<(TS, V2)>, B::R>, _>(
+                        batch,
+                        |pk, (ts, v)| (pk.clone(), Descending((ts.clone(), v.clone()))),
+                    )
+                })
+                .mark_sharded()
+                .integrate_trace();
+
+            let affected = left
+                .apply_named("LeftTimes", |batch: &B| {
+                    affected_times::<_, TS, V1>(batch, false)
+                })
+                .plus(&right.apply_named("RightTimes", |batch: &B2| {
+                    affected_times::<_, TS, V2>(batch, true)
+                }))
+                .mark_sharded();
+
+            let (output_trace_delayed, z1feedback) =
+                circuit.add_feedback(<Z1Trace<
+                    Spine<OrdPartitionedIndexedZSet<B::Key, TS, (V1, Option<V2>), B::R>>,
+                >>::new(false, self.circuit().root_scope()));
+            output_trace_delayed.mark_sharded();
+
+            let output = circuit
+                .add_quaternary_operator(
+                    <AsofJoin<TS, V1, V2>>::new(),
+                    &affected,
+                    &left_trace,
+                    &right_trace,
+                    &output_trace_delayed,
+                )
+                .mark_sharded();
+
+            let output_trace = circuit
+                .add_binary_operator_with_preference(
+                    <UntimedTraceAppend<Spine<_>>>::new(),
+                    (
+                        &output_trace_delayed,
+                        OwnershipPreference::STRONGLY_PREFER_OWNED,
+                    ),
+                    (&output, OwnershipPreference::PREFER_OWNED),
+                )
+                .mark_sharded();
+
+            z1feedback
+                .connect_with_preference(&output_trace, OwnershipPreference::STRONGLY_PREFER_OWNED);
+
+            circuit.cache_insert(
+                DelayedTraceId::new(output_trace.origin_node_id().clone()),
+                output_trace_delayed,
+            );
+            circuit.cache_insert(
+                IntegrateTraceId::new(output.origin_node_id().clone()),
+                output_trace,
+            );
+
+            output
+        })
+    }
+}
+
+/// Quaternary operator that implements the internals of `asof_join`.
+///
+/// * Input stream 1: timestamps updated in the left and right inputs,
+///   labeled with `false` and `true` respectively.
+/// * Input stream 2: trace of the left time series.
+/// * Input stream 3: trace of the right time series with records in each
+///   partition in reverse order.
+/// * Input stream 4: trace of previously produced outputs.  Used to compute
+///   retractions.
+struct AsofJoin<TS, V1, V2> {
+    phantom: PhantomData<(TS, V1, V2)>,
+}
+
+impl<TS, V1, V2> AsofJoin<TS, V1, V2>
+where
+    TS: DBData,
+    V1: DBData,
+    V2: DBData,
+{
+    fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the latest record in the current partition of `right_cursor`
+    /// with timestamp `<= ts`.
+    fn latest_match<'s, K, R, C>(right_cursor: &mut C, ts: &TS) -> Option<(TS, V2)>
+    where
+        R: ZRingValue,
+        C: Cursor<'s, K, Descending<(TS, V2)>, (), R>,
+    {
+        right_cursor.rewind_vals();
+        right_cursor.seek_val_with(|Descending((rts, _))| rts <= ts);
+        while right_cursor.val_valid() {
+            if is_positive(&right_cursor.weight()) {
+                return Some(right_cursor.val().0.clone());
+            }
+            right_cursor.step_val();
+        }
+        None
+    }
+
+    /// Computes ranges of timestamps whose outputs may be affected by the
+    /// updates in the current partition of `delta_cursor`.
+    ///
+    /// An update to the left input at time `ts` affects records with timestamp
+    /// `ts`.  An update to the right input at time `ts` affects left records
+    /// with timestamps starting from `ts` up to, but not including, the first
+    /// left timestamp that matches a later right record.  Returns a list of
+    /// non-overlapping half-open ranges ordered by start time.  `None` as the
+    /// end of a range means that the range extends to the end of the
+    /// partition.
+    fn affected_ranges<'a, 'b, 'c, K, R, C1, C2, C3>(
+        &self,
+        delta_cursor: &mut C1,
+        left_cursor: &mut C2,
+        right_cursor: &mut C3,
+        right_found: bool,
+    ) -> Vec<(TS, Option<TS>)>
+    where
+        K: Eq,
+        R: ZRingValue,
+        C1: Cursor<'a, K, (TS, bool), (), R>,
+        C2: Cursor<'b, K, (TS, V1), (), R>,
+        C3: Cursor<'c, K, Descending<(TS, V2)>, (), R>,
+    {
+        let mut ranges: Vec<(TS, Option<TS>)> = Vec::new();
+
+        left_cursor.seek_key(delta_cursor.key());
+        let left_found = left_cursor.key_valid() && left_cursor.key() == delta_cursor.key();
+
+        while delta_cursor.val_valid() {
+            let (from, from_right) = delta_cursor.val();
+
+            let to = if left_found {
+                // Skip left records with timestamp `from`, which are always
+                // affected.
+                left_cursor.rewind_vals();
+                left_cursor.seek_val_with(|(ts, _)| ts > from);
+
+                if *from_right && right_found {
+                    // Find the first left timestamp whose match is later than
+                    // `from`.
+                    let mut to = None;
+                    while left_cursor.val_valid() {
+                        let ts = left_cursor.val().0.clone();
+                        if matches!(Self::latest_match(right_cursor, &ts), Some((rts, _)) if &rts > from)
+                        {
+                            to = Some(ts);
+                            break;
+                        }
+                        left_cursor.seek_val_with(|(t, _)| t > &ts);
+                    }
+                    to
+                } else if *from_right {
+                    None
+                } else {
+                    left_cursor.val_valid().then(|| left_cursor.val().0.clone())
+                }
+            } else {
+                // The partition is empty: all old outputs must be retracted.
+                None
+            };
+
+            match ranges.last_mut() {
+                Some((_, last_to)) if before(from, last_to) => {
+                    *last_to = match (last_to.take(), to) {
+                        (Some(to1), Some(to2)) => Some(max(to1, to2)),
+                        _ => None,
+                    };
+                }
+                _ => ranges.push((from.clone(), to)),
+            }
+
+            delta_cursor.step_val();
+        }
+
+        ranges
+    }
+}
+
+/// Checks whether `ts` is before the end `to` of a half-open range.
+fn before<TS: Ord>(ts: &TS, to: &Option<TS>) -> bool {
+    to.as_ref().map_or(true, |to| ts < to)
+}
+
+impl<TS, V1, V2> Operator for AsofJoin<TS, V1, V2>
+where
+    TS: 'static,
+    V1: 'static,
+    V2: 'static,
+{
+    fn name(&self) -> Cow<'static, str> {
+        Cow::from("AsofJoin")
+    }
+
+    fn fixedpoint(&self, _scope: Scope) -> bool {
+        true
+    }
+}
+
+impl<TS, V1, V2, A, LT, RT, OT, O> QuaternaryOperator<A, LT, RT, OT, O> for AsofJoin<TS, V1, V2>
+where
+    TS: DBData,
+    V1: DBData,
+    V2: DBData,
+    A: PartitionedBatchReader<TS, bool> + Clone,
+    A::R: ZRingValue,
+    LT: PartitionedBatchReader<TS, V1, Key = A::Key, R = A::R> + Clone,
+    RT: BatchReader<Key = A::Key, Val = Descending<(TS, V2)>, Time = (), R = A::R> + Clone,
+    OT: PartitionedBatchReader<TS, (V1, Option<V2>), Key = A::Key, R = A::R> + Clone,
+    O: IndexedZSet<Key = A::Key, Val = (TS, (V1, Option<V2>)), R = A::R>,
+{
+    fn eval<'a>(
+        &mut self,
+        affected: Cow<'a, A>,
+        left_trace: Cow<'a, LT>,
+        right_trace: Cow<'a, RT>,
+        output_trace: Cow<'a, OT>,
+    ) -> O {
+        let mut delta_cursor = affected.cursor();
+        let mut range_cursor = left_trace.cursor();
+        let mut left_trace_cursor = left_trace.cursor();
+        let mut right_trace_cursor = right_trace.cursor();
+        let mut output_trace_cursor = output_trace.cursor();
+
+        let mut tuples = Vec::with_capacity(affected.len());
+
+        // Iterate over affected partitions.
+        while delta_cursor.key_valid() {
+            let key = delta_cursor.key().clone();
+
+            right_trace_cursor.seek_key(&key);
+            let right_found = right_trace_cursor.key_valid() && right_trace_cursor.key() == &key;
+
+            let ranges = self.affected_ranges(
+                &mut delta_cursor,
+                &mut range_cursor,
+                &mut right_trace_cursor,
+                right_found,
+            );
+
+            // Retract old outputs.
+            output_trace_cursor.seek_key(&key);
+            if output_trace_cursor.key_valid() && output_trace_cursor.key() == &key {
+                for (from, to) in ranges.iter() {
+                    output_trace_cursor.seek_val_with(|(ts, _)| ts >= from);
+                    while output_trace_cursor.val_valid()
+                        && before(&output_trace_cursor.val().0, to)
+                    {
+                        let weight = output_trace_cursor.weight();
+                        if !weight.is_zero() {
+                            tuples.push((
+                                O::item_from(key.clone(), output_trace_cursor.val().clone()),
+                                weight.neg(),
+                            ));
+                        }
+                        output_trace_cursor.step_val();
+                    }
+                }
+            }
+
+            // Compute new outputs.
+            left_trace_cursor.seek_key(&key);
+            if left_trace_cursor.key_valid() && left_trace_cursor.key() == &key {
+                // Match for the last left timestamp we've seen.
+                let mut current: Option<(TS, Option<V2>)> = None;
+
+                for (from, to) in ranges.iter() {
+                    left_trace_cursor.seek_val_with(|(ts, _)| ts >= from);
+                    while left_trace_cursor.val_valid() && before(&left_trace_cursor.val().0, to) {
+                        let weight = left_trace_cursor.weight();
+                        if !weight.is_zero() {
+                            let (ts, v) = left_trace_cursor.val();
+
+                            if current.as_ref().map_or(true, |(cts, _)| cts != ts) {
+                                let matched = if right_found {
+                                    Self::latest_match(&mut right_trace_cursor, ts)
+                                        .map(|(_, v2)| v2)
+                                } else {
+                                    None
+                                };
+                                current = Some((ts.clone(), matched));
+                            }
+
+                            let matched = current.as_ref().unwrap().1.clone();
+                            tuples.push((
+                                O::item_from(key.clone(), (ts.clone(), (v.clone(), matched))),
+                                weight,
+                            ));
+                        }
+                        left_trace_cursor.step_val();
+                    }
+                }
+            }
+
+            delta_cursor.step_key();
+        }
+
+        O::from_tuples((), tuples)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{indexed_zset, Circuit, OrdIndexedZSet, RootCircuit};
+
+    type AsofBatch = OrdIndexedZSet<u64, (u64, (i64, Option<i64>)), isize>;
+
+    #[test]
+    fn test_asof_join() {
+        let (mut circuit, (trades_handle, quotes_handle, output_handle, delta_handle)) =
+            RootCircuit::build(move |circuit| {
+                let (trades, trades_handle) =
+                    circuit.add_input_indexed_zset::<u64, (u64, i64), isize>();
+                let (quotes, quotes_handle) =
+                    circuit.add_input_indexed_zset::<u64, (u64, i64), isize>();
+
+                let output = trades.asof_join::<u64, i64, i64, _>(&quotes);
+                let output_handle = output.integrate().output();
+                let delta_handle = output.output();
+
+                (trades_handle, quotes_handle, output_handle, delta_handle)
+            })
+            .unwrap();
+
+        quotes_handle.append(&mut vec![(1, ((10, 100), 1)), (1, ((20, 200), 1))]);
+        trades_handle.append(&mut vec![
+            (1, ((5, 1), 1)),
+            (1, ((15, 2), 1)),
+            (1, ((25, 3), 1)),
+            (2, ((10, 4), 1)),
+        ]);
+        circuit.step().unwrap();
+
+        let expected: AsofBatch = indexed_zset! {
+            1 => {(5, (1, None)) => 1, (15, (2, Some(100))) => 1, (25, (3, Some(200))) => 1},
+            2 => {(10, (4, None)) => 1}
+        };
+        assert_eq!(output_handle.consolidate(), expected);
+
+        // A late quote changes the match of the trades that follow it, up to
+        // the next quote.
+        quotes_handle.append(&mut vec![(1, ((12, 120), 1))]);
+        circuit.step().unwrap();
+
+        let expected: AsofBatch = indexed_zset! {
+            1 => {(15, (2, Some(100))) => -1, (15, (2, Some(120))) => 1}
+        };
+        assert_eq!(delta_handle.consolidate(), expected);
+
+        // Delete a quote, add a trade and a quote in another partition.
+        quotes_handle.append(&mut vec![(1, ((20, 200), -1)), (2, ((5, 50), 1))]);
+        trades_handle.append(&mut vec![(1, ((30, 5), 1))]);
+        circuit.step().unwrap();
+
+        let expected: AsofBatch = indexed_zset! {
+            1 => {(25, (3, Some(200))) => -1, (25, (3, Some(120))) => 1, (30, (5, Some(120))) => 1},
+            2 => {(10, (4, None)) => -1, (10, (4, Some(50))) => 1}
+        };
+        assert_eq!(delta_handle.consolidate(), expected);
+
+        let expected: AsofBatch = indexed_zset! {
+            1 => {(5, (1, None)) => 1, (15, (2, Some(120))) => 1, (25, (3, Some(120))) => 1, (30, (5, Some(120))) => 1},
+            2 => {(10, (4, Some(50))) => 1}
+        };
+        assert_eq!(output_handle.consolidate(), expected);
+
+        // Deleting a trade retracts its output.
+        trades_handle.append(&mut vec![(1, ((15, 2), -1))]);
+        circuit.step().unwrap();
+
+        let expected: AsofBatch = indexed_zset! {
+            1 => {(15, (2, Some(120))) => -1}
+        };
+        assert_eq!(delta_handle.consolidate(), expected);
+    }
+}
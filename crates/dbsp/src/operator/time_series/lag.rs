@@ -0,0 +1,510 @@
+//! Operators that pair each record of a time series with its neighbors.
+
+use crate::{
+    algebra::{HasZero, IndexedZSet, ZRingValue},
+    circuit::{
+        operator_traits::{Operator, QuaternaryOperator},
+        OwnershipPreference, Scope,
+    },
+    operator::{
+        time_series::{OrdPartitionedIndexedZSet, PartitionedBatchReader, PartitionedIndexedZSet},
+        trace::{DelayedTraceId, IntegrateTraceId, UntimedTraceAppend, Z1Trace},
+    },
+    trace::{Batch, BatchReader, Cursor, Spine},
+    Circuit, DBData, OrdIndexedZSet, RootCircuit, Stream,
+};
+use bincode::{Decode, Encode};
+use size_of::SizeOf;
+use std::{
+    borrow::Cow,
+    cmp::{max, Ordering},
+    collections::VecDeque,
+    marker::PhantomData,
+    ops::Neg,
+};
+
+pub type OrdPartitionedLagStream<PK, TS, V, R> =
+    Stream<RootCircuit, OrdPartitionedIndexedZSet<PK, TS, (V, Option<V>), R>>;
+
+/// Wrapper type that reverses the ordering of `T`.
+///
+/// Used to scan a time series backward using cursors that only move forward.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SizeOf, Encode, Decode)]
+pub(super) struct Descending<T>(pub(super) T);
+
+impl<T: Ord> Ord for Descending<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.cmp(&self.0)
+    }
+}
+
+impl<T: Ord> PartialOrd for Descending<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Applies `map_func` to each key/value pair in `batch`.
+pub(super) fn map_batch<B, O, F>(batch: &B, map_func: F) -> O
+where
+    B: BatchReader<Time = ()>,
+    O: Batch<Time = (), R = B::R>,
+    F: Fn(&B::Key, &B::Val) -> (O::Key, O::Val),
+{
+    let mut tuples = Vec::with_capacity(batch.len());
+    let mut cursor = batch.cursor();
+    while cursor.key_valid() {
+        while cursor.val_valid() {
+            let (k, v) = map_func(cursor.key(), cursor.val());
+            tuples.push((O::item_from(k, v), cursor.weight()));
+            cursor.step_val();
+        }
+        cursor.step_key();
+    }
+    O::from_tuples((), tuples)
+}
+
+impl<B> Stream<RootCircuit, B> {
+    /// Pair each record of a partitioned time series with the value of the
+    /// record `n` positions before it in the same partition.
+    ///
+    /// Records within a partition are ordered by timestamp and, for records
+    /// with the same timestamp, by value.  Each `(timestamp, value)` pair
+    /// with a positive weight counts as one record.  For each record, outputs
+    /// `(timestamp, (value, lag))`, where `lag` is `None` if there are fewer
+    /// than `n` preceding records in the partition.  Output records have the
+    /// same weights as the corresponding input records.
+    ///
+    /// This operator is incremental: inserting or deleting a record only
+    /// updates the outputs of the record itself and of the `n` records that
+    /// follow it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn partitioned_lag<TS, V>(&self, n: usize) -> OrdPartitionedLagStream<B::Key, TS, V, B::R>
+    where
+        B: PartitionedIndexedZSet<TS, V>,
+        B::R: ZRingValue,
+        TS: DBData,
+        V: DBData,
+    {
+        assert!(n > 0, "partitioned_lag: offset must be positive");
+
+        // ```
+        //                  ┌───────────────┐   input_trace
+        //      ┌──────────►│integrate_trace├─────────────────┐                    output
+        //      │           └───────────────┘                 │                ┌──────────────────────────────────────►
+        //      │                                             ▼                │
+        // self │    ┌───┐  ┌───────────────┐ reversed ┌────────────────────┐  │  ┌──────────────────┐ output_trace
+        // ─────┼───►│map├─►│integrate_trace├─────────►│ PartitionedLag     ├──┴──┤UntimedTraceAppend├────────┐
+        //      │    └───┘  └───────────────┘  trace   └────────────────────┘     └──────────────────┘        │
+        //      │                                        ▲            ▲               ▲                       │
+        //      └────────────────────────────────────────┘            │             ┌─┴──┐                    │
+        //                                                            └─────────────┤Z^-1│◄───────────────────┘
+        //                                                       output_trace_delayed └────┘
+        // ```
+        self.circuit().region("partitioned_lag", || {
+            let circuit = self.circuit();
+            let stream = self.shard();
+
+            let input_trace = stream.integrate_trace();
+
+            // The same trace, with records of each partition in reverse order,
+            // used to find records preceding a given timestamp.
+            let reversed_trace = stream
+                .apply_named("ReversePartitions", |batch: &B| {
+                    map_batch::<_, OrdIndexedZSet<B::Key, Descending<(TS, V)>, B::R>, _>(
+                        batch,
+                        |pk, (ts, v)| (pk.clone(), Descending((ts.clone(), v.clone()))),
+                    )
+                })
+                .mark_sharded()
+                .integrate_trace();
+
+            let (output_trace_delayed, z1feedback) =
+                circuit.add_feedback(<Z1Trace<
+                    Spine<OrdPartitionedIndexedZSet<B::Key, TS, (V, Option<V>), B::R>>,
+                >>::new(false, self.circuit().root_scope()));
+            output_trace_delayed.mark_sharded();
+
+            let output = circuit
+                .add_quaternary_operator(
+                    <PartitionedLag<TS, V>>::new(n),
+                    &stream,
+                    &input_trace,
+                    &reversed_trace,
+                    &output_trace_delayed,
+                )
+                .mark_sharded();
+
+            let output_trace = circuit
+                .add_binary_operator_with_preference(
+                    <UntimedTraceAppend<Spine<_>>>::new(),
+                    (
+                        &output_trace_delayed,
+                        OwnershipPreference::STRONGLY_PREFER_OWNED,
+                    ),
+                    (&output, OwnershipPreference::PREFER_OWNED),
+                )
+                .mark_sharded();
+
+            z1feedback
+                .connect_with_preference(&output_trace, OwnershipPreference::STRONGLY_PREFER_OWNED);
+
+            circuit.cache_insert(
+                DelayedTraceId::new(output_trace.origin_node_id().clone()),
+                output_trace_delayed,
+            );
+            circuit.cache_insert(
+                IntegrateTraceId::new(output.origin_node_id().clone()),
+                output_trace,
+            );
+
+            output
+        })
+    }
+
+    /// Pair each record of a partitioned time series with the value of the
+    /// record `n` positions after it in the same partition.
+    ///
+    /// This is the mirror image of [`Self::partitioned_lag`]: `lead` is
+    /// `None` if there are fewer than `n` following records in the partition.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn partitioned_lead<TS, V>(&self, n: usize) -> OrdPartitionedLagStream<B::Key, TS, V, B::R>
+    where
+        B: PartitionedIndexedZSet<TS, V>,
+        B::R: ZRingValue,
+        TS: DBData,
+        V: DBData,
+    {
+        // `lead` is `lag` over the time series in reverse order.
+        let reversed =
+            self.apply_named("ReverseTimeSeries", |batch: &B| {
+                map_batch::<
+                    _,
+                    OrdPartitionedIndexedZSet<B::Key, Descending<TS>, Descending<V>, B::R>,
+                    _,
+                >(batch, |pk, (ts, v)| {
+                    (pk.clone(), (Descending(ts.clone()), Descending(v.clone())))
+                })
+            });
+        reversed.mark_sharded_if(self);
+
+        let output = reversed
+            .partitioned_lag::<Descending<TS>, Descending<V>>(n)
+            .apply_named("RestoreTimeSeries", |batch| {
+                map_batch(batch, |pk, (ts, (v, lead))| {
+                    (
+                        pk.clone(),
+                        (
+                            ts.0.clone(),
+                            (v.0.clone(), lead.as_ref().map(|lead| lead.0.clone())),
+                        ),
+                    )
+                })
+            });
+        output.mark_sharded();
+        output
+    }
+}
+
+/// Quaternary operator that implements the internals of `partitioned_lag`.
+///
+/// * Input stream 1: updates to the time series.  Used to identify affected
+///   partitions and times.
+/// * Input stream 2: trace containing the accumulated time series data.
+/// * Input stream 3: the same trace with records in each partition in reverse
+///   order.
+/// * Input stream 4: trace of previously produced outputs.  Used to compute
+///   retractions.
+struct PartitionedLag<TS, V> {
+    n: usize,
+    phantom: PhantomData<(TS, V)>,
+}
+
+impl<TS, V> PartitionedLag<TS, V>
+where
+    TS: DBData,
+    V: DBData,
+{
+    fn new(n: usize) -> Self {
+        Self {
+            n,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Computes ranges of timestamps whose outputs may be affected by the
+    /// updates in the current partition of `delta_cursor`.
+    ///
+    /// A record inserted or deleted at time `ts` affects all records with
+    /// timestamp `ts` and up to `n` records with larger timestamps.  Returns
+    /// a list of non-overlapping inclusive ranges ordered by start time.
+    /// `None` as the end of a range means that the range extends to the end
+    /// of the partition.
+    fn affected_ranges<'a, 'b, K, R, C1, C2>(
+        &self,
+        delta_cursor: &mut C1,
+        trace_cursor: &mut C2,
+    ) -> Vec<(TS, Option<TS>)>
+    where
+        K: Eq,
+        R: ZRingValue,
+        C1: Cursor<'a, K, (TS, V), (), R>,
+        C2: Cursor<'b, K, (TS, V), (), R>,
+    {
+        let mut ranges: Vec<(TS, Option<TS>)> = Vec::new();
+
+        trace_cursor.seek_key(delta_cursor.key());
+        let partition_found = trace_cursor.key_valid() && trace_cursor.key() == delta_cursor.key();
+
+        while delta_cursor.val_valid() {
+            let from = delta_cursor.val().0.clone();
+
+            let to = if partition_found {
+                // Find the `n`th record following `from`.
+                trace_cursor.rewind_vals();
+                trace_cursor.seek_val_with(|(ts, _)| ts > &from);
+
+                let mut count = 0;
+                let mut to = None;
+                while trace_cursor.val_valid() {
+                    if is_positive(&trace_cursor.weight()) {
+                        count += 1;
+                        if count == self.n {
+                            to = Some(trace_cursor.val().0.clone());
+                            break;
+                        }
+                    }
+                    trace_cursor.step_val();
+                }
+                to
+            } else {
+                Some(from.clone())
+            };
+
+            match ranges.last_mut() {
+                Some((_, last_to)) if in_range(&from, last_to) => {
+                    *last_to = match (last_to.take(), to) {
+                        (Some(to1), Some(to2)) => Some(max(to1, to2)),
+                        _ => None,
+                    };
+                }
+                _ => ranges.push((from, to)),
+            }
+
+            delta_cursor.step_val();
+        }
+
+        ranges
+    }
+}
+
+/// Checks whether `ts` is within a range with upper bound `to`.
+fn in_range<TS: Ord>(ts: &TS, to: &Option<TS>) -> bool {
+    to.as_ref().map_or(true, |to| ts <= to)
+}
+
+pub(super) fn is_positive<R: ZRingValue>(weight: &R) -> bool {
+    weight.ge0() && !weight.is_zero()
+}
+
+impl<TS, V> Operator for PartitionedLag<TS, V>
+where
+    TS: 'static,
+    V: 'static,
+{
+    fn name(&self) -> Cow<'static, str> {
+        Cow::from("PartitionedLag")
+    }
+
+    fn fixedpoint(&self, _scope: Scope) -> bool {
+        true
+    }
+}
+
+impl<TS, V, B, T, RT, OT, O> QuaternaryOperator<B, T, RT, OT, O> for PartitionedLag<TS, V>
+where
+    TS: DBData,
+    V: DBData,
+    B: PartitionedBatchReader<TS, V> + Clone,
+    B::R: ZRingValue,
+    T: PartitionedBatchReader<TS, V, Key = B::Key, R = B::R> + Clone,
+    RT: BatchReader<Key = B::Key, Val = Descending<(TS, V)>, Time = (), R = B::R> + Clone,
+    OT: PartitionedBatchReader<TS, (V, Option<V>), Key = B::Key, R = B::R> + Clone,
+    O: IndexedZSet<Key = B::Key, Val = (TS, (V, Option<V>)), R = B::R>,
+{
+    fn eval<'a>(
+        &mut self,
+        input_delta: Cow<'a, B>,
+        input_trace: Cow<'a, T>,
+        reversed_trace: Cow<'a, RT>,
+        output_trace: Cow<'a, OT>,
+    ) -> O {
+        let mut delta_cursor = input_delta.cursor();
+        let mut range_cursor = input_trace.cursor();
+        let mut input_trace_cursor = input_trace.cursor();
+        let mut reversed_trace_cursor = reversed_trace.cursor();
+        let mut output_trace_cursor = output_trace.cursor();
+
+        let mut tuples = Vec::with_capacity(input_delta.len());
+
+        // Iterate over affected partitions.
+        while delta_cursor.key_valid() {
+            let key = delta_cursor.key().clone();
+            let ranges = self.affected_ranges(&mut delta_cursor, &mut range_cursor);
+
+            // Retract old outputs.
+            output_trace_cursor.seek_key(&key);
+            if output_trace_cursor.key_valid() && output_trace_cursor.key() == &key {
+                for (from, to) in ranges.iter() {
+                    output_trace_cursor.seek_val_with(|(ts, _)| ts >= from);
+                    while output_trace_cursor.val_valid()
+                        && in_range(&output_trace_cursor.val().0, to)
+                    {
+                        let weight = output_trace_cursor.weight();
+                        if !weight.is_zero() {
+                            tuples.push((
+                                O::item_from(key.clone(), output_trace_cursor.val().clone()),
+                                weight.neg(),
+                            ));
+                        }
+                        output_trace_cursor.step_val();
+                    }
+                }
+            }
+
+            // Compute new outputs.
+            input_trace_cursor.seek_key(&key);
+            reversed_trace_cursor.seek_key(&key);
+            if input_trace_cursor.key_valid() && input_trace_cursor.key() == &key {
+                debug_assert!(reversed_trace_cursor.key_valid());
+                debug_assert!(reversed_trace_cursor.key() == &key);
+
+                for (from, to) in ranges.iter() {
+                    // Values of up to `n` records preceding the range, oldest
+                    // first.
+                    let mut window = VecDeque::with_capacity(self.n + 1);
+
+                    reversed_trace_cursor.rewind_vals();
+                    reversed_trace_cursor.seek_val_with(|Descending((ts, _))| ts < from);
+                    while reversed_trace_cursor.val_valid() && window.len() < self.n {
+                        if is_positive(&reversed_trace_cursor.weight()) {
+                            window.push_front(reversed_trace_cursor.val().0 .1.clone());
+                        }
+                        reversed_trace_cursor.step_val();
+                    }
+
+                    input_trace_cursor.seek_val_with(|(ts, _)| ts >= from);
+                    while input_trace_cursor.val_valid()
+                        && in_range(&input_trace_cursor.val().0, to)
+                    {
+                        let weight = input_trace_cursor.weight();
+                        if is_positive(&weight) {
+                            let (ts, v) = input_trace_cursor.val();
+                            let lag = if window.len() == self.n {
+                                window.front().cloned()
+                            } else {
+                                None
+                            };
+                            tuples.push((
+                                O::item_from(key.clone(), (ts.clone(), (v.clone(), lag))),
+                                weight,
+                            ));
+
+                            window.push_back(v.clone());
+                            if window.len() > self.n {
+                                window.pop_front();
+                            }
+                        }
+                        input_trace_cursor.step_val();
+                    }
+                }
+            }
+
+            delta_cursor.step_key();
+        }
+
+        O::from_tuples((), tuples)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{indexed_zset, Circuit, OrdIndexedZSet, RootCircuit};
+
+    type LagBatch = OrdIndexedZSet<u64, (u64, (i64, Option<i64>)), isize>;
+
+    #[test]
+    fn test_lag_lead() {
+        let (mut circuit, (input_handle, lag_handle, lag_delta_handle, lead_handle)) =
+            RootCircuit::build(move |circuit| {
+                let (input, input_handle) =
+                    circuit.add_input_indexed_zset::<u64, (u64, i64), isize>();
+
+                let lag = input.partitioned_lag::<u64, i64>(1);
+                let lag_handle = lag.integrate().output();
+                let lag_delta_handle = lag.output();
+                let lead_handle = input.partitioned_lead::<u64, i64>(2).integrate().output();
+
+                (input_handle, lag_handle, lag_delta_handle, lead_handle)
+            })
+            .unwrap();
+
+        input_handle.append(&mut vec![
+            (1, ((10, 100), 1)),
+            (1, ((20, 200), 1)),
+            (1, ((40, 400), 1)),
+            (1, ((50, 500), 1)),
+            (2, ((10, -1), 1)),
+        ]);
+        circuit.step().unwrap();
+
+        let expected: LagBatch = indexed_zset! {
+            1 => {(10, (100, None)) => 1, (20, (200, Some(100))) => 1, (40, (400, Some(200))) => 1, (50, (500, Some(400))) => 1},
+            2 => {(10, (-1, None)) => 1}
+        };
+        assert_eq!(lag_handle.consolidate(), expected);
+
+        let expected: LagBatch = indexed_zset! {
+            1 => {(10, (100, Some(400))) => 1, (20, (200, Some(500))) => 1, (40, (400, None)) => 1, (50, (500, None)) => 1},
+            2 => {(10, (-1, None)) => 1}
+        };
+        assert_eq!(lead_handle.consolidate(), expected);
+
+        // Insert a record in the middle of a partition: only the new record and
+        // its successor are updated.
+        input_handle.append(&mut vec![(1, ((30, 300), 1))]);
+        circuit.step().unwrap();
+
+        let expected: LagBatch = indexed_zset! {
+            1 => {(30, (300, Some(200))) => 1, (40, (400, Some(200))) => -1, (40, (400, Some(300))) => 1}
+        };
+        assert_eq!(lag_delta_handle.consolidate(), expected);
+
+        let expected: LagBatch = indexed_zset! {
+            1 => {(10, (100, Some(300))) => 1, (20, (200, Some(400))) => 1, (30, (300, Some(500))) => 1, (40, (400, None)) => 1, (50, (500, None)) => 1},
+            2 => {(10, (-1, None)) => 1}
+        };
+        assert_eq!(lead_handle.consolidate(), expected);
+
+        // Delete the first record of the partition.
+        input_handle.append(&mut vec![(1, ((10, 100), -1))]);
+        circuit.step().unwrap();
+
+        let expected: LagBatch = indexed_zset! {
+            1 => {(10, (100, None)) => -1, (20, (200, Some(100))) => -1, (20, (200, None)) => 1}
+        };
+        assert_eq!(lag_delta_handle.consolidate(), expected);
+
+        let expected: LagBatch = indexed_zset! {
+            1 => {(20, (200, None)) => 1, (30, (300, Some(200))) => 1, (40, (400, Some(300))) => 1, (50, (500, Some(400))) => 1},
+            2 => {(10, (-1, None)) => 1}
+        };
+        assert_eq!(lag_handle.consolidate(), expected);
+    }
+}
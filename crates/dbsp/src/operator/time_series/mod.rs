@@ -1,12 +1,20 @@
+mod asof_join;
+mod lag;
 mod partitioned;
 mod radix_tree;
 mod range;
 mod rolling_aggregate;
+mod session;
 mod watermark;
 mod window;
+mod window_aggregate;
 
+pub use asof_join::OrdAsofJoinStream;
+pub use lag::OrdPartitionedLagStream;
 pub use partitioned::{
     OrdPartitionedIndexedZSet, PartitionCursor, PartitionedBatch, PartitionedBatchReader,
     PartitionedIndexedZSet,
 };
 pub use range::{Range, RelOffset, RelRange};
+pub use session::{OrdSessionAggregateStream, OrdSessionStream, Session};
+pub use window_aggregate::{OrdWindowAggregateStream, WindowEmitMode};
@@ -463,7 +463,7 @@ mod test {
                 range::{Range, RelOffset, RelRange},
                 PartitionCursor,
             },
-            Fold,
+            ApproxPercentile, Fold, Percentile,
         },
         trace::{Batch, BatchReader, Cursor},
         CollectionHandle, DBSPHandle, OrdIndexedZSet, RootCircuit, Runtime, Stream,
@@ -671,6 +671,75 @@ mod test {
         circuit.kill().unwrap();
     }
 
+    // Latency percentiles per endpoint over a sliding window: the approximate
+    // percentile must be within the relative accuracy of the exact one.
+    #[test]
+    fn test_rolling_percentiles() {
+        let (mut circuit, mut input) = Runtime::init_circuit(4, |circuit| {
+            let (input_stream, input_handle) =
+                circuit.add_input_indexed_zset::<u64, (u64, i64), isize>();
+
+            let range_spec = RelRange::new(RelOffset::Before(300), RelOffset::Before(0));
+            let exact = input_stream
+                .partitioned_rolling_aggregate::<u64, i64, _>(
+                    Percentile::new(0.99),
+                    range_spec.clone(),
+                )
+                .gather(0)
+                .integrate();
+            let approx = input_stream
+                .partitioned_rolling_aggregate::<u64, i64, _>(
+                    ApproxPercentile::new(0.99, 0.01),
+                    range_spec,
+                )
+                .gather(0)
+                .integrate();
+
+            exact.apply2(&approx, |exact, approx| {
+                assert_eq!(exact.len(), approx.len());
+
+                let mut exact = exact.cursor();
+                let mut approx = approx.cursor();
+
+                while exact.key_valid() {
+                    assert_eq!(exact.key(), approx.key());
+                    while exact.val_valid() {
+                        let (ts, expected) = exact.val();
+                        let (approx_ts, actual) = approx.val();
+                        assert_eq!(ts, approx_ts);
+
+                        let expected = expected.unwrap() as f64;
+                        let actual = actual.unwrap().into_inner();
+                        assert!((actual - expected).abs() <= 0.01 * expected.abs());
+
+                        exact.step_val();
+                        approx.step_val();
+                    }
+                    exact.step_key();
+                    approx.step_key();
+                }
+            });
+
+            input_handle
+        })
+        .unwrap();
+
+        for step in 0..20u64 {
+            let mut latencies: Vec<_> = (0..50u64)
+                .map(|i| {
+                    let endpoint = i % 3;
+                    let ts = step * 60 + i;
+                    let latency = ((i * 7919 + step * 104729) % 10_000) as i64 + 1;
+                    (endpoint, ((ts, latency), 1))
+                })
+                .collect();
+            input.append(&mut latencies);
+            circuit.step().unwrap();
+        }
+
+        circuit.kill().unwrap();
+    }
+
     use proptest::{collection, prelude::*};
 
     type InputTuple = (u64, ((u64, i64), isize));
@@ -0,0 +1,653 @@
+//! Session windows.
+
+use crate::{
+    algebra::{IndexedZSet, ZRingValue},
+    circuit::{
+        operator_traits::{Operator, QuaternaryOperator},
+        OwnershipPreference, Scope,
+    },
+    operator::{
+        time_series::{
+            lag::{is_positive, map_batch, Descending},
+            OrdPartitionedIndexedZSet, PartitionedBatchReader, PartitionedIndexedZSet,
+        },
+        trace::{DelayedTraceId, IntegrateTraceId, UntimedTraceAppend, Z1Trace},
+        Aggregator,
+    },
+    trace::{Batch, BatchReader, Cursor, Spine},
+    Circuit, DBData, OrdIndexedZSet, RootCircuit, Stream,
+};
+use bincode::{Decode, Encode};
+use num::PrimInt;
+use size_of::SizeOf;
+use std::{borrow::Cow, cmp::max, marker::PhantomData, ops::Neg};
+
+/// A session window: a maximal sequence of records in a partition in which
+/// consecutive records are less than the session gap apart.
+///
+/// `start` is the timestamp of the first record in the session; `end` is the
+/// timestamp of the last record plus the gap, i.e., the earliest time at
+/// which a new record starts a new session.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, SizeOf, Encode, Decode,
+)]
+pub struct Session<TS> {
+    pub start: TS,
+    pub end: TS,
+}
+
+pub type OrdSessionStream<PK, TS, V, R> =
+    Stream<RootCircuit, OrdPartitionedIndexedZSet<PK, Session<TS>, (TS, V), R>>;
+
+pub type OrdSessionAggregateStream<PK, TS, A, R> =
+    Stream<RootCircuit, OrdIndexedZSet<(PK, Session<TS>), A, R>>;
+
+impl<B> Stream<RootCircuit, B> {
+    /// Assign each record of a partitioned time series to its session
+    /// window.
+    ///
+    /// Sessions are computed independently for each partition.  Two records
+    /// belong to the same session if they are separated by a sequence of
+    /// records in which consecutive timestamps differ by less than `gap`
+    /// (see [`Session`]).  For each record `(timestamp, value)` with a
+    /// positive weight, outputs `(session, (timestamp, value))` with the same
+    /// weight.
+    ///
+    /// This operator is incremental: a record inserted or deleted at time
+    /// `ts` can create a new session, extend an existing session, merge two
+    /// sessions, or split a session in two.  In each case the operator
+    /// retracts the records of the sessions that changed and inserts them
+    /// again with their new sessions.  Records of other sessions are not
+    /// affected, so out-of-order records only cost work proportional to the
+    /// size of the sessions they touch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `gap` is not positive.
+    pub fn session_window<TS, V>(&self, gap: TS) -> OrdSessionStream<B::Key, TS, V, B::R>
+    where
+        B: PartitionedIndexedZSet<TS, V>,
+        B::R: ZRingValue,
+        TS: DBData + PrimInt,
+        V: DBData,
+    {
+        assert!(gap > TS::zero(), "session_window: gap must be positive");
+
+        // The circuit has the same structure as the one built by
+        // `partitioned_lag`.
+        self.circuit().region("session_window", || {
+            let circuit = self.circuit();
+            let stream = self.shard();
+
+            let input_trace = stream.integrate_trace();
+
+            // The same trace, with records of each partition in reverse order,
+            // used to find the beginning of a session.
+            let reversed_trace = stream
+                .apply_named("ReversePartitions", |batch: &B| {
+                    map_batch::<_, OrdIndexedZSet<B::Key, Descending<(TS, V)>, B::R>, _>(
+                        batch,
+                        |pk, (ts, v)| (pk.clone(), Descending((*ts, v.clone()))),
+                    )
+                })
+                .mark_sharded()
+                .integrate_trace();
+
+            let (output_trace_delayed, z1feedback) =
+                circuit.add_feedback(<Z1Trace<
+                    Spine<OrdPartitionedIndexedZSet<B::Key, Session<TS>, (TS, V), B::R>>,
+                >>::new(false, self.circuit().root_scope()));
+            output_trace_delayed.mark_sharded();
+
+            let output = circuit
+                .add_quaternary_operator(
+                    <SessionWindow<TS, V>>::new(gap),
+                    &stream,
+                    &input_trace,
+                    &reversed_trace,
+                    &output_trace_delayed,
+                )
+                .mark_sharded();
+
+            let output_trace = circuit
+                .add_binary_operator_with_preference(
+                    <UntimedTraceAppend<Spine<_>>>::new(),
+                    (
+                        &output_trace_delayed,
+                        OwnershipPreference::STRONGLY_PREFER_OWNED,
+                    ),
+                    (&output, OwnershipPreference::PREFER_OWNED),
+                )
+                .mark_sharded();
+
+            z1feedback
+                .connect_with_preference(&output_trace, OwnershipPreference::STRONGLY_PREFER_OWNED);
+
+            circuit.cache_insert(
+                DelayedTraceId::new(output_trace.origin_node_id().clone()),
+                output_trace_delayed,
+            );
+            circuit.cache_insert(
+                IntegrateTraceId::new(output.origin_node_id().clone()),
+                output_trace,
+            );
+
+            output
+        })
+    }
+
+    /// Aggregate the records of each session window.
+    ///
+    /// Applies `aggregator` to the values of each session computed by
+    /// [`Self::session_window`] and outputs the resulting aggregates indexed
+    /// by partition key and session.  When a session changes, the operator
+    /// retracts the aggregate of the old session and inserts the aggregate of
+    /// the new one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `gap` is not positive.
+    pub fn session_window_aggregate<TS, V, A>(
+        &self,
+        gap: TS,
+        aggregator: A,
+    ) -> OrdSessionAggregateStream<B::Key, TS, A::Output, B::R>
+    where
+        B: PartitionedIndexedZSet<TS, V>,
+        B::R: ZRingValue,
+        A: Aggregator<V, (), B::R>,
+        TS: DBData + PrimInt,
+        V: DBData,
+    {
+        self.session_window::<TS, V>(gap)
+            .map_index(|(pk, (session, (_ts, v)))| ((pk.clone(), *session), v.clone()))
+            .aggregate(aggregator)
+    }
+}
+
+/// Quaternary operator that implements the internals of `session_window`.
+///
+/// * Input stream 1: updates to the time series.  Used to identify affected
+///   partitions and times.
+/// * Input stream 2: trace containing the accumulated time series data.
+/// * Input stream 3: the same trace with records in each partition in reverse
+///   order.
+/// * Input stream 4: trace of previously produced outputs.  Used to compute
+///   retractions.
+struct SessionWindow<TS, V> {
+    gap: TS,
+    phantom: PhantomData<V>,
+}
+
+impl<TS, V> SessionWindow<TS, V>
+where
+    TS: DBData + PrimInt,
+    V: DBData,
+{
+    fn new(gap: TS) -> Self {
+        Self {
+            gap,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Computes ranges of timestamps whose sessions may be affected by the
+    /// updates in the current partition of `delta_cursor`.
+    ///
+    /// Starting from each updated timestamp, extends the range backward and
+    /// forward for as long as it finds records less than `gap` apart,
+    /// counting both current records and records deleted by the update.  The
+    /// records immediately outside the range are therefore at least `gap`
+    /// apart from the records inside it both before and after the update,
+    /// so sessions outside the range do not change.  Returns a list of
+    /// non-overlapping inclusive ranges ordered by start time.
+    fn affected_ranges<'a, 'b, 'c, K, R, C1, C2, C3>(
+        &self,
+        delta_cursor: &mut C1,
+        trace_cursor: &mut C2,
+        reversed_cursor: &mut C3,
+    ) -> Vec<(TS, TS)>
+    where
+        K: Eq,
+        R: ZRingValue,
+        C1: Cursor<'a, K, (TS, V), (), R>,
+        C2: Cursor<'b, K, (TS, V), (), R>,
+        C3: Cursor<'c, K, Descending<(TS, V)>, (), R>,
+    {
+        // Updated timestamps in ascending order.
+        let mut updated: Vec<TS> = Vec::new();
+        while delta_cursor.val_valid() {
+            let ts = delta_cursor.val().0;
+            if updated.last() != Some(&ts) {
+                updated.push(ts);
+            }
+            delta_cursor.step_val();
+        }
+
+        // The trace includes the current update.  It may not contain the
+        // partition at all if the update deletes the whole partition, in
+        // which case the partition is treated as empty: every old record is
+        // then updated, so each old session starts at an updated timestamp.
+        trace_cursor.seek_key(delta_cursor.key());
+        let trace_found = trace_cursor.key_valid() && trace_cursor.key() == delta_cursor.key();
+        reversed_cursor.seek_key(delta_cursor.key());
+        let reversed_found =
+            reversed_cursor.key_valid() && reversed_cursor.key() == delta_cursor.key();
+
+        // Records that exist before or after the update.
+        let is_record =
+            |ts: &TS, weight: &R| is_positive(weight) || updated.binary_search(ts).is_ok();
+
+        let mut ranges: Vec<(TS, TS)> = Vec::new();
+
+        for ts in updated.iter() {
+            if matches!(ranges.last(), Some((_, to)) if ts <= to) {
+                continue;
+            }
+
+            let mut from = *ts;
+            if reversed_found {
+                reversed_cursor.rewind_vals();
+                reversed_cursor.seek_val_with(|Descending((t, _))| t < ts);
+                while reversed_cursor.val_valid() {
+                    let t = reversed_cursor.val().0 .0;
+                    if t.saturating_add(self.gap) <= from {
+                        break;
+                    }
+                    if is_record(&t, &reversed_cursor.weight()) {
+                        from = t;
+                    }
+                    reversed_cursor.step_val();
+                }
+            }
+
+            let mut to = *ts;
+            if trace_found {
+                trace_cursor.rewind_vals();
+                trace_cursor.seek_val_with(|(t, _)| t > ts);
+                while trace_cursor.val_valid() {
+                    let t = trace_cursor.val().0;
+                    if to.saturating_add(self.gap) <= t {
+                        break;
+                    }
+                    if is_record(&t, &trace_cursor.weight()) {
+                        to = t;
+                    }
+                    trace_cursor.step_val();
+                }
+            }
+
+            match ranges.last_mut() {
+                Some((_, last_to)) if from <= *last_to => *last_to = max(*last_to, to),
+                _ => ranges.push((from, to)),
+            }
+        }
+
+        ranges
+    }
+
+    /// Outputs `records` as a single session and clears `records`.
+    fn flush_session<K, R, O>(
+        &self,
+        key: &K,
+        records: &mut Vec<((TS, V), R)>,
+        tuples: &mut Vec<(O::Item, R)>,
+    ) where
+        K: Clone,
+        O: Batch<Key = K, Val = (Session<TS>, (TS, V)), R = R>,
+    {
+        let session = match (records.first(), records.last()) {
+            (Some(((start, _), _)), Some(((last, _), _))) => Session {
+                start: *start,
+                end: last.saturating_add(self.gap),
+            },
+            _ => return,
+        };
+
+        for (record, weight) in records.drain(..) {
+            tuples.push((O::item_from(key.clone(), (session, record)), weight));
+        }
+    }
+}
+
+impl<TS, V> Operator for SessionWindow<TS, V>
+where
+    TS: 'static,
+    V: 'static,
+{
+    fn name(&self) -> Cow<'static, str> {
+        Cow::from("SessionWindow")
+    }
+
+    fn fixedpoint(&self, _scope: Scope) -> bool {
+        true
+    }
+}
+
+impl<TS, V, B, T, RT, OT, O> QuaternaryOperator<B, T, RT, OT, O> for SessionWindow<TS, V>
+where
+    TS: DBData + PrimInt,
+    V: DBData,
+    B: PartitionedBatchReader<TS, V> + Clone,
+    B::R: ZRingValue,
+    T: PartitionedBatchReader<TS, V, Key = B::Key, R = B::R> + Clone,
+    RT: BatchReader<Key = B::Key, Val = Descending<(TS, V)>, Time = (), R = B::R> + Clone,
+    OT: PartitionedBatchReader<Session<TS>, (TS, V), Key = B::Key, R = B::R> + Clone,
+    O: IndexedZSet<Key = B::Key, Val = (Session<TS>, (TS, V)), R = B::R>,
+{
+    fn eval<'a>(
+        &mut self,
+        input_delta: Cow<'a, B>,
+        input_trace: Cow<'a, T>,
+        reversed_trace: Cow<'a, RT>,
+        output_trace: Cow<'a, OT>,
+    ) -> O {
+        let mut delta_cursor = input_delta.cursor();
+        let mut range_cursor = input_trace.cursor();
+        let mut input_trace_cursor = input_trace.cursor();
+        let mut reversed_trace_cursor = reversed_trace.cursor();
+        let mut output_trace_cursor = output_trace.cursor();
+
+        let mut tuples = Vec::with_capacity(input_delta.len());
+
+        // Iterate over affected partitions.
+        while delta_cursor.key_valid() {
+            let key = delta_cursor.key().clone();
+            let ranges = self.affected_ranges(
+                &mut delta_cursor,
+                &mut range_cursor,
+                &mut reversed_trace_cursor,
+            );
+
+            // Retract old sessions.  Sessions are ordered by start time, and
+            // every old session that overlaps an affected range starts inside
+            // it.
+            output_trace_cursor.seek_key(&key);
+            if output_trace_cursor.key_valid() && output_trace_cursor.key() == &key {
+                for (from, to) in ranges.iter() {
+                    output_trace_cursor.seek_val_with(|(session, _)| &session.start >= from);
+                    while output_trace_cursor.val_valid()
+                        && &output_trace_cursor.val().0.start <= to
+                    {
+                        let weight = output_trace_cursor.weight();
+                        if !weight.is_zero() {
+                            tuples.push((
+                                O::item_from(key.clone(), output_trace_cursor.val().clone()),
+                                weight.neg(),
+                            ));
+                        }
+                        output_trace_cursor.step_val();
+                    }
+                }
+            }
+
+            // Compute new sessions.
+            input_trace_cursor.seek_key(&key);
+            if input_trace_cursor.key_valid() && input_trace_cursor.key() == &key {
+                for (from, to) in ranges.iter() {
+                    // Records of the current session.
+                    let mut records: Vec<((TS, V), B::R)> = Vec::new();
+
+                    input_trace_cursor.seek_val_with(|(ts, _)| ts >= from);
+                    while input_trace_cursor.val_valid() && &input_trace_cursor.val().0 <= to {
+                        let weight = input_trace_cursor.weight();
+                        if is_positive(&weight) {
+                            let (ts, v) = input_trace_cursor.val();
+                            let new_session = match records.last() {
+                                Some(((last, _), _)) => last.saturating_add(self.gap) <= *ts,
+                                None => false,
+                            };
+                            if new_session {
+                                self.flush_session::<_, _, O>(&key, &mut records, &mut tuples);
+                            }
+                            records.push(((*ts, v.clone()), weight));
+                        }
+                        input_trace_cursor.step_val();
+                    }
+                    self.flush_session::<_, _, O>(&key, &mut records, &mut tuples);
+                }
+            }
+
+            delta_cursor.step_key();
+        }
+
+        O::from_tuples((), tuples)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Session;
+    use crate::{
+        algebra::DefaultSemigroup,
+        indexed_zset,
+        operator::Fold,
+        trace::{Batch, BatchReader, Cursor},
+        Circuit, OrdIndexedZSet, RootCircuit, Runtime,
+    };
+
+    type DataBatch = OrdIndexedZSet<u64, (u64, i64), isize>;
+    type SessionBatch = OrdIndexedZSet<u64, (Session<u64>, (u64, i64)), isize>;
+
+    fn s(start: u64, end: u64) -> Session<u64> {
+        Session { start, end }
+    }
+
+    #[test]
+    fn test_session_window() {
+        let (mut circuit, (input_handle, sessions_handle, delta_handle, count_handle)) =
+            RootCircuit::build(move |circuit| {
+                let (input, input_handle) =
+                    circuit.add_input_indexed_zset::<u64, (u64, i64), isize>();
+
+                let sessions = input.session_window::<u64, i64>(10);
+                let sessions_handle = sessions.integrate().output();
+                let delta_handle = sessions.output();
+
+                let count = <Fold<_, DefaultSemigroup<_>, _, _>>::new(
+                    0isize,
+                    |acc: &mut isize, _v: &i64, w: isize| *acc += w,
+                );
+                let count_handle = input
+                    .session_window_aggregate::<u64, i64, _>(10, count)
+                    .integrate()
+                    .output();
+
+                (input_handle, sessions_handle, delta_handle, count_handle)
+            })
+            .unwrap();
+
+        input_handle.append(&mut vec![
+            (1, ((10, 100), 1)),
+            (1, ((15, 150), 1)),
+            (1, ((40, 400), 1)),
+            (2, ((10, -1), 1)),
+        ]);
+        circuit.step().unwrap();
+
+        let expected: SessionBatch = indexed_zset! {
+            1 => {(s(10, 25), (10, 100)) => 1, (s(10, 25), (15, 150)) => 1, (s(40, 50), (40, 400)) => 1},
+            2 => {(s(10, 20), (10, -1)) => 1}
+        };
+        assert_eq!(sessions_handle.consolidate(), expected);
+
+        let expected = indexed_zset! {
+            (1, s(10, 25)) => {2 => 1},
+            (1, s(40, 50)) => {1 => 1},
+            (2, s(10, 20)) => {1 => 1}
+        };
+        assert_eq!(count_handle.consolidate(), expected);
+
+        // An out-of-order record merges two sessions.
+        input_handle.append(&mut vec![(1, ((30, 300), 1))]);
+        circuit.step().unwrap();
+
+        let expected: SessionBatch = indexed_zset! {
+            1 => {
+                (s(10, 25), (10, 100)) => -1,
+                (s(10, 25), (15, 150)) => -1,
+                (s(40, 50), (40, 400)) => -1,
+                (s(10, 50), (10, 100)) => 1,
+                (s(10, 50), (15, 150)) => 1,
+                (s(10, 50), (30, 300)) => 1,
+                (s(10, 50), (40, 400)) => 1
+            }
+        };
+        assert_eq!(delta_handle.consolidate(), expected);
+
+        let expected = indexed_zset! {
+            (1, s(10, 50)) => {4 => 1},
+            (2, s(10, 20)) => {1 => 1}
+        };
+        assert_eq!(count_handle.consolidate(), expected);
+
+        // Deleting the record splits the session again.  A record far from
+        // existing records starts a new session without affecting others.
+        input_handle.append(&mut vec![(1, ((30, 300), -1)), (1, ((100, 1000), 1))]);
+        circuit.step().unwrap();
+
+        let expected: SessionBatch = indexed_zset! {
+            1 => {
+                (s(10, 50), (10, 100)) => -1,
+                (s(10, 50), (15, 150)) => -1,
+                (s(10, 50), (30, 300)) => -1,
+                (s(10, 50), (40, 400)) => -1,
+                (s(10, 25), (10, 100)) => 1,
+                (s(10, 25), (15, 150)) => 1,
+                (s(40, 50), (40, 400)) => 1,
+                (s(100, 110), (100, 1000)) => 1
+            }
+        };
+        assert_eq!(delta_handle.consolidate(), expected);
+
+        // Extending a session only updates that session.
+        input_handle.append(&mut vec![(1, ((45, 450), 1))]);
+        circuit.step().unwrap();
+
+        let expected: SessionBatch = indexed_zset! {
+            1 => {
+                (s(40, 50), (40, 400)) => -1,
+                (s(40, 55), (40, 400)) => 1,
+                (s(40, 55), (45, 450)) => 1
+            }
+        };
+        assert_eq!(delta_handle.consolidate(), expected);
+
+        // Deleting whole partitions retracts all of their sessions.
+        input_handle.append(&mut vec![
+            (1, ((10, 100), -1)),
+            (1, ((15, 150), -1)),
+            (1, ((40, 400), -1)),
+            (1, ((45, 450), -1)),
+            (1, ((100, 1000), -1)),
+            (2, ((10, -1), -1)),
+        ]);
+        circuit.step().unwrap();
+
+        let expected: SessionBatch = indexed_zset! {
+            1 => {
+                (s(10, 25), (10, 100)) => -1,
+                (s(10, 25), (15, 150)) => -1,
+                (s(40, 55), (40, 400)) => -1,
+                (s(40, 55), (45, 450)) => -1,
+                (s(100, 110), (100, 1000)) => -1
+            },
+            2 => {(s(10, 20), (10, -1)) => -1}
+        };
+        assert_eq!(delta_handle.consolidate(), expected);
+        assert!(sessions_handle.consolidate().is_empty());
+        assert!(count_handle.consolidate().is_empty());
+
+        // The partition can be recreated afterwards.
+        input_handle.append(&mut vec![(2, ((20, -2), 1))]);
+        circuit.step().unwrap();
+
+        let expected: SessionBatch = indexed_zset! {
+            2 => {(s(20, 30), (20, -2)) => 1}
+        };
+        assert_eq!(sessions_handle.consolidate(), expected);
+    }
+
+    // Reference implementation of `session_window` for testing.
+    fn session_window_slow(batch: &DataBatch, gap: u64) -> SessionBatch {
+        let mut tuples = Vec::new();
+        let mut cursor = batch.cursor();
+
+        while cursor.key_valid() {
+            let mut sessions: Vec<Vec<((u64, i64), isize)>> = Vec::new();
+            while cursor.val_valid() {
+                let (ts, v) = *cursor.val();
+                let w = cursor.weight();
+                if w > 0 {
+                    match sessions.last_mut() {
+                        Some(session) if session.last().unwrap().0 .0 + gap > ts => {
+                            session.push(((ts, v), w))
+                        }
+                        _ => sessions.push(vec![((ts, v), w)]),
+                    }
+                }
+                cursor.step_val();
+            }
+
+            for records in sessions {
+                let session = s(records[0].0 .0, records.last().unwrap().0 .0 + gap);
+                for (record, w) in records {
+                    tuples.push(((*cursor.key(), (session, record)), w));
+                }
+            }
+            cursor.step_key();
+        }
+
+        SessionBatch::from_tuples((), tuples)
+    }
+
+    // Insert and delete records in random order and compare the output
+    // against sessions computed from scratch.
+    #[test]
+    fn test_session_window_random() {
+        let (mut circuit, input_handle) = Runtime::init_circuit(4, |circuit| {
+            let (input, input_handle) = circuit.add_input_indexed_zset::<u64, (u64, i64), isize>();
+
+            let expected = input
+                .gather(0)
+                .integrate()
+                .apply(|batch| session_window_slow(batch, 5));
+            input
+                .session_window::<u64, i64>(5)
+                .gather(0)
+                .integrate()
+                .apply2(&expected, |actual, expected| assert_eq!(actual, expected));
+
+            input_handle
+        })
+        .unwrap();
+
+        // Linear congruential generator, so that the test is deterministic.
+        let mut state = 1u64;
+        let mut next = move |n: u64| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) % n
+        };
+
+        let mut inserted = Vec::new();
+        for _ in 0..50 {
+            let mut updates = Vec::new();
+            for _ in 0..10 {
+                if !inserted.is_empty() && next(3) == 0 {
+                    let (pk, ts, v) = inserted.swap_remove(next(inserted.len() as u64) as usize);
+                    updates.push((pk, ((ts, v), -1)));
+                } else {
+                    let record = (next(3), next(200), next(4) as i64);
+                    inserted.push(record);
+                    updates.push((record.0, ((record.1, record.2), 1)));
+                }
+            }
+            input_handle.append(&mut updates);
+            circuit.step().unwrap();
+        }
+
+        circuit.kill().unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     operator::communication::new_exchange_operators,
-    trace::{cursor::Cursor, BatchReader},
+    trace::{cursor::Cursor, BatchReader, Serializable},
     Circuit, NumEntries, RootCircuit, Runtime, Stream,
 };
 use size_of::SizeOf;
@@ -33,7 +33,7 @@ where
     pub fn watermark_monotonic<W, TS>(&self, watermark_func: W) -> Stream<RootCircuit, TS>
     where
         W: Fn(&B::Key) -> TS + 'static,
-        TS: Ord + Clone + Default + SizeOf + NumEntries + Send + 'static,
+        TS: Ord + Clone + Default + SizeOf + NumEntries + Send + Serializable + 'static,
     {
         let local_watermark = self.stream_fold(TS::default(), move |old_watermark, batch| {
             let mut cursor = batch.cursor();
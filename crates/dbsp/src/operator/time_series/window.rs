@@ -1,5 +1,7 @@
 //! Operators to organize time series data into windows.
 
+#[cfg(feature = "checkpoint")]
+use crate::circuit::checkpoint;
 use crate::{
     algebra::{IndexedZSet, NegByRef},
     circuit::{
@@ -8,6 +10,8 @@ use crate::{
     },
     trace::{cursor::Cursor, ord::OrdZSet, Batch, BatchReader, Spine},
 };
+#[cfg(feature = "checkpoint")]
+use bincode::error::{DecodeError, EncodeError};
 use std::{borrow::Cow, cmp::max, marker::PhantomData};
 
 impl<C, B> Stream<C, B>
@@ -112,6 +116,21 @@ where
         // Do we have meaningful examples of using windows inside nested scopes?
         panic!("'Window' operator used in fixedpoint iteration")
     }
+
+    fn is_stateful(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        checkpoint::encode(&self.window).map(Some)
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.window = checkpoint::decode(state)?;
+        Ok(())
+    }
 }
 
 impl<B> TernaryOperator<Spine<B>, B, (B::Key, B::Key), OrdZSet<B::Val, B::R>> for Window<B>
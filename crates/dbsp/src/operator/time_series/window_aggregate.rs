@@ -0,0 +1,409 @@
+//! Tumbling and hopping window aggregation.
+
+#[cfg(feature = "checkpoint")]
+use crate::circuit::checkpoint;
+use crate::{
+    algebra::ZRingValue,
+    circuit::{
+        operator_traits::{Operator, TernaryOperator},
+        Scope,
+    },
+    operator::{
+        time_series::{OrdPartitionedIndexedZSet, PartitionedIndexedZSet},
+        Aggregator, FilterMap,
+    },
+    trace::{Batch, BatchReader, Cursor, Spine},
+    Circuit, DBData, OrdIndexedZSet, RootCircuit, Stream,
+};
+#[cfg(feature = "checkpoint")]
+use bincode::error::{DecodeError, EncodeError};
+use num::PrimInt;
+use std::{borrow::Cow, marker::PhantomData};
+
+pub type OrdWindowAggregateStream<PK, TS, A, R> =
+    Stream<RootCircuit, OrdPartitionedIndexedZSet<PK, TS, A, R>>;
+
+/// Specifies when window aggregation operators output window aggregates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowEmitMode {
+    /// Output the aggregate of each window once the watermark passes the end
+    /// of the window.  Records that arrive after that (late records) update
+    /// previously output aggregates, i.e., the operator retracts the old
+    /// value of the aggregate and inserts the new one.
+    #[default]
+    OnWatermark,
+
+    /// Output the aggregate of each window exactly once, when the watermark
+    /// passes the end of the window.  Late records are ignored, so the
+    /// output of the operator never contains retractions.
+    Final,
+}
+
+impl<B> Stream<RootCircuit, B> {
+    /// Tumbling window aggregation.
+    ///
+    /// Partitions the time axis into non-overlapping windows of length `size`
+    /// (`[0, size)`, `[size, 2*size)`, ...), applies `aggregator` to the
+    /// records of each partition within each window, and outputs the
+    /// resulting aggregates indexed by partition key and window start time.
+    ///
+    /// The output of the operator for a window is delayed until
+    /// `watermark` passes the end of the window.  `emit_mode` determines how
+    /// records that arrive after that are handled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is not positive.
+    pub fn tumbling_window_aggregate<TS, V, A>(
+        &self,
+        watermark: &Stream<RootCircuit, TS>,
+        size: TS,
+        aggregator: A,
+        emit_mode: WindowEmitMode,
+    ) -> OrdWindowAggregateStream<B::Key, TS, A::Output, B::R>
+    where
+        B: PartitionedIndexedZSet<TS, V>,
+        B::R: ZRingValue,
+        A: Aggregator<V, (), B::R>,
+        TS: DBData + PrimInt,
+        V: DBData,
+    {
+        self.hopping_window_aggregate(watermark, size, size, aggregator, emit_mode)
+    }
+
+    /// Hopping window aggregation.
+    ///
+    /// Like [`Self::tumbling_window_aggregate`], but windows of length `size`
+    /// start every `step` time units (`[0, size)`, `[step, step+size)`, ...).
+    /// When `step < size`, windows overlap and each record is aggregated in
+    /// multiple windows.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` or `step` is not positive.
+    pub fn hopping_window_aggregate<TS, V, A>(
+        &self,
+        watermark: &Stream<RootCircuit, TS>,
+        size: TS,
+        step: TS,
+        aggregator: A,
+        emit_mode: WindowEmitMode,
+    ) -> OrdWindowAggregateStream<B::Key, TS, A::Output, B::R>
+    where
+        B: PartitionedIndexedZSet<TS, V>,
+        B::R: ZRingValue,
+        A: Aggregator<V, (), B::R>,
+        TS: DBData + PrimInt,
+        V: DBData,
+    {
+        assert!(size > TS::zero(), "window size must be positive");
+        assert!(step > TS::zero(), "window step must be positive");
+
+        // ```
+        //       ┌──────────────┐          ┌─────────┐           ┌───┐           ┌───────────────┐
+        // ─────►│assign windows├─────────►│aggregate├──────────►│map├────┬─────►│integrate_trace│
+        // self  └──────────────┘ windowed └─────────┘ aggregate └───┘    │      └───────┬───────┘
+        //                                                                │              │ trace
+        //                                                                ▼              ▼
+        //                                                             ┌──────────────────────┐
+        //                                             watermark ─────►│ WindowEmit           ├─────►
+        //                                                             └──────────────────────┘
+        // ```
+        self.circuit().region("hopping_window_aggregate", || {
+            // Index records by `(window_start, partition_key)`.
+            let windowed = self.apply_named("AssignWindows", move |batch: &B| {
+                let mut tuples = Vec::with_capacity(batch.len());
+                let mut cursor = batch.cursor();
+                while cursor.key_valid() {
+                    while cursor.val_valid() {
+                        let (ts, v) = cursor.val();
+                        let w = cursor.weight();
+                        for start in windows_of(*ts, size, step) {
+                            tuples.push((((start, cursor.key().clone()), v.clone()), w.clone()));
+                        }
+                        cursor.step_val();
+                    }
+                    cursor.step_key();
+                }
+                <OrdIndexedZSet<(TS, B::Key), V, B::R>>::from_tuples((), tuples)
+            });
+
+            // Aggregates indexed by window start time only, so that the
+            // `WindowEmit` operator can look up windows by time.
+            let aggregate = windowed
+                .aggregate(aggregator)
+                .map_index(|((start, pk), agg)| (*start, (pk.clone(), agg.clone())));
+            let trace = aggregate.integrate_trace();
+
+            self.circuit().add_ternary_operator(
+                WindowEmit::new(size, emit_mode),
+                &aggregate,
+                &trace,
+                watermark,
+            )
+        })
+    }
+}
+
+/// Returns start times of all windows that contain `ts`, in descending order.
+fn windows_of<TS>(ts: TS, size: TS, step: TS) -> impl Iterator<Item = TS>
+where
+    TS: PrimInt,
+{
+    // Start of the last window containing `ts`, rounded down to a multiple of
+    // `step` (`%` rounds towards zero for negative timestamps).
+    let rem = ts % step;
+    let last = if rem < TS::zero() {
+        ts - rem - step
+    } else {
+        ts - rem
+    };
+
+    let mut next = Some(last);
+    std::iter::from_fn(move || {
+        let start = next?;
+        if ts - start >= size {
+            return None;
+        }
+        next = start.checked_sub(&step);
+        Some(start)
+    })
+}
+
+/// Ternary operator that implements the emission logic of window aggregation
+/// operators.
+///
+/// * Input stream 1: changes to window aggregates indexed by window start time.
+/// * Input stream 2: trace of window aggregates, including the current changes.
+/// * Input stream 3: watermark.
+struct WindowEmit<TS, PK, A, R> {
+    size: TS,
+    emit_mode: WindowEmitMode,
+    /// Watermark at the previous clock cycle.
+    watermark: Option<TS>,
+    _type: PhantomData<(PK, A, R)>,
+}
+
+impl<TS, PK, A, R> WindowEmit<TS, PK, A, R>
+where
+    TS: PrimInt,
+{
+    fn new(size: TS, emit_mode: WindowEmitMode) -> Self {
+        Self {
+            size,
+            emit_mode,
+            watermark: None,
+            _type: PhantomData,
+        }
+    }
+
+    /// Returns the start time of the latest window closed by `watermark`, or
+    /// `None` if no windows are closed.
+    fn closed_bound(&self, watermark: &Option<TS>) -> Option<TS> {
+        watermark
+            .as_ref()
+            .and_then(|watermark| watermark.checked_sub(&self.size))
+    }
+}
+
+impl<TS, PK, A, R> Operator for WindowEmit<TS, PK, A, R>
+where
+    TS: DBData,
+    PK: 'static,
+    A: 'static,
+    R: 'static,
+{
+    fn name(&self) -> Cow<'static, str> {
+        Cow::from("WindowEmit")
+    }
+
+    fn clock_start(&mut self, _scope: Scope) {
+        self.watermark = None;
+    }
+
+    fn fixedpoint(&self, _scope: Scope) -> bool {
+        // Window aggregation can currently only be used in top-level circuits.
+        panic!("'WindowEmit' operator used in fixedpoint iteration")
+    }
+
+    fn is_stateful(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        checkpoint::encode(&self.watermark).map(Some)
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        self.watermark = checkpoint::decode(state)?;
+        Ok(())
+    }
+}
+
+impl<TS, PK, A, R>
+    TernaryOperator<
+        OrdIndexedZSet<TS, (PK, A), R>,
+        Spine<OrdIndexedZSet<TS, (PK, A), R>>,
+        TS,
+        OrdPartitionedIndexedZSet<PK, TS, A, R>,
+    > for WindowEmit<TS, PK, A, R>
+where
+    TS: DBData + PrimInt,
+    PK: DBData,
+    A: DBData,
+    R: ZRingValue + DBData,
+{
+    fn eval(
+        &mut self,
+        delta: Cow<'_, OrdIndexedZSet<TS, (PK, A), R>>,
+        trace: Cow<'_, Spine<OrdIndexedZSet<TS, (PK, A), R>>>,
+        watermark: Cow<'_, TS>,
+    ) -> OrdPartitionedIndexedZSet<PK, TS, A, R> {
+        let watermark = Some(
+            watermark
+                .into_owned()
+                .max(self.watermark.unwrap_or(TS::min_value())),
+        );
+        let old_bound = self.closed_bound(&self.watermark);
+        let new_bound = self.closed_bound(&watermark);
+
+        let mut tuples = Vec::new();
+
+        // Changes to windows closed at previous clock cycles.
+        if self.emit_mode == WindowEmitMode::OnWatermark {
+            if let Some(old_bound) = &old_bound {
+                let mut cursor = delta.cursor();
+                while cursor.key_valid() && cursor.key() <= old_bound {
+                    let start = *cursor.key();
+                    cursor.map_values(|(pk, agg), w| {
+                        tuples.push(((pk.clone(), (start, agg.clone())), w.clone()))
+                    });
+                    cursor.step_key();
+                }
+            }
+        }
+
+        // Complete contents of windows closed during this clock cycle.
+        if let Some(new_bound) = &new_bound {
+            let mut cursor = trace.cursor();
+            if let Some(old_bound) = &old_bound {
+                cursor.seek_key(old_bound);
+                if cursor.key_valid() && cursor.key() == old_bound {
+                    cursor.step_key();
+                }
+            }
+
+            while cursor.key_valid() && cursor.key() <= new_bound {
+                let start = *cursor.key();
+                cursor.map_values(|(pk, agg), w| {
+                    if !w.is_zero() {
+                        tuples.push(((pk.clone(), (start, agg.clone())), w.clone()))
+                    }
+                });
+                cursor.step_key();
+            }
+        }
+
+        self.watermark = watermark;
+        OrdPartitionedIndexedZSet::from_tuples((), tuples)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WindowEmitMode;
+    use crate::{
+        algebra::DefaultSemigroup,
+        indexed_zset,
+        operator::{time_series::OrdPartitionedIndexedZSet, Fold},
+        trace::Batch,
+        Circuit, RootCircuit,
+    };
+
+    type Output = OrdPartitionedIndexedZSet<u64, u64, i64, isize>;
+
+    fn test_window_aggregate(emit_mode: WindowEmitMode) {
+        let (mut circuit, (input_handle, watermark_handle, tumbling_handle, hopping_handle)) =
+            RootCircuit::build(move |circuit| {
+                let (input, input_handle) =
+                    circuit.add_input_indexed_zset::<u64, (u64, i64), isize>();
+                let (watermark, watermark_handle) = circuit.add_input_stream::<u64>();
+
+                let sum = <Fold<_, DefaultSemigroup<_>, _, _>>::new(
+                    0i64,
+                    |acc: &mut i64, v: &i64, w: isize| *acc += *v * w as i64,
+                );
+
+                let tumbling_handle = input
+                    .tumbling_window_aggregate(&watermark, 10, sum.clone(), emit_mode)
+                    .output();
+                let hopping_handle = input
+                    .hopping_window_aggregate(&watermark, 10, 5, sum, emit_mode)
+                    .output();
+
+                (
+                    input_handle,
+                    watermark_handle,
+                    tumbling_handle,
+                    hopping_handle,
+                )
+            })
+            .unwrap();
+
+        input_handle.append(&mut vec![
+            (1, ((1, 1), 1)),
+            (1, ((7, 2), 1)),
+            (1, ((12, 4), 1)),
+            (2, ((3, 8), 1)),
+        ]);
+        watermark_handle.set_for_all(5);
+        circuit.step().unwrap();
+
+        // No windows are closed yet.
+        assert_eq!(tumbling_handle.consolidate(), Output::empty(()));
+        assert_eq!(hopping_handle.consolidate(), Output::empty(()));
+
+        watermark_handle.set_for_all(10);
+        circuit.step().unwrap();
+
+        let expected: Output = indexed_zset! { 1 => {(0, 3) => 1}, 2 => {(0, 8) => 1} };
+        assert_eq!(tumbling_handle.consolidate(), expected);
+        let expected: Output = indexed_zset! {
+            1 => {(0, 3) => 1},
+            2 => {(0, 8) => 1}
+        };
+        assert_eq!(hopping_handle.consolidate(), expected);
+
+        // Late record.
+        input_handle.append(&mut vec![(1, ((9, 16), 1))]);
+        watermark_handle.set_for_all(15);
+        circuit.step().unwrap();
+
+        let expected: Output = match emit_mode {
+            WindowEmitMode::OnWatermark => indexed_zset! { 1 => {(0, 3) => -1, (0, 19) => 1} },
+            WindowEmitMode::Final => Output::empty(()),
+        };
+        assert_eq!(tumbling_handle.consolidate(), expected);
+
+        // Window `[5, 15)` closes at watermark 15.
+        let expected: Output = match emit_mode {
+            WindowEmitMode::OnWatermark => indexed_zset! {
+                1 => {(0, 3) => -1, (0, 19) => 1, (5, 22) => 1}
+            },
+            WindowEmitMode::Final => indexed_zset! { 1 => {(5, 22) => 1} },
+        };
+        assert_eq!(hopping_handle.consolidate(), expected);
+    }
+
+    #[test]
+    fn test_window_aggregate_on_watermark() {
+        test_window_aggregate(WindowEmitMode::OnWatermark);
+    }
+
+    #[test]
+    fn test_window_aggregate_final() {
+        test_window_aggregate(WindowEmitMode::Final);
+    }
+}
@@ -0,0 +1,299 @@
+//! Top-K and row-number operators.
+
+use crate::{
+    algebra::{HasZero, IndexedZSet, ZRingValue},
+    circuit::{
+        operator_traits::{Operator, TernaryOperator},
+        Scope,
+    },
+    trace::{cursor::Cursor, Batch, BatchReader, Spine},
+    Circuit, OrdIndexedZSet, RootCircuit, Stream,
+};
+use std::{borrow::Cow, collections::VecDeque, marker::PhantomData, ops::Neg};
+
+impl<B> Stream<RootCircuit, B>
+where
+    B: IndexedZSet + Send,
+    B::R: ZRingValue,
+{
+    /// Pick `k` smallest values in each group.
+    ///
+    /// For each key in the input indexed Z-set, outputs the `k` smallest
+    /// values associated with this key with positive weights (i.e., values
+    /// that are present in the collection), along with their weights.
+    /// Values are ranked by their `Ord` implementation; each distinct value
+    /// counts once regardless of its weight.
+    ///
+    /// This operator is incremental: it only outputs changes for keys whose
+    /// top-`k` values were affected by the input.
+    pub fn topk_asc(&self, k: usize) -> Self {
+        self.topk_generic("TopKAsc", k, false, |vals| vals)
+    }
+
+    /// Pick `k` largest values in each group.
+    ///
+    /// Like [`Self::topk_asc`], but picks the largest values.
+    ///
+    /// Since cursors can only iterate over values in ascending order, this
+    /// operator scans all values associated with each affected key.
+    pub fn topk_desc(&self, k: usize) -> Self {
+        self.topk_generic("TopKDesc", k, true, |vals| vals)
+    }
+
+    /// Number the `k` smallest values in each group.
+    ///
+    /// Computes the same set of values as [`Self::topk_asc`], but extends
+    /// each value with its 1-based row number within the group, similar to
+    /// `ROW_NUMBER() OVER (PARTITION BY key ORDER BY val)` in SQL restricted
+    /// to the first `k` rows.
+    ///
+    /// A change to the values of a group renumbers the affected rows, so an
+    /// insertion near the top of a group updates up to `k` output records.
+    pub fn topk_row_number_asc(
+        &self,
+        k: usize,
+    ) -> Stream<RootCircuit, OrdIndexedZSet<B::Key, (i64, B::Val), B::R>> {
+        self.topk_generic("TopKRowNumberAsc", k, false, number_rows)
+    }
+
+    /// Number the `k` largest values in each group.
+    ///
+    /// Like [`Self::topk_row_number_asc`], but numbers values in descending
+    /// order, i.e., the largest value in each group gets row number 1.
+    pub fn topk_row_number_desc(
+        &self,
+        k: usize,
+    ) -> Stream<RootCircuit, OrdIndexedZSet<B::Key, (i64, B::Val), B::R>> {
+        self.topk_generic("TopKRowNumberDesc", k, true, number_rows)
+    }
+
+    /// Generic implementation of the top-K operators.
+    ///
+    /// `output_func` converts top-K values of a group, ordered by rank, into
+    /// output values.
+    fn topk_generic<O, F>(
+        &self,
+        name: &'static str,
+        k: usize,
+        descending: bool,
+        output_func: F,
+    ) -> Stream<RootCircuit, O>
+    where
+        O: Batch<Key = B::Key, Time = (), R = B::R>,
+        F: Fn(Vec<(B::Val, B::R)>) -> Vec<(O::Val, O::R)> + 'static,
+    {
+        // We construct the following circuit.  For each key in the input batch,
+        // the `TopK` operator computes the top `k` values in the trace before
+        // and after the batch, retracts the former and inserts the latter.
+        //
+        // ```text
+        //           ┌──────────────────────────────────────────────┐
+        //           │                                              ▼
+        //  stream   │   ┌───────────────┐  trace              ┌──────┐
+        // ──────────┴──►│integrate_trace├──┬─────────────────►│ TopK ├─────►
+        //               └───────────────┘  │  ┌─────────────┐ └──────┘
+        //                                  └─►│ delay_trace ├────▲
+        //                                     └─────────────┘
+        // ```
+        let stream = self.shard();
+        let trace = stream.integrate_trace();
+        let delayed_trace = trace.delay_trace();
+
+        self.circuit()
+            .add_ternary_operator(
+                TopK::new(name, k, descending, output_func),
+                &stream,
+                &trace,
+                &delayed_trace,
+            )
+            .mark_sharded()
+    }
+}
+
+/// Output function of the row-number operators.
+fn number_rows<V, R>(vals: Vec<(V, R)>) -> Vec<((i64, V), R)> {
+    vals.into_iter()
+        .enumerate()
+        .map(|(i, (v, w))| ((i as i64 + 1, v), w))
+        .collect()
+}
+
+/// Computes changes to the top `k` values of each key affected by the input
+/// batch.
+struct TopK<B, O, F> {
+    name: &'static str,
+    k: usize,
+    descending: bool,
+    output_func: F,
+    _type: PhantomData<(B, O)>,
+}
+
+impl<B, O, F> TopK<B, O, F>
+where
+    B: IndexedZSet,
+    B::R: ZRingValue,
+{
+    fn new(name: &'static str, k: usize, descending: bool, output_func: F) -> Self {
+        Self {
+            name,
+            k,
+            descending,
+            output_func,
+            _type: PhantomData,
+        }
+    }
+
+    /// Returns the top `k` values associated with `key` in `cursor`, ordered
+    /// by rank.
+    fn topk<'s, C>(&self, cursor: &mut C, key: &B::Key) -> Vec<(B::Val, B::R)>
+    where
+        C: Cursor<'s, B::Key, B::Val, (), B::R>,
+    {
+        if self.k == 0 {
+            return Vec::new();
+        }
+
+        cursor.seek_key(key);
+        if !cursor.key_valid() || cursor.key() != key {
+            return Vec::new();
+        }
+
+        let mut result = VecDeque::with_capacity(self.k);
+        while cursor.val_valid() {
+            let w = cursor.weight();
+            if w.ge0() && !w.is_zero() {
+                if self.descending {
+                    // Keep the last `k` values.
+                    if result.len() == self.k {
+                        result.pop_back();
+                    }
+                    result.push_front((cursor.val().clone(), w));
+                } else {
+                    result.push_back((cursor.val().clone(), w));
+                    if result.len() == self.k {
+                        break;
+                    }
+                }
+            }
+            cursor.step_val();
+        }
+
+        result.into()
+    }
+}
+
+impl<B, O, F> Operator for TopK<B, O, F>
+where
+    B: 'static,
+    O: 'static,
+    F: 'static,
+{
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(self.name)
+    }
+
+    fn fixedpoint(&self, _scope: Scope) -> bool {
+        true
+    }
+}
+
+impl<B, O, F> TernaryOperator<B, Spine<B>, Spine<B>, O> for TopK<B, O, F>
+where
+    B: IndexedZSet,
+    B::R: ZRingValue,
+    O: Batch<Key = B::Key, Time = (), R = B::R>,
+    F: Fn(Vec<(B::Val, B::R)>) -> Vec<(O::Val, O::R)> + 'static,
+{
+    /// * `delta` - changes to the input collection in the current clock cycle.
+    /// * `trace` - trace of the input collection, including `delta`.
+    /// * `delayed_trace` - trace of the input collection up to, but not
+    ///   including the current clock cycle.
+    fn eval(
+        &mut self,
+        delta: Cow<'_, B>,
+        trace: Cow<'_, Spine<B>>,
+        delayed_trace: Cow<'_, Spine<B>>,
+    ) -> O {
+        let mut tuples = Vec::new();
+
+        let mut delta_cursor = delta.cursor();
+        let mut cursor = trace.cursor();
+        let mut delayed_cursor = delayed_trace.cursor();
+
+        while delta_cursor.key_valid() {
+            let key = delta_cursor.key();
+
+            for (v, w) in (self.output_func)(self.topk(&mut delayed_cursor, key)) {
+                tuples.push((O::item_from(key.clone(), v), w.neg()));
+            }
+            for (v, w) in (self.output_func)(self.topk(&mut cursor, key)) {
+                tuples.push((O::item_from(key.clone(), v), w));
+            }
+
+            delta_cursor.step_key();
+        }
+
+        // Consolidation cancels out unchanged values.
+        O::from_tuples((), tuples)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{indexed_zset, trace::Batch, Circuit, OrdIndexedZSet, RootCircuit};
+
+    #[test]
+    fn topk_test() {
+        let (mut circuit, (input_handle, asc_handle, desc_handle, rn_handle)) =
+            RootCircuit::build(move |circuit| {
+                let (input, input_handle) = circuit.add_input_indexed_zset::<u64, i64, isize>();
+
+                let asc_handle = input.topk_asc(2).integrate().output();
+                let desc_handle = input.topk_desc(2).integrate().output();
+                let rn_handle = input.topk_row_number_asc(2).output();
+
+                (input_handle, asc_handle, desc_handle, rn_handle)
+            })
+            .unwrap();
+
+        input_handle.append(&mut vec![
+            (1, (3, 1)),
+            (1, (1, 1)),
+            (1, (2, 2)),
+            (1, (5, 1)),
+            (2, (10, 1)),
+        ]);
+        circuit.step().unwrap();
+
+        let expected: OrdIndexedZSet<u64, i64, isize> =
+            indexed_zset! {1 => {1 => 1, 2 => 2}, 2 => {10 => 1}};
+        assert_eq!(asc_handle.consolidate(), expected);
+        let expected: OrdIndexedZSet<u64, i64, isize> =
+            indexed_zset! {1 => {3 => 1, 5 => 1}, 2 => {10 => 1}};
+        assert_eq!(desc_handle.consolidate(), expected);
+        let expected: OrdIndexedZSet<u64, (i64, i64), isize> =
+            indexed_zset! {1 => {(1, 1) => 1, (2, 2) => 2}, 2 => {(1, 10) => 1}};
+        assert_eq!(rn_handle.consolidate(), expected);
+
+        // Changes that don't affect the top-K produce no output.
+        input_handle.append(&mut vec![(1, (4, 1))]);
+        circuit.step().unwrap();
+        assert_eq!(rn_handle.consolidate(), OrdIndexedZSet::empty(()));
+
+        // Delete the smallest value: the row-number operator renumbers the
+        // remaining rows.
+        input_handle.append(&mut vec![(1, (1, -1)), (1, (6, 1))]);
+        circuit.step().unwrap();
+
+        let expected: OrdIndexedZSet<u64, i64, isize> =
+            indexed_zset! {1 => {2 => 2, 3 => 1}, 2 => {10 => 1}};
+        assert_eq!(asc_handle.consolidate(), expected);
+        let expected: OrdIndexedZSet<u64, i64, isize> =
+            indexed_zset! {1 => {5 => 1, 6 => 1}, 2 => {10 => 1}};
+        assert_eq!(desc_handle.consolidate(), expected);
+        let expected: OrdIndexedZSet<u64, (i64, i64), isize> = indexed_zset! {
+            1 => {(1, 1) => -1, (2, 2) => -2, (1, 2) => 2, (2, 3) => 1}
+        };
+        assert_eq!(rn_handle.consolidate(), expected);
+    }
+}
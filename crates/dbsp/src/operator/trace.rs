@@ -1,3 +1,9 @@
+#[cfg(feature = "checkpoint")]
+use crate::{
+    circuit::checkpoint,
+    operator::communication::{key_shard, split_sharded},
+    trace::serialize::{filter_traces, DecodeTrace, EncodeTrace},
+};
 use crate::{
     circuit::{
         metadata::{MetaItem, OperatorMeta},
@@ -6,15 +12,123 @@ use crate::{
         WithClock,
     },
     circuit_cache_key,
-    trace::{cursor::Cursor, Batch, BatchReader, Builder, Spine, Trace},
-    Timestamp,
+    trace::{cursor::Cursor, spill::SpillConfig, Batch, BatchReader, Builder, Spine, Trace},
+    RootCircuit, Timestamp,
 };
+#[cfg(feature = "checkpoint")]
+use bincode::error::{DecodeError, EncodeError};
 use size_of::SizeOf;
-use std::{borrow::Cow, marker::PhantomData};
+#[cfg(feature = "checkpoint")]
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::{borrow::Cow, cell::RefCell, marker::PhantomData, rc::Rc};
 
 circuit_cache_key!(TraceId<B, D>(GlobalNodeId => Stream<B, D>));
 circuit_cache_key!(DelayedTraceId<B, D>(GlobalNodeId => Stream<B, D>));
 circuit_cache_key!(IntegrateTraceId<B, D>(GlobalNodeId => Stream<B, D>));
+circuit_cache_key!(TraceBoundId<K>(GlobalNodeId => TraceBound<K>));
+circuit_cache_key!(TraceSpillId(GlobalNodeId => TraceSpill));
+
+/// Lower bound on keys retained in the traces of a stream.
+///
+/// The bound is shared between the operators that maintain traces of the
+/// stream, which pass it to [`Trace::truncate_keys_below`], and the operator
+/// that advances it, usually based on the watermark of the stream.  See
+/// [`Stream::truncate_traces_below`].
+#[derive(Clone)]
+pub struct TraceBound<K>(Rc<RefCell<Option<K>>>);
+
+impl<K> Default for TraceBound<K> {
+    fn default() -> Self {
+        Self(Rc::new(RefCell::new(None)))
+    }
+}
+
+impl<K> TraceBound<K> {
+    /// Creates a bound that doesn't discard any keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the bound to `bound`.
+    pub fn set(&self, bound: K) {
+        *self.0.borrow_mut() = Some(bound);
+    }
+
+    /// Returns the current value of the bound.
+    pub fn get(&self) -> Option<K>
+    where
+        K: Clone,
+    {
+        self.0.borrow().clone()
+    }
+
+    /// Informs `trace` of the current bound.
+    fn apply<T>(&self, trace: &mut T)
+    where
+        T: Trace<Key = K>,
+    {
+        if let Some(bound) = self.0.borrow().as_ref() {
+            trace.truncate_keys_below(bound);
+        }
+    }
+}
+
+/// Spill configuration of the traces of a stream.
+///
+/// Shared between the operators that maintain traces of the stream, which
+/// pass it to [`Trace::set_spill_config`].  See [`Stream::spill_traces`].
+#[derive(Clone, Default)]
+pub struct TraceSpill(Rc<RefCell<Option<SpillConfig>>>);
+
+impl TraceSpill {
+    /// Creates a handle that leaves the default configuration of traces
+    /// unchanged (see [`SpillConfig::set_default`]).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the configuration to `config`.
+    pub fn set(&self, config: SpillConfig) {
+        *self.0.borrow_mut() = Some(config);
+    }
+
+    /// Returns the current configuration.
+    pub fn get(&self) -> Option<SpillConfig> {
+        self.0.borrow().clone()
+    }
+
+    /// Calls `f` with the current configuration, if any, as the default
+    /// configuration of new traces (see [`SpillConfig::set_default`]).
+    #[cfg(feature = "checkpoint")]
+    fn with_default<F, U>(&self, f: F) -> U
+    where
+        F: FnOnce() -> U,
+    {
+        match self.get() {
+            Some(config) => {
+                let default = SpillConfig::default_config();
+                SpillConfig::set_default(Some(config));
+                let result = f();
+                SpillConfig::set_default(default);
+                result
+            }
+            None => f(),
+        }
+    }
+
+    /// Passes the current configuration to `trace`.
+    fn apply<T>(&self, trace: &mut T)
+    where
+        T: Trace,
+    {
+        if let Some(config) = self.0.borrow().as_ref() {
+            trace.set_spill_config(config.clone());
+        }
+    }
+}
 
 // TODO: add infrastructure to compact the trace during slack time.
 
@@ -70,15 +184,21 @@ where
         B: BatchReader<Time = ()>,
         T: Trace<Key = B::Key, Val = B::Val, R = B::R, Time = <C as WithClock>::Time> + Clone,
     {
+        let bound = self.trace_bound();
+        let spill = self.trace_spill();
+
         self.circuit()
             .cache_get_or_insert_with(TraceId::new(self.origin_node_id().clone()), || {
                 let circuit = self.circuit();
 
                 circuit.region("trace", || {
-                    let (ExportStream { local, export }, z1feedback) =
-                        circuit.add_feedback_with_export(Z1Trace::new(false, circuit.root_scope()));
+                    let (ExportStream { local, export }, z1feedback) = circuit
+                        .add_feedback_with_export(
+                            Z1Trace::new(false, circuit.root_scope()).with_spill(spill.clone()),
+                        );
                     let trace = circuit.add_binary_operator_with_preference(
-                        <TraceAppend<T, B, C>>::new(circuit.clone()),
+                        <TraceAppend<T, B, C>>::with_bound(circuit.clone(), bound.clone())
+                            .with_spill(spill.clone()),
                         (&local, OwnershipPreference::STRONGLY_PREFER_OWNED),
                         (
                             &self.try_sharded_version(),
@@ -110,16 +230,22 @@ where
         B: Batch,
         Spine<B>: SizeOf,
     {
+        let bound = self.trace_bound();
+        let spill = self.trace_spill();
+
         self.circuit()
             .cache_get_or_insert_with(IntegrateTraceId::new(self.origin_node_id().clone()), || {
                 let circuit = self.circuit();
 
                 circuit.region("integrate_trace", || {
-                    let (ExportStream { local, export }, z1feedback) =
-                        circuit.add_feedback_with_export(Z1Trace::new(true, circuit.root_scope()));
+                    let (ExportStream { local, export }, z1feedback) = circuit
+                        .add_feedback_with_export(
+                            Z1Trace::new(true, circuit.root_scope()).with_spill(spill.clone()),
+                        );
 
                     let trace = circuit.add_binary_operator_with_preference(
-                        UntimedTraceAppend::<Spine<B>>::new(),
+                        UntimedTraceAppend::<Spine<B>>::with_bound(bound.clone())
+                            .with_spill(spill.clone()),
                         (&local, OwnershipPreference::STRONGLY_PREFER_OWNED),
                         (
                             &self.try_sharded_version(),
@@ -146,6 +272,79 @@ where
             })
             .clone()
     }
+
+    /// Returns the lower bound on keys retained in traces of `self`.
+    ///
+    /// The bound is shared by all traces created by [`Self::trace`] and
+    /// [`Self::integrate_trace`] from `self` or from its sharded version,
+    /// including traces created internally by operators such as `join`,
+    /// `aggregate` and `distinct`.
+    pub fn trace_bound(&self) -> TraceBound<B::Key>
+    where
+        B: BatchReader,
+    {
+        self.circuit()
+            .cache_get_or_insert_with(
+                TraceBoundId::new(self.try_sharded_version().origin_node_id().clone()),
+                TraceBound::new,
+            )
+            .clone()
+    }
+
+    /// Returns the spill configuration of traces of `self`.
+    ///
+    /// Like [`Self::trace_bound`], the configuration is shared by all traces
+    /// of `self` and of its sharded version.
+    pub fn trace_spill(&self) -> TraceSpill {
+        self.circuit()
+            .cache_get_or_insert_with(
+                TraceSpillId::new(self.try_sharded_version().origin_node_id().clone()),
+                TraceSpill::new,
+            )
+            .clone()
+    }
+}
+
+impl<B> Stream<RootCircuit, B>
+where
+    B: Batch<Time = ()> + Send,
+{
+    /// Discard state below a watermark in traces of `self`.
+    ///
+    /// Whenever `watermark` changes, sets the lower bound on keys retained in
+    /// traces of `self` to `bound_func(watermark)`.  Traces discard keys below
+    /// the bound as they merge batches, keeping the state of long-running
+    /// operators such as `join`, `aggregate`, `distinct`, and `window` from
+    /// growing forever.
+    ///
+    /// Use this method when keys below the bound can no longer affect the
+    /// output of the circuit, e.g., when the key of `self` is a timestamp,
+    /// `watermark` is computed using [`Stream::watermark_monotonic`], and
+    /// the circuit ignores updates older than the watermark.
+    ///
+    /// Since traces are sharded across workers, this method shards `self`
+    /// so that the bound applies to the sharded traces.
+    pub fn truncate_traces_below<TS, F>(&self, watermark: &Stream<RootCircuit, TS>, bound_func: F)
+    where
+        TS: 'static,
+        F: Fn(&TS) -> B::Key + 'static,
+    {
+        let bound = self.shard().trace_bound();
+        watermark.inspect(move |watermark| bound.set(bound_func(watermark)));
+    }
+
+    /// Spill large batches in traces of `self` to disk.
+    ///
+    /// Traces of `self`, including traces created internally by operators
+    /// such as `join` and `aggregate`, write batches with at least
+    /// `config.min_batch_size` updates to memory-mapped files in
+    /// `config.directory`.  This overrides the configuration set for the
+    /// whole circuit using [`SpillConfig::set_default`].
+    ///
+    /// Like [`Self::truncate_traces_below`], this method shards `self`.
+    pub fn spill_traces(&self, config: SpillConfig) {
+        self.shard().trace_spill().set(config);
+    }
 }
 
 impl<C, T> Stream<C, T>
@@ -169,6 +368,8 @@ pub struct UntimedTraceAppend<T>
 where
     T: Trace,
 {
+    bound: TraceBound<T::Key>,
+    spill: TraceSpill,
     _phantom: PhantomData<T>,
 }
 
@@ -177,10 +378,23 @@ where
     T: Trace,
 {
     pub fn new() -> Self {
+        Self::with_bound(TraceBound::new())
+    }
+
+    /// Creates an operator that discards keys below `bound` from the trace.
+    pub fn with_bound(bound: TraceBound<T::Key>) -> Self {
         Self {
+            bound,
+            spill: TraceSpill::new(),
             _phantom: PhantomData,
         }
     }
+
+    /// Configures spilling of the trace to disk according to `spill`.
+    pub fn with_spill(mut self, spill: TraceSpill) -> Self {
+        self.spill = spill;
+        self
+    }
 }
 
 impl<T> Default for UntimedTraceAppend<T>
@@ -215,6 +429,8 @@ where
     }
 
     fn eval_owned_and_ref(&mut self, mut trace: T, batch: &T::Batch) -> T {
+        self.bound.apply(&mut trace);
+        self.spill.apply(&mut trace);
         trace.insert(batch.clone());
         trace
     }
@@ -226,6 +442,8 @@ where
     }
 
     fn eval_owned(&mut self, mut trace: T, batch: T::Batch) -> T {
+        self.bound.apply(&mut trace);
+        self.spill.apply(&mut trace);
         trace.insert(batch);
         trace
     }
@@ -238,23 +456,44 @@ where
     }
 }
 
-pub struct TraceAppend<T, B, C> {
+pub struct TraceAppend<T, B, C>
+where
+    T: Trace,
+{
     clock: C,
+    bound: TraceBound<T::Key>,
+    spill: TraceSpill,
     _phantom: PhantomData<(T, B)>,
 }
 
-impl<T, B, C> TraceAppend<T, B, C> {
+impl<T, B, C> TraceAppend<T, B, C>
+where
+    T: Trace,
+{
     pub fn new(clock: C) -> Self {
+        Self::with_bound(clock, TraceBound::new())
+    }
+
+    /// Creates an operator that discards keys below `bound` from the trace.
+    pub fn with_bound(clock: C, bound: TraceBound<T::Key>) -> Self {
         Self {
             clock,
+            bound,
+            spill: TraceSpill::new(),
             _phantom: PhantomData,
         }
     }
+
+    /// Configures spilling of the trace to disk according to `spill`.
+    pub fn with_spill(mut self, spill: TraceSpill) -> Self {
+        self.spill = spill;
+        self
+    }
 }
 
 impl<T, B, Clk> Operator for TraceAppend<T, B, Clk>
 where
-    T: 'static,
+    T: Trace + 'static,
     B: 'static,
     Clk: 'static,
 {
@@ -279,6 +518,8 @@ where
     }
 
     fn eval_owned_and_ref(&mut self, mut trace: T, batch: &B) -> T {
+        self.bound.apply(&mut trace);
+        self.spill.apply(&mut trace);
         // TODO: extend `trace` type to feed untimed batches directly
         // (adding fixed timestamp on the fly).
         trace.insert(batch_add_time(batch, &self.clock.time()));
@@ -292,6 +533,8 @@ where
     }
 
     fn eval_owned(&mut self, mut trace: T, batch: B) -> T {
+        self.bound.apply(&mut trace);
+        self.spill.apply(&mut trace);
         trace.insert(batch_add_time(&batch, &self.clock.time()));
         trace
     }
@@ -312,6 +555,11 @@ pub struct Z1Trace<T: Trace> {
     dirty: Vec<bool>,
     root_scope: Scope,
     reset_on_clock_start: bool,
+    spill: TraceSpill,
+    // Set if the circuit splits keys across workers (see
+    // `Stream::shard_split`), so the trace is not partitioned by key.
+    #[cfg(feature = "checkpoint")]
+    split_sharded: Option<Arc<AtomicBool>>,
 }
 
 impl<T> Z1Trace<T>
@@ -325,8 +573,18 @@ where
             dirty: vec![false; root_scope as usize + 1],
             root_scope,
             reset_on_clock_start,
+            spill: TraceSpill::new(),
+            #[cfg(feature = "checkpoint")]
+            split_sharded: split_sharded(),
         }
     }
+
+    /// Restores traces from checkpoints with the spill configuration
+    /// `spill`, so that spilled batches stay on disk.
+    pub fn with_spill(mut self, spill: TraceSpill) -> Self {
+        self.spill = spill;
+        self
+    }
 }
 
 impl<T> Operator for Z1Trace<T>
@@ -380,6 +638,80 @@ where
     fn fixedpoint(&self, scope: Scope) -> bool {
         !self.dirty[scope as usize]
     }
+
+    fn is_stateful(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        checkpoint::encode(&(
+            &self.time,
+            &self.dirty,
+            self.trace.as_ref().map(EncodeTrace),
+        ))
+        .map(Some)
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        let (time, dirty, trace): (T::Time, Vec<bool>, Option<DecodeTrace<T>>) =
+            self.spill.with_default(|| checkpoint::decode(state))?;
+
+        self.time = time;
+        self.dirty = dirty;
+        self.trace = trace.map(|DecodeTrace(trace)| trace);
+        Ok(())
+    }
+
+    // All workers take checkpoints at the same clock cycle, so their
+    // timestamps are identical.  Keys are reassigned to workers the same
+    // way `Stream::shard` assigns them.
+    #[cfg(feature = "checkpoint")]
+    fn restore_rescaled(
+        &mut self,
+        states: &[&[u8]],
+        worker: usize,
+        num_workers: usize,
+    ) -> Result<(), DecodeError> {
+        if let Some(split_sharded) = &self.split_sharded {
+            if split_sharded.load(Ordering::Acquire) {
+                return Err(DecodeError::Other(
+                    "traces of a circuit that uses `shard_split` cannot be migrated to a different number of workers",
+                ));
+            }
+        }
+
+        let mut traces = Vec::with_capacity(states.len());
+
+        for state in states {
+            let (time, dirty, trace): (T::Time, Vec<bool>, Option<DecodeTrace<T>>) =
+                self.spill.with_default(|| checkpoint::decode(state))?;
+
+            if traces.is_empty() {
+                self.time = time;
+                self.dirty = dirty;
+            } else {
+                for (flag, other) in self.dirty.iter_mut().zip(dirty) {
+                    *flag |= other;
+                }
+            }
+            traces.push(trace.map(|DecodeTrace(trace)| trace));
+        }
+
+        self.trace = if traces.iter().all(Option::is_none) {
+            None
+        } else {
+            let mut trace = self.spill.with_default(|| T::new(None));
+            filter_traces(
+                traces.iter().flatten(),
+                |key| key_shard(key, num_workers) == worker,
+                &mut trace,
+            );
+            Some(trace)
+        };
+        Ok(())
+    }
 }
 
 impl<T> StrictOperator<T> for Z1Trace<T>
@@ -425,3 +757,180 @@ where
         OwnershipPreference::PREFER_OWNED
     }
 }
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "checkpoint")]
+    use crate::trace::{spill::SpillConfig, spine_fueled::Spine};
+    #[cfg(all(feature = "checkpoint", not(feature = "persistence")))]
+    use crate::{operator::Min, trace::Batch, OrdIndexedZSet, OrdZSet};
+    use crate::{
+        trace::{cursor::Cursor, BatchReader},
+        Circuit, RootCircuit,
+    };
+    use std::{cell::RefCell, rc::Rc};
+    #[cfg(feature = "checkpoint")]
+    use std::{env::temp_dir, fs::read_dir, process};
+
+    #[test]
+    fn truncate_traces_below() {
+        let trace_len = Rc::new(RefCell::new(0));
+        let trace_len_clone = trace_len.clone();
+
+        let (mut circuit, input_handle) = RootCircuit::build(move |circuit| {
+            let (input, input_handle) = circuit.add_input_zset::<u64, isize>();
+
+            let watermark = input.watermark_monotonic(|ts| *ts);
+            input.truncate_traces_below(&watermark, |watermark| watermark.saturating_sub(10));
+
+            // Count updates that remain visible through a cursor.  Traces
+            // may discard keys below the bound lazily, so `trace.len()` is
+            // only an upper bound.
+            input.integrate_trace().inspect(move |trace| {
+                let mut len = 0;
+                let mut cursor = trace.cursor();
+                while cursor.key_valid() {
+                    while cursor.val_valid() {
+                        cursor.map_times(|_, _| len += 1);
+                        cursor.step_val();
+                    }
+                    cursor.step_key();
+                }
+                *trace_len_clone.borrow_mut() = len;
+            });
+
+            input_handle
+        })
+        .unwrap();
+
+        for ts in 0..1000 {
+            input_handle.push(ts, 1);
+            circuit.step().unwrap();
+        }
+
+        // An extra step makes sure that the trace has observed the final
+        // bound, 999 - 10.
+        circuit.step().unwrap();
+
+        // Only keys `989..1000` remain.
+        assert_eq!(*trace_len.borrow(), 11);
+    }
+
+    // The test uses an in-memory spine explicitly, so that it also runs with
+    // the `persistence` feature, whose default trace implementation ignores
+    // the spill configuration.
+    #[cfg(feature = "checkpoint")]
+    #[test]
+    fn spill_traces() {
+        let directory = temp_dir().join(format!("dbsp-spill-traces-{}", process::id()));
+        let directory_clone = directory.clone();
+
+        let (mut circuit, input_handle) = RootCircuit::build(move |circuit| {
+            let (input, input_handle) = circuit.add_input_zset::<u64, isize>();
+
+            input.spill_traces(SpillConfig::new(directory_clone).with_min_batch_size(64));
+
+            let mut steps = 0;
+            input.trace::<Spine<_>>().inspect(move |trace| {
+                steps += 1;
+
+                // The trace contains keys `0..steps*10`, each with weight 1,
+                // regardless of where batches are stored.
+                let mut cursor = trace.cursor();
+                let mut expected = 0;
+                while cursor.key_valid() {
+                    assert_eq!(*cursor.key(), expected);
+                    assert!(cursor.val_valid());
+                    assert_eq!(cursor.weight(), 1);
+                    expected += 1;
+                    cursor.step_key();
+                }
+                assert_eq!(expected, steps * 10);
+                assert_eq!(trace.len() as u64, steps * 10);
+            });
+
+            input_handle
+        })
+        .unwrap();
+
+        for step in 0..100 {
+            for key in step * 10..(step + 1) * 10 {
+                input_handle.push(key, 1);
+            }
+            circuit.step().unwrap();
+        }
+
+        // Most of the trace is spilled to a small number of files.
+        let files = read_dir(&directory).unwrap().count();
+        assert!(files > 0 && files < 10);
+
+        // Files are deleted with the trace.
+        drop(circuit);
+        assert_eq!(read_dir(&directory).unwrap().count(), 0);
+    }
+
+    // Traces created by `join` and `aggregate` spill to disk given a default
+    // spill configuration.  With the `persistence` feature, these operators
+    // use the persistent trace, which stores its state in RocksDB instead.
+    #[cfg(all(feature = "checkpoint", not(feature = "persistence")))]
+    #[test]
+    fn spill_join_aggregate() {
+        let directory = temp_dir().join(format!("dbsp-spill-join-aggregate-{}", process::id()));
+        let directory_clone = directory.clone();
+
+        let (mut circuit, (input_handle, probe_handle)) = RootCircuit::build(move |circuit| {
+            SpillConfig::set_default(Some(
+                SpillConfig::new(directory_clone).with_min_batch_size(64),
+            ));
+
+            let (input, input_handle) = circuit.add_input_zset::<u64, isize>();
+            let (probe, probe_handle) = circuit.add_input_zset::<u64, isize>();
+
+            // Each probe key matches a key added to `input` in an earlier
+            // step, which the join looks up in the (spilled) trace of `input`.
+            let mut step = 0;
+            input
+                .index_with(|k| (*k, *k))
+                .join(&probe.index_with(|k| (*k, ())), |k, v, _| (*k, v + 1))
+                .inspect(move |delta: &OrdZSet<(u64, u64), isize>| {
+                    assert_eq!(delta, &OrdZSet::from_keys((), vec![((step, step + 1), 1)]));
+                    step += 1;
+                });
+
+            // Every step updates all groups, so the aggregate reads the trace
+            // of each group, but the minimum only changes in the first step.
+            let mut step = 0;
+            input.index_with(|k| (*k % 7, *k)).aggregate(Min).inspect(
+                move |delta: &OrdIndexedZSet<u64, u64, isize>| {
+                    if step == 0 {
+                        assert_eq!(
+                            delta,
+                            &OrdIndexedZSet::from_tuples((), (0..7).map(|k| ((k, k), 1)).collect())
+                        );
+                    } else {
+                        assert!(delta.is_empty());
+                    }
+                    step += 1;
+                },
+            );
+
+            (input_handle, probe_handle)
+        })
+        .unwrap();
+
+        for step in 0..100 {
+            for key in step * 10..(step + 1) * 10 {
+                input_handle.push(key, 1);
+            }
+            probe_handle.push(step, 1);
+            circuit.step().unwrap();
+        }
+
+        // Both operators store most of their state in files.
+        assert!(read_dir(&directory).unwrap().count() > 0);
+
+        drop(circuit);
+        SpillConfig::set_default(None);
+        assert_eq!(read_dir(&directory).unwrap().count(), 0);
+    }
+}
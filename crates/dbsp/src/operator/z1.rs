@@ -1,5 +1,7 @@
 //! z^-1 operator delays its input by one timestamp.
 
+#[cfg(feature = "checkpoint")]
+use crate::circuit::checkpoint;
 use crate::{
     algebra::HasZero,
     circuit::{
@@ -8,8 +10,12 @@ use crate::{
         Circuit, ExportId, ExportStream, FeedbackConnector, GlobalNodeId, OwnershipPreference,
         Scope, Stream,
     },
-    circuit_cache_key, NumEntries,
+    circuit_cache_key,
+    trace::Serializable,
+    NumEntries,
 };
+#[cfg(feature = "checkpoint")]
+use bincode::error::{DecodeError, EncodeError};
 use size_of::{Context, SizeOf};
 use std::{borrow::Cow, mem::replace};
 
@@ -33,7 +39,7 @@ where
 impl<C, D> DelayedFeedback<C, D>
 where
     C: Circuit,
-    D: Eq + SizeOf + NumEntries + Clone + HasZero + 'static,
+    D: Eq + SizeOf + NumEntries + Clone + HasZero + Serializable + 'static,
 {
     /// Create a feedback loop with `Z1` operator.  Use [`Self::connect`] to
     /// close the loop.
@@ -82,7 +88,7 @@ pub struct DelayedNestedFeedback<C, D> {
 impl<C, D> DelayedNestedFeedback<C, D>
 where
     C: Circuit,
-    D: Eq + SizeOf + NumEntries + Clone + 'static,
+    D: Eq + SizeOf + NumEntries + Clone + Serializable + 'static,
 {
     /// Create a feedback loop with `Z1` operator.  Use [`Self::connect`] to
     /// close the loop.
@@ -116,7 +122,7 @@ where
     /// Applies [`Z1`] operator to `self`.
     pub fn delay(&self) -> Stream<C, D>
     where
-        D: Eq + SizeOf + NumEntries + Clone + HasZero + 'static,
+        D: Eq + SizeOf + NumEntries + Clone + HasZero + Serializable + 'static,
     {
         self.circuit()
             .cache_get_or_insert_with(DelayedId::new(self.origin_node_id().clone()), || {
@@ -128,7 +134,7 @@ where
     /// Applies [`Z1Nested`] operator to `self`.
     pub fn delay_nested(&self) -> Stream<C, D>
     where
-        D: Eq + Clone + HasZero + SizeOf + NumEntries + 'static,
+        D: Eq + Clone + HasZero + SizeOf + NumEntries + Serializable + 'static,
     {
         self.circuit()
             .cache_get_or_insert_with(NestedDelayedId::new(self.origin_node_id().clone()), || {
@@ -181,7 +187,7 @@ where
 
 impl<T> Operator for Z1<T>
 where
-    T: Eq + SizeOf + NumEntries + Clone + 'static,
+    T: Eq + SizeOf + NumEntries + Clone + Serializable + 'static,
 {
     fn name(&self) -> Cow<'static, str> {
         Cow::from("Z^-1")
@@ -211,11 +217,28 @@ where
             true
         }
     }
+
+    fn is_stateful(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        checkpoint::encode(&(&self.values, self.empty_output)).map(Some)
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        let (values, empty_output) = checkpoint::decode(state)?;
+        self.values = values;
+        self.empty_output = empty_output;
+        Ok(())
+    }
 }
 
 impl<T> UnaryOperator<T, T> for Z1<T>
 where
-    T: Eq + SizeOf + NumEntries + Clone + 'static,
+    T: Eq + SizeOf + NumEntries + Clone + Serializable + 'static,
 {
     fn eval(&mut self, i: &T) -> T {
         replace(&mut self.values, i.clone())
@@ -232,7 +255,7 @@ where
 
 impl<T> StrictOperator<T> for Z1<T>
 where
-    T: Eq + SizeOf + NumEntries + Clone + 'static,
+    T: Eq + SizeOf + NumEntries + Clone + Serializable + 'static,
 {
     fn get_output(&mut self) -> T {
         self.empty_output = self.values.num_entries_shallow() == 0;
@@ -246,7 +269,7 @@ where
 
 impl<T> StrictUnaryOperator<T, T> for Z1<T>
 where
-    T: Eq + SizeOf + NumEntries + Clone + 'static,
+    T: Eq + SizeOf + NumEntries + Clone + Serializable + 'static,
 {
     fn eval_strict(&mut self, i: &T) {
         self.values = i.clone();
@@ -318,7 +341,7 @@ impl<T> Z1Nested<T> {
 
 impl<T> Operator for Z1Nested<T>
 where
-    T: Eq + SizeOf + NumEntries + Clone + 'static,
+    T: Eq + SizeOf + NumEntries + Clone + Serializable + 'static,
 {
     fn name(&self) -> Cow<'static, str> {
         Cow::from("Z^-1 (nested)")
@@ -378,11 +401,28 @@ where
             false
         }
     }
+
+    fn is_stateful(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn checkpoint(&mut self) -> Result<Option<Vec<u8>>, EncodeError> {
+        checkpoint::encode(&(self.timestamp as u64, &self.values)).map(Some)
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn restore(&mut self, state: &[u8]) -> Result<(), DecodeError> {
+        let (timestamp, values): (u64, Vec<T>) = checkpoint::decode(state)?;
+        self.timestamp = timestamp as usize;
+        self.values = values;
+        Ok(())
+    }
 }
 
 impl<T> UnaryOperator<T, T> for Z1Nested<T>
 where
-    T: Eq + SizeOf + NumEntries + Clone + 'static,
+    T: Eq + SizeOf + NumEntries + Clone + Serializable + 'static,
 {
     fn eval(&mut self, i: &T) -> T {
         debug_assert!(self.timestamp <= self.values.len());
@@ -421,7 +461,7 @@ where
 
 impl<T> StrictOperator<T> for Z1Nested<T>
 where
-    T: Eq + SizeOf + NumEntries + Clone + 'static,
+    T: Eq + SizeOf + NumEntries + Clone + Serializable + 'static,
 {
     fn get_output(&mut self) -> T {
         if self.timestamp >= self.values.len() {
@@ -445,7 +485,7 @@ where
 
 impl<T> StrictUnaryOperator<T, T> for Z1Nested<T>
 where
-    T: Eq + SizeOf + NumEntries + Clone + 'static,
+    T: Eq + SizeOf + NumEntries + Clone + Serializable + 'static,
 {
     fn eval_strict(&mut self, i: &T) {
         debug_assert!(self.timestamp < self.values.len());
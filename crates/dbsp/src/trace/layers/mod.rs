@@ -77,6 +77,21 @@ pub trait Trie: Sized {
         merger.push_merge(self.cursor(), other.cursor());
         merger.done()
     }
+
+    /// Removes the first `lower` keys, along with their sub-collections, from
+    /// the collection.
+    fn truncate_below(&mut self, lower: usize) {
+        if lower == 0 {
+            return;
+        }
+
+        let keys = self.keys();
+        let mut builder = Self::MergeBuilder::with_key_capacity(keys.saturating_sub(lower));
+        if lower < keys {
+            builder.copy_range(self, lower, keys);
+        }
+        *self = builder.done();
+    }
 }
 
 /// A type used to assemble collections.
@@ -17,6 +17,9 @@ pub mod ord;
 #[cfg(feature = "persistence")]
 pub mod persistent;
 pub mod rc_batch;
+#[cfg(feature = "checkpoint")]
+pub mod serialize;
+pub mod spill;
 pub mod spine_fueled;
 
 pub use cursor::{Consumer, Cursor, UnorderedCursor, ValueConsumer};
@@ -31,9 +34,15 @@ use crate::{
     time::{AntichainRef, Timestamp},
     NumEntries,
 };
-#[cfg(feature = "persistence")]
-use bincode::{Decode, Encode};
+#[cfg(feature = "checkpoint")]
+use bincode::{
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
 use size_of::SizeOf;
+use spill::SpillConfig;
 use std::{fmt::Debug, hash::Hash};
 
 /// Trait for data stored in batches.
@@ -43,24 +52,46 @@ use std::{fmt::Debug, hash::Hash};
 /// must be generic over any relational data, it is sufficient to impose
 /// `DBData` as a trait bound on types.  Conversely, a trait bound of the form
 /// `B: BatchReader` implies `B::Key: DBData` and `B::Val: DBData`.
-#[cfg(feature = "persistence")]
+#[cfg(feature = "checkpoint")]
 pub trait DBData:
     Clone + Eq + Ord + Hash + SizeOf + Send + Debug + Decode + Encode + 'static
 {
 }
 
-#[cfg(not(feature = "persistence"))]
+#[cfg(not(feature = "checkpoint"))]
 pub trait DBData: Clone + Eq + Ord + Hash + SizeOf + Send + Debug + 'static {}
 
-#[cfg(feature = "persistence")]
+#[cfg(feature = "checkpoint")]
 impl<T> DBData for T where
     T: Clone + Eq + Ord + Hash + SizeOf + Send + Debug + Decode + Encode + 'static
 {
 }
 
-#[cfg(not(feature = "persistence"))]
+#[cfg(not(feature = "checkpoint"))]
 impl<T> DBData for T where T: Clone + Eq + Ord + Hash + SizeOf + Send + Debug + 'static {}
 
+/// Trait for values that get serialized with `bincode`.
+///
+/// Used as a bound on batches and on the state of operators.  With the
+/// `checkpoint` feature (enabled by default), values of such types are
+/// serialized to checkpoint circuits, to spill traces to disk, and to exchange
+/// data between hosts, and this trait requires `Encode` and `Decode`, as does
+/// [`DBData`].  Without the feature, all types implement `Serializable` and
+/// these capabilities are not available.  The feature is independent of the
+/// trace implementation: [`Spine`] is only replaced by the RocksDB-backed
+/// persistent trace with the `persistence` feature.
+#[cfg(feature = "checkpoint")]
+pub trait Serializable: Decode + Encode {}
+
+#[cfg(not(feature = "checkpoint"))]
+pub trait Serializable {}
+
+#[cfg(feature = "checkpoint")]
+impl<T> Serializable for T where T: Decode + Encode {}
+
+#[cfg(not(feature = "checkpoint"))]
+impl<T> Serializable for T {}
+
 /// Trait for data types used as weights.
 ///
 /// A type used for weights in a batch (i.e., as `BatchReader::R`) must behave
@@ -116,6 +147,22 @@ pub trait Trace: BatchReader {
     /// timestamp representation.
     fn recede_to(&mut self, frontier: &Self::Time);
 
+    /// Allows the trace to discard keys smaller than `lower_bound`.
+    ///
+    /// The caller promises that such keys can no longer affect the output
+    /// of any operator that reads the trace, e.g., because they are below
+    /// the watermark of the input stream.  Cursors created after this call
+    /// skip these keys, but the trace is not required to free the memory
+    /// they occupy immediately.  Lower bounds can only move forward: a bound
+    /// smaller than the previous one is ignored.
+    fn truncate_keys_below(&mut self, lower_bound: &Self::Key);
+
+    /// Enables spilling large batches of the trace to disk.
+    ///
+    /// Traces that don't keep their state in memory may ignore this setting.
+    /// See [`spill`] for details.
+    fn set_spill_config(&mut self, config: SpillConfig);
+
     /// Exert merge effort, even without updates.
     fn exert(&mut self, effort: &mut isize);
 
@@ -146,6 +193,27 @@ pub trait Trace: BatchReader {
 
     /// Returns the value of the dirty flag.
     fn dirty(&self) -> bool;
+
+    /// Serializes the contents of the trace as part of a checkpoint.
+    ///
+    /// The default implementation serializes all updates in the trace using
+    /// [`serialize::encode_batch`].  Traces that store batches in files can
+    /// instead add the files to the checkpoint directory (see
+    /// [`checkpoint::current_dir`](`crate::circuit::checkpoint::current_dir`))
+    /// and only serialize references to them.
+    #[cfg(feature = "checkpoint")]
+    fn encode_checkpoint<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        serialize::encode_batch(self, encoder)
+    }
+
+    /// Restores a trace serialized by [`Self::encode_checkpoint`].
+    ///
+    /// The restored trace is created with the default spill configuration of
+    /// the current thread (see [`SpillConfig::set_default`]).
+    #[cfg(feature = "checkpoint")]
+    fn decode_checkpoint<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        serialize::decode_trace(decoder)
+    }
 }
 
 /// A batch of updates whose contents may be read.
@@ -208,7 +276,11 @@ where
 }
 
 /// An immutable collection of updates.
-pub trait Batch: BatchReader + Clone
+///
+/// With the `checkpoint` feature, batch implementations typically implement
+/// `Encode` and `Decode` by delegating to `serialize::encode_batch` and
+/// `serialize::decode_batch`.
+pub trait Batch: BatchReader + Clone + Serializable
 where
     Self: Sized,
 {
@@ -271,6 +343,9 @@ where
     /// Modifies all timestamps `t` that are not less than or equal to
     /// `frontier` to `t.meet(frontier)`.  See [`Trace::recede_to`].
     fn recede_to(&mut self, frontier: &Self::Time);
+
+    /// Removes all keys smaller than `lower_bound` from the batch.
+    fn truncate_keys_below(&mut self, lower_bound: &Self::Key);
 }
 
 impl<B> HasZero for B
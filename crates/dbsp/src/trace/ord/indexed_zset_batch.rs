@@ -259,6 +259,37 @@ where
     }
 }
 
+#[cfg(feature = "checkpoint")]
+impl<K, V, R, O> bincode::Encode for OrdIndexedZSet<K, V, R, O>
+where
+    K: DBData,
+    V: DBData,
+    R: DBWeight,
+    O: OrdOffset,
+{
+    fn encode<E: bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> core::result::Result<(), bincode::error::EncodeError> {
+        crate::trace::serialize::encode_batch(self, encoder)
+    }
+}
+
+#[cfg(feature = "checkpoint")]
+impl<K, V, R, O> bincode::Decode for OrdIndexedZSet<K, V, R, O>
+where
+    K: DBData,
+    V: DBData,
+    R: DBWeight,
+    O: OrdOffset,
+{
+    fn decode<D: bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> Result<Self, bincode::error::DecodeError> {
+        crate::trace::serialize::decode_batch(decoder)
+    }
+}
+
 impl<K, V, R, O> Batch for OrdIndexedZSet<K, V, R, O>
 where
     K: DBData,
@@ -293,6 +324,11 @@ where
 
     fn recede_to(&mut self, _frontier: &()) {}
 
+    fn truncate_keys_below(&mut self, lower_bound: &Self::Key) {
+        let index = self.layer.keys.partition_point(|key| key < lower_bound);
+        self.layer.truncate_below(index);
+    }
+
     fn empty(_time: Self::Time) -> Self {
         Self {
             layer: OrderedLayer::default(),
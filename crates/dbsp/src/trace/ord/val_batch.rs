@@ -124,6 +124,39 @@ where
     }
 }
 
+#[cfg(feature = "checkpoint")]
+impl<K, V, T, R, O> bincode::Encode for OrdValBatch<K, V, T, R, O>
+where
+    K: DBData,
+    V: DBData,
+    T: DBTimestamp,
+    R: DBWeight,
+    O: OrdOffset,
+{
+    fn encode<E: bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> core::result::Result<(), bincode::error::EncodeError> {
+        crate::trace::serialize::encode_batch(self, encoder)
+    }
+}
+
+#[cfg(feature = "checkpoint")]
+impl<K, V, T, R, O> bincode::Decode for OrdValBatch<K, V, T, R, O>
+where
+    K: DBData,
+    V: DBData,
+    T: DBTimestamp,
+    R: DBWeight,
+    O: OrdOffset,
+{
+    fn decode<D: bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> Result<Self, bincode::error::DecodeError> {
+        crate::trace::serialize::decode_batch(decoder)
+    }
+}
+
 impl<K, V, T, R, O> Batch for OrdValBatch<K, V, T, R, O>
 where
     K: DBData,
@@ -163,6 +196,11 @@ where
             self.do_recede_to(frontier);
         }
     }
+
+    fn truncate_keys_below(&mut self, lower_bound: &Self::Key) {
+        let index = self.layer.keys.partition_point(|key| key < lower_bound);
+        self.layer.truncate_below(index);
+    }
 }
 
 impl<K, V, T, R, O> OrdValBatch<K, V, T, R, O>
@@ -217,6 +217,33 @@ where
     }
 }
 
+#[cfg(feature = "checkpoint")]
+impl<K, R> bincode::Encode for OrdZSet<K, R>
+where
+    K: DBData,
+    R: DBWeight,
+{
+    fn encode<E: bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> core::result::Result<(), bincode::error::EncodeError> {
+        crate::trace::serialize::encode_batch(self, encoder)
+    }
+}
+
+#[cfg(feature = "checkpoint")]
+impl<K, R> bincode::Decode for OrdZSet<K, R>
+where
+    K: DBData,
+    R: DBWeight,
+{
+    fn decode<D: bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> Result<Self, bincode::error::DecodeError> {
+        crate::trace::serialize::decode_batch(decoder)
+    }
+}
+
 impl<K, R> Batch for OrdZSet<K, R>
 where
     K: DBData,
@@ -241,6 +268,11 @@ where
 
     fn recede_to(&mut self, _frontier: &()) {}
 
+    fn truncate_keys_below(&mut self, lower_bound: &Self::Key) {
+        let index = self.layer.keys().partition_point(|key| key < lower_bound);
+        self.layer.truncate_below(index);
+    }
+
     fn empty(_time: Self::Time) -> Self {
         Self {
             layer: ColumnLayer::empty(),
@@ -16,6 +16,7 @@ use crate::algebra::AddAssignByRef;
 use crate::circuit::Activator;
 use crate::time::{Antichain, Timestamp};
 use crate::trace::cursor::Cursor;
+use crate::trace::spill::SpillConfig;
 use crate::trace::{
     AntichainRef, Batch, BatchReader, Builder, Consumer, DBData, DBTimestamp, DBWeight, HasZero,
     Trace, ValueConsumer,
@@ -595,6 +596,27 @@ where
         }
     }
 
+    /// Keys are deleted from RocksDB eagerly, since we don't control when
+    /// RocksDB compacts the trace.
+    fn truncate_keys_below(&mut self, lower_bound: &B::Key) {
+        let mut tmp_key = ReusableEncodeBuffer::default();
+
+        let mut cursor = self.cursor();
+        while cursor.key_valid() && cursor.key() < lower_bound {
+            let key = cursor.key();
+            let encoded_key = tmp_key.encode(&key).expect("Can't encode `key`");
+
+            ROCKS_DB_INSTANCE
+                .delete_cf(&self.cf, encoded_key)
+                .expect("Can't delete key");
+            cursor.step_key();
+        }
+    }
+
+    fn set_spill_config(&mut self, _config: SpillConfig) {
+        // The persistent trace is already stored on disk by RocksDB.
+    }
+
     fn exert(&mut self, _effort: &mut isize) {
         // This is a no-op for the persistent trace as RocksDB will decide when
         // to apply the merge / compaction operators etc.
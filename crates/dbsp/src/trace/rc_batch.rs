@@ -268,6 +268,10 @@ where
     fn recede_to(&mut self, frontier: &B::Time) {
         Rc::get_mut(self).unwrap().recede_to(frontier);
     }
+
+    fn truncate_keys_below(&mut self, lower_bound: &B::Key) {
+        Rc::get_mut(self).unwrap().truncate_keys_below(lower_bound);
+    }
 }
 
 /// Wrapper type for batching reference counted batches.
@@ -0,0 +1,432 @@
+//! Serialization of batches and traces.
+//!
+//! Batches are serialized as a flat sequence of `(key, val, time, weight)`
+//! tuples, independent of their in-memory representation.  As a result, the
+//! contents of any batch or trace can be decoded as any batch type with the
+//! same key, value, timestamp, and weight types.  In particular, the contents
+//! of a [`Trace`] is serialized as a single batch, unless the trace overrides
+//! [`Trace::encode_checkpoint`].
+
+use crate::{
+    time::Timestamp,
+    trace::{cursor::Cursor, Batch, BatchReader, Trace},
+    DBData, DBTimestamp, DBWeight,
+};
+use bincode::{
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
+use std::{collections::BTreeMap, mem::take};
+
+/// Number of updates in each batch inserted by [`decode_trace`] and
+/// [`filter_traces`].
+const INSERT_BATCH_SIZE: usize = 1 << 16;
+
+/// Serialize all updates in `batch`.
+///
+/// `batch` can be any type that implements [`BatchReader`], including
+/// traces.  Use [`decode_batch`] to deserialize the result.
+pub fn encode_batch<B, E>(batch: &B, encoder: &mut E) -> Result<(), EncodeError>
+where
+    B: BatchReader,
+    E: Encoder,
+{
+    encode_cursor(&mut batch.cursor(), encoder)
+}
+
+/// Serialize all updates in the collection that `cursor` iterates over.
+///
+/// Rewinds the cursor before serializing.  Use [`decode_batch`] to
+/// deserialize the result.
+pub fn encode_cursor<'s, C, K, V, T, R, E>(
+    cursor: &mut C,
+    encoder: &mut E,
+) -> Result<(), EncodeError>
+where
+    C: Cursor<'s, K, V, T, R>,
+    K: DBData,
+    V: DBData,
+    T: DBTimestamp,
+    R: DBWeight,
+    E: Encoder,
+{
+    // `BatchReader::len` is not guaranteed to be precise, so we count updates
+    // explicitly before serializing them.
+    let mut len = 0u64;
+    cursor.rewind_keys();
+    while cursor.key_valid() {
+        while cursor.val_valid() {
+            cursor.map_times(|_, _| len += 1);
+            cursor.step_val();
+        }
+        cursor.step_key();
+    }
+    Encode::encode(&len, encoder)?;
+
+    let mut result = Ok(());
+    cursor.rewind_keys();
+    while cursor.key_valid() {
+        while cursor.val_valid() {
+            let key = cursor.key().clone();
+            let val = cursor.val().clone();
+            cursor.map_times(|time, weight| {
+                if result.is_ok() {
+                    result = Encode::encode(&(&key, &val, time, weight), encoder);
+                }
+            });
+            result?;
+            cursor.step_val();
+        }
+        cursor.step_key();
+    }
+
+    Ok(())
+}
+
+/// Deserialize a batch serialized by [`encode_batch`].
+pub fn decode_batch<B, D>(decoder: &mut D) -> Result<B, DecodeError>
+where
+    B: Batch,
+    D: Decoder,
+{
+    let len: u64 = Decode::decode(decoder)?;
+
+    let mut updates = BTreeMap::<B::Time, Vec<(B::Item, B::R)>>::new();
+    for _ in 0..len {
+        let (key, val, time, weight): (B::Key, B::Val, B::Time, B::R) = Decode::decode(decoder)?;
+        updates
+            .entry(time)
+            .or_default()
+            .push((B::item_from(key, val), weight));
+    }
+
+    Ok(batch_from_updates(updates))
+}
+
+/// Deserialize a trace serialized by [`encode_batch`].
+///
+/// Inserts updates into the trace in batches of bounded size, so that the
+/// contents of the trace need not fit in memory if the trace is stored on
+/// disk.
+pub fn decode_trace<T, D>(decoder: &mut D) -> Result<T, DecodeError>
+where
+    T: Trace,
+    D: Decoder,
+{
+    let len: u64 = Decode::decode(decoder)?;
+
+    let mut trace = T::new(None);
+    let mut updates = BTreeMap::<T::Time, Vec<(<T::Batch as Batch>::Item, T::R)>>::new();
+    let mut batch_len = 0;
+    for _ in 0..len {
+        let (key, val, time, weight): (T::Key, T::Val, T::Time, T::R) = Decode::decode(decoder)?;
+        updates
+            .entry(time)
+            .or_default()
+            .push((T::Batch::item_from(key, val), weight));
+        batch_len += 1;
+        if batch_len >= INSERT_BATCH_SIZE {
+            trace.insert(batch_from_updates(take(&mut updates)));
+            batch_len = 0;
+        }
+    }
+
+    if batch_len > 0 {
+        trace.insert(batch_from_updates(updates));
+    }
+    trace.clear_dirty_flag();
+    Ok(trace)
+}
+
+/// Insert the updates in `sources` whose keys satisfy `filter` into `trace`.
+///
+/// Used to redistribute state restored from checkpoints of multiple workers.
+/// Updates are inserted in batches of bounded size, so that the filtered
+/// updates need not fit in memory if `trace` spills batches to disk.
+pub fn filter_traces<'a, S, T, I, F>(sources: I, mut filter: F, trace: &mut T)
+where
+    S: BatchReader<Key = T::Key, Val = T::Val, Time = T::Time, R = T::R> + 'a,
+    T: Trace,
+    I: IntoIterator<Item = &'a S>,
+    F: FnMut(&T::Key) -> bool,
+{
+    let mut updates = BTreeMap::<T::Time, Vec<(<T::Batch as Batch>::Item, T::R)>>::new();
+    let mut len = 0;
+    for source in sources {
+        let mut cursor = source.cursor();
+        while cursor.key_valid() {
+            if filter(cursor.key()) {
+                while cursor.val_valid() {
+                    let key = cursor.key().clone();
+                    let val = cursor.val().clone();
+                    cursor.map_times(|time, weight| {
+                        updates.entry(time.clone()).or_default().push((
+                            T::Batch::item_from(key.clone(), val.clone()),
+                            weight.clone(),
+                        ));
+                        len += 1;
+                    });
+                    cursor.step_val();
+                }
+                if len >= INSERT_BATCH_SIZE {
+                    trace.insert(batch_from_updates(take(&mut updates)));
+                    len = 0;
+                }
+            }
+            cursor.step_key();
+        }
+    }
+
+    if len > 0 {
+        trace.insert(batch_from_updates(updates));
+    }
+    trace.clear_dirty_flag();
+}
+
+// Batchers and builders assign the same timestamp to all updates, so we build
+// a separate batch for each distinct timestamp and merge them.
+fn batch_from_updates<B>(updates: BTreeMap<B::Time, Vec<(B::Item, B::R)>>) -> B
+where
+    B: Batch,
+{
+    updates
+        .into_iter()
+        .map(|(time, tuples)| B::from_tuples(time, tuples))
+        .reduce(|batch1, batch2| batch1.merge(&batch2))
+        .unwrap_or_else(|| B::empty(B::Time::minimum()))
+}
+
+/// Wrapper that implements [`Encode`] for any [`BatchReader`] using
+/// [`encode_batch`].
+pub struct EncodeBatch<'a, B>(pub &'a B);
+
+impl<'a, B> Encode for EncodeBatch<'a, B>
+where
+    B: BatchReader,
+{
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        encode_batch(self.0, encoder)
+    }
+}
+
+/// Wrapper that implements [`Decode`] for any [`Batch`] using
+/// [`decode_batch`].
+pub struct DecodeBatch<B>(pub B);
+
+impl<B> Decode for DecodeBatch<B>
+where
+    B: Batch,
+{
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        decode_batch(decoder).map(Self)
+    }
+}
+
+/// Wrapper that implements [`Encode`] for any [`Trace`] using
+/// [`Trace::encode_checkpoint`].
+pub struct EncodeTrace<'a, T>(pub &'a T);
+
+impl<'a, T> Encode for EncodeTrace<'a, T>
+where
+    T: Trace,
+{
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.0.encode_checkpoint(encoder)
+    }
+}
+
+/// Wrapper that implements [`Decode`] for any [`Trace`] using
+/// [`Trace::decode_checkpoint`].
+pub struct DecodeTrace<T>(pub T);
+
+impl<T> Decode for DecodeTrace<T>
+where
+    T: Trace,
+{
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        T::decode_checkpoint(decoder).map(Self)
+    }
+}
+
+/// Create a trace containing all updates in `batch`.
+///
+/// Used to restore a trace serialized with [`encode_batch`].
+pub fn trace_from_batch<T>(batch: T::Batch) -> T
+where
+    T: Trace,
+{
+    let mut trace = T::new(None);
+    if !batch.is_empty() {
+        trace.insert(batch);
+    }
+    trace.clear_dirty_flag();
+    trace
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        filter_traces, trace_from_batch, DecodeBatch, DecodeTrace, EncodeBatch, EncodeTrace,
+    };
+    use crate::{
+        circuit::checkpoint,
+        time::NestedTimestamp32,
+        trace::{
+            cursor::Cursor, ord::OrdValBatch, spill::SpillConfig, spine_fueled, Batch, BatchReader,
+            Spine, Trace,
+        },
+        OrdIndexedZSet, OrdZSet,
+    };
+    use bincode::{config::standard, decode_from_slice, encode_to_vec};
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, read_dir, remove_dir_all},
+        process,
+    };
+
+    type TestBatch = OrdValBatch<u64, u64, NestedTimestamp32, isize>;
+
+    fn tuples<B: BatchReader>(batch: &B) -> Vec<(B::Key, B::Val, B::Time, B::R)> {
+        let mut result = Vec::new();
+        let mut cursor = batch.cursor();
+        while cursor.key_valid() {
+            while cursor.val_valid() {
+                let key = cursor.key().clone();
+                let val = cursor.val().clone();
+                cursor.map_times(|time, weight| {
+                    result.push((key.clone(), val.clone(), time.clone(), weight.clone()))
+                });
+                cursor.step_val();
+            }
+            cursor.step_key();
+        }
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn zset_roundtrip() {
+        let zset = OrdZSet::from_keys((), vec![(1, 1), (2, -1), (5, 3)]);
+
+        let bytes = encode_to_vec(EncodeBatch(&zset), standard()).unwrap();
+        let (DecodeBatch(decoded), _): (DecodeBatch<OrdZSet<i32, isize>>, _) =
+            decode_from_slice(&bytes, standard()).unwrap();
+
+        assert_eq!(zset, decoded);
+    }
+
+    #[test]
+    fn empty_roundtrip() {
+        let zset = OrdIndexedZSet::<u64, String, isize>::empty(());
+
+        let bytes = encode_to_vec(EncodeBatch(&zset), standard()).unwrap();
+        let (DecodeBatch(decoded), _): (DecodeBatch<OrdIndexedZSet<u64, String, isize>>, _) =
+            decode_from_slice(&bytes, standard()).unwrap();
+
+        assert!(decoded.is_empty());
+    }
+
+    #[test]
+    fn trace_roundtrip() {
+        let mut trace = <Spine<TestBatch>>::new(None);
+        trace.insert(TestBatch::from_tuples(
+            NestedTimestamp32::new(false, 0),
+            vec![((1, 1), 1), ((2, 2), 2)],
+        ));
+        trace.insert(TestBatch::from_tuples(
+            NestedTimestamp32::new(true, 1),
+            vec![((1, 1), -1), ((3, 3), 1)],
+        ));
+
+        let bytes = encode_to_vec(EncodeBatch(&trace), standard()).unwrap();
+        let (DecodeBatch(batch), _): (DecodeBatch<TestBatch>, _) =
+            decode_from_slice(&bytes, standard()).unwrap();
+        let restored: Spine<TestBatch> = trace_from_batch(batch);
+
+        assert_eq!(tuples(&trace), tuples(&restored));
+        assert!(!restored.dirty());
+    }
+
+    #[test]
+    fn spilled_trace_roundtrip() {
+        type TestSpine = spine_fueled::Spine<OrdZSet<u64, isize>>;
+
+        let directory = temp_dir().join(format!("dbsp-spilled-roundtrip-{}", process::id()));
+        let config = SpillConfig::new(directory.join("spill")).with_min_batch_size(16);
+        let checkpoint_dir = directory.join("checkpoint");
+        create_dir_all(&checkpoint_dir).unwrap();
+
+        let mut trace = TestSpine::new(None);
+        trace.set_spill_config(config.clone());
+        for i in 0..100u64 {
+            trace.insert(OrdZSet::from_keys(
+                (),
+                (i * 10..(i + 1) * 10).map(|k| (k, 1)).collect(),
+            ));
+        }
+        let expected = tuples(&trace);
+
+        // Spilled batches are added to the checkpoint directory instead of
+        // being serialized.
+        let bytes = checkpoint::with_dir(&checkpoint_dir, || {
+            encode_to_vec(EncodeTrace(&trace), standard())
+        })
+        .unwrap();
+        let files = read_dir(&checkpoint_dir).unwrap().count();
+        assert!(files > 0);
+        assert!(
+            bytes.len()
+                < encode_to_vec(EncodeBatch(&trace), standard())
+                    .unwrap()
+                    .len()
+        );
+
+        // The checkpoint outlives the trace.
+        drop(trace);
+        assert_eq!(read_dir(&checkpoint_dir).unwrap().count(), files);
+
+        SpillConfig::set_default(Some(config));
+        let (DecodeTrace(restored), _): (DecodeTrace<TestSpine>, _) =
+            checkpoint::with_dir(&checkpoint_dir, || decode_from_slice(&bytes, standard()))
+                .unwrap();
+        SpillConfig::set_default(None);
+        assert_eq!(tuples(&restored), expected);
+        assert!(!restored.dirty());
+        drop(restored);
+
+        // Without a spill configuration, the trace is restored in memory.
+        let (DecodeTrace(restored), _): (DecodeTrace<TestSpine>, _) =
+            checkpoint::with_dir(&checkpoint_dir, || decode_from_slice(&bytes, standard()))
+                .unwrap();
+        assert_eq!(tuples(&restored), expected);
+        assert_eq!(read_dir(&checkpoint_dir).unwrap().count(), files);
+
+        remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn filter() {
+        let batch1 = TestBatch::from_tuples(
+            NestedTimestamp32::new(false, 0),
+            vec![((1, 1), 1), ((2, 2), 2)],
+        );
+        let batch2 = TestBatch::from_tuples(
+            NestedTimestamp32::new(true, 1),
+            vec![((1, 1), -1), ((3, 3), 1), ((4, 4), 1)],
+        );
+
+        let mut filtered = <Spine<TestBatch>>::new(None);
+        filter_traces([&batch1, &batch2], |key| key % 2 == 1, &mut filtered);
+
+        assert_eq!(
+            tuples(&filtered),
+            vec![
+                (1, 1, NestedTimestamp32::new(false, 0), 1),
+                (1, 1, NestedTimestamp32::new(true, 1), -1),
+                (3, 3, NestedTimestamp32::new(true, 1), 1),
+            ]
+        );
+    }
+}
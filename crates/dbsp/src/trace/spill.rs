@@ -0,0 +1,938 @@
+//! Spilling trace batches to disk.
+//!
+//! By default, [`Spine`](`crate::trace::spine_fueled::Spine`) keeps all of its
+//! batches in memory.  Given a [`SpillConfig`], the spine instead writes
+//! batches that grow to at least [`SpillConfig::min_batch_size`] updates to
+//! files in [`SpillConfig::directory`] and accesses them through memory maps.
+//! Large batches at the bottom of the spine are rarely merged, so most of the
+//! state of a long-running circuit ends up on disk and only occupies memory
+//! while the OS keeps its pages cached.
+//!
+//! A file consists of a sequence of bincode-encoded chunks, each holding all
+//! updates for a run of consecutive keys.  The first key of each chunk is
+//! kept in memory, so that cursors can seek directly to the chunk containing
+//! a key and only decode that chunk.  The file is deleted when the batch is
+//! dropped.
+//!
+//! Spilled batches are merged incrementally by a [`FileBatchMerger`], which
+//! streams the merged updates into a new file.  A checkpoint of a trace can
+//! reference its batch files instead of copying their contents (see
+//! `FileBatch::link_to`).
+//!
+//! Spilling requires the `checkpoint` feature (enabled by default), which
+//! makes the contents of traces serializable.  Without the feature, traces
+//! ignore their spill configuration and keep all batches in memory.  The
+//! persistent trace used by operators with the `persistence` feature stores
+//! its state in RocksDB and also ignores the spill configuration.
+
+use crate::{
+    trace::{
+        consolidation::consolidate,
+        cursor::{Cursor, CursorList},
+        Batch,
+    },
+    DBData, DBTimestamp, DBWeight,
+};
+#[cfg(feature = "checkpoint")]
+use bincode::{config::standard, decode_from_slice, encode_to_vec, Decode, Encode};
+use memmap2::Mmap;
+#[cfg(feature = "checkpoint")]
+use std::fs::{copy, hard_link};
+use std::{
+    cell::RefCell,
+    cmp::max,
+    collections::BTreeMap,
+    fmt::{self, Debug},
+    fs::{create_dir_all, remove_file, File},
+    io::{self, BufWriter, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Target number of updates in a chunk.
+///
+/// A chunk is only closed at a key boundary, so chunks with many updates per
+/// key can be larger.
+const CHUNK_SIZE: usize = 4096;
+
+/// Whether traces can spill batches to disk (see the module documentation).
+pub(crate) const SPILL_SUPPORTED: bool = cfg!(feature = "checkpoint");
+
+/// Used to generate unique file names.
+static NEXT_FILE_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // Spill configuration of traces created by the current thread.
+    static DEFAULT_SPILL_CONFIG: RefCell<Option<SpillConfig>> = RefCell::new(None);
+}
+
+/// Configuration of traces that spill batches to disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpillConfig {
+    /// Directory where batch files are created.
+    pub directory: PathBuf,
+    /// Batches with fewer updates stay in memory.
+    pub min_batch_size: usize,
+}
+
+impl SpillConfig {
+    /// Default value of [`Self::min_batch_size`].
+    pub const DEFAULT_MIN_BATCH_SIZE: usize = 1 << 20;
+
+    /// Spill batches to files in `directory`.
+    pub fn new<P>(directory: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            directory: directory.into(),
+            min_batch_size: Self::DEFAULT_MIN_BATCH_SIZE,
+        }
+    }
+
+    /// Only spill batches with at least `min_batch_size` updates.
+    pub fn with_min_batch_size(mut self, min_batch_size: usize) -> Self {
+        self.min_batch_size = min_batch_size;
+        self
+    }
+
+    /// Sets the spill configuration of all traces subsequently created by
+    /// the current thread.
+    ///
+    /// Circuits create their traces in the thread that builds them.  Calling
+    /// this function from the constructor passed to
+    /// [`RootCircuit::build`](`crate::RootCircuit::build`) or
+    /// [`Runtime::init_circuit`](`crate::Runtime::init_circuit`) before adding
+    /// any operators enables spilling for all traces in the circuit.  Use
+    /// [`Stream::spill_traces`](`crate::Stream::spill_traces`) to configure
+    /// traces of individual streams instead.
+    pub fn set_default(config: Option<SpillConfig>) {
+        DEFAULT_SPILL_CONFIG.with(|default| *default.borrow_mut() = config);
+    }
+
+    /// Returns the configuration set by [`Self::set_default`].
+    pub fn default_config() -> Option<SpillConfig> {
+        DEFAULT_SPILL_CONFIG.with(|default| default.borrow().clone())
+    }
+}
+
+/// Updates for a run of keys, grouped by key and value.
+type ChunkData<K, V, T, R> = Vec<(K, Vec<(V, Vec<(T, R)>)>)>;
+
+/// Location of a chunk in a file.
+struct ChunkIndex<K> {
+    first_key: K,
+    start: usize,
+    end: usize,
+}
+
+/// An immutable collection of updates stored in a memory-mapped file.
+pub struct FileBatch<K, V, T, R> {
+    path: PathBuf,
+    mmap: Mmap,
+    index: Vec<ChunkIndex<K>>,
+    last_key: K,
+    key_count: usize,
+    len: usize,
+    _phantom: PhantomData<(V, T, R)>,
+}
+
+impl<K, V, T, R> Debug for FileBatch<K, V, T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileBatch")
+            .field("path", &self.path)
+            .field("key_count", &self.key_count)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<K, V, T, R> Drop for FileBatch<K, V, T, R> {
+    fn drop(&mut self) {
+        // The file is private to this batch; failure to delete it only
+        // wastes disk space.
+        let _ = remove_file(&self.path);
+    }
+}
+
+impl<K, V, T, R> FileBatch<K, V, T, R>
+where
+    K: DBData,
+    V: DBData,
+    T: DBTimestamp,
+    R: DBWeight,
+{
+    /// Writes the contents of `cursor` to a new file in `directory`.
+    ///
+    /// Skips keys below `lower_bound` and replaces each timestamp `t` with
+    /// `map_time(t)`, consolidating updates whose timestamps become equal.
+    /// Returns `None` if there are no updates to write.
+    pub fn from_cursor<'s, C, F>(
+        directory: &Path,
+        cursor: &mut C,
+        lower_bound: Option<&K>,
+        map_time: F,
+    ) -> io::Result<Option<Self>>
+    where
+        C: Cursor<'s, K, V, T, R>,
+        F: Fn(&T) -> T,
+    {
+        let mut writer = FileBatchWriter::new(directory)?;
+
+        if let Some(lower_bound) = lower_bound {
+            cursor.seek_key(lower_bound);
+        }
+
+        while cursor.key_valid() {
+            writer.push_cursor_key(cursor, &map_time)?;
+            cursor.step_key();
+        }
+
+        writer.finish()
+    }
+
+    /// Adds the batch file to checkpoint directory `directory` and syncs it
+    /// to disk.
+    ///
+    /// The file is hard-linked rather than copied if possible and remains in
+    /// `directory` after the batch is dropped.  Returns the metadata needed
+    /// to open it with [`Self::open_link`].
+    #[cfg(feature = "checkpoint")]
+    pub fn link_to(&self, directory: &Path) -> io::Result<FileBatchMeta<K>> {
+        let file_name = self
+            .path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let link = directory.join(&file_name);
+        link_or_copy(&self.path, &link)?;
+        File::open(&link)?.sync_all()?;
+
+        Ok(FileBatchMeta {
+            file_name,
+            index: self
+                .index
+                .iter()
+                .map(|chunk| {
+                    (
+                        chunk.first_key.clone(),
+                        chunk.start as u64,
+                        chunk.end as u64,
+                    )
+                })
+                .collect(),
+            last_key: self.last_key.clone(),
+            key_count: self.key_count as u64,
+            len: self.len as u64,
+        })
+    }
+
+    /// Opens a batch file added to checkpoint directory `directory` by
+    /// [`Self::link_to`].
+    ///
+    /// The batch accesses the file through a new link in `spill_directory`,
+    /// which is deleted with the batch, leaving the checkpoint intact.
+    #[cfg(feature = "checkpoint")]
+    pub fn open_link(
+        directory: &Path,
+        meta: FileBatchMeta<K>,
+        spill_directory: &Path,
+    ) -> io::Result<Self> {
+        create_dir_all(spill_directory)?;
+        let path = new_file_path(spill_directory);
+        link_or_copy(&directory.join(&meta.file_name), &path)?;
+
+        // SAFETY: Batch files are never modified after they are written.
+        let mmap = File::open(&path)
+            .and_then(|file| unsafe { Mmap::map(&file) })
+            .and_then(|mmap| {
+                if meta
+                    .index
+                    .iter()
+                    .all(|(_, start, end)| start <= end && *end as usize <= mmap.len())
+                {
+                    Ok(mmap)
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("batch file '{}' is truncated", meta.file_name),
+                    ))
+                }
+            })
+            .map_err(|error| {
+                let _ = remove_file(&path);
+                error
+            })?;
+
+        Ok(Self {
+            path,
+            mmap,
+            index: meta
+                .index
+                .into_iter()
+                .map(|(first_key, start, end)| ChunkIndex {
+                    first_key,
+                    start: start as usize,
+                    end: end as usize,
+                })
+                .collect(),
+            last_key: meta.last_key,
+            key_count: meta.key_count as usize,
+            len: meta.len as usize,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Path to the file that stores the batch.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of distinct keys in the batch.
+    pub fn key_count(&self) -> usize {
+        self.key_count
+    }
+
+    /// Number of updates in the batch.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the batch contains no updates.
+    ///
+    /// Empty batches are never written to disk, so this always returns
+    /// `false`.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Creates a cursor over the batch.
+    pub fn cursor(&self) -> FileCursor<'_, K, V, T, R> {
+        FileCursor::new(self)
+    }
+
+    /// Reads the contents of the file into an in-memory batch.
+    pub fn to_batch<B>(&self) -> B
+    where
+        B: Batch<Key = K, Val = V, Time = T, R = R>,
+    {
+        // Builders assign the same timestamp to all updates, so we build a
+        // separate batch for each distinct timestamp and merge them.
+        let mut updates = BTreeMap::<T, Vec<(B::Item, R)>>::new();
+        let mut cursor = self.cursor();
+        while cursor.key_valid() {
+            while cursor.val_valid() {
+                let key = cursor.key().clone();
+                let val = cursor.val().clone();
+                cursor.map_times(|time, weight| {
+                    updates
+                        .entry(time.clone())
+                        .or_default()
+                        .push((B::item_from(key.clone(), val.clone()), weight.clone()))
+                });
+                cursor.step_val();
+            }
+            cursor.step_key();
+        }
+
+        updates
+            .into_iter()
+            .map(|(time, tuples)| B::from_tuples(time, tuples))
+            .reduce(|batch1, batch2| batch1.merge(&batch2))
+            .unwrap_or_else(|| B::empty(T::minimum()))
+    }
+
+    fn decode_chunk(&self, chunk: usize) -> ChunkData<K, V, T, R> {
+        let ChunkIndex { start, end, .. } = self.index[chunk];
+        decode_chunk(&self.mmap[start..end]).unwrap_or_else(|error| {
+            panic!(
+                "failed to decode chunk {chunk} of '{}': {error}",
+                self.path.display()
+            )
+        })
+    }
+}
+
+/// Metadata of a batch file in a checkpoint (see [`FileBatch::link_to`]).
+#[cfg(feature = "checkpoint")]
+#[derive(Encode, Decode)]
+pub struct FileBatchMeta<K> {
+    file_name: String,
+    /// First key, start offset, and end offset of each chunk.
+    index: Vec<(K, u64, u64)>,
+    last_key: K,
+    key_count: u64,
+    len: u64,
+}
+
+/// Creates a hard link `to` to file `from` or, if that fails, e.g., because
+/// the paths are on different file systems, copies the file.
+#[cfg(feature = "checkpoint")]
+fn link_or_copy(from: &Path, to: &Path) -> io::Result<()> {
+    match hard_link(from, to) {
+        // Never overwrite an existing file, which may be `from` itself.
+        Err(error) if error.kind() != io::ErrorKind::AlreadyExists => copy(from, to).map(drop),
+        result => result,
+    }
+}
+
+/// Returns a unique path for a new batch file in `directory`.
+fn new_file_path(directory: &Path) -> PathBuf {
+    directory.join(format!(
+        "batch-{}-{}.dat",
+        process::id(),
+        NEXT_FILE_ID.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+#[cfg(feature = "checkpoint")]
+fn encode_chunk<C: Encode>(chunk: &C) -> io::Result<Vec<u8>> {
+    encode_to_vec(chunk, standard())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+#[cfg(feature = "checkpoint")]
+fn decode_chunk<C: Decode>(bytes: &[u8]) -> io::Result<C> {
+    decode_from_slice(bytes, standard())
+        .map(|(chunk, _)| chunk)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+// Without the `checkpoint` feature, traces never create batch files (see
+// `SPILL_SUPPORTED`).
+#[cfg(not(feature = "checkpoint"))]
+fn encode_chunk<C>(_chunk: &C) -> io::Result<Vec<u8>> {
+    unreachable!("spilling requires the `checkpoint` feature")
+}
+
+#[cfg(not(feature = "checkpoint"))]
+fn decode_chunk<C>(_bytes: &[u8]) -> io::Result<C> {
+    unreachable!("spilling requires the `checkpoint` feature")
+}
+
+/// Writes updates to a new batch file, one key at a time.
+struct FileBatchWriter<K, V, T, R> {
+    path: PathBuf,
+    file: BufWriter<File>,
+    offset: usize,
+    index: Vec<ChunkIndex<K>>,
+    chunk: ChunkData<K, V, T, R>,
+    chunk_len: usize,
+    last_key: Option<K>,
+    key_count: usize,
+    len: usize,
+}
+
+impl<K, V, T, R> FileBatchWriter<K, V, T, R>
+where
+    K: DBData,
+    V: DBData,
+    T: DBTimestamp,
+    R: DBWeight,
+{
+    fn new(directory: &Path) -> io::Result<Self> {
+        create_dir_all(directory)?;
+
+        let path = new_file_path(directory);
+        let file = BufWriter::new(File::create(&path)?);
+
+        Ok(Self {
+            path,
+            file,
+            offset: 0,
+            index: Vec::new(),
+            chunk: Vec::new(),
+            chunk_len: 0,
+            last_key: None,
+            key_count: 0,
+            len: 0,
+        })
+    }
+
+    /// Appends updates for `key`, which must be greater than all previously
+    /// pushed keys.
+    fn push(&mut self, key: K, vals: Vec<(V, Vec<(T, R)>)>) -> io::Result<()> {
+        debug_assert!(self.last_key.as_ref().map_or(true, |last| last < &key));
+
+        let updates: usize = vals.iter().map(|(_, times)| times.len()).sum();
+        self.key_count += 1;
+        self.len += updates;
+        self.chunk_len += updates;
+        self.last_key = Some(key.clone());
+        self.chunk.push((key, vals));
+
+        if self.chunk_len >= CHUNK_SIZE {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    /// Appends the updates for the current key of `cursor`, replacing each
+    /// timestamp `t` with `map_time(t)` and consolidating the results.
+    ///
+    /// Leaves the cursor at the same key.  Returns the number of updates read
+    /// from the cursor.
+    fn push_cursor_key<'s, C, F>(&mut self, cursor: &mut C, map_time: &F) -> io::Result<usize>
+    where
+        C: Cursor<'s, K, V, T, R>,
+        F: Fn(&T) -> T,
+    {
+        let mut read = 0;
+        let mut vals = Vec::new();
+        while cursor.val_valid() {
+            let mut times = cursor.fold_times(Vec::new(), |mut times, time, weight| {
+                times.push((map_time(time), weight.clone()));
+                times
+            });
+            read += times.len();
+            consolidate(&mut times);
+            if !times.is_empty() {
+                vals.push((cursor.val().clone(), times));
+            }
+            cursor.step_val();
+        }
+        if !vals.is_empty() {
+            self.push(cursor.key().clone(), vals)?;
+        }
+        Ok(read)
+    }
+
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        let bytes = encode_chunk(&self.chunk)?;
+        self.file.write_all(&bytes)?;
+
+        self.index.push(ChunkIndex {
+            first_key: self.chunk[0].0.clone(),
+            start: self.offset,
+            end: self.offset + bytes.len(),
+        });
+        self.offset += bytes.len();
+        self.chunk.clear();
+        self.chunk_len = 0;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<Option<FileBatch<K, V, T, R>>> {
+        self.flush_chunk()?;
+
+        let file = self.file.into_inner().map_err(|error| error.into_error())?;
+        let last_key = match self.last_key {
+            Some(last_key) => last_key,
+            None => {
+                drop(file);
+                remove_file(&self.path)?;
+                return Ok(None);
+            }
+        };
+
+        // SAFETY: The file is created by this process and is never modified
+        // after this point.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        Ok(Some(FileBatch {
+            path: self.path,
+            mmap,
+            index: self.index,
+            last_key,
+            key_count: self.key_count,
+            len: self.len,
+            _phantom: PhantomData,
+        }))
+    }
+}
+
+/// A merge of two [`FileBatch`]es into a new batch file, performed in
+/// increments.
+///
+/// The merger does not own the batches it merges: each call to
+/// [`Self::work`] must pass the same two batches, which must stay alive until
+/// the merge is finished.
+pub struct FileBatchMerger<K, V, T, R> {
+    /// `None` once the merge is finished.
+    writer: Option<FileBatchWriter<K, V, T, R>>,
+    /// The smallest key that hasn't been merged yet, if the merge has
+    /// started.
+    next_key: Option<K>,
+    done: bool,
+}
+
+impl<K, V, T, R> Debug for FileBatchMerger<K, V, T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileBatchMerger")
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl<K, V, T, R> Drop for FileBatchMerger<K, V, T, R> {
+    fn drop(&mut self) {
+        // Delete the output of an abandoned merge.
+        if let Some(writer) = self.writer.take() {
+            let _ = remove_file(&writer.path);
+        }
+    }
+}
+
+impl<K, V, T, R> FileBatchMerger<K, V, T, R>
+where
+    K: DBData,
+    V: DBData,
+    T: DBTimestamp,
+    R: DBWeight,
+{
+    /// Starts a merge that writes its output to a new file in `directory`.
+    pub fn new(directory: &Path) -> io::Result<Self> {
+        Ok(Self {
+            writer: Some(FileBatchWriter::new(directory)?),
+            next_key: None,
+            done: false,
+        })
+    }
+
+    /// Returns `true` if all updates have been merged.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Merges keys of `batch1` and `batch2` until it runs out of `fuel`,
+    /// measured in updates read from the batches, skipping keys below
+    /// `lower_bound`.
+    pub fn work(
+        &mut self,
+        batch1: &FileBatch<K, V, T, R>,
+        batch2: &FileBatch<K, V, T, R>,
+        lower_bound: Option<&K>,
+        fuel: &mut isize,
+    ) -> io::Result<()> {
+        // Cursors borrow the batches, so each increment creates new cursors
+        // and seeks to where the previous one stopped.
+        let mut cursor = CursorList::new(vec![batch1.cursor(), batch2.cursor()]);
+        for key in self.next_key.iter().chain(lower_bound) {
+            cursor.seek_key(key);
+        }
+
+        let writer = self.writer.as_mut().unwrap();
+        while *fuel > 0 && cursor.key_valid() {
+            let read = writer.push_cursor_key(&mut cursor, &Clone::clone)?;
+            *fuel -= max(read, 1) as isize;
+            cursor.step_key();
+        }
+
+        if cursor.key_valid() {
+            self.next_key = Some(cursor.key().clone());
+        } else {
+            self.done = true;
+        }
+        Ok(())
+    }
+
+    /// Returns the merged batch or `None` if it is empty.
+    ///
+    /// Must only be called once the merge is done.
+    pub fn finish(mut self) -> io::Result<Option<FileBatch<K, V, T, R>>> {
+        debug_assert!(self.done);
+        self.writer.take().unwrap().finish()
+    }
+}
+
+/// A cursor over a [`FileBatch`].
+///
+/// The cursor decodes one chunk of the file at a time.
+pub struct FileCursor<'s, K, V, T, R> {
+    batch: &'s FileBatch<K, V, T, R>,
+    /// Index of the decoded chunk; equals the number of chunks when the
+    /// cursor is exhausted.
+    chunk_index: usize,
+    chunk: ChunkData<K, V, T, R>,
+    key_index: usize,
+    val_index: usize,
+}
+
+impl<'s, K, V, T, R> FileCursor<'s, K, V, T, R>
+where
+    K: DBData,
+    V: DBData,
+    T: DBTimestamp,
+    R: DBWeight,
+{
+    fn new(batch: &'s FileBatch<K, V, T, R>) -> Self {
+        let mut cursor = Self {
+            batch,
+            chunk_index: 0,
+            chunk: Vec::new(),
+            key_index: 0,
+            val_index: 0,
+        };
+        cursor.load_chunk(0);
+        cursor
+    }
+
+    fn load_chunk(&mut self, chunk: usize) {
+        self.chunk_index = chunk;
+        self.chunk = if chunk < self.batch.index.len() {
+            self.batch.decode_chunk(chunk)
+        } else {
+            Vec::new()
+        };
+        self.key_index = 0;
+        self.val_index = 0;
+    }
+
+    fn vals(&self) -> &[(V, Vec<(T, R)>)] {
+        &self.chunk[self.key_index].1
+    }
+
+    fn times(&self) -> &[(T, R)] {
+        &self.vals()[self.val_index].1
+    }
+}
+
+impl<'s, K, V, T, R> Cursor<'s, K, V, T, R> for FileCursor<'s, K, V, T, R>
+where
+    K: DBData,
+    V: DBData,
+    T: DBTimestamp,
+    R: DBWeight,
+{
+    fn key_valid(&self) -> bool {
+        self.key_index < self.chunk.len()
+    }
+
+    fn val_valid(&self) -> bool {
+        self.key_valid() && self.val_index < self.vals().len()
+    }
+
+    fn key(&self) -> &K {
+        &self.chunk[self.key_index].0
+    }
+
+    fn val(&self) -> &V {
+        &self.vals()[self.val_index].0
+    }
+
+    fn fold_times<F, U>(&mut self, init: U, mut fold: F) -> U
+    where
+        F: FnMut(U, &T, &R) -> U,
+    {
+        self.times()
+            .iter()
+            .fold(init, |init, (time, weight)| fold(init, time, weight))
+    }
+
+    fn fold_times_through<F, U>(&mut self, upper: &T, init: U, mut fold: F) -> U
+    where
+        F: FnMut(U, &T, &R) -> U,
+    {
+        self.times()
+            .iter()
+            .filter(|(time, _)| time.less_equal(upper))
+            .fold(init, |init, (time, weight)| fold(init, time, weight))
+    }
+
+    fn weight(&mut self) -> R
+    where
+        T: PartialEq<()>,
+    {
+        debug_assert!(self.val_valid());
+        self.times()[0].1.clone()
+    }
+
+    fn step_key(&mut self) {
+        self.key_index += 1;
+        self.val_index = 0;
+        if self.key_index == self.chunk.len() && self.chunk_index < self.batch.index.len() {
+            self.load_chunk(self.chunk_index + 1);
+        }
+    }
+
+    fn seek_key(&mut self, key: &K) {
+        if !self.key_valid() || self.key() >= key {
+            return;
+        }
+
+        // Find the last chunk whose first key does not exceed `key`.
+        let chunk = self.batch.index[self.chunk_index..]
+            .partition_point(|chunk| &chunk.first_key <= key)
+            + self.chunk_index
+            - 1;
+        if chunk != self.chunk_index {
+            self.load_chunk(chunk);
+        }
+
+        self.key_index = max(self.key_index, self.chunk.partition_point(|(k, _)| k < key));
+        self.val_index = 0;
+        if self.key_index == self.chunk.len() {
+            self.load_chunk(self.chunk_index + 1);
+        }
+    }
+
+    fn last_key(&mut self) -> Option<&K> {
+        Some(&self.batch.last_key)
+    }
+
+    fn step_val(&mut self) {
+        self.val_index += 1;
+    }
+
+    fn seek_val(&mut self, val: &V) {
+        if self.key_valid() {
+            self.val_index =
+                self.val_index + self.vals()[self.val_index..].partition_point(|(v, _)| v < val);
+        }
+    }
+
+    fn seek_val_with<P>(&mut self, predicate: P)
+    where
+        P: Fn(&V) -> bool + Clone,
+    {
+        while self.val_valid() && !predicate(self.val()) {
+            self.val_index += 1;
+        }
+    }
+
+    fn rewind_keys(&mut self) {
+        if self.chunk_index == 0 {
+            self.key_index = 0;
+            self.val_index = 0;
+        } else {
+            self.load_chunk(0);
+        }
+    }
+
+    fn rewind_vals(&mut self) {
+        self.val_index = 0;
+    }
+}
+
+#[cfg(all(test, feature = "checkpoint"))]
+mod test {
+    use super::{FileBatch, FileBatchMerger, SpillConfig};
+    use crate::{
+        trace::{cursor::Cursor, Batch, BatchReader},
+        OrdIndexedZSet, OrdZSet,
+    };
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, read_dir, remove_dir_all},
+        process,
+    };
+
+    #[test]
+    fn file_batch_cursor() {
+        let directory = temp_dir().join(format!("dbsp-spill-test-{}", process::id()));
+        let config = SpillConfig::new(&directory);
+
+        let tuples: Vec<_> = (0..10_000u64)
+            .flat_map(|k| (0..3i64).map(move |v| ((k, v), 1isize)))
+            .filter(|((k, v), _)| (k + *v as u64) % 5 != 0)
+            .collect();
+        let batch = OrdIndexedZSet::<u64, i64, isize>::from_tuples((), tuples.clone());
+
+        let file_batch =
+            FileBatch::from_cursor(&config.directory, &mut batch.cursor(), None, |t| *t)
+                .unwrap()
+                .unwrap();
+        let path = file_batch.path().to_path_buf();
+        assert!(path.exists());
+        assert_eq!(file_batch.len(), batch.len());
+        assert_eq!(file_batch.key_count(), batch.key_count());
+
+        // Read back all updates.
+        let mut contents = Vec::new();
+        let mut cursor = file_batch.cursor();
+        while cursor.key_valid() {
+            while cursor.val_valid() {
+                contents.push(((*cursor.key(), *cursor.val()), cursor.weight()));
+                cursor.step_val();
+            }
+            cursor.step_key();
+        }
+        assert_eq!(contents, tuples);
+
+        // Seek across chunk boundaries.
+        let mut cursor = file_batch.cursor();
+        for key in [7, 4095, 4096, 8000, 9999] {
+            cursor.seek_key(&key);
+            assert_eq!(cursor.key(), &key);
+            cursor.seek_val(&2);
+            assert_eq!(cursor.val_valid(), (key + 2) % 5 != 0);
+        }
+        cursor.seek_key(&10_000);
+        assert!(!cursor.key_valid());
+        cursor.rewind_keys();
+        assert_eq!(cursor.key(), &0);
+        assert_eq!(cursor.last_key(), Some(&9999));
+
+        // Truncation and conversion back to an in-memory batch.
+        let truncated = FileBatch::from_cursor(
+            &config.directory,
+            &mut file_batch.cursor(),
+            Some(&5000),
+            |t| *t,
+        )
+        .unwrap()
+        .unwrap();
+        let expected = OrdIndexedZSet::<u64, i64, isize>::from_tuples(
+            (),
+            tuples
+                .into_iter()
+                .filter(|((k, _), _)| *k >= 5000)
+                .collect(),
+        );
+        assert_eq!(
+            truncated.to_batch::<OrdIndexedZSet<u64, i64, isize>>(),
+            expected
+        );
+
+        drop(file_batch);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn merge_and_link() {
+        let directory = temp_dir().join(format!("dbsp-spill-merge-test-{}", process::id()));
+        let checkpoint = directory.join("checkpoint");
+        create_dir_all(&checkpoint).unwrap();
+
+        let batch1 = OrdZSet::<u64, isize>::from_keys((), (0..5000).map(|k| (k * 2, 1)).collect());
+        let batch2 = OrdZSet::<u64, isize>::from_keys((), (0..5000).map(|k| (k * 3, 1)).collect());
+        let file1 = FileBatch::from_cursor(&directory, &mut batch1.cursor(), None, |t| *t)
+            .unwrap()
+            .unwrap();
+        let file2 = FileBatch::from_cursor(&directory, &mut batch2.cursor(), None, |t| *t)
+            .unwrap()
+            .unwrap();
+
+        // Merge in small increments, discarding keys below 100.
+        let mut merger = FileBatchMerger::new(&directory).unwrap();
+        let mut increments = 0;
+        while !merger.is_done() {
+            let mut fuel = 1000;
+            merger.work(&file1, &file2, Some(&100), &mut fuel).unwrap();
+            increments += 1;
+        }
+        assert!(increments > 5);
+
+        let merged = merger.finish().unwrap().unwrap();
+        let mut expected = batch1.merge(&batch2);
+        expected.truncate_keys_below(&100);
+        assert_eq!(merged.to_batch::<OrdZSet<u64, isize>>(), expected);
+
+        // The linked file outlives the batch and can be reopened.
+        let meta = merged.link_to(&checkpoint).unwrap();
+        drop(merged);
+        let reopened = FileBatch::open_link(&checkpoint, meta, &directory).unwrap();
+        assert_eq!(reopened.to_batch::<OrdZSet<u64, isize>>(), expected);
+        drop(reopened);
+        assert_eq!(read_dir(&checkpoint).unwrap().count(), 1);
+
+        remove_dir_all(&directory).unwrap();
+    }
+}
@@ -78,22 +78,58 @@
 //! at low layers: they should still extract fuel from new updates even though
 //! they have completed, at least until they have paid back any "debt" to higher
 //! layers by continuing to provide fuel as updates arrive.
+//!
+//! ## Spilling
+//!
+//! A spine configured with a [`SpillConfig`] writes batches with at least
+//! [`SpillConfig::min_batch_size`] updates to disk as they get extracted from
+//! completed merges (see [`spill`](`crate::trace::spill`)).  Spilled batches
+//! are kept outside of the layers: they are merged with each other whenever
+//! the size of a spilled batch grows close to the size of the preceding one,
+//! so that their sizes decrease geometrically and the number of files is
+//! logarithmic in the size of the trace.  Like merges of in-memory batches,
+//! merges of spilled batches proceed incrementally as the spine receives
+//! fuel, and cursors read from both inputs until the merge completes.
+//!
+//! With the `checkpoint` feature, a checkpoint of the spine references its
+//! spilled batches by adding their files to the checkpoint directory instead
+//! of serializing their contents.
 
+#[cfg(feature = "checkpoint")]
+use crate::{
+    circuit::checkpoint,
+    trace::{
+        serialize::{decode_batch, encode_batch, encode_cursor},
+        spill::FileBatchMeta,
+    },
+};
 use crate::{
     circuit::Activator,
     time::{Antichain, AntichainRef, Timestamp},
     trace::{
+        consolidation::consolidate,
         cursor::{Cursor, CursorList},
         rc_batch::RcBatchCursor,
-        Batch, BatchReader, Consumer, Merger, Trace, ValueConsumer,
+        spill::{FileBatch, FileBatchMerger, FileCursor, SpillConfig, SPILL_SUPPORTED},
+        Batch, BatchReader, Builder, Consumer, Merger, Trace, ValueConsumer,
     },
     NumEntries,
 };
+#[cfg(feature = "checkpoint")]
+use bincode::{
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
 use size_of::SizeOf;
 use std::{
+    collections::BTreeMap,
     fmt::{self, Debug, Display},
+    io,
+    iter::once,
     marker::PhantomData,
-    mem::replace,
+    mem::{replace, take},
     rc::Rc,
 };
 use textwrap::indent;
@@ -115,6 +151,57 @@ where
     effort: usize,
     activator: Option<Activator>,
     dirty: bool,
+    /// Keys below this bound are discarded when merging batches.
+    lower_key_bound: Option<B::Key>,
+    /// Spill large batches to disk, if set.
+    #[size_of(skip)]
+    spill: Option<SpillConfig>,
+    /// Batches spilled to disk, in order of decreasing size.
+    #[size_of(skip)]
+    spilled: Vec<SpillLayer<B>>,
+}
+
+/// A batch of a spine spilled to disk.
+type SpilledBatch<B> = FileBatch<
+    <B as BatchReader>::Key,
+    <B as BatchReader>::Val,
+    <B as BatchReader>::Time,
+    <B as BatchReader>::R,
+>;
+
+/// An in-progress merge of two spilled batches.
+type SpillMerger<B> = FileBatchMerger<
+    <B as BatchReader>::Key,
+    <B as BatchReader>::Val,
+    <B as BatchReader>::Time,
+    <B as BatchReader>::R,
+>;
+
+/// A spilled batch or a pair of adjacent spilled batches being merged.
+enum SpillLayer<B>
+where
+    B: Batch,
+{
+    Single(SpilledBatch<B>),
+    Merging(SpilledBatch<B>, SpilledBatch<B>, SpillMerger<B>),
+}
+
+impl<B> SpillLayer<B>
+where
+    B: Batch,
+{
+    /// The batches in the layer; both inputs of a merge until it completes.
+    fn batches(&self) -> impl Iterator<Item = &SpilledBatch<B>> {
+        let (batch1, batch2) = match self {
+            Self::Single(batch) => (batch, None),
+            Self::Merging(batch1, batch2, _) => (batch1, Some(batch2)),
+        };
+        once(batch1).chain(batch2)
+    }
+
+    fn is_merging(&self) -> bool {
+        matches!(self, Self::Merging(..))
+    }
 }
 
 impl<B> Display for Spine<B>
@@ -147,7 +234,7 @@ where
     const CONST_NUM_ENTRIES: Option<usize> = None;
 
     fn num_entries_shallow(&self) -> usize {
-        self.fold_batches(0, |acc, batch| acc + batch.len())
+        self.len()
     }
 
     fn num_entries_deep(&self) -> usize {
@@ -170,11 +257,17 @@ where
     type Consumer = SpineConsumer<B>;
 
     fn key_count(&self) -> usize {
-        self.fold_batches(0, |acc, batch| acc + batch.key_count())
+        self.spilled.iter().flat_map(SpillLayer::batches).fold(
+            self.fold_batches(0, |acc, batch| acc + batch.key_count()),
+            |acc, batch| acc + batch.key_count(),
+        )
     }
 
     fn len(&self) -> usize {
-        self.fold_batches(0, |acc, batch| acc + batch.len())
+        self.spilled.iter().flat_map(SpillLayer::batches).fold(
+            self.fold_batches(0, |acc, batch| acc + batch.len()),
+            |acc, batch| acc + batch.len(),
+        )
     }
 
     fn lower(&self) -> AntichainRef<'_, Self::Time> {
@@ -186,23 +279,26 @@ where
     }
 
     fn cursor(&self) -> Self::Cursor<'_> {
-        let mut cursors = Vec::with_capacity(self.merging.len());
+        let mut cursors = Vec::with_capacity(self.merging.len() + self.spilled.len());
+        for batch in self.spilled.iter().flat_map(SpillLayer::batches) {
+            cursors.push(SpineBatchCursor::File(batch.cursor()));
+        }
         for merge_state in self.merging.iter().rev() {
             match merge_state {
                 MergeState::Double(MergeVariant::InProgress(batch1, batch2, _)) => {
                     if !batch1.is_empty() {
-                        cursors.push(batch1.cursor());
+                        cursors.push(SpineBatchCursor::Memory(batch1.cursor()));
                     }
 
                     if !batch2.is_empty() {
-                        cursors.push(batch2.cursor());
+                        cursors.push(SpineBatchCursor::Memory(batch2.cursor()));
                     }
                 }
 
                 MergeState::Double(MergeVariant::Complete(Some(batch)))
                 | MergeState::Single(Some(batch)) => {
                     if !batch.is_empty() {
-                        cursors.push(batch.cursor());
+                        cursors.push(SpineBatchCursor::Memory(batch.cursor()));
                     }
                 }
 
@@ -212,7 +308,7 @@ where
             }
         }
 
-        SpineCursor::new(cursors)
+        SpineCursor::new(cursors, self.lower_key_bound.as_ref())
     }
 
     fn consumer(self) -> Self::Consumer {
@@ -280,9 +376,15 @@ where
     }
 }
 
+/// Cursor over all batches of a spine.
+///
+/// Skips keys below the spine's lower key bound (see
+/// [`Trace::truncate_keys_below`]), which the spine only discards physically
+/// when merging batches.
 pub struct SpineCursor<'s, B: Batch + 's> {
     #[allow(clippy::type_complexity)]
-    cursor: CursorList<'s, B::Key, B::Val, B::Time, B::R, RcBatchCursor<'s, B>>,
+    cursor: CursorList<'s, B::Key, B::Val, B::Time, B::R, SpineBatchCursor<'s, B>>,
+    lower_key_bound: Option<&'s B::Key>,
 }
 
 impl<'s, B: Batch> SpineCursor<'s, B>
@@ -290,9 +392,18 @@ where
     B::Key: Ord,
     B::Val: Ord,
 {
-    fn new(cursors: Vec<RcBatchCursor<'s, B>>) -> Self {
-        Self {
+    fn new(cursors: Vec<SpineBatchCursor<'s, B>>, lower_key_bound: Option<&'s B::Key>) -> Self {
+        let mut cursor = Self {
             cursor: CursorList::new(cursors),
+            lower_key_bound,
+        };
+        cursor.skip_truncated_keys();
+        cursor
+    }
+
+    fn skip_truncated_keys(&mut self) {
+        if let Some(bound) = self.lower_key_bound {
+            self.cursor.seek_key(bound);
         }
     }
 }
@@ -362,7 +473,10 @@ where
     }
 
     fn last_key(&mut self) -> Option<&B::Key> {
-        self.cursor.last_key()
+        let bound = self.lower_key_bound;
+        self.cursor
+            .last_key()
+            .filter(|key| bound.map_or(true, |bound| *key >= bound))
     }
 
     fn step_val(&mut self) {
@@ -382,6 +496,7 @@ where
 
     fn rewind_keys(&mut self) {
         self.cursor.rewind_keys();
+        self.skip_truncated_keys();
     }
 
     fn rewind_vals(&mut self) {
@@ -389,6 +504,131 @@ where
     }
 }
 
+/// Cursor over an in-memory or a spilled batch of a spine.
+pub enum SpineBatchCursor<'s, B: Batch + 's> {
+    Memory(RcBatchCursor<'s, B>),
+    File(FileCursor<'s, B::Key, B::Val, B::Time, B::R>),
+}
+
+impl<'s, B: Batch> Cursor<'s, B::Key, B::Val, B::Time, B::R> for SpineBatchCursor<'s, B> {
+    fn key_valid(&self) -> bool {
+        match self {
+            Self::Memory(cursor) => cursor.key_valid(),
+            Self::File(cursor) => cursor.key_valid(),
+        }
+    }
+
+    fn val_valid(&self) -> bool {
+        match self {
+            Self::Memory(cursor) => cursor.val_valid(),
+            Self::File(cursor) => cursor.val_valid(),
+        }
+    }
+
+    fn key(&self) -> &B::Key {
+        match self {
+            Self::Memory(cursor) => cursor.key(),
+            Self::File(cursor) => cursor.key(),
+        }
+    }
+
+    fn val(&self) -> &B::Val {
+        match self {
+            Self::Memory(cursor) => cursor.val(),
+            Self::File(cursor) => cursor.val(),
+        }
+    }
+
+    fn fold_times<F, U>(&mut self, init: U, fold: F) -> U
+    where
+        F: FnMut(U, &B::Time, &B::R) -> U,
+    {
+        match self {
+            Self::Memory(cursor) => cursor.fold_times(init, fold),
+            Self::File(cursor) => cursor.fold_times(init, fold),
+        }
+    }
+
+    fn fold_times_through<F, U>(&mut self, upper: &B::Time, init: U, fold: F) -> U
+    where
+        F: FnMut(U, &B::Time, &B::R) -> U,
+    {
+        match self {
+            Self::Memory(cursor) => cursor.fold_times_through(upper, init, fold),
+            Self::File(cursor) => cursor.fold_times_through(upper, init, fold),
+        }
+    }
+
+    fn weight(&mut self) -> B::R
+    where
+        B::Time: PartialEq<()>,
+    {
+        match self {
+            Self::Memory(cursor) => cursor.weight(),
+            Self::File(cursor) => cursor.weight(),
+        }
+    }
+
+    fn step_key(&mut self) {
+        match self {
+            Self::Memory(cursor) => cursor.step_key(),
+            Self::File(cursor) => cursor.step_key(),
+        }
+    }
+
+    fn seek_key(&mut self, key: &B::Key) {
+        match self {
+            Self::Memory(cursor) => cursor.seek_key(key),
+            Self::File(cursor) => cursor.seek_key(key),
+        }
+    }
+
+    fn last_key(&mut self) -> Option<&B::Key> {
+        match self {
+            Self::Memory(cursor) => cursor.last_key(),
+            Self::File(cursor) => cursor.last_key(),
+        }
+    }
+
+    fn step_val(&mut self) {
+        match self {
+            Self::Memory(cursor) => cursor.step_val(),
+            Self::File(cursor) => cursor.step_val(),
+        }
+    }
+
+    fn seek_val(&mut self, val: &B::Val) {
+        match self {
+            Self::Memory(cursor) => cursor.seek_val(val),
+            Self::File(cursor) => cursor.seek_val(val),
+        }
+    }
+
+    fn seek_val_with<P>(&mut self, predicate: P)
+    where
+        P: Fn(&B::Val) -> bool + Clone,
+    {
+        match self {
+            Self::Memory(cursor) => cursor.seek_val_with(predicate),
+            Self::File(cursor) => cursor.seek_val_with(predicate),
+        }
+    }
+
+    fn rewind_keys(&mut self) {
+        match self {
+            Self::Memory(cursor) => cursor.rewind_keys(),
+            Self::File(cursor) => cursor.rewind_keys(),
+        }
+    }
+
+    fn rewind_vals(&mut self) {
+        match self {
+            Self::Memory(cursor) => cursor.rewind_vals(),
+            Self::File(cursor) => cursor.rewind_vals(),
+        }
+    }
+}
+
 pub struct SpineConsumer<B>
 where
     B: Batch,
@@ -400,7 +640,8 @@ impl<B> Consumer<B::Key, B::Val, B::R, B::Time> for SpineConsumer<B>
 where
     B: Batch,
 {
-    type ValueConsumer<'a> = SpineValueConsumer<'a, B>
+    type ValueConsumer<'a>
+        = SpineValueConsumer<'a, B>
     where
         Self: 'a;
 
@@ -472,8 +713,41 @@ where
         // Complete all in-progress merges, as we don't have an easy way to update
         // timestamps in an ongoing merge.
         self.complete_merges();
+        self.complete_spill_merges();
 
         self.map_batches_mut(|b| b.recede_to(frontier));
+
+        // Spilled batches are immutable, so we rewrite them with new timestamps.
+        for layer in take(&mut self.spilled) {
+            for batch in layer.batches() {
+                if let Some(batch) = Self::spill_cursor(
+                    self.spill.as_ref().unwrap(),
+                    &mut batch.cursor(),
+                    self.lower_key_bound.as_ref(),
+                    |time| time.meet(frontier),
+                ) {
+                    self.spilled.push(SpillLayer::Single(batch));
+                }
+            }
+        }
+    }
+
+    fn truncate_keys_below(&mut self, lower_bound: &Self::Key) {
+        // Keys below the bound are discarded lazily, as batches get merged,
+        // so truncation costs no more than the merges themselves.
+        if self
+            .lower_key_bound
+            .as_ref()
+            .map_or(true, |bound| bound < lower_bound)
+        {
+            self.lower_key_bound = Some(lower_bound.clone());
+        }
+    }
+
+    fn set_spill_config(&mut self, config: SpillConfig) {
+        if SPILL_SUPPORTED {
+            self.spill = Some(config);
+        }
     }
 
     /// Apply some amount of effort to trace maintenance.
@@ -499,6 +773,11 @@ where
             if let Some(activator) = &self.activator {
                 activator.activate();
             }
+        } else if self.spill_merging() {
+            self.apply_spill_fuel(*effort);
+            if let Some(activator) = &self.activator {
+                activator.activate();
+            }
         }
     }
 
@@ -508,12 +787,21 @@ where
         while !self.reduced() {
             self.exert(&mut fuel);
         }
-        // Return the sole remaining batch (if one exists).
+
+        if !self.spilled.is_empty() {
+            return self.consolidate_spilled();
+        }
+
+        // Find the sole remaining batch (if one exists).
+        let mut result = None;
         for merging in self.merging.into_iter() {
             if let MergeState::Single(Some(batch)) = merging {
                 if !batch.is_empty() {
                     match Rc::try_unwrap(batch) {
-                        Ok(batch) => return Some(batch),
+                        Ok(batch) => {
+                            result = Some(batch);
+                            break;
+                        }
                         Err(_) => {
                             // Ref counter can only be >1 while iterating over batch,
                             // which should be impossible as we own `self`.
@@ -524,8 +812,11 @@ where
             }
         }
 
-        // Consolidated trace is empty.
-        None
+        // The last batch may not have been merged since the bound was set.
+        if let (Some(bound), Some(batch)) = (&self.lower_key_bound, result.as_mut()) {
+            batch.truncate_keys_below(bound);
+        }
+        result
     }
 
     // Ideally, this method acts as insertion of `batch`, even if we are not yet
@@ -551,7 +842,7 @@ where
         self.introduce_batch(Some(Rc::new(batch)), index.trailing_zeros() as usize);
 
         // If more than one batch remains reschedule ourself.
-        if !self.reduced() {
+        if !self.reduced() || self.spill_merging() {
             if let Some(activator) = &self.activator {
                 activator.activate();
             }
@@ -565,6 +856,79 @@ where
     fn dirty(&self) -> bool {
         self.dirty
     }
+
+    // Spilled batches are added to the checkpoint directory, if it exists on
+    // this host, and only their metadata is serialized.  In-memory batches
+    // are serialized individually.
+    #[cfg(feature = "checkpoint")]
+    fn encode_checkpoint<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        Encode::encode(&self.lower_key_bound, encoder)?;
+        Encode::encode(self.lower.as_slice(), encoder)?;
+        Encode::encode(self.upper.as_slice(), encoder)?;
+
+        let directory = checkpoint::current_dir().filter(|directory| directory.is_dir());
+        let files: Vec<_> = self.spilled.iter().flat_map(SpillLayer::batches).collect();
+        Encode::encode(&directory.is_some(), encoder)?;
+        Encode::encode(&(files.len() as u64), encoder)?;
+        for file in files {
+            match &directory {
+                Some(directory) => {
+                    let meta = file.link_to(directory).map_err(|error| {
+                        EncodeError::OtherString(format!(
+                            "failed to add '{}' to the checkpoint: {error}",
+                            file.path().display()
+                        ))
+                    })?;
+                    Encode::encode(&meta, encoder)?;
+                }
+                None => encode_cursor(&mut file.cursor(), encoder)?,
+            }
+        }
+
+        let num_batches = self.fold_batches(0u64, |acc, batch| acc + u64::from(!batch.is_empty()));
+        Encode::encode(&num_batches, encoder)?;
+        self.try_fold_batches((), |_, batch| {
+            if batch.is_empty() {
+                Ok(())
+            } else {
+                encode_batch(batch, encoder)
+            }
+        })
+    }
+
+    #[cfg(feature = "checkpoint")]
+    fn decode_checkpoint<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let mut trace = Self::new(None);
+        trace.lower_key_bound = Decode::decode(decoder)?;
+        let lower: Vec<B::Time> = Decode::decode(decoder)?;
+        let upper: Vec<B::Time> = Decode::decode(decoder)?;
+
+        let linked: bool = Decode::decode(decoder)?;
+        let num_files: u64 = Decode::decode(decoder)?;
+        for _ in 0..num_files {
+            if linked {
+                trace
+                    .restore_file(Decode::decode(decoder)?)
+                    .map_err(|error| {
+                        DecodeError::OtherString(format!(
+                            "failed to restore a spilled batch: {error}"
+                        ))
+                    })?;
+            } else {
+                trace.restore_batch(decode_batch(decoder)?);
+            }
+        }
+
+        let num_batches: u64 = Decode::decode(decoder)?;
+        for _ in 0..num_batches {
+            trace.restore_batch(decode_batch(decoder)?);
+        }
+
+        trace.lower = lower.into();
+        trace.upper = upper.into();
+        trace.clear_dirty_flag();
+        Ok(trace)
+    }
 }
 
 impl<B> Spine<B>
@@ -594,6 +958,48 @@ where
         true
     }
 
+    /// Returns `true` if any spilled batches are being merged.
+    fn spill_merging(&self) -> bool {
+        self.spilled.iter().any(SpillLayer::is_merging)
+    }
+
+    /// Builds the result of [`Trace::consolidate`] for a spine with spilled
+    /// batches.
+    ///
+    /// Reads all batches through a single cursor, so that the contents of
+    /// spilled batches are only loaded into memory as part of the result.
+    fn consolidate_spilled(self) -> Option<B> {
+        // Builders assign the same timestamp to all updates, so we build a
+        // separate batch for each distinct timestamp and merge them.
+        let mut builders = BTreeMap::<B::Time, B::Builder>::new();
+        let mut cursor = self.cursor();
+        while cursor.key_valid() {
+            while cursor.val_valid() {
+                let mut times = cursor.fold_times(Vec::new(), |mut times, time, weight| {
+                    times.push((time.clone(), weight.clone()));
+                    times
+                });
+                consolidate(&mut times);
+                for (time, weight) in times {
+                    builders
+                        .entry(time.clone())
+                        .or_insert_with(|| B::Builder::new_builder(time))
+                        .push((
+                            B::item_from(cursor.key().clone(), cursor.val().clone()),
+                            weight,
+                        ));
+                }
+                cursor.step_val();
+            }
+            cursor.step_key();
+        }
+
+        builders
+            .into_values()
+            .map(|builder| builder.done())
+            .reduce(|batch1, batch2| batch1.merge(&batch2))
+    }
+
     /// Describes the merge progress of layers in the trace.
     ///
     /// Intended for diagnostics rather than public consumption.
@@ -628,6 +1034,9 @@ where
             effort,
             activator,
             dirty: false,
+            lower_key_bound: None,
+            spill: SpillConfig::default_config().filter(|_| SPILL_SUPPORTED),
+            spilled: Vec::new(),
         }
     }
 
@@ -784,6 +1193,8 @@ where
                 self.insert_at(complete, index + 1);
             }
         }
+
+        self.apply_spill_fuel(*fuel);
     }
 
     /// Inserts a batch at a specific location.
@@ -812,8 +1223,178 @@ where
     }
 
     /// Completes and extracts what ever is at layer `index`.
+    ///
+    /// Discards keys below `self.lower_key_bound` from the extracted batch.
+    /// If the batch is large enough to be spilled to disk, spills it and
+    /// returns `None`.
     fn complete_at(&mut self, index: usize) -> Option<Rc<B>> {
-        self.merging[index].complete()
+        let mut batch = self.merging[index].complete();
+
+        if let (Some(bound), Some(batch)) = (&self.lower_key_bound, batch.as_mut()) {
+            // If the batch is shared, we truncate a private copy, but only if
+            // there is something to discard.
+            let truncate = {
+                let cursor = batch.cursor();
+                cursor.key_valid() && cursor.key() < bound
+            };
+            if truncate {
+                Rc::make_mut(batch).truncate_keys_below(bound);
+            }
+        }
+
+        let spill = match (&self.spill, &batch) {
+            (Some(config), Some(batch)) => batch.len() >= config.min_batch_size,
+            _ => false,
+        };
+        if spill {
+            self.spill_batch(&batch.unwrap());
+            None
+        } else {
+            batch
+        }
+    }
+
+    /// Writes `batch` to disk and starts merging spilled batches as needed to
+    /// keep their sizes geometrically decreasing.
+    fn spill_batch(&mut self, batch: &B) {
+        let config = self.spill.as_ref().unwrap();
+        let bound = self.lower_key_bound.as_ref();
+
+        if let Some(batch) = Self::spill_cursor(config, &mut batch.cursor(), bound, Clone::clone) {
+            self.spilled.push(SpillLayer::Single(batch));
+        }
+        self.start_spill_merges();
+    }
+
+    /// Starts merging adjacent spilled batches whose sizes are within a
+    /// factor of two.
+    fn start_spill_merges(&mut self) {
+        let config = match &self.spill {
+            Some(config) => config,
+            None => return,
+        };
+
+        let mut layers = Vec::with_capacity(self.spilled.len());
+        for layer in take(&mut self.spilled) {
+            match (layers.pop(), layer) {
+                (Some(SpillLayer::Single(batch1)), SpillLayer::Single(batch2))
+                    if batch1.len() <= 2 * batch2.len() =>
+                {
+                    let merger =
+                        Self::spill_result(config, FileBatchMerger::new(&config.directory));
+                    layers.push(SpillLayer::Merging(batch1, batch2, merger));
+                }
+                (previous, layer) => {
+                    layers.extend(previous);
+                    layers.push(layer);
+                }
+            }
+        }
+        self.spilled = layers;
+    }
+
+    /// Applies `fuel` to each merge of spilled batches in progress, replacing
+    /// completed merges with their results.
+    fn apply_spill_fuel(&mut self, fuel: isize) {
+        let config = match &self.spill {
+            Some(config) if fuel > 0 && self.spill_merging() => config,
+            _ => return,
+        };
+        let bound = self.lower_key_bound.as_ref();
+
+        let mut completed = false;
+        for layer in self.spilled.iter_mut() {
+            if let SpillLayer::Merging(batch1, batch2, merger) = layer {
+                let mut fuel = fuel;
+                Self::spill_result(config, merger.work(batch1, batch2, bound, &mut fuel));
+                completed |= merger.is_done();
+            }
+        }
+
+        if completed {
+            for layer in take(&mut self.spilled) {
+                match layer {
+                    // Dropping the inputs deletes their files.
+                    SpillLayer::Merging(_, _, merger) if merger.is_done() => {
+                        let merged = Self::spill_result(config, merger.finish());
+                        self.spilled.extend(merged.map(SpillLayer::Single));
+                    }
+                    layer => self.spilled.push(layer),
+                }
+            }
+            self.start_spill_merges();
+        }
+    }
+
+    /// Completes all merges of spilled batches, including merges that get
+    /// started as a result.
+    fn complete_spill_merges(&mut self) {
+        while self.spill_merging() {
+            self.apply_spill_fuel(isize::max_value());
+        }
+    }
+
+    /// Writes the contents of `cursor` to a new file in the spill directory.
+    fn spill_cursor<'s, C, F>(
+        config: &SpillConfig,
+        cursor: &mut C,
+        lower_bound: Option<&B::Key>,
+        map_time: F,
+    ) -> Option<SpilledBatch<B>>
+    where
+        C: Cursor<'s, B::Key, B::Val, B::Time, B::R>,
+        F: Fn(&B::Time) -> B::Time,
+    {
+        Self::spill_result(
+            config,
+            FileBatch::from_cursor(&config.directory, cursor, lower_bound, map_time),
+        )
+    }
+
+    /// Unwraps the result of writing a batch file.
+    ///
+    /// Panics on I/O errors, which the trace API has no way to report.
+    fn spill_result<T>(config: &SpillConfig, result: io::Result<T>) -> T {
+        result.unwrap_or_else(|error| {
+            panic!(
+                "failed to spill trace batch to '{}': {error}",
+                config.directory.display()
+            )
+        })
+    }
+
+    /// Adds a batch file from the checkpoint being restored to the trace.
+    ///
+    /// Without a spill configuration, loads the batch into memory.
+    #[cfg(feature = "checkpoint")]
+    fn restore_file(&mut self, meta: FileBatchMeta<B::Key>) -> io::Result<()> {
+        let directory = checkpoint::current_dir().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "the checkpoint references batch files, but its directory is unknown",
+            )
+        })?;
+
+        match self.spill.as_ref().map(|config| config.directory.clone()) {
+            Some(spill_directory) => {
+                let batch = FileBatch::open_link(&directory, meta, &spill_directory)?;
+                self.spilled.push(SpillLayer::Single(batch));
+            }
+            None => {
+                // The temporary link is deleted with the batch.
+                let batch = FileBatch::open_link(&directory, meta, &directory)?;
+                self.restore_batch(batch.to_batch());
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds an in-memory batch from a checkpoint to the trace.
+    #[cfg(feature = "checkpoint")]
+    fn restore_batch(&mut self, batch: B) {
+        if !batch.is_empty() {
+            self.insert(batch);
+        }
     }
 
     /// Attempts to draw down large layers to size appropriate layers.
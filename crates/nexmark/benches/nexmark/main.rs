@@ -17,8 +17,8 @@ use dbsp_nexmark::{
     config::{Config as NexmarkConfig, Query as NexmarkQuery},
     model::Event,
     queries::{
-        q0, q1, q12, q13, q13_side_input, q14, q15, q16, q17, q18, q19, q2, q20, q21, q22, q3, q4,
-        q5, q6, q7, q8, q9,
+        q0, q1, q10, q11, q12, q13, q13_side_input, q14, q15, q16, q17, q18, q19, q2, q20, q21,
+        q22, q3, q4, q5, q6, q7, q8, q9,
     },
     NexmarkSource,
 };
@@ -247,6 +247,8 @@ fn main() -> Result<()> {
             q7,
             q8,
             q9,
+            q10,
+            q11,
             q12,
             q13,
             q14,
@@ -240,8 +240,11 @@ pub mod tests {
 
     use super::*;
     use core::ops::Range;
-    use dbsp::{trace::Batch, OrdZSet, RootCircuit};
-    use rand::rngs::mock::StepRng;
+    use dbsp::{operator::Max, trace::Batch, OrdIndexedZSet, OrdZSet, RootCircuit, Runtime};
+    use rand::{
+        rngs::{mock::StepRng, SmallRng},
+        SeedableRng,
+    };
     use rstest::rstest;
 
     /// Returns a source that generates the default events/s with the specified
@@ -391,4 +394,51 @@ pub mod tests {
             .into_iter()
             .for_each(|v| assert_eq!(VecDeque::from(v), rx.recv().unwrap()));
     }
+
+    // With a high hot auction ratio, almost all bids go to the same auction,
+    // so the worker that owns it receives most of the bids when they are
+    // sharded by auction.  `aggregate_split` spreads the bids for the hot
+    // auction across workers and computes the same result as `aggregate`.
+    #[test]
+    fn test_hot_auctions() {
+        let mut generator = NexmarkGenerator::new(
+            GeneratorConfig {
+                nexmark_config: NexmarkConfig {
+                    hot_auction_ratio: 100,
+                    ..NexmarkConfig::default()
+                },
+                max_events: 1500,
+                ..GeneratorConfig::default()
+            },
+            SmallRng::seed_from_u64(0),
+            0,
+        );
+
+        let mut bids = Vec::new();
+        while let Some(next_event) = generator.next_event().unwrap() {
+            if let Event::Bid(bid) = next_event.event {
+                bids.push((bid.auction, (bid.price, 1)));
+            }
+        }
+
+        let (mut dbsp, mut input_handle) = Runtime::init_circuit(4, |circuit| {
+            let (bids, input_handle) = circuit.add_input_indexed_zset::<u64, usize, isize>();
+
+            let expected = bids.aggregate(Max).gather(0);
+            let stats = bids.shard_stats().unwrap();
+            expected.inspect(move |_| assert!(stats.skew() > 2.0));
+
+            bids.aggregate_split(Max, 4).gather(0).apply2(
+                &expected,
+                |actual: &OrdIndexedZSet<u64, usize, isize>, expected| assert_eq!(actual, expected),
+            );
+
+            input_handle
+        })
+        .unwrap();
+
+        input_handle.append(&mut bids);
+        dbsp.step().unwrap();
+        dbsp.kill().unwrap();
+    }
 }
@@ -39,6 +39,8 @@ declare_queries! {
     q7,
     q8,
     q9,
+    q10,
+    q11,
     q12,
     q13,
     q14,
@@ -54,6 +56,9 @@ declare_queries! {
 
 pub use q13::q13_side_input;
 
+#[cfg(test)]
+mod validation;
+
 fn process_time() -> u64 {
     SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
@@ -13,6 +13,7 @@ mod tests {
     use crate::{
         generator::tests::{make_auction, make_bid},
         model::{Auction, Bid, Event},
+        queries::validation::validate,
     };
     use dbsp::{trace::Batch, RootCircuit, OrdZSet};
 
@@ -101,4 +102,13 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q0_reference(events: &[Event]) -> OrdZSet<Event, isize> {
+        OrdZSet::from_keys((), events.iter().map(|e| (e.clone(), 1)).collect())
+    }
+
+    #[test]
+    fn test_q0_reference() {
+        validate(q0, q0_reference);
+    }
 }
@@ -43,6 +43,7 @@ mod tests {
     use crate::{
         generator::tests::{make_auction, make_bid},
         model::{Auction, Bid, Event},
+        queries::validation::validate,
     };
     use dbsp::{trace::Batch, RootCircuit, OrdZSet};
 
@@ -144,4 +145,25 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q1_reference(events: &[Event]) -> OrdZSet<Event, isize> {
+        let tuples = events
+            .iter()
+            .map(|e| match e {
+                Event::Bid(b) => Event::Bid(Bid {
+                    price: b.price * 89 / 100,
+                    ..b.clone()
+                }),
+                _ => e.clone(),
+            })
+            .map(|e| (e, 1))
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q1_reference() {
+        validate(q1, q1_reference);
+    }
 }
@@ -0,0 +1,164 @@
+use super::NexmarkStream;
+use crate::model::Event;
+use arcstr::ArcStr;
+use dbsp::{operator::FilterMap, OrdZSet, RootCircuit, Stream};
+use std::time::{Duration, SystemTime};
+use time::{format_description, OffsetDateTime};
+
+///
+/// Query 10: Log to File System (Not in original suite)
+///
+/// Log all events to file system. Illustrates windows streaming data into
+/// partitioned file system.
+///
+/// Every minute, save all events from the last period into partitioned log
+/// files.
+///
+/// ```sql
+/// CREATE TABLE fs_sink (
+///     auction  BIGINT,
+///     bidder  BIGINT,
+///     price  BIGINT,
+///     dateTime  TIMESTAMP(3),
+///     extra  VARCHAR,
+///     dt STRING,
+///     hm STRING
+/// ) PARTITIONED BY (dt, hm) WITH (
+///     'connector' = 'filesystem',
+///     'path' = 'file://${FLINK_HOME}/data/output/data/q10',
+///     'format' = 'csv',
+///     'sink.partition-commit.trigger' = 'partition-time',
+///     'sink.partition-commit.delay' = '1 min',
+///     'sink.partition-commit.policy.kind' = 'success-file',
+///     'partition.time-extractor.timestamp-pattern' = '$dt $hm:00',
+///     'sink.rolling-policy.rollover-interval' = '1min',
+///     'sink.rolling-policy.check-interval' = '1min'
+/// );
+///
+/// INSERT INTO fs_sink
+/// SELECT auction, bidder, price, dateTime, extra, DATE_FORMAT(dateTime, 'yyyy-MM-dd'), DATE_FORMAT(dateTime, 'HH:mm')
+/// FROM bid;
+/// ```
+///
+/// The query computes the partition columns `dt` and `hm` of each bid.
+/// Writing the output to partitioned files is up to the output connector
+/// attached to the circuit.
+
+type Q10Output = (u64, u64, usize, u64, ArcStr, ArcStr, ArcStr);
+
+type Q10Stream = Stream<RootCircuit, OrdZSet<Q10Output, isize>>;
+
+pub fn q10(input: NexmarkStream) -> Q10Stream {
+    let day_format = format_description::parse("[year]-[month]-[day]").unwrap();
+    let minute_format = format_description::parse("[hour]:[minute]").unwrap();
+
+    input.flat_map(move |event| match event {
+        Event::Bid(b) => {
+            let date_time = SystemTime::UNIX_EPOCH + Duration::from_millis(b.date_time);
+            let date_time = <SystemTime as Into<OffsetDateTime>>::into(date_time);
+
+            Some((
+                b.auction,
+                b.bidder,
+                b.price,
+                b.date_time,
+                b.extra.clone(),
+                date_time.format(&day_format).unwrap().into(),
+                date_time.format(&minute_format).unwrap().into(),
+            ))
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, validate},
+    };
+    use dbsp::{trace::Batch, zset};
+
+    #[test]
+    fn test_q10() {
+        let input_vecs = vec![vec![
+            (
+                Event::Bid(Bid {
+                    auction: 1,
+                    // 2022-09-01T13:05:59.999Z
+                    date_time: 1_662_037_559_999,
+                    ..make_bid()
+                }),
+                1,
+            ),
+            (
+                Event::Bid(Bid {
+                    auction: 2,
+                    date_time: 0,
+                    ..make_bid()
+                }),
+                1,
+            ),
+        ]]
+        .into_iter();
+
+        let (circuit, mut input_handle) = RootCircuit::build(move |circuit| {
+            let (stream, input_handle) = circuit.add_input_zset::<Event, isize>();
+
+            let mut expected_output = vec![zset![
+                (1, 1, 99, 1_662_037_559_999, ArcStr::new(), ArcStr::from("2022-09-01"), ArcStr::from("13:05")) => 1,
+                (2, 1, 99, 0, ArcStr::new(), ArcStr::from("1970-01-01"), ArcStr::from("00:00")) => 1,
+            ]]
+            .into_iter();
+
+            let output = q10(stream);
+
+            output.inspect(move |batch| assert_eq!(batch, &expected_output.next().unwrap()));
+
+            input_handle
+        })
+        .unwrap();
+
+        for mut vec in input_vecs {
+            input_handle.append(&mut vec);
+            circuit.step().unwrap();
+        }
+    }
+
+    fn q10_reference(events: &[Event]) -> OrdZSet<Q10Output, isize> {
+        let tuples = bids(events)
+            .map(|b| {
+                let date =
+                    OffsetDateTime::from_unix_timestamp((b.date_time / 1000) as i64).unwrap();
+                let minutes = b.date_time / (60 * 1000) % (24 * 60);
+
+                (
+                    (
+                        b.auction,
+                        b.bidder,
+                        b.price,
+                        b.date_time,
+                        b.extra.clone(),
+                        ArcStr::from(format!(
+                            "{:04}-{:02}-{:02}",
+                            date.year(),
+                            u8::from(date.month()),
+                            date.day()
+                        )),
+                        ArcStr::from(format!("{:02}:{:02}", minutes / 60, minutes % 60)),
+                    ),
+                    1,
+                )
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q10_reference() {
+        validate(q10, q10_reference);
+    }
+}
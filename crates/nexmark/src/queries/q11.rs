@@ -0,0 +1,160 @@
+use super::NexmarkStream;
+use crate::model::Event;
+use dbsp::{operator::FilterMap, OrdIndexedZSet, OrdZSet, RootCircuit, Stream};
+
+///
+/// Query 11: User Sessions (Not in original suite)
+///
+/// How many bids did a user make in each session they were active?
+/// Illustrates session windows.
+///
+/// Group bids by the same user into sessions with max session gap.
+/// Emit the number of bids per session.
+///
+/// ```sql
+/// CREATE TABLE discard_sink (
+///   bidder BIGINT,
+///   bid_count BIGINT,
+///   starttime TIMESTAMP(3),
+///   endtime TIMESTAMP(3)
+/// ) WITH (
+///   'connector' = 'blackhole'
+/// );
+///
+/// INSERT INTO discard_sink
+/// SELECT
+///     B.bidder,
+///     count(*) as bid_count,
+///     SESSION_START(B.dateTime, INTERVAL '10' SECOND) as starttime,
+///     SESSION_END(B.dateTime, INTERVAL '10' SECOND) as endtime
+/// FROM bid B
+/// GROUP BY B.bidder, SESSION(B.dateTime, INTERVAL '10' SECOND);
+/// ```
+
+type Q11Stream = Stream<RootCircuit, OrdZSet<(u64, u64, u64, u64), isize>>;
+
+const SESSION_GAP_SECONDS: u64 = 10;
+
+pub fn q11(input: NexmarkStream) -> Q11Stream {
+    let bids_by_bidder: Stream<_, OrdIndexedZSet<u64, (u64, ()), _>> =
+        input.flat_map_index(|event| match event {
+            Event::Bid(b) => Some((b.bidder, (b.date_time, ()))),
+            _ => None,
+        });
+
+    // Bids that arrive out of order can merge or split sessions, in which
+    // case `session_window` retracts the bids of the old sessions, and the
+    // count below is updated accordingly.
+    bids_by_bidder
+        .session_window::<u64, ()>(SESSION_GAP_SECONDS * 1000)
+        .map_index(|(bidder, (session, _bid))| ((*bidder, *session), ()))
+        .aggregate_linear(|_key, &()| -> isize { 1 })
+        .map(|((bidder, session), count)| (*bidder, *count as u64, session.start, session.end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, validate},
+    };
+    use dbsp::{trace::Batch, zset};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_q11() {
+        let input_vecs = vec![
+            vec![(1, 1_000), (1, 5_000), (1, 22_000), (2, 2_000)],
+            // An out-of-order bid merges the two sessions of bidder 1.
+            vec![(1, 14_000)],
+            // A bid that is exactly `SESSION_GAP_SECONDS` away starts a new
+            // session.
+            vec![(2, 12_000)],
+        ]
+        .into_iter()
+        .map(|batch| {
+            batch
+                .into_iter()
+                .map(|(bidder, date_time)| {
+                    (
+                        Event::Bid(Bid {
+                            bidder,
+                            date_time,
+                            ..make_bid()
+                        }),
+                        1,
+                    )
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let (circuit, mut input_handle) = RootCircuit::build(move |circuit| {
+            let (stream, input_handle) = circuit.add_input_zset::<Event, isize>();
+
+            let mut expected_output = vec![
+                zset! {
+                    (1, 2, 1_000, 15_000) => 1,
+                    (1, 1, 22_000, 32_000) => 1,
+                    (2, 1, 2_000, 12_000) => 1,
+                },
+                zset! {
+                    (1, 2, 1_000, 15_000) => -1,
+                    (1, 1, 22_000, 32_000) => -1,
+                    (1, 4, 1_000, 32_000) => 1,
+                },
+                zset! {
+                    (2, 1, 12_000, 22_000) => 1,
+                },
+            ]
+            .into_iter();
+
+            let output = q11(stream);
+
+            output.inspect(move |batch| assert_eq!(batch, &expected_output.next().unwrap()));
+
+            input_handle
+        })
+        .unwrap();
+
+        for mut vec in input_vecs {
+            input_handle.append(&mut vec);
+            circuit.step().unwrap();
+        }
+    }
+
+    fn q11_reference(events: &[Event]) -> OrdZSet<(u64, u64, u64, u64), isize> {
+        let mut bids_by_bidder: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+        for b in bids(events) {
+            bids_by_bidder
+                .entry(b.bidder)
+                .or_default()
+                .push(b.date_time);
+        }
+
+        let gap = SESSION_GAP_SECONDS * 1000;
+        let mut tuples = Vec::new();
+        for (bidder, mut times) in bids_by_bidder {
+            times.sort();
+
+            let mut start = 0;
+            for i in 1..=times.len() {
+                if i == times.len() || times[i] - times[i - 1] >= gap {
+                    tuples.push((
+                        (bidder, (i - start) as u64, times[start], times[i - 1] + gap),
+                        1,
+                    ));
+                    start = i;
+                }
+            }
+        }
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q11_reference() {
+        validate(q11, q11_reference);
+    }
+}
@@ -69,10 +69,11 @@ mod tests {
     use crate::{
         generator::tests::make_bid,
         model::{Bid, Event},
+        queries::validation::{bids, validate, BASE_TIME},
     };
-    use dbsp::{zset, RootCircuit};
+    use dbsp::{trace::Batch, zset, RootCircuit};
     use rstest::rstest;
-    use std::cell::RefCell;
+    use std::{cell::RefCell, collections::BTreeMap};
 
     #[rstest]
     #[case::one_bidder_single_window(
@@ -140,4 +141,30 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q12_reference(events: &[Event]) -> OrdZSet<(u64, u64, u64, u64), isize> {
+        let (starttime, endtime) = window_for_process_time(BASE_TIME);
+
+        let mut counts: BTreeMap<u64, u64> = BTreeMap::new();
+        for b in bids(events) {
+            *counts.entry(b.bidder).or_default() += 1;
+        }
+
+        let tuples = counts
+            .into_iter()
+            .map(|(bidder, count)| ((bidder, count, starttime, endtime), 1))
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q12_reference() {
+        // Use a fixed process time, so that the reference implementation can
+        // reproduce the windows.
+        validate(
+            |input| q12_for_process_time(input, || BASE_TIME),
+            q12_reference,
+        );
+    }
 }
@@ -128,9 +128,16 @@ pub fn q13(input: NexmarkStream, side_input: SideInputStream) -> Q13Stream {
 mod tests {
     use super::*;
     use dbsp::{
-        zset,
+        operator::Generator,
+        trace::Batch,
+        zset, Circuit,
     };
-    use crate::{generator::tests::make_bid, model::Bid};
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, validate},
+    };
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_q13() {
@@ -193,4 +200,46 @@ mod tests {
             got
         );
     }
+
+    fn q13_reference(
+        events: &[Event],
+        side_input: &[((usize, String, u64), isize)],
+    ) -> OrdZSet<(u64, u64, usize, u64, String), isize> {
+        let side_values: BTreeMap<usize, &String> = side_input
+            .iter()
+            .map(|((key, value, _p_time), _w)| (*key, value))
+            .collect();
+
+        let tuples = bids(events)
+            .filter_map(|b| {
+                side_values
+                    .get(&((b.auction % 10_000) as usize))
+                    .map(|value| {
+                        (
+                            (b.auction, b.bidder, b.price, b.date_time, (*value).clone()),
+                            1,
+                        )
+                    })
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q13_reference() {
+        let side_input = q13_side_input();
+
+        // Feed the side input to the circuit during the first step only.
+        let mut side_batch = Some(OrdZSet::from_keys((), side_input.clone()));
+        validate(
+            move |input: NexmarkStream| {
+                let side_stream = input.circuit().add_source(Generator::new(move || {
+                    side_batch.take().unwrap_or_else(|| OrdZSet::empty(()))
+                }));
+                q13(input, side_stream)
+            },
+            |events| q13_reference(events, &side_input),
+        );
+    }
 }
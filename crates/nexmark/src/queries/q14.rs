@@ -148,8 +148,12 @@ pub fn q14(input: NexmarkStream) -> Q14Stream {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{generator::tests::make_bid, model::Bid};
-    use dbsp::zset;
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, date_time_for_millis, validate},
+    };
+    use dbsp::{trace::Batch, zset};
     use rstest::rstest;
 
     #[rstest]
@@ -196,4 +200,41 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q14_reference(events: &[Event]) -> OrdZSet<Q14Output, isize> {
+        let tuples = bids(events)
+            .filter_map(|b| {
+                let price = Decimal::from(b.price) * Decimal::new(908, 3);
+                if price <= Decimal::from(1_000_000) || price >= Decimal::from(50_000_000) {
+                    return None;
+                }
+
+                let bid_time_type = match date_time_for_millis(b.date_time).hour() {
+                    8..=18 => BidTimeType::Day,
+                    20..=23 | 0..=6 => BidTimeType::Night,
+                    _ => BidTimeType::Other,
+                };
+
+                Some((
+                    Q14Output(
+                        b.auction,
+                        b.bidder,
+                        BincodeDecimal(price),
+                        bid_time_type,
+                        b.date_time,
+                        b.extra.clone(),
+                        b.extra.chars().filter(|&c| c == 'c').count(),
+                    ),
+                    1,
+                ))
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q14_reference() {
+        validate(q14, q14_reference);
+    }
 }
@@ -428,9 +428,15 @@ pub fn q15(input: NexmarkStream) -> Q15Stream {
 mod tests {
     use super::*;
     use dbsp::{
+        trace::Batch,
         zset, Runtime,
     };
-    use crate::{generator::tests::make_bid, model::Bid};
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, iso8601_day, price_rank, validate},
+    };
+    use std::collections::{BTreeMap, BTreeSet};
     use rstest::rstest;
 
     // 52 years with 13 leap years (extra days)
@@ -653,4 +659,50 @@ mod tests {
             dbsp.step().unwrap();
         }
     }
+
+    fn q15_reference(events: &[Event]) -> OrdZSet<Q15Output, isize> {
+        // Number of bids, distinct bidders and distinct auctions per day, across
+        // all bids (index 0) and for each price range (indexes 1 to 3).
+        let mut days: BTreeMap<String, [(usize, BTreeSet<u64>, BTreeSet<u64>); 4]> =
+            BTreeMap::new();
+        for b in bids(events) {
+            let stats = days.entry(iso8601_day(b.date_time)).or_default();
+            for rank in [0, price_rank(b.price)] {
+                stats[rank].0 += 1;
+                stats[rank].1.insert(b.bidder);
+                stats[rank].2.insert(b.auction);
+            }
+        }
+
+        let tuples = days
+            .into_iter()
+            .map(|(day, stats)| {
+                (
+                    Q15Output {
+                        day,
+                        total_bids: stats[0].0,
+                        rank1_bids: stats[1].0,
+                        rank2_bids: stats[2].0,
+                        rank3_bids: stats[3].0,
+                        total_bidders: stats[0].1.len(),
+                        rank1_bidders: stats[1].1.len(),
+                        rank2_bidders: stats[2].1.len(),
+                        rank3_bidders: stats[3].1.len(),
+                        total_auctions: stats[0].2.len(),
+                        rank1_auctions: stats[1].2.len(),
+                        rank2_auctions: stats[2].2.len(),
+                        rank3_auctions: stats[3].2.len(),
+                    },
+                    1,
+                )
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q15_reference() {
+        validate(q15, q15_reference);
+    }
 }
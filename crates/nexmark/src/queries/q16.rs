@@ -585,9 +585,12 @@ pub fn q16(input: NexmarkStream) -> Q16Stream {
 mod tests {
     use super::*;
     use crate::{
-        generator::tests::make_bid, model::Bid,
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, date_time_for_millis, iso8601_day, price_rank, validate},
     };
-    use dbsp::{zset, Runtime};
+    use dbsp::{trace::Batch, zset, Runtime};
+    use std::collections::{BTreeMap, BTreeSet};
     use rstest::rstest;
 
     // 52 years with 13 leap years (extra days)
@@ -836,4 +839,59 @@ mod tests {
         }
         dbsp.dump_profile(std::env::temp_dir().join("q16")).unwrap();
     }
+
+    fn q16_reference(events: &[Event]) -> OrdZSet<Q16Output, isize> {
+        // Number of bids, distinct bidders and distinct auctions per channel and
+        // day, across all bids (index 0) and for each price range (indexes 1 to 3),
+        // and the latest minute of the day with a bid.
+        type ChannelDayStats = ([(usize, BTreeSet<u64>, BTreeSet<u64>); 4], (u8, u8));
+
+        let mut channel_days: BTreeMap<(ArcStr, String), ChannelDayStats> = BTreeMap::new();
+        for b in bids(events) {
+            let (stats, max_minute) = channel_days
+                .entry((b.channel.clone(), iso8601_day(b.date_time)))
+                .or_default();
+            for rank in [0, price_rank(b.price)] {
+                stats[rank].0 += 1;
+                stats[rank].1.insert(b.bidder);
+                stats[rank].2.insert(b.auction);
+            }
+
+            let date_time = date_time_for_millis(b.date_time);
+            *max_minute = (*max_minute).max((date_time.hour(), date_time.minute()));
+        }
+
+        let tuples = channel_days
+            .into_iter()
+            .map(|((channel, day), (stats, (hour, minute)))| {
+                (
+                    Q16Output {
+                        channel,
+                        day: day.into(),
+                        minute: format!("{hour:02}:{minute:02}").into(),
+                        total_bids: stats[0].0,
+                        rank1_bids: stats[1].0,
+                        rank2_bids: stats[2].0,
+                        rank3_bids: stats[3].0,
+                        total_bidders: stats[0].1.len(),
+                        rank1_bidders: stats[1].1.len(),
+                        rank2_bidders: stats[2].1.len(),
+                        rank3_bidders: stats[3].1.len(),
+                        total_auctions: stats[0].2.len(),
+                        rank1_auctions: stats[1].2.len(),
+                        rank2_auctions: stats[2].2.len(),
+                        rank3_auctions: stats[3].2.len(),
+                    },
+                    1,
+                )
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q16_reference() {
+        validate(q16, q16_reference);
+    }
 }
@@ -187,8 +187,13 @@ pub fn q17(input: NexmarkStream) -> Q17Stream {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use dbsp::zset;
-    use crate::{generator::tests::make_bid, model::Bid};
+    use dbsp::{trace::Batch, zset};
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, iso8601_day, price_rank, validate},
+    };
+    use std::collections::BTreeMap;
     use rstest::rstest;
 
     #[rstest]
@@ -343,4 +348,47 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q17_reference(events: &[Event]) -> OrdZSet<Q17Output, isize> {
+        let mut prices: BTreeMap<(u64, String), Vec<usize>> = BTreeMap::new();
+        for b in bids(events) {
+            prices
+                .entry((b.auction, iso8601_day(b.date_time)))
+                .or_default()
+                .push(b.price);
+        }
+
+        let tuples = prices
+            .into_iter()
+            .map(|((auction, day), prices)| {
+                let count_rank =
+                    |rank| prices.iter().filter(|&&p| price_rank(p) == rank).count() as isize;
+                let total_bids = prices.len() as isize;
+                let sum_price = prices.iter().sum::<usize>() as isize;
+
+                (
+                    (
+                        auction,
+                        ArcStr::from(day),
+                        total_bids,
+                        count_rank(1),
+                        count_rank(2),
+                        count_rank(3),
+                        *prices.iter().min().unwrap(),
+                        *prices.iter().max().unwrap(),
+                        sum_price / total_bids,
+                        sum_price,
+                    ),
+                    1,
+                )
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q17_reference() {
+        validate(q17, q17_reference);
+    }
 }
@@ -52,8 +52,13 @@ pub fn q18(input: NexmarkStream) -> Q18Stream {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{generator::tests::make_bid, model::Bid};
-    use dbsp::zset;
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, validate},
+    };
+    use dbsp::{trace::Batch, zset};
+    use std::collections::{BTreeMap, BTreeSet};
     use rstest::rstest;
 
     #[rstest]
@@ -393,4 +398,32 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q18_reference(events: &[Event]) -> OrdZSet<Bid, isize> {
+        let mut bids_by_auction_bidder: BTreeMap<(u64, u64), BTreeSet<&Bid>> = BTreeMap::new();
+        for b in bids(events) {
+            bids_by_auction_bidder
+                .entry((b.auction, b.bidder))
+                .or_default()
+                .insert(b);
+        }
+
+        // Among bids with the same latest date time, the smallest one wins.
+        let tuples = bids_by_auction_bidder
+            .into_values()
+            .map(|bids| {
+                let latest = bids.iter().map(|b| b.date_time).max().unwrap();
+                let last_bid = bids.into_iter().find(|b| b.date_time == latest).unwrap();
+
+                (last_bid.clone(), 1)
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q18_reference() {
+        validate(q18, q18_reference);
+    }
 }
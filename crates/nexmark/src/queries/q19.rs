@@ -59,8 +59,13 @@ pub fn q19(input: NexmarkStream) -> Q19Stream {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use dbsp::zset;
-    use crate::{generator::tests::make_bid, model::Bid};
+    use dbsp::{trace::Batch, zset};
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, validate},
+    };
+    use std::collections::{BTreeMap, BTreeSet};
     use rstest::rstest;
 
     #[rstest]
@@ -297,4 +302,27 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q19_reference(events: &[Event]) -> OrdZSet<Bid, isize> {
+        let mut bids_by_auction: BTreeMap<u64, BTreeSet<(usize, &Bid)>> = BTreeMap::new();
+        for b in bids(events) {
+            bids_by_auction
+                .entry(b.auction)
+                .or_default()
+                .insert((b.price, b));
+        }
+
+        let tuples = bids_by_auction
+            .into_values()
+            .flat_map(|bids| bids.into_iter().rev().take(TOP_BIDS))
+            .map(|(_price, b)| (b.clone(), 1))
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q19_reference() {
+        validate(q19, q19_reference);
+    }
 }
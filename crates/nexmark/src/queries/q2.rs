@@ -35,6 +35,7 @@ mod tests {
     use crate::{
         generator::tests::make_bid,
         model::{Bid, Event},
+        queries::validation::{bids, validate},
     };
     use dbsp::{trace::Batch, RootCircuit, OrdZSet};
 
@@ -109,4 +110,18 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q2_reference(events: &[Event]) -> OrdZSet<(u64, usize), isize> {
+        let tuples = bids(events)
+            .filter(|b| b.auction % AUCTION_ID_MODULO == 0)
+            .map(|b| ((b.auction, b.price), 1))
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q2_reference() {
+        validate(q2, q2_reference);
+    }
 }
@@ -79,8 +79,9 @@ mod tests {
     use crate::{
         generator::tests::{make_auction, make_bid},
         model::{Auction, Bid},
+        queries::validation::{auctions, bids, validate},
     };
-    use dbsp::zset;
+    use dbsp::{trace::Batch, zset};
     use rstest::rstest;
 
     #[rstest]
@@ -249,4 +250,20 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q20_reference(events: &[Event]) -> OrdZSet<(Bid, Auction), isize> {
+        let mut tuples = Vec::new();
+        for a in auctions(events).filter(|a| a.category == FILTERED_CATEGORY) {
+            for b in bids(events).filter(|b| b.auction == a.id) {
+                tuples.push(((b.clone(), a.clone()), 1));
+            }
+        }
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q20_reference() {
+        validate(q20, q20_reference);
+    }
 }
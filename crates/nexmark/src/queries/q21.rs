@@ -65,8 +65,12 @@ pub fn q21(input: NexmarkStream) -> Q21Stream {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{generator::tests::make_bid, model::Bid};
-    use dbsp::zset;
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, validate},
+    };
+    use dbsp::{trace::Batch, zset};
     use rstest::rstest;
 
     #[rstest]
@@ -138,4 +142,43 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q21_reference(events: &[Event]) -> Q21Set {
+        const CHANNEL_ID_PARAM: &str = "channel_id=";
+
+        let tuples = bids(events)
+            .filter_map(|b| {
+                let channel_id = match b.channel.to_lowercase().as_str() {
+                    "apple" => Some("0"),
+                    "google" => Some("1"),
+                    "facebook" => Some("2"),
+                    "baidu" => Some("3"),
+                    _ => b.channel.find(CHANNEL_ID_PARAM).map(|start| {
+                        let value = &b.channel[start + CHANNEL_ID_PARAM.len()..];
+                        value.split('&').next().unwrap()
+                    }),
+                };
+
+                channel_id.map(|channel_id| {
+                    (
+                        (
+                            b.auction,
+                            b.bidder,
+                            b.price,
+                            b.channel.clone(),
+                            ArcStr::from(channel_id),
+                        ),
+                        1,
+                    )
+                })
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q21_reference() {
+        validate(q21, q21_reference);
+    }
 }
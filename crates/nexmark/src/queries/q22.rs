@@ -60,8 +60,12 @@ pub fn q22(input: NexmarkStream) -> Q22Stream {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{generator::tests::make_bid, model::Bid};
-    use dbsp::zset;
+    use crate::{
+        generator::tests::make_bid,
+        model::Bid,
+        queries::validation::{bids, validate},
+    };
+    use dbsp::{trace::Batch, zset};
     use rstest::rstest;
 
     #[rstest]
@@ -124,4 +128,34 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q22_reference(events: &[Event]) -> Q22Set {
+        let tuples = bids(events)
+            .map(|b| {
+                // Path components of URLs of the form `scheme://host/dir1/dir2/dir3`.
+                let dirs: Vec<&str> = b.channel.split('/').collect();
+                let dir = |i: usize| ArcStr::from(dirs.get(i).copied().unwrap_or_default());
+
+                (
+                    (
+                        b.auction,
+                        b.bidder,
+                        b.price,
+                        b.channel.clone(),
+                        dir(3),
+                        dir(4),
+                        dir(5),
+                    ),
+                    1,
+                )
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q22_reference() {
+        validate(q22, q22_reference);
+    }
 }
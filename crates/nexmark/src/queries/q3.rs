@@ -68,6 +68,7 @@ mod tests {
     use crate::{
         generator::tests::{make_auction, make_person},
         model::{Auction, Person},
+        queries::validation::{auctions, people, validate},
     };
     use dbsp::{trace::Batch, RootCircuit, OrdZSet};
 
@@ -218,4 +219,32 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q3_reference(events: &[Event]) -> OrdZSet<(String, String, String, u64), isize> {
+        let people: Vec<_> = people(events)
+            .filter(|p| STATES_OF_INTEREST.contains(&p.state.as_str()))
+            .collect();
+
+        let mut tuples = Vec::new();
+        for a in auctions(events).filter(|a| a.category == CATEGORY_OF_INTEREST) {
+            for p in people.iter().filter(|p| p.id == a.seller) {
+                tuples.push((
+                    (
+                        p.name.to_string(),
+                        p.city.to_string(),
+                        p.state.to_string(),
+                        a.id,
+                    ),
+                    1,
+                ));
+            }
+        }
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q3_reference() {
+        validate(q3, q3_reference);
+    }
 }
@@ -88,7 +88,9 @@ mod tests {
     use crate::{
         generator::tests::{make_auction, make_bid},
         model::{Auction, Bid, Event},
+        queries::validation::{auctions, bids, validate},
     };
+    use std::collections::BTreeMap;
     use dbsp::{trace::Batch, RootCircuit, OrdZSet};
 
     #[test]
@@ -235,4 +237,35 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q4_reference(events: &[Event]) -> OrdZSet<(usize, usize), isize> {
+        let mut winning_bids: BTreeMap<(u64, usize), usize> = BTreeMap::new();
+        for a in auctions(events) {
+            for b in bids(events).filter(|b| b.auction == a.id) {
+                if b.date_time >= a.date_time && b.date_time <= a.expires {
+                    let price = winning_bids.entry((a.id, a.category)).or_default();
+                    *price = (*price).max(b.price);
+                }
+            }
+        }
+
+        let mut by_category: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+        for ((_auction, category), price) in winning_bids {
+            let (sum, count) = by_category.entry(category).or_default();
+            *sum += price;
+            *count += 1;
+        }
+
+        let tuples = by_category
+            .into_iter()
+            .map(|(category, (sum, count))| ((category, sum / count), 1))
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q4_reference() {
+        validate(q4, q4_reference);
+    }
 }
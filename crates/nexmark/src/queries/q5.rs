@@ -123,8 +123,10 @@ mod tests {
     use crate::{
         generator::tests::make_bid,
         model::{Bid, Event},
+        queries::validation::{bids, validate},
     };
-    use dbsp::{zset, RootCircuit};
+    use dbsp::{trace::Batch, zset, RootCircuit};
+    use std::collections::BTreeMap;
     use rstest::rstest;
 
     #[rstest]
@@ -210,4 +212,32 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q5_reference(events: &[Event]) -> OrdZSet<(u64, usize), isize> {
+        let watermark = bids(events)
+            .map(|b| b.date_time - WATERMARK_INTERVAL_SECONDS * 1000)
+            .max()
+            .unwrap_or_default();
+        let end = watermark - watermark % (TUMBLE_SECONDS * 1000);
+        let start = end.saturating_sub(WINDOW_WIDTH_SECONDS * 1000);
+
+        let mut counts: BTreeMap<u64, usize> = BTreeMap::new();
+        for b in bids(events).filter(|b| b.date_time >= start && b.date_time < end) {
+            *counts.entry(b.auction).or_default() += 1;
+        }
+
+        let max_count = counts.values().copied().max().unwrap_or_default();
+        let tuples = counts
+            .into_iter()
+            .filter(|&(_auction, count)| count == max_count)
+            .map(|(auction, count)| ((auction, count), 1))
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q5_reference() {
+        validate(q5, q5_reference);
+    }
 }
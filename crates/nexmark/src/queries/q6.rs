@@ -116,8 +116,10 @@ mod tests {
     use crate::{
         generator::tests::{make_auction, make_bid},
         model::{Auction, Bid, Event},
+        queries::validation::{auctions, bids, validate},
     };
-    use dbsp::{indexed_zset, RootCircuit};
+    use dbsp::{indexed_zset, trace::Batch, RootCircuit};
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_q6_single_seller_single_auction() {
@@ -637,4 +639,42 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q6_reference(events: &[Event]) -> OrdIndexedZSet<u64, usize, isize> {
+        // Winning bids of each seller, ordered by auction id.
+        let mut winning_bids: BTreeMap<u64, BTreeMap<u64, usize>> = BTreeMap::new();
+        for a in auctions(events) {
+            for b in bids(events).filter(|b| b.auction == a.id) {
+                if b.date_time >= a.date_time && b.date_time <= a.expires {
+                    let price = winning_bids
+                        .entry(a.seller)
+                        .or_default()
+                        .entry(a.id)
+                        .or_default();
+                    *price = (*price).max(b.price);
+                }
+            }
+        }
+
+        let tuples = winning_bids
+            .into_iter()
+            .map(|(seller, prices)| {
+                let last: Vec<usize> = prices
+                    .into_values()
+                    .rev()
+                    .take(NUM_AUCTIONS_PER_SELLER)
+                    .collect();
+                let avg = last.iter().sum::<usize>() / last.len();
+
+                ((seller, avg), 1)
+            })
+            .collect();
+
+        OrdIndexedZSet::from_tuples((), tuples)
+    }
+
+    #[test]
+    fn test_q6_reference() {
+        validate(q6, q6_reference);
+    }
 }
@@ -99,8 +99,9 @@ mod tests {
     use crate::{
         generator::tests::make_bid,
         model::{Bid, Event},
+        queries::validation::{bids, validate},
     };
-    use dbsp::{zset, RootCircuit};
+    use dbsp::{trace::Batch, zset, RootCircuit};
     use rstest::rstest;
 
     type Q7Tuple = (u64, u64, usize, u64, ArcStr);
@@ -183,4 +184,36 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q7_reference(events: &[Event]) -> OrdZSet<Q7Output, isize> {
+        let watermark = bids(events)
+            .map(|b| b.date_time - WATERMARK_INTERVAL_SECONDS * 1000)
+            .max()
+            .unwrap_or_default();
+        let end = watermark - watermark % (TUMBLE_SECONDS * 1000);
+        let start = end.saturating_sub(TUMBLE_SECONDS * 1000);
+
+        let windowed_bids: Vec<_> = bids(events)
+            .filter(|b| b.date_time >= start && b.date_time < end)
+            .collect();
+        let max_price = windowed_bids.iter().map(|b| b.price).max();
+
+        let tuples = windowed_bids
+            .into_iter()
+            .filter(|b| Some(b.price) == max_price)
+            .map(|b| {
+                (
+                    (b.auction, b.bidder, b.price, b.date_time, b.extra.clone()),
+                    1,
+                )
+            })
+            .collect();
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q7_reference() {
+        validate(q7, q7_reference);
+    }
 }
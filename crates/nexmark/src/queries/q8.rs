@@ -96,8 +96,9 @@ mod tests {
     use crate::{
         generator::tests::{make_auction, make_person},
         model::{Auction, Event, Person},
+        queries::validation::{auctions, people, validate},
     };
-    use dbsp::{zset, RootCircuit};
+    use dbsp::{trace::Batch, zset, RootCircuit};
     use arcstr::ArcStr;
     use rstest::rstest;
 
@@ -213,4 +214,35 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q8_reference(events: &[Event]) -> OrdZSet<(u64, ArcStr, u64), isize> {
+        let watermark = auctions(events)
+            .map(|a| a.date_time - TUMBLE_SECONDS * 1000)
+            .max()
+            .unwrap_or_default();
+        let end = watermark - watermark % (TUMBLE_SECONDS * 1000);
+        let start = end.saturating_sub(TUMBLE_SECONDS * 1000);
+        let in_window = |date_time: u64| date_time >= start && date_time < end;
+
+        let mut tuples = Vec::new();
+        for p in people(events).filter(|p| in_window(p.date_time)) {
+            for _a in auctions(events).filter(|a| in_window(a.date_time) && a.seller == p.id) {
+                tuples.push((
+                    (
+                        p.id,
+                        p.name.clone(),
+                        p.date_time - p.date_time % (TUMBLE_SECONDS * 1000),
+                    ),
+                    1,
+                ));
+            }
+        }
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q8_reference() {
+        validate(q8, q8_reference);
+    }
 }
@@ -271,8 +271,9 @@ mod tests {
     use crate::{
         generator::tests::{make_auction, make_bid},
         model::{Auction, Bid, Event},
+        queries::validation::{auctions, bids, validate},
     };
-    use dbsp::zset;
+    use dbsp::{trace::Batch, zset};
 
     #[test]
     fn test_q9() {
@@ -396,4 +397,45 @@ mod tests {
             circuit.step().unwrap();
         }
     }
+
+    fn q9_reference(events: &[Event]) -> OrdZSet<Q9Output, isize> {
+        let mut tuples = Vec::new();
+        for a in auctions(events) {
+            let winning_bid = bids(events)
+                .filter(|b| {
+                    b.auction == a.id && b.date_time >= a.date_time && b.date_time <= a.expires
+                })
+                .max_by_key(|b| (b.price, b.bidder, b.date_time, b.extra.clone()));
+
+            if let Some(b) = winning_bid {
+                tuples.push((
+                    Q9Output(
+                        a.id,
+                        a.item_name.clone(),
+                        a.description.clone(),
+                        a.initial_bid,
+                        a.reserve,
+                        a.date_time,
+                        a.expires,
+                        a.seller,
+                        a.category,
+                        a.extra.clone(),
+                        b.auction,
+                        b.bidder,
+                        b.price,
+                        b.date_time,
+                        b.extra.clone(),
+                    ),
+                    1,
+                ));
+            }
+        }
+
+        OrdZSet::from_keys((), tuples)
+    }
+
+    #[test]
+    fn test_q9_reference() {
+        validate(q9, q9_reference);
+    }
 }
@@ -0,0 +1,143 @@
+//! Validation of query results against reference implementations.
+//!
+//! Each query is checked by feeding a generated event stream to its circuit
+//! in small batches, and comparing the integral of the query output after
+//! every step against a straightforward, non-incremental reference
+//! implementation of the query evaluated over all events seen so far.
+//! Reference implementations live next to the queries they validate.
+
+use super::NexmarkStream;
+use crate::{
+    config::Config as NexmarkConfig,
+    generator::{config::Config as GeneratorConfig, NexmarkGenerator},
+    model::{Auction, Bid, Event, Person},
+};
+use dbsp::{IndexedZSet, RootCircuit, Stream};
+use rand::{rngs::SmallRng, SeedableRng};
+use std::fmt::Debug;
+use time::{
+    format_description::well_known::{iso8601, iso8601::FormattedComponents, Iso8601},
+    OffsetDateTime,
+};
+
+/// Event time of the first generated event: 2022-09-01T00:00:00Z.
+pub(crate) const BASE_TIME: u64 = 1_661_990_400_000;
+
+/// Number of events fed to the circuit at each step.
+const STEP_SIZE: usize = 250;
+
+/// Generate a deterministic sequence of events spanning a few minutes of
+/// event time, with a small amount of out-of-order jitter.
+pub(crate) fn generate_events() -> Vec<Event> {
+    let config = GeneratorConfig::new(
+        NexmarkConfig {
+            num_event_generators: 1,
+            first_event_rate: 20,
+            max_events: 5_000,
+            out_of_order_group_size: 4,
+            ..NexmarkConfig::default()
+        },
+        BASE_TIME,
+        0,
+        0,
+    );
+    let mut generator = NexmarkGenerator::new(config, SmallRng::seed_from_u64(0), BASE_TIME);
+
+    let mut events = Vec::new();
+    while let Some(next_event) = generator.next_event().unwrap() {
+        events.push(next_event.event);
+    }
+    events
+}
+
+/// Check that `query` produces the same results as `reference` after each
+/// step of the circuit.
+///
+/// `reference` computes the complete (integrated) output of the query over
+/// the prefix of the event stream fed to the circuit so far.
+pub(crate) fn validate<B, Q, R>(query: Q, reference: R)
+where
+    B: IndexedZSet + Send + Debug,
+    Q: FnOnce(NexmarkStream) -> Stream<RootCircuit, B> + 'static,
+    R: Fn(&[Event]) -> B,
+{
+    let (circuit, (mut input_handle, output_handle)) = RootCircuit::build(move |circuit| {
+        let (stream, input_handle) = circuit.add_input_zset::<Event, isize>();
+        let output_handle = query(stream).integrate().output();
+
+        (input_handle, output_handle)
+    })
+    .unwrap();
+
+    let events = generate_events();
+    for end in (STEP_SIZE..=events.len()).step_by(STEP_SIZE) {
+        let mut batch = events[end - STEP_SIZE..end]
+            .iter()
+            .map(|event| (event.clone(), 1))
+            .collect();
+        input_handle.append(&mut batch);
+        circuit.step().unwrap();
+
+        assert_eq!(
+            output_handle.consolidate(),
+            reference(&events[..end]),
+            "query output diverges from the reference after {end} events"
+        );
+    }
+}
+
+/// All bids in `events`.
+pub(crate) fn bids(events: &[Event]) -> impl Iterator<Item = &Bid> {
+    events.iter().filter_map(|event| match event {
+        Event::Bid(b) => Some(b),
+        _ => None,
+    })
+}
+
+/// All auctions in `events`.
+pub(crate) fn auctions(events: &[Event]) -> impl Iterator<Item = &Auction> {
+    events.iter().filter_map(|event| match event {
+        Event::Auction(a) => Some(a),
+        _ => None,
+    })
+}
+
+/// All people in `events`.
+pub(crate) fn people(events: &[Event]) -> impl Iterator<Item = &Person> {
+    events.iter().filter_map(|event| match event {
+        Event::Person(p) => Some(p),
+        _ => None,
+    })
+}
+
+/// Convert milliseconds since the epoch into a UTC date and time.
+pub(crate) fn date_time_for_millis(millis: u64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp_nanos(millis as i128 * 1_000_000).unwrap()
+}
+
+/// The ISO 8601 date of a timestamp in milliseconds since the epoch,
+/// formatted the same way as the per-day queries do.
+pub(crate) fn iso8601_day(millis: u64) -> String {
+    const DAY_FORMAT: Iso8601<
+        {
+            iso8601::Config::DEFAULT
+                .set_formatted_components(FormattedComponents::Date)
+                .encode()
+        },
+    > = Iso8601;
+
+    date_time_for_millis(millis)
+        .date()
+        .format(&DAY_FORMAT)
+        .unwrap()
+}
+
+/// The price range of a bid used by the per-day queries: 1 for prices below
+/// 10,000, 2 for prices below 1,000,000, and 3 for all other prices.
+pub(crate) fn price_rank(price: usize) -> usize {
+    match price {
+        0..=9_999 => 1,
+        10_000..=999_999 => 2,
+        _ => 3,
+    }
+}
@@ -136,6 +136,8 @@ observed by the user is outdated, so the request is rejected."
         dbsp_adapters::OutputEndpointConfig,
         dbsp_adapters::TransportConfig,
         dbsp_adapters::FormatConfig,
+        dbsp_adapters::transport::DirectoryInputConfig,
+        dbsp_adapters::transport::FileOrder,
         dbsp_adapters::transport::FileInputConfig,
         dbsp_adapters::transport::FileOutputConfig,
         dbsp_adapters::transport::KafkaInputConfig,
@@ -144,6 +146,9 @@ observed by the user is outdated, so the request is rejected."
         dbsp_adapters::transport::KafkaOutputConfig,
         dbsp_adapters::format::CsvEncoderConfig,
         dbsp_adapters::format::CsvParserConfig,
+        dbsp_adapters::format::JsonEncoderConfig,
+        dbsp_adapters::format::JsonParserConfig,
+        dbsp_adapters::format::JsonUpdateFormat,
         ProjectId,
         PipelineId,
         ConfigId,
@@ -167,8 +167,8 @@ class DBSPPipelineConfig:
         pipeline.delete()
 
 class CsvInputFormatConfig(FormatConfig):
-    def __init__(self):
-         super().__init__('csv', CsvParserConfig())
+    def __init__(self, **csv_config_options):
+        super().__init__('csv', CsvParserConfig.from_dict(csv_config_options))
 
 
 class CsvOutputFormatConfig(FormatConfig):